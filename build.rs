@@ -0,0 +1,35 @@
+// build.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// with the ffi feature, generate the C header for the library
+#[cfg(feature = "ffi")]
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is not set");
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+    cbindgen::Builder::new()
+        .with_src(format!("{crate_dir}/src/ffi.rs"))
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate the C header")
+        .write_to_file(format!("{crate_dir}/include/icontool.h"));
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
+
+#[cfg(not(feature = "ffi"))]
+fn main() {}
@@ -0,0 +1,94 @@
+#ifndef ICONTOOL_H
+#define ICONTOOL_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The call succeeded.
+ */
+#define ICONTOOL_OK 0
+
+/**
+ * A pointer was null, or text was not valid UTF-8.
+ */
+#define ICONTOOL_ERROR_ARGUMENT 1
+
+/**
+ * The .dmi file could not be decoded or encoded as a PNG.
+ */
+#define ICONTOOL_ERROR_PNG 2
+
+/**
+ * The .dmi file has no metadata.
+ */
+#define ICONTOOL_ERROR_MISSING_METADATA 3
+
+/**
+ * The .dmi metadata could not be parsed, or describes an invalid icon.
+ */
+#define ICONTOOL_ERROR_METADATA 4
+
+/**
+ * The .dmi.yml text could not be read.
+ */
+#define ICONTOOL_ERROR_YAML 5
+
+/**
+ * Any other error, including a bug inside icontool.
+ */
+#define ICONTOOL_ERROR_OTHER 6
+
+/**
+ * Bytes allocated by icontool; release them with icontool_free.
+ */
+typedef struct IconToolBuffer {
+  uint8_t *data;
+  size_t len;
+} IconToolBuffer;
+
+/**
+ * Compile .dmi.yml text into the bytes of a .dmi file.
+ *
+ * # Safety
+ * `yaml` must point to `yaml_len` readable bytes, and `error` must be null
+ * or point to a writable int32_t.
+ */
+struct IconToolBuffer icontool_compile(const uint8_t *yaml, size_t yaml_len, int32_t *error);
+
+/**
+ * Decompile the bytes of a .dmi file into .dmi.yml text (UTF-8, not
+ * nul-terminated).
+ *
+ * # Safety
+ * `dmi` must point to `dmi_len` readable bytes, and `error` must be null
+ * or point to a writable int32_t.
+ */
+struct IconToolBuffer icontool_decompile(const uint8_t *dmi, size_t dmi_len, int32_t *error);
+
+/**
+ * Read the metadata text (UTF-8, not nul-terminated) of a .dmi file.
+ *
+ * # Safety
+ * `dmi` must point to `dmi_len` readable bytes, and `error` must be null
+ * or point to a writable int32_t.
+ */
+struct IconToolBuffer icontool_metadata(const uint8_t *dmi, size_t dmi_len, int32_t *error);
+
+/**
+ * Release a buffer returned by icontool.
+ *
+ * # Safety
+ * `buffer` must have been returned by icontool, and not already freed.
+ */
+void icontool_free(struct IconToolBuffer buffer);
+
+/**
+ * The message for the last error on this thread, or null if the last call
+ * succeeded. The message is valid until the next call on this thread.
+ */
+const char *icontool_last_error(void);
+
+#endif  /* ICONTOOL_H */
@@ -0,0 +1,361 @@
+// apply.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use base64::prelude::*;
+use indexmap::IndexMap;
+use lz4_flex::block::decompress_size_prepended;
+use serde_yml::Value;
+use std::path::Path;
+
+use crate::atomic::write_atomic;
+use crate::cmdline::ApplyArgs;
+use crate::constant::{DMI_METADATA_KEY, ICONTOOL_KEYS, IMAGE_HEIGHT_KEY, IMAGE_WIDTH_KEY};
+use crate::error::{IconToolError, Result};
+use crate::indexmap_helper::IndexMapHelper;
+use crate::parser::{parse_metadata, DreamMakerIconMetadata, DreamMakerIconState};
+use crate::validate::VALID_DIRS;
+
+// the properties of an icon_state that an overlay can set
+const OVERLAY_KEYS: [&str; 7] = [
+    "dirs", "frames", "delay", "loop", "rewind", "movement", "pixels",
+];
+
+pub fn apply(args: &ApplyArgs) -> Result<()> {
+    let base = std::fs::read_to_string(&args.base)?;
+    let overlay = std::fs::read_to_string(&args.overlay)?;
+    let yaml = apply_overlay(&base, &overlay)?;
+    write_atomic(Path::new(&args.output), yaml)
+}
+
+// layer an overlay onto a .dmi.yml; the overlay maps icon_state names to
+// the properties that change, like:
+//
+//   open:
+//     delay: [1, 2]
+//     pixels: |-
+//       <one base64 frame per line, like a .dmi.yml>
+//
+// icon_states that aren't in the base are added to the end; the merge
+// happens on the .dmi.yml itself, so everything the overlay doesn't touch
+// (other __ keys, @ref: frames, included icon_states) comes through as-is
+pub fn apply_overlay(base: &str, overlay: &str) -> Result<String> {
+    let mut yaml: IndexMap<String, Value> = serde_yml::from_str(base)?;
+    let mut dmi = parse_metadata(&yaml.get_string(DMI_METADATA_KEY)?)?;
+    let overlay: IndexMap<String, Value> = serde_yml::from_str(overlay)?;
+    for (key, changes) in &overlay {
+        apply_state(&mut yaml, &mut dmi, key, changes)
+            .map_err(|reason| IconToolError::OverlayConflict(key.clone(), reason))?;
+    }
+    grow_image(&mut yaml, &dmi);
+    yaml.insert(DMI_METADATA_KEY.to_string(), Value::String(dmi.to_string()));
+    Ok(serde_yml::to_string(&yaml)?)
+}
+
+fn apply_state(
+    yaml: &mut IndexMap<String, Value>,
+    dmi: &mut DreamMakerIconMetadata,
+    key: &str,
+    changes: &Value,
+) -> std::result::Result<(), String> {
+    let changes = changes
+        .as_mapping()
+        .ok_or_else(|| String::from("expected a mapping of properties"))?;
+    let mut properties = IndexMap::new();
+    for (name, value) in changes {
+        match name.as_str() {
+            Some(name) if OVERLAY_KEYS.contains(&name) => properties.insert(name, value),
+            _ => return Err(format!("unknown property {name:?}")),
+        };
+    }
+
+    // start from the icon_state in the base, or from a new one
+    if ICONTOOL_KEYS.contains(&key) {
+        return Err(String::from("not an icon_state"));
+    }
+    let index = dmi.find_state(key);
+    let mut state = match index {
+        Some(index) => dmi.states[index].clone(),
+        None => DreamMakerIconState::new(key, 1, 1),
+    };
+    let shape = (state.dirs, state.frames);
+
+    // the shape of the icon_state and its pixels have to agree
+    let (width, height) = (dmi.width, dmi.height);
+    let pixels = match properties.get("pixels") {
+        Some(value) => Some(decode_frames(value, width, height)?),
+        None => None,
+    };
+    if let Some(value) = properties.get("dirs") {
+        state.dirs = get_count(value, "dirs")?;
+        if !VALID_DIRS.contains(&state.dirs) {
+            return Err(format!("dirs = {}; expected 1, 4, or 8", state.dirs));
+        }
+    }
+    match (properties.get("frames"), &pixels) {
+        (Some(value), _) => state.frames = get_count(value, "frames")?,
+        (None, Some(pixels)) if index.is_none() => {
+            state.frames = (pixels.len() as u32 / state.dirs).max(1);
+        }
+        _ => {}
+    }
+    match &pixels {
+        Some(pixels) if pixels.len() != (state.dirs * state.frames) as usize => {
+            return Err(format!(
+                "{} frame(s) of pixels, but {} dir(s) of {} frame(s) need {}",
+                pixels.len(),
+                state.dirs,
+                state.frames,
+                state.dirs * state.frames
+            ));
+        }
+        Some(_) => {}
+        None if index.is_none() => {
+            return Err(String::from("a new icon_state needs pixels"));
+        }
+        None if (state.dirs, state.frames) != shape => {
+            return Err(String::from(
+                "dirs or frames changed, but there are no new pixels",
+            ));
+        }
+        None => {}
+    }
+
+    // the animation properties are copied into the metadata
+    if let Some(value) = properties.get("delay") {
+        state.delay = Some(get_list(value)?);
+    }
+    if let Some(value) = properties.get("loop") {
+        state._loop = Some(get_scalar(value, "loop")?);
+    }
+    if let Some(value) = properties.get("rewind") {
+        state.rewind = Some(get_scalar(value, "rewind")?);
+    }
+    if let Some(value) = properties.get("movement") {
+        state.movement = !matches!(get_scalar(value, "movement")?.as_str(), "0" | "false");
+    }
+    if let Some(delay) = &state.delay {
+        if delay.len() != state.frames as usize {
+            return Err(format!(
+                "{} delay(s) for {} frame(s)",
+                delay.len(),
+                state.frames
+            ));
+        }
+    }
+
+    // put the icon_state back where it came from; a new one goes after
+    // the last icon_state in the file, ahead of the trailing __ keys
+    if let Some(pixels) = pixels {
+        let pixels = Value::String(pixels.join("\n"));
+        if yaml.contains_key(key) {
+            yaml.insert(key.to_string(), pixels);
+        } else {
+            let position = state_position(yaml, dmi);
+            yaml.shift_insert(position, key.to_string(), pixels);
+        }
+    }
+    match index {
+        Some(index) => dmi.states[index] = state,
+        None => dmi.states.push(state),
+    }
+    Ok(())
+}
+
+// check that each line of the pixels is a frame of the right size, and
+// hand back the lines as they'll be written to the .dmi.yml
+fn decode_frames(
+    value: &Value,
+    width: u32,
+    height: u32,
+) -> std::result::Result<Vec<String>, String> {
+    let text = value
+        .as_str()
+        .ok_or_else(|| String::from("pixels should be base64 frames, one per line"))?;
+    let mut frames = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let compressed = BASE64_STANDARD
+            .decode(line.trim())
+            .map_err(|x| format!("frame {index} of the pixels isn't base64: {x}"))?;
+        let pixels = decompress_size_prepended(&compressed)
+            .map_err(|x| format!("frame {index} of the pixels can't be decompressed: {x}"))?;
+        if pixels.len() as u64 != width as u64 * height as u64 * 4 {
+            return Err(format!(
+                "frame {index} of the pixels isn't {width}x{height}"
+            ));
+        }
+        frames.push(line.trim().to_string());
+    }
+    Ok(frames)
+}
+
+// where a new icon_state goes in the .dmi.yml
+fn state_position(yaml: &IndexMap<String, Value>, dmi: &DreamMakerIconMetadata) -> usize {
+    let keys = dmi.state_keys();
+    match keys.iter().filter_map(|key| yaml.get_index_of(key)).max() {
+        Some(last) => last + 1,
+        None => yaml.get_index_of(DMI_METADATA_KEY).unwrap_or(yaml.len()),
+    }
+}
+
+// add rows to the sheet if the new frames don't fit; compile would do the
+// same, but it warns about it
+fn grow_image(yaml: &mut IndexMap<String, Value>, dmi: &DreamMakerIconMetadata) {
+    let width = yaml.get(IMAGE_WIDTH_KEY).and_then(Value::as_u64);
+    let height = yaml.get(IMAGE_HEIGHT_KEY).and_then(Value::as_u64);
+    let (Some(width), Some(height)) = (width, height) else {
+        return;
+    };
+    let (icon_width, icon_height) = (dmi.width as u64, dmi.height as u64);
+    let frames_per_row = width / icon_width.max(1);
+    if frames_per_row == 0 {
+        return;
+    }
+    let frames_needed: u64 = dmi
+        .states
+        .iter()
+        .map(|state| state.dirs as u64 * state.frames as u64)
+        .sum();
+    if frames_needed >= frames_per_row * (height / icon_height.max(1)) {
+        let rows_needed = frames_needed / frames_per_row + 1;
+        yaml.insert(
+            IMAGE_HEIGHT_KEY.to_string(),
+            Value::from(rows_needed * icon_height),
+        );
+    }
+}
+
+fn get_count(value: &Value, name: &str) -> std::result::Result<u32, String> {
+    match value.as_u64() {
+        Some(count) if count > 0 && count <= u32::MAX as u64 => Ok(count as u32),
+        _ => Err(format!(
+            "{name} should be a positive number, found {value:?}"
+        )),
+    }
+}
+
+fn get_scalar(value: &Value, name: &str) -> std::result::Result<String, String> {
+    match value {
+        Value::Number(x) => Ok(x.to_string()),
+        Value::String(x) => Ok(x.clone()),
+        Value::Bool(x) => Ok(String::from(if *x { "1" } else { "0" })),
+        _ => Err(format!("{name} should be a number, found {value:?}")),
+    }
+}
+
+fn get_list(value: &Value) -> std::result::Result<Vec<String>, String> {
+    // delays can be a list, or a comma separated string like the metadata
+    match value {
+        Value::Sequence(items) => items.iter().map(|x| get_scalar(x, "delay")).collect(),
+        Value::String(x) => Ok(x.split(',').map(|x| x.trim().to_string()).collect()),
+        _ => Ok(vec![get_scalar(value, "delay")?]),
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile_bytes;
+    use crate::constant::{DMI_PATH_KEY, TRANSFORMS_KEY};
+    use crate::decompile::decompile_bytes;
+    use crate::dmi_file::DmiFile;
+    use image::{Rgba, RgbaImage};
+    use lz4_flex::block::compress_prepend_size;
+
+    fn base() -> String {
+        decompile_bytes(&std::fs::read("tests/data/edit/spin.dmi").unwrap()).unwrap()
+    }
+
+    fn red_frame() -> String {
+        let red = RgbaImage::from_pixel(32, 32, Rgba([255, 0, 0, 255]));
+        BASE64_STANDARD.encode(compress_prepend_size(red.as_raw()))
+    }
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_apply_overlay() {
+        let overlay = format!(
+            "spin:\n  delay: [2, 2, 2]\n  rewind: 1\nglow:\n  pixels: |-\n    {}\n    {}\n",
+            red_frame(),
+            red_frame()
+        );
+        let yaml = apply_overlay(&base(), &overlay).unwrap();
+        let merged = DmiFile::from_bytes(&compile_bytes(&yaml).unwrap()).unwrap();
+        let original = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        assert_eq!(
+            vec!["spin", "walk", "still", "glow"],
+            merged.metadata.state_keys()
+        );
+        let spin = &merged.metadata.states[0];
+        assert_eq!(Some(vec!["2".to_string(); 3]), spin.delay);
+        assert_eq!(Some(String::from("1")), spin.rewind);
+        assert_eq!(original.frames[0], merged.frames[0]);
+        assert_eq!(2, merged.metadata.states[3].frames);
+    }
+
+    #[test]
+    fn test_apply_overlay_keeps_base_keys() {
+        let base = format!(
+            "__dmi_path: icons/spin.dmi\n{}__transforms:\n- state: still\n  ops: [flip: horizontal]\n",
+            base()
+        );
+        let overlay = format!("glow:\n  pixels: |-\n    {}\n", red_frame());
+        let yaml = apply_overlay(&base, &overlay).unwrap();
+        let before: IndexMap<String, Value> = serde_yml::from_str(&base).unwrap();
+        let after: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        assert_eq!(before[DMI_PATH_KEY], after[DMI_PATH_KEY]);
+        assert_eq!(before[TRANSFORMS_KEY], after[TRANSFORMS_KEY]);
+        assert_eq!(before["still"], after["still"]);
+        let keys: Vec<&str> = after.keys().map(String::as_str).collect();
+        assert_eq!(
+            Some(&"glow"),
+            keys.get(keys.iter().position(|x| *x == "still").unwrap() + 1)
+        );
+    }
+
+    #[test]
+    fn test_apply_overlay_conflicts() {
+        let conflicts = [
+            ("spin:\n  frames: 4\n", "dirs or frames changed"),
+            ("spin:\n  delay: 1,2\n", "2 delay(s) for 3 frame(s)"),
+            ("glow:\n  loop: 1\n", "a new icon_state needs pixels"),
+            ("still:\n  sparkle: 1\n", "unknown property"),
+            ("still:\n  dirs: 3\n", "expected 1, 4, or 8"),
+        ];
+        for (overlay, reason) in conflicts {
+            match apply_overlay(&base(), overlay) {
+                Err(IconToolError::OverlayConflict(_, x)) => assert!(x.contains(reason), "{x}"),
+                _ => panic!("test_apply_overlay_conflicts: Expected OverlayConflict error"),
+            }
+        }
+        let overlay = format!("still:\n  dirs: 4\n  pixels: |-\n    {}\n", red_frame());
+        match apply_overlay(&base(), &overlay) {
+            Err(IconToolError::OverlayConflict(key, x)) => {
+                assert_eq!("still", key);
+                assert_eq!("1 frame(s) of pixels, but 4 dir(s) of 1 frame(s) need 4", x);
+            }
+            _ => panic!("test_apply_overlay_conflicts: Expected OverlayConflict error"),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// archive.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::atomic::write_atomic;
+use crate::constant::MAX_INPUT_BYTES;
+use crate::error::{IconToolError, Result};
+use crate::walk::{create_parent_dir, find_files};
+
+// a file to read, either on its own or inside a .zip archive
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    File(PathBuf),
+    Entry(PathBuf, String),
+}
+
+impl Input {
+    // the name to show the user; a file in an archive is named like
+    // icons.zip:mob/neck.dmi
+    pub fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.display().to_string(),
+            Input::Entry(archive, name) => format!("{}:{name}", archive.display()),
+        }
+    }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Input::File(path) => Ok(std::fs::read(path)?),
+            Input::Entry(archive, name) => read_entry(archive, name),
+        }
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("zip"))
+}
+
+// like find_files, but a .zip archive stands for the files in it that have
+// the extension
+pub fn find_inputs(paths: &[String], recursive: bool, extension: &str) -> Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for path in find_files(paths, recursive, extension)? {
+        if !is_archive(&path) {
+            inputs.push(Input::File(path));
+            continue;
+        }
+        for name in list_entries(&path, extension)? {
+            inputs.push(Input::Entry(path.clone(), name));
+        }
+    }
+    Ok(inputs)
+}
+
+// the names of the files in an archive that have the extension, in order;
+// a name that would land outside the directory it's unpacked into, like
+// ../neck.dmi, is left out
+pub fn list_entries(path: &Path, extension: &str) -> Result<Vec<String>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut names = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.is_file() && entry.enclosed_name().is_some() && entry.name().ends_with(extension) {
+            names.push(entry.name().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn read_entry(path: &Path, name: &str) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let entry = archive.by_name(name)?;
+
+    // the size the archive gives can't be trusted, so stop at the limit
+    let mut bytes = Vec::new();
+    entry
+        .take(MAX_INPUT_BYTES as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > MAX_INPUT_BYTES {
+        return Err(IconToolError::InvalidArchive(format!(
+            "{}:{name} unpacks to more than {MAX_INPUT_BYTES} bytes",
+            path.display()
+        )));
+    }
+    Ok(bytes)
+}
+
+// add files to an archive, replacing any with the same names; the archive
+// is made if it doesn't exist yet, and is rewritten all at once, so a write
+// that is interrupted can't leave it damaged
+pub fn write_entries(path: &Path, files: &[(String, Vec<u8>)]) -> Result<()> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    if path.exists() {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            if !files.iter().any(|(name, _)| name == entry.name()) {
+                writer.raw_copy_file(entry)?;
+            }
+        }
+    }
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, bytes) in files {
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(bytes)?;
+    }
+    let bytes = writer.finish()?.into_inner();
+    create_parent_dir(path)?;
+    write_atomic(path, bytes)
+}
+
+// the name in an archive of a path under it, like mob/neck.dmi.yml for
+// yaml.zip/mob/neck.dmi.yml; archives always separate directories with /
+pub fn get_entry_name(archive: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(archive).unwrap_or(path);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_write_and_read_entries() {
+        let dir = Path::new("tests/data/archive/entries.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("icons.zip");
+        let _ = std::fs::remove_file(&path);
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let files = vec![
+            (String::from("mob/spin.dmi"), dmi.clone()),
+            (String::from("README.txt"), b"icons".to_vec()),
+            (String::from("../escape.dmi"), dmi.clone()),
+        ];
+        write_entries(&path, &files).unwrap();
+        assert_eq!(vec!["mob/spin.dmi"], list_entries(&path, ".dmi").unwrap());
+        assert_eq!(dmi, read_entry(&path, "mob/spin.dmi").unwrap());
+
+        // writing a name again replaces it, and keeps the rest
+        write_entries(
+            &path,
+            &[(String::from("README.txt"), b"more icons".to_vec())],
+        )
+        .unwrap();
+        assert_eq!(
+            b"more icons".to_vec(),
+            read_entry(&path, "README.txt").unwrap()
+        );
+        assert_eq!(dmi, read_entry(&path, "mob/spin.dmi").unwrap());
+        assert_eq!(2, list_entries(&path, "").unwrap().len());
+
+        match read_entry(&path, "mob/missing.dmi") {
+            Err(IconToolError::InvalidArchive(_)) => {}
+            _ => panic!("test_write_and_read_entries: Expected InvalidArchive error"),
+        }
+        match list_entries(Path::new("README.md"), ".dmi") {
+            Err(IconToolError::InvalidArchive(_)) => {}
+            _ => panic!("test_write_and_read_entries: Expected InvalidArchive error"),
+        }
+    }
+
+    #[test]
+    fn test_find_inputs() {
+        let dir = Path::new("tests/data/archive/inputs.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("icons.zip");
+        let _ = std::fs::remove_file(&path);
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let files = vec![
+            (String::from("b.dmi"), dmi.clone()),
+            (String::from("a.dmi"), dmi),
+        ];
+        write_entries(&path, &files).unwrap();
+        let paths = vec![
+            String::from("tests/data/edit/spin.dmi"),
+            path.display().to_string(),
+        ];
+        let inputs = find_inputs(&paths, false, ".dmi").unwrap();
+        assert_eq!(3, inputs.len());
+        assert_eq!("tests/data/edit/spin.dmi", inputs[0].name());
+        assert_eq!(format!("{}:a.dmi", path.display()), inputs[1].name());
+        assert_eq!(inputs[0].read().unwrap(), inputs[2].read().unwrap());
+    }
+
+    #[test]
+    fn test_get_entry_name() {
+        let archive = Path::new("build/yaml.zip");
+        assert_eq!(
+            "mob/neck.dmi.yml",
+            get_entry_name(archive, Path::new("build/yaml.zip/mob/neck.dmi.yml"))
+        );
+        assert_eq!(
+            "neck.dmi.yml",
+            get_entry_name(archive, Path::new("neck.dmi.yml"))
+        );
+    }
+}
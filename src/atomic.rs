@@ -0,0 +1,118 @@
+// atomic.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{IconToolError, Result};
+
+// write the contents to the path, so that the path either keeps its old
+// contents or gets all of the new ones, even if we're interrupted
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    create_atomic(path, |writer| Ok(writer.write_all(contents.as_ref())?))
+}
+
+// stream output to a temporary file next to the path, and only rename it
+// into place once everything has been written; a failed or interrupted
+// write leaves whatever was at the path before untouched
+pub fn create_atomic<T>(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T>,
+) -> Result<T> {
+    // the temporary file must be in the same directory, because a rename
+    // can't move a file to another filesystem
+    let temp_path = get_temp_path(path)?;
+    let result = write_temp_file(&temp_path, write).and_then(|value| {
+        std::fs::rename(&temp_path, path)?;
+        #[cfg(feature = "cli")]
+        crate::hook::after_write(path)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_temp_file<T>(
+    temp_path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<T>,
+) -> Result<T> {
+    let mut writer = BufWriter::new(File::create(temp_path)?);
+    let value = write(&mut writer)?;
+    let file = writer.into_inner().map_err(|x| x.into_error())?;
+    file.sync_all()?;
+    Ok(value)
+}
+
+fn get_temp_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| IconToolError::PathError(format!("'{}' is not a file", path.display())))?
+        .to_string_lossy();
+    let temp_name = format!(".{file_name}.{}.tmp", std::process::id());
+    Ok(path.with_file_name(temp_name))
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        std::fs::create_dir_all("tests/data/atomic").unwrap();
+        let path = Path::new("tests/data/atomic/write.out.txt");
+        write_atomic(path, "old").unwrap();
+        write_atomic(path, "new").unwrap();
+        assert_eq!("new", std::fs::read_to_string(path).unwrap());
+        assert!(!get_temp_path(path).unwrap().exists());
+    }
+
+    #[test]
+    fn test_create_atomic_failure() {
+        std::fs::create_dir_all("tests/data/atomic").unwrap();
+        let path = Path::new("tests/data/atomic/failure.out.txt");
+        write_atomic(path, "old").unwrap();
+        let result: Result<()> = create_atomic(path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(IconToolError::TooManyFrames())
+        });
+        assert!(result.is_err());
+        assert_eq!("old", std::fs::read_to_string(path).unwrap());
+        assert!(!get_temp_path(path).unwrap().exists());
+    }
+
+    #[test]
+    fn test_get_temp_path() {
+        let temp_path = get_temp_path(Path::new("icons/mob/neck.dmi")).unwrap();
+        assert_eq!(Path::new("icons/mob"), temp_path.parent().unwrap());
+        let temp_name = temp_path.file_name().unwrap().to_string_lossy();
+        assert!(temp_name.starts_with(".neck.dmi."));
+        assert!(temp_name.ends_with(".tmp"));
+        assert!(get_temp_path(Path::new("..")).is_err());
+    }
+}
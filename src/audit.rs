@@ -0,0 +1,253 @@
+// audit.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::archive::{find_inputs, Input};
+use crate::cmdline::AuditArgs;
+use crate::diagnostics::Diagnostics;
+use crate::dmi::{decode_image, decode_metadata, to_rgba_image};
+use crate::dmi_file::DmiFile;
+use crate::error::{get_error_code, get_error_text, MissingMetadata, Result};
+use crate::parser::parse_metadata;
+
+// a summary of every .dmi file found by the audit
+#[derive(Debug, Default)]
+pub struct AuditReport {
+    pub files: usize,
+    pub states: usize,
+    pub frames: usize,
+    pub icon_sizes: BTreeMap<String, usize>,
+    pub wasted: Vec<(String, u32)>, // unused frame slots in the sheet
+    pub empty: Vec<(String, String)>, // fully transparent icon_states
+    pub duplicates: Vec<(String, usize)>, // frames repeating an earlier frame
+    pub unreadable: Vec<(String, String, String)>, // error code and message
+}
+
+pub fn audit(args: &AuditArgs) -> Result<()> {
+    let mut report = AuditReport::default();
+    for input in find_inputs(&args.paths, args.recursive, ".dmi")? {
+        audit_input(&input, &mut report);
+    }
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&to_json(&report))
+            .expect("Failed to serialize audit report to JSON");
+        println!("{json}");
+    } else {
+        for line in format_report(&report) {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
+
+pub fn audit_file(path: &Path, report: &mut AuditReport) {
+    audit_input(&Input::File(path.to_path_buf()), report);
+}
+
+// like audit_file, but the file may be inside a .zip archive
+pub fn audit_input(input: &Input, report: &mut AuditReport) {
+    let name = input.name();
+    report.files += 1;
+
+    // read the file; one bad file shouldn't stop the audit
+    let (image, dmi) = match read_dmi_file(input) {
+        Ok(x) => x,
+        Err(x) => {
+            let code = get_error_code(&x).to_string();
+            report.unreadable.push((name, code, get_error_text(x)));
+            return;
+        }
+    };
+
+    // count the icon_states and frames
+    let (width, height) = (dmi.metadata.width, dmi.metadata.height);
+    let num_frames: usize = dmi.frames.iter().map(|x| x.len()).sum();
+    report.states += dmi.metadata.states.len();
+    report.frames += num_frames;
+    *report
+        .icon_sizes
+        .entry(format!("{width}x{height}"))
+        .or_default() += 1;
+
+    // a whole row or more of empty slots is more than the layout needs
+    let frames_per_row = image.0 / width.max(1);
+    let slots = frames_per_row * (image.1 / height.max(1));
+    let unused = slots.saturating_sub(num_frames as u32);
+    if unused >= frames_per_row.max(1) {
+        report.wasted.push((name.clone(), unused));
+    }
+
+    // look for icon_states without a single visible pixel
+    for state in dmi.states() {
+        if state.is_empty() {
+            report.empty.push((name.clone(), state.name().to_string()));
+        }
+    }
+
+    // look for frames that repeat an earlier frame of the file
+    let mut seen = HashSet::new();
+    let repeated = dmi
+        .frames
+        .iter()
+        .flatten()
+        .filter(|frame| !seen.insert(frame.as_raw()))
+        .count();
+    if repeated > 0 {
+        report.duplicates.push((name, repeated));
+    }
+}
+
+fn read_dmi_file(input: &Input) -> Result<((u32, u32), DmiFile)> {
+    let bytes = input.read()?;
+    let mut diagnostics = Diagnostics::new();
+    let image = to_rgba_image(decode_image(&bytes)?, &input.name(), &mut diagnostics);
+    diagnostics.print();
+    let image = image::DynamicImage::ImageRgba8(image);
+    let text = decode_metadata(&bytes)?.ok_or_else(|| MissingMetadata(input.name().into()))?;
+    let metadata = parse_metadata(&text)?;
+    let dimensions = (image.width(), image.height());
+    Ok((dimensions, DmiFile::from_image(metadata, &image)?))
+}
+
+pub fn format_report(report: &AuditReport) -> Vec<String> {
+    let mut lines = vec![
+        format!("files: {}", report.files),
+        format!("icon_states: {}", report.states),
+        format!("frames: {}", report.frames),
+        String::from("icon sizes:"),
+    ];
+    for (size, count) in &report.icon_sizes {
+        lines.push(format!("  {size}: {count}"));
+    }
+
+    // only list the sections that found something
+    let mut section = |heading: &str, entries: Vec<String>| {
+        if !entries.is_empty() {
+            lines.push(heading.to_string());
+            lines.extend(entries);
+        }
+    };
+    let wasted = report.wasted.iter();
+    section(
+        "wasted grid space:",
+        wasted
+            .map(|(file, x)| format!("  {file}: {x} unused frame slot(s)"))
+            .collect(),
+    );
+    let empty = report.empty.iter();
+    section(
+        "empty icon_states:",
+        empty
+            .map(|(file, x)| format!("  {file}: \"{x}\""))
+            .collect(),
+    );
+    let duplicates = report.duplicates.iter();
+    section(
+        "duplicate frames:",
+        duplicates
+            .map(|(file, x)| format!("  {file}: {x} repeated frame(s)"))
+            .collect(),
+    );
+    let unreadable = report.unreadable.iter();
+    section(
+        "unreadable files:",
+        unreadable
+            .map(|(file, code, x)| format!("  {file}: [{code}] {x}"))
+            .collect(),
+    );
+    lines
+}
+
+fn to_json(report: &AuditReport) -> serde_json::Value {
+    json!({
+        "files": report.files,
+        "icon_states": report.states,
+        "frames": report.frames,
+        "icon_sizes": report.icon_sizes,
+        "wasted": report.wasted.iter().map(|(file, x)| json!({ "file": file, "unused_slots": x })).collect::<Vec<_>>(),
+        "empty": report.empty.iter().map(|(file, x)| json!({ "file": file, "state": x })).collect::<Vec<_>>(),
+        "duplicates": report.duplicates.iter().map(|(file, x)| json!({ "file": file, "frames": x })).collect::<Vec<_>>(),
+        "unreadable": report.unreadable.iter().map(|(file, code, x)| json!({ "file": file, "code": code, "error": x })).collect::<Vec<_>>(),
+    })
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_audit_file() {
+        // spin.dmi has 12 frames in a 4x4 grid, and README.md is not a .dmi
+        let mut report = AuditReport::default();
+        audit_file(Path::new("tests/data/edit/spin.dmi"), &mut report);
+        audit_file(Path::new("README.md"), &mut report);
+        assert_eq!(2, report.files);
+        assert_eq!(3, report.states);
+        assert_eq!(12, report.frames);
+        assert_eq!(Some(&1), report.icon_sizes.get("32x32"));
+        assert_eq!(1, report.unreadable.len());
+        let lines = format_report(&report);
+        assert_eq!("files: 2", lines[0]);
+        assert!(lines.contains(&String::from("  32x32: 1")));
+        assert!(lines.contains(&String::from("unreadable files:")));
+        assert_eq!(json!(12), to_json(&report)["frames"]);
+        let json = to_json(&report);
+        assert_eq!(json!("README.md"), json["unreadable"][0]["file"]);
+        assert!(json["unreadable"][0]["code"]
+            .as_str()
+            .unwrap()
+            .starts_with("ICT"));
+    }
+
+    #[test]
+    fn test_audit_archive() {
+        let dir = Path::new("tests/data/audit/archive.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        let archive = dir.join("icons.zip");
+        let _ = std::fs::remove_file(&archive);
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let files = vec![
+            (String::from("mob/spin.dmi"), dmi),
+            (String::from("mob/broken.dmi"), b"not a png".to_vec()),
+        ];
+        crate::archive::write_entries(&archive, &files).unwrap();
+        let mut report = AuditReport::default();
+        let paths = vec![archive.display().to_string()];
+        for input in find_inputs(&paths, false, ".dmi").unwrap() {
+            audit_input(&input, &mut report);
+        }
+        assert_eq!(2, report.files);
+        assert_eq!(12, report.frames);
+        assert_eq!(
+            format!("{}:mob/broken.dmi", archive.display()),
+            report.unreadable[0].0
+        );
+    }
+}
@@ -0,0 +1,508 @@
+// build.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::cache::{
+    get_cache_path, get_target_id, hash_file, hash_source, is_fresh, load_cache, save_cache,
+    BuildCache, CachedTarget,
+};
+use crate::cmdline::{BuildArgs, CleanArgs, CompileArgs, WatchArgs};
+use crate::compile::compile_files;
+use crate::config::{load_config, Config};
+use crate::error::{get_error_message, IconToolError, Result};
+use crate::hook::set_post_process;
+use crate::include::find_includes;
+use crate::log::diagnostic;
+use crate::summary::BatchSummary;
+
+// what happened to a target that watch noticed had changed
+#[derive(Debug, PartialEq)]
+pub enum WatchEvent {
+    Built(String),
+    Failed(String),
+}
+
+// the size and modification time of a file, or None if it isn't there
+type Fingerprint = Option<(u64, SystemTime)>;
+
+// watch keeps an eye on the manifest and the sources it lists, and builds
+// the targets of any source that changes
+pub struct Watcher {
+    manifest: PathBuf,
+    targets: Vec<CompileArgs>,
+    cache: BuildCache,
+    seen: HashMap<PathBuf, Fingerprint>,
+}
+
+pub fn build(args: &BuildArgs) -> Result<()> {
+    let path = Path::new(&args.manifest);
+    let config = load_project(path)?;
+
+    // the cache lives beside the manifest, like the paths of the targets
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut cache = load_cache(base);
+
+    // compile each target; one broken source shouldn't stop the others
+    let mut summary = BatchSummary::new();
+    for target in &config.targets {
+        match build_target(target, base, &mut cache, args.force_rebuild) {
+            Ok(_) => summary.succeed(),
+            Err(x) => {
+                let message = get_error_message(x);
+                let message = message.trim_start_matches("icontool: ");
+                diagnostic(&format!("{}: {message}", target.file));
+                summary.fail(&target.file, message);
+            }
+        }
+    }
+    save_cache(base, &cache)?;
+
+    // if anything failed to build, tell the caller
+    summary.report();
+    if summary.num_failed() > 0 {
+        return Err(IconToolError::BuildFailed(summary.num_failed()));
+    }
+    Ok(())
+}
+
+pub fn watch(args: &WatchArgs) -> Result<()> {
+    let mut watcher = Watcher::new(Path::new(&args.manifest))?;
+    println!("icontool: watching {}", args.manifest);
+    loop {
+        for event in watcher.poll() {
+            match event {
+                WatchEvent::Built(x) => println!("{x}"),
+                WatchEvent::Failed(x) => diagnostic(&x),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(args.interval));
+    }
+}
+
+impl Watcher {
+    pub fn new(manifest: &Path) -> Result<Watcher> {
+        load_project(manifest)?;
+        let base = manifest.parent().unwrap_or(Path::new(""));
+        Ok(Watcher {
+            manifest: manifest.to_path_buf(),
+            targets: Vec::new(),
+            cache: load_cache(base),
+            seen: HashMap::new(),
+        })
+    }
+
+    // look for changes since the last poll, and build the targets they affect
+    pub fn poll(&mut self) -> Vec<WatchEvent> {
+        let mut events = Vec::new();
+
+        // an edited manifest may have new targets, or new options for old ones
+        if self.has_changed(&self.manifest.clone()) {
+            match load_config(&self.manifest) {
+                Ok(config) => {
+                    self.targets = config.targets;
+                    self.seen.retain(|path, _| *path == self.manifest);
+                }
+                Err(x) => {
+                    let message = get_error_message(x);
+                    let message = message.trim_start_matches("icontool: ");
+                    let manifest = self.manifest.display();
+                    events.push(WatchEvent::Failed(format!("{manifest}: {message}")));
+                    return events;
+                }
+            }
+        }
+
+        // several targets may share a source or an include, so find every
+        // changed file first
+        let prerequisites: Vec<Vec<PathBuf>> = self
+            .targets
+            .iter()
+            .map(|target| {
+                let path = PathBuf::from(&target.file);
+                let mut files = find_includes(&path).unwrap_or_default();
+                files.insert(0, path);
+                files
+            })
+            .collect();
+        let files: HashSet<&PathBuf> = prerequisites.iter().flatten().collect();
+        let changed: HashSet<&PathBuf> =
+            files.into_iter().filter(|x| self.has_changed(x)).collect();
+        if changed.is_empty() {
+            return events;
+        }
+
+        let base = self.manifest.parent().unwrap_or(Path::new(""));
+        for (target, files) in self.targets.iter().zip(&prerequisites) {
+            if !files.iter().any(|x| changed.contains(x)) {
+                continue;
+            }
+            match build_target(target, base, &mut self.cache, false) {
+                Ok(true) => events.push(WatchEvent::Built(format!(
+                    "icontool: built {}",
+                    target.file
+                ))),
+                Ok(false) => {}
+                Err(x) => events.push(WatchEvent::Failed(describe_failure(target, x))),
+            }
+        }
+        if let Err(x) = save_cache(base, &self.cache) {
+            events.push(WatchEvent::Failed(get_error_message(x)));
+        }
+        events
+    }
+
+    // true if the file is new, gone, or different since it was last seen
+    fn has_changed(&mut self, path: &Path) -> bool {
+        let fingerprint = std::fs::metadata(path)
+            .ok()
+            .and_then(|x| Some((x.len(), x.modified().ok()?)));
+        self.seen.insert(path.to_path_buf(), fingerprint) != Some(fingerprint)
+    }
+}
+
+// read the manifest that build and watch work from
+fn load_project(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Err(IconToolError::InvalidArgument(format!(
+            "the project manifest '{}' does not exist",
+            path.display()
+        )));
+    }
+    let config = load_config(path)?;
+    // --post-process, or icontool.yml in the current directory, comes first
+    if let Some(command) = &config.post_process {
+        set_post_process(command);
+    }
+    Ok(config)
+}
+
+fn describe_failure(target: &CompileArgs, error: IconToolError) -> String {
+    let message = get_error_message(error);
+    let message = message.trim_start_matches("icontool: ");
+    format!("{}: {message}", target.file)
+}
+
+// remove what build wrote, as recorded in its cache; a file that has changed
+// since then isn't build's to remove anymore, so it is left alone
+pub fn clean(args: &CleanArgs) -> Result<()> {
+    let base = Path::new(&args.manifest).parent().unwrap_or(Path::new(""));
+    let cache = load_cache(base);
+    for target in cache.targets.values() {
+        for (output, hash) in &target.outputs {
+            let path = base.join(output);
+            match hash_file(&path) {
+                Some(x) if &x == hash => std::fs::remove_file(&path)?,
+                Some(_) => diagnostic(&format!(
+                    "icontool: {} was changed after it was built; leaving it",
+                    path.display()
+                )),
+                None => {}
+            }
+        }
+    }
+
+    // the cache directory goes too, unless something else was put in it
+    let cache_path = get_cache_path(base);
+    if cache_path.exists() {
+        std::fs::remove_file(&cache_path)?;
+    }
+    if let Some(dir) = cache_path.parent() {
+        let _ = std::fs::remove_dir(dir);
+    }
+    Ok(())
+}
+
+// compile a target, unless its source and options are the same as the
+// last time, and what it wrote then is still there; true if it compiled
+fn build_target(
+    target: &CompileArgs,
+    base: &Path,
+    cache: &mut BuildCache,
+    force_rebuild: bool,
+) -> Result<bool> {
+    let id = get_target_id(target);
+    let hash = hash_source(Path::new(&target.file));
+    if let (Some(cached), Some(hash), false) = (cache.targets.get(&id), &hash, force_rebuild) {
+        if is_fresh(cached, hash, base) {
+            return Ok(false);
+        }
+    }
+
+    // remember what was written, and how it looked, for the next build
+    let relative = |path: &Path| {
+        let path = path.strip_prefix(base).unwrap_or(path);
+        path.to_string_lossy().to_string()
+    };
+    let mut outputs = IndexMap::new();
+    for path in compile_files(target)? {
+        outputs.insert(relative(&path), hash_file(&path).unwrap_or_default());
+    }
+    // an older entry, from before the options changed, no longer owns them
+    for other in cache.targets.values_mut() {
+        other.outputs.retain(|path, _| !outputs.contains_key(path));
+    }
+    cache.targets.retain(|_, x| !x.outputs.is_empty());
+    let cached = CachedTarget {
+        source: relative(Path::new(&target.file)),
+        hash: hash.unwrap_or_default(),
+        outputs,
+    };
+    cache.targets.insert(id, cached);
+    Ok(true)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmi_file::DmiFile;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_build() {
+        let dir = "tests/data/build/project.out.d";
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let yaml =
+            format!("{yaml}__transforms:\n- state: still\n  ops: [tint: \"${{TEAM_COLOR}}\"]\n");
+        std::fs::write(format!("{dir}/team.dmi.yml"), yaml).unwrap();
+        let manifest = format!("{dir}/icontool.yml");
+        std::fs::write(
+            &manifest,
+            "targets:\n\
+             - source: team.dmi.yml\n  output: red.dmi\n  define: {TEAM_COLOR: \"#ff0000\"}\n\
+             - source: team.dmi.yml\n  output: blue.dmi\n  define: {TEAM_COLOR: \"#0000ff\"}\n  pack: row\n",
+        )
+        .unwrap();
+        let mut args = BuildArgs {
+            manifest: manifest.clone(),
+            force_rebuild: false,
+        };
+        build(&args).unwrap();
+        let red = DmiFile::open(Path::new(&format!("{dir}/red.dmi"))).unwrap();
+        let blue = DmiFile::open(Path::new(&format!("{dir}/blue.dmi"))).unwrap();
+        assert_eq!(red.frames[0], blue.frames[0]);
+        assert_ne!(red.frames[2], blue.frames[2]);
+
+        // a target that fails doesn't stop the rest
+        std::fs::remove_file(format!("{dir}/blue.dmi")).unwrap();
+        std::fs::write(
+            &manifest,
+            "targets:\n\
+             - source: missing.dmi.yml\n\
+             - source: team.dmi.yml\n  output: blue.dmi\n  define: {TEAM_COLOR: \"#0000ff\"}\n",
+        )
+        .unwrap();
+        match build(&args) {
+            Err(IconToolError::BuildFailed(1)) => {}
+            _ => panic!("test_build: Expected BuildFailed error"),
+        }
+        assert!(Path::new(&format!("{dir}/blue.dmi")).exists());
+
+        args.manifest = format!("{dir}/missing.yml");
+        match build(&args) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_build: Expected InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    fn test_build_cache() {
+        let dir = "tests/data/build/cache.out.d";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        std::fs::write(format!("{dir}/spin.dmi.yml"), &yaml).unwrap();
+        let manifest = format!("{dir}/icontool.yml");
+        std::fs::write(&manifest, "targets:\n- source: spin.dmi.yml\n").unwrap();
+        let mut args = BuildArgs {
+            manifest,
+            force_rebuild: false,
+        };
+        let output = format!("{dir}/spin.dmi");
+        let modified = || std::fs::metadata(&output).unwrap().modified().unwrap();
+
+        // the first build compiles, and records what it wrote
+        build(&args).unwrap();
+        let cache = load_cache(Path::new(dir));
+        assert_eq!(1, cache.targets.len());
+        let cached = &cache.targets[0];
+        assert_eq!("spin.dmi.yml", cached.source);
+        assert_eq!(vec!["spin.dmi"], cached.outputs.keys().collect::<Vec<_>>());
+        let built = modified();
+
+        // nothing changed, so nothing is written
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        build(&args).unwrap();
+        assert_eq!(built, modified());
+
+        // unless asked to
+        args.force_rebuild = true;
+        build(&args).unwrap();
+        assert_ne!(built, modified());
+        let built = modified();
+
+        // an output that was changed by hand is written again
+        args.force_rebuild = false;
+        std::fs::write(&output, "oops").unwrap();
+        build(&args).unwrap();
+        assert!(DmiFile::open(Path::new(&output)).is_ok());
+        assert_ne!(built, modified());
+
+        // and so is one with a changed source
+        std::fs::write(format!("{dir}/spin.dmi.yml"), format!("{yaml}\n")).unwrap();
+        let before = load_cache(Path::new(dir)).targets[0].hash.clone();
+        build(&args).unwrap();
+        let after = load_cache(Path::new(dir)).targets[0].hash.clone();
+        assert_ne!(before, after);
+
+        // or a changed include
+        std::fs::write(format!("{dir}/shared.yml"), "unused: AAAA\n").unwrap();
+        std::fs::write(
+            format!("{dir}/spin.dmi.yml"),
+            format!("{yaml}__includes: [shared.yml]\n"),
+        )
+        .unwrap();
+        build(&args).unwrap();
+        let before = load_cache(Path::new(dir)).targets[0].hash.clone();
+        std::fs::write(format!("{dir}/shared.yml"), "unused: BBBB\n").unwrap();
+        build(&args).unwrap();
+        assert_ne!(before, load_cache(Path::new(dir)).targets[0].hash);
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = "tests/data/build/clean.out.d";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        std::fs::write(format!("{dir}/spin.dmi.yml"), &yaml).unwrap();
+        std::fs::write(format!("{dir}/walk.dmi.yml"), &yaml).unwrap();
+        let manifest = format!("{dir}/icontool.yml");
+        std::fs::write(
+            &manifest,
+            "targets:\n- source: spin.dmi.yml\n- source: walk.dmi.yml\n  output: out/walk.dmi\n",
+        )
+        .unwrap();
+        build(&BuildArgs {
+            manifest: manifest.clone(),
+            force_rebuild: false,
+        })
+        .unwrap();
+        assert!(Path::new(&format!("{dir}/spin.dmi")).exists());
+        assert!(Path::new(&format!("{dir}/out/walk.dmi")).exists());
+
+        // a target built again with other options replaces its old entry
+        std::fs::write(
+            &manifest,
+            "targets:\n- source: spin.dmi.yml\n  pack: row\n- source: walk.dmi.yml\n  output: out/walk.dmi\n",
+        )
+        .unwrap();
+        build(&BuildArgs {
+            manifest: manifest.clone(),
+            force_rebuild: false,
+        })
+        .unwrap();
+        assert_eq!(2, load_cache(Path::new(dir)).targets.len());
+
+        // a file edited after the build is kept, as is anything else
+        std::fs::write(format!("{dir}/out/walk.dmi"), "edited").unwrap();
+        std::fs::write(format!("{dir}/other.dmi"), "mine").unwrap();
+        clean(&CleanArgs { manifest }).unwrap();
+        assert!(!Path::new(&format!("{dir}/spin.dmi")).exists());
+        assert!(Path::new(&format!("{dir}/out/walk.dmi")).exists());
+        assert!(Path::new(&format!("{dir}/other.dmi")).exists());
+        assert!(Path::new(&format!("{dir}/spin.dmi.yml")).exists());
+        assert!(!Path::new(&format!("{dir}/.icontool-cache")).exists());
+    }
+
+    #[test]
+    fn test_watcher() {
+        let dir = "tests/data/build/watch.out.d";
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        std::fs::write(format!("{dir}/spin.dmi.yml"), &yaml).unwrap();
+        std::fs::write(format!("{dir}/walk.dmi.yml"), &yaml).unwrap();
+        let manifest = format!("{dir}/icontool.yml");
+        std::fs::write(
+            &manifest,
+            "targets:\n- source: spin.dmi.yml\n- source: walk.dmi.yml\n",
+        )
+        .unwrap();
+        let built = |name: &str| WatchEvent::Built(format!("icontool: built {dir}/{name}"));
+
+        // everything is built at first, and then nothing until a change
+        let mut watcher = Watcher::new(Path::new(&manifest)).unwrap();
+        assert_eq!(
+            vec![built("spin.dmi.yml"), built("walk.dmi.yml")],
+            watcher.poll()
+        );
+        assert!(watcher.poll().is_empty());
+
+        // only the target of a changed source is built again
+        std::fs::write(format!("{dir}/walk.dmi.yml"), format!("{yaml}\n")).unwrap();
+        assert_eq!(vec![built("walk.dmi.yml")], watcher.poll());
+
+        // or of a changed include
+        std::fs::write(format!("{dir}/shared.yml"), "unused: AAAA\n").unwrap();
+        let walk = format!("{yaml}__includes: [shared.yml]\n");
+        std::fs::write(format!("{dir}/walk.dmi.yml"), walk).unwrap();
+        assert_eq!(vec![built("walk.dmi.yml")], watcher.poll());
+        std::fs::write(format!("{dir}/shared.yml"), "unused: AAAAAAAA\n").unwrap();
+        assert_eq!(vec![built("walk.dmi.yml")], watcher.poll());
+
+        // a broken source is reported, and watching carries on
+        std::fs::write(format!("{dir}/walk.dmi.yml"), "oops").unwrap();
+        match &watcher.poll()[..] {
+            [WatchEvent::Failed(x)] => assert!(x.starts_with(&format!("{dir}/walk.dmi.yml: "))),
+            x => panic!("test_watcher: Expected one failure, found {x:?}"),
+        }
+
+        // a changed manifest is read again, and its new targets built
+        std::fs::write(
+            &manifest,
+            "targets:\n- source: spin.dmi.yml\n- source: spin.dmi.yml\n  output: row.dmi\n  pack: row\n",
+        )
+        .unwrap();
+        assert_eq!(vec![built("spin.dmi.yml")], watcher.poll());
+        assert!(Path::new(&format!("{dir}/row.dmi")).exists());
+        std::fs::write(&manifest, "targets: oops\n").unwrap();
+        match &watcher.poll()[..] {
+            [WatchEvent::Failed(x)] => assert!(x.contains("targets must be a list"), "{x}"),
+            x => panic!("test_watcher: Expected one failure, found {x:?}"),
+        }
+
+        std::fs::remove_file(&manifest).unwrap();
+        assert!(Watcher::new(Path::new(&manifest)).is_err());
+    }
+}
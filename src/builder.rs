@@ -0,0 +1,272 @@
+// builder.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// DmiBuilder puts together a .dmi file one icon_state at a time, writing
+// the metadata for you:
+//
+//     let dmi = DmiBuilder::new(32, 32)
+//         .state("open")
+//         .dirs(4)
+//         .frames(2)
+//         .delay(&[1.0, 2.0])     // or .fps(5.0) for the same delay on every frame
+//         .push_frame(south_1)
+//         ...
+//         .build()?;
+//
+// Frames are pushed in sheet order: every direction of frame 1, then every
+// direction of frame 2, and so on. Problems are reported by build().
+
+use image::RgbaImage;
+
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+use crate::parser::{fps_to_delay, normalize_number, DreamMakerIconMetadata, DreamMakerIconState};
+use crate::validate::validate_metadata;
+
+#[derive(Debug)]
+pub struct DmiBuilder {
+    width: u32,
+    height: u32,
+    states: Vec<(DreamMakerIconState, Vec<RgbaImage>)>,
+    problem: Option<IconToolError>, // the first problem, reported by build()
+}
+
+impl DmiBuilder {
+    pub fn new(width: u32, height: u32) -> DmiBuilder {
+        DmiBuilder {
+            width,
+            height,
+            states: Vec::new(),
+            problem: None,
+        }
+    }
+
+    // start a new icon_state, with 1 dir and 1 frame until told otherwise
+    pub fn state(mut self, name: &str) -> DmiBuilder {
+        self.states
+            .push((DreamMakerIconState::new(name, 1, 1), Vec::new()));
+        self
+    }
+
+    pub fn dirs(self, dirs: u32) -> DmiBuilder {
+        self.edit_state("dirs", |state, _| state.dirs = dirs)
+    }
+
+    pub fn frames(self, frames: u32) -> DmiBuilder {
+        self.edit_state("frames", |state, _| state.frames = frames)
+    }
+
+    // the delay of each frame, in ticks
+    pub fn delay(self, delay: &[f64]) -> DmiBuilder {
+        if let Some(bad) = delay.iter().find(|x| !x.is_finite() || **x <= 0.0) {
+            let problem = format!("delays must be positive numbers, found {bad}");
+            return self.fail(IconToolError::InvalidDelay(problem));
+        }
+        let delay = delay
+            .iter()
+            .map(|x| normalize_number(&x.to_string()))
+            .collect();
+        self.edit_state("delay", |state, _| state.delay = Some(delay))
+    }
+
+    // the same delay for every frame, from frames per second; call it after
+    // frames(), since it sets one delay per frame
+    pub fn fps(self, fps: f64) -> DmiBuilder {
+        let delay = match fps_to_delay(fps) {
+            Ok(delay) => normalize_number(&delay.to_string()),
+            Err(x) => return self.fail(x),
+        };
+        self.edit_state("fps", |state, _| {
+            state.delay = Some(vec![delay; state.frames as usize])
+        })
+    }
+
+    // how many times the animation plays; by default, it loops forever
+    pub fn loop_count(self, count: u32) -> DmiBuilder {
+        self.edit_state("loop_count", |state, _| {
+            state._loop = Some(count.to_string())
+        })
+    }
+
+    pub fn rewind(self, rewind: bool) -> DmiBuilder {
+        let value = if rewind {
+            Some(String::from("1"))
+        } else {
+            None
+        };
+        self.edit_state("rewind", |state, _| state.rewind = value)
+    }
+
+    pub fn movement(self, movement: bool) -> DmiBuilder {
+        self.edit_state("movement", |state, _| state.movement = movement)
+    }
+
+    pub fn push_frame(self, frame: RgbaImage) -> DmiBuilder {
+        if frame.dimensions() != (self.width, self.height) {
+            let problem = format!(
+                "frames must be {}x{}, but a {}x{} frame was pushed",
+                self.width,
+                self.height,
+                frame.width(),
+                frame.height()
+            );
+            return self.fail(IconToolError::InvalidArgument(problem));
+        }
+        self.edit_state("push_frame", |_, frames| frames.push(frame))
+    }
+
+    pub fn build(self) -> Result<DmiFile> {
+        if let Some(problem) = self.problem {
+            return Err(problem);
+        }
+
+        // make sure the metadata is something BYOND will understand
+        let mut metadata = DreamMakerIconMetadata::new(self.width, self.height);
+        metadata.states = self.states.iter().map(|(state, _)| state.clone()).collect();
+        validate_metadata(&metadata)?;
+
+        // and that every icon_state got all of its frames
+        let mut dmi = DmiFile {
+            metadata: DreamMakerIconMetadata::new(self.width, self.height),
+            frames: Vec::new(),
+        };
+        for (state, frames) in self.states {
+            dmi.push_state(state, frames)?;
+        }
+        Ok(dmi)
+    }
+
+    fn edit_state<F>(mut self, method: &str, edit: F) -> DmiBuilder
+    where
+        F: FnOnce(&mut DreamMakerIconState, &mut Vec<RgbaImage>),
+    {
+        match self.states.last_mut() {
+            Some((state, frames)) => edit(state, frames),
+            None => {
+                let problem = format!("{method}() was called before state()");
+                return self.fail(IconToolError::InvalidArgument(problem));
+            }
+        }
+        self
+    }
+
+    fn fail(mut self, problem: IconToolError) -> DmiBuilder {
+        self.problem.get_or_insert(problem);
+        self
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_build() {
+        std::fs::create_dir_all("tests/data/builder").unwrap();
+        let red = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255]));
+        let mut builder = DmiBuilder::new(8, 8)
+            .state("open")
+            .dirs(4)
+            .frames(2)
+            .delay(&[1.0, 2.5]);
+        for _ in 0..4 {
+            builder = builder.push_frame(red.clone());
+        }
+        for _ in 0..4 {
+            builder = builder.push_frame(blue.clone());
+        }
+        let dmi = builder
+            .state("closed")
+            .push_frame(red.clone())
+            .build()
+            .unwrap();
+        let path = PathBuf::from("tests/data/builder/door.out.dmi");
+        dmi.save(&path).unwrap();
+
+        let dmi = DmiFile::open(&path).unwrap();
+        let open = dmi.state("open").unwrap();
+        assert_eq!(
+            Some(vec![String::from("1"), String::from("2.5")]),
+            open.metadata.delay
+        );
+        assert_eq!(&blue, open.frame(1, 3));
+        assert_eq!(&red, dmi.state("closed").unwrap().frame(0, 0));
+    }
+
+    #[test]
+    fn test_fps() {
+        let frame = RgbaImage::new(8, 8);
+        let dmi = DmiBuilder::new(8, 8)
+            .state("spin")
+            .frames(2)
+            .fps(4.0)
+            .push_frame(frame.clone())
+            .push_frame(frame.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            Some(vec![String::from("2.5"), String::from("2.5")]),
+            dmi.metadata.states[0].delay
+        );
+        match DmiBuilder::new(8, 8).state("x").fps(0.0).build() {
+            Err(IconToolError::InvalidDelay(_)) => {}
+            _ => panic!("test_fps: Expected InvalidDelay error"),
+        }
+    }
+
+    #[test]
+    fn test_build_problems() {
+        let frame = RgbaImage::new(8, 8);
+        match DmiBuilder::new(8, 8).dirs(4).state("x").build() {
+            Err(IconToolError::InvalidArgument(x)) => assert!(x.starts_with("dirs()")),
+            _ => panic!("test_build_problems: Expected InvalidArgument error"),
+        }
+        match DmiBuilder::new(8, 8).state("x").dirs(3).build() {
+            Err(IconToolError::InvalidDirs(_, 3)) => {}
+            _ => panic!("test_build_problems: Expected InvalidDirs error"),
+        }
+        match DmiBuilder::new(8, 8)
+            .state("x")
+            .frames(2)
+            .push_frame(frame)
+            .build()
+        {
+            Err(IconToolError::FrameCountMismatch(_, 2, 1)) => {}
+            _ => panic!("test_build_problems: Expected FrameCountMismatch error"),
+        }
+        match DmiBuilder::new(8, 8)
+            .state("x")
+            .push_frame(RgbaImage::new(4, 4))
+            .build()
+        {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_build_problems: Expected InvalidArgument error"),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// cache.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use indexmap::IndexMap;
+use serde_yml::{Mapping, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::atomic::write_atomic;
+use crate::cmdline::CompileArgs;
+use crate::compile::{Packing, PadWith};
+use crate::constant::{CACHE_DIR_NAME, CACHE_FILE_NAME};
+use crate::dmi::MetadataChunk;
+use crate::error::Result;
+use crate::include::find_includes;
+use crate::lock::to_hex;
+use crate::walk::create_parent_dir;
+
+// what build remembers about the last time it compiled a target
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedTarget {
+    pub source: String,
+    pub hash: String,
+    pub outputs: IndexMap<String, String>,
+}
+
+// the targets build has compiled, by the hash of their options; paths are
+// relative to the directory holding the manifest
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildCache {
+    pub targets: IndexMap<String, CachedTarget>,
+}
+
+pub fn get_cache_path(base: &Path) -> PathBuf {
+    base.join(CACHE_DIR_NAME).join(CACHE_FILE_NAME)
+}
+
+// read the cache; one that is missing or damaged just means a full rebuild
+pub fn load_cache(base: &Path) -> BuildCache {
+    std::fs::read_to_string(get_cache_path(base))
+        .ok()
+        .and_then(|x| serde_yml::from_str::<Value>(&x).ok())
+        .and_then(|x| from_yaml(&x))
+        .unwrap_or_default()
+}
+
+pub fn save_cache(base: &Path, cache: &BuildCache) -> Result<()> {
+    let path = get_cache_path(base);
+    create_parent_dir(&path)?;
+    write_atomic(&path, serde_yml::to_string(&to_yaml(cache))?)
+}
+
+// a target is known by all of its options, including the paths, so that
+// changing any of them (or icontool itself) compiles it again
+pub fn get_target_id(args: &CompileArgs) -> String {
+    let flag = |x: bool| Some(String::from(if x { "true" } else { "false" }));
+    let pack = args.pack.as_ref().map(|x| match x {
+        Packing::Square => String::from("square"),
+        Packing::Row => String::from("row"),
+        Packing::Width(width) => format!("width={width}"),
+    });
+    let metadata_chunk = match args.metadata_chunk {
+        MetadataChunk::Ztxt => "ztxt",
+        MetadataChunk::Text => "text",
+        MetadataChunk::Itxt => "itxt",
+    };
+    let pad_with = match args.pad_with {
+        PadWith::Transparent => "transparent",
+        PadWith::Checker => "checker",
+    };
+    let mut options = vec![
+        ("icontool", Some(String::from(env!("CARGO_PKG_VERSION")))),
+        ("file", Some(args.file.clone())),
+        ("output", args.output.clone()),
+        ("output_dir", args.output_dir.clone()),
+        ("strip_prefix", args.strip_prefix.clone()),
+        ("use_recorded_path", flag(args.use_recorded_path)),
+        ("base", args.base.clone()),
+        ("normalize_metadata", flag(args.normalize_metadata)),
+        ("no_palette", flag(args.no_palette)),
+        ("metadata_chunk", Some(String::from(metadata_chunk))),
+        ("pack", pack),
+        ("deny_warnings", flag(args.deny_warnings)),
+        ("check", flag(args.check)),
+        ("pad_missing", flag(args.pad_missing)),
+        ("pad_with", Some(String::from(pad_with))),
+        (
+            "max_frame_bytes",
+            args.max_frame_bytes.map(|x| x.to_string()),
+        ),
+        ("max_states", args.max_states.map(|x| x.to_string())),
+        ("split", flag(args.split)),
+        ("depfile", args.depfile.clone()),
+    ];
+    for (name, value) in &args.define {
+        options.push(("define", Some(name.clone())));
+        options.push(("value", Some(value.clone())));
+    }
+
+    // every name and value is framed by its length, so no two sets of
+    // options can run together into the same bytes
+    let mut hasher = Sha256::new();
+    for (name, value) in options {
+        update_framed(&mut hasher, name.as_bytes());
+        match value {
+            Some(value) => update_framed(&mut hasher, value.as_bytes()),
+            None => hasher.update(u64::MAX.to_le_bytes()),
+        }
+    }
+    to_hex(&hasher.finalize())
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+// the hash of a file, or None if it can't be read
+pub fn hash_file(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|x| hash_bytes(&x))
+}
+
+// the hash of a .dmi.yml and everything it includes, or None if any of
+// them can't be read
+pub fn hash_source(path: &Path) -> Option<String> {
+    // each file goes in with its path, so moving bytes from one file to
+    // another, or renaming an include, changes the hash
+    let mut hasher = Sha256::new();
+    let mut paths = vec![path.to_path_buf()];
+    paths.extend(find_includes(path).ok()?);
+    for path in paths {
+        update_framed(&mut hasher, path.to_string_lossy().as_bytes());
+        update_framed(&mut hasher, &std::fs::read(path).ok()?);
+    }
+    Some(to_hex(&hasher.finalize()))
+}
+
+fn update_framed(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+// true if the target was compiled from the same source, and every file it
+// wrote is still there, just as it was written
+pub fn is_fresh(cached: &CachedTarget, hash: &str, base: &Path) -> bool {
+    cached.hash == hash
+        && !cached.outputs.is_empty()
+        && cached
+            .outputs
+            .iter()
+            .all(|(path, hash)| hash_file(&base.join(path)).as_deref() == Some(hash))
+}
+
+fn to_yaml(cache: &BuildCache) -> Value {
+    let mut targets = Mapping::new();
+    for (id, target) in &cache.targets {
+        let mut outputs = Mapping::new();
+        for (path, hash) in &target.outputs {
+            outputs.insert(Value::from(path.as_str()), Value::from(hash.as_str()));
+        }
+        let mut entry = Mapping::new();
+        entry.insert(Value::from("source"), Value::from(target.source.as_str()));
+        entry.insert(Value::from("hash"), Value::from(target.hash.as_str()));
+        entry.insert(Value::from("outputs"), Value::Mapping(outputs));
+        targets.insert(Value::from(id.as_str()), Value::Mapping(entry));
+    }
+    let mut yaml = Mapping::new();
+    yaml.insert(Value::from("targets"), Value::Mapping(targets));
+    Value::Mapping(yaml)
+}
+
+fn from_yaml(yaml: &Value) -> Option<BuildCache> {
+    let mut targets = IndexMap::new();
+    for (id, entry) in yaml.get("targets")?.as_mapping()? {
+        let mut outputs = IndexMap::new();
+        for (path, hash) in entry.get("outputs")?.as_mapping()? {
+            outputs.insert(path.as_str()?.to_string(), hash.as_str()?.to_string());
+        }
+        let target = CachedTarget {
+            source: entry.get("source")?.as_str()?.to_string(),
+            hash: entry.get("hash")?.as_str()?.to_string(),
+            outputs,
+        };
+        targets.insert(id.as_str()?.to_string(), target);
+    }
+    Some(BuildCache { targets })
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_save_load_cache() {
+        let base = Path::new("tests/data/cache/project.out.d");
+        let _ = std::fs::remove_dir_all(base);
+        assert_eq!(BuildCache::default(), load_cache(base));
+
+        let mut cache = BuildCache::default();
+        let mut outputs = IndexMap::new();
+        outputs.insert(String::from("red.dmi"), String::from("0badf00d"));
+        let target = CachedTarget {
+            source: String::from("team.dmi.yml"),
+            hash: String::from("12345678"),
+            outputs,
+        };
+        cache.targets.insert(String::from("cafebabe"), target);
+        save_cache(base, &cache).unwrap();
+        assert!(base.join(".icontool-cache/build.yml").exists());
+        assert_eq!(cache, load_cache(base));
+
+        // a damaged cache is as good as none
+        std::fs::write(get_cache_path(base), "targets: [oops]\n").unwrap();
+        assert_eq!(BuildCache::default(), load_cache(base));
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let base = Path::new("tests/data/cache/fresh.out.d");
+        std::fs::create_dir_all(base).unwrap();
+        std::fs::write(base.join("icon.dmi"), "pixels").unwrap();
+        let mut outputs = IndexMap::new();
+        outputs.insert(String::from("icon.dmi"), hash_bytes(b"pixels"));
+        let cached = CachedTarget {
+            source: String::from("icon.dmi.yml"),
+            hash: String::from("12345678"),
+            outputs,
+        };
+        assert!(is_fresh(&cached, "12345678", base));
+        assert!(!is_fresh(&cached, "87654321", base));
+        std::fs::write(base.join("icon.dmi"), "edited").unwrap();
+        assert!(!is_fresh(&cached, "12345678", base));
+        std::fs::remove_file(base.join("icon.dmi")).unwrap();
+        assert!(!is_fresh(&cached, "12345678", base));
+    }
+
+    #[test]
+    fn test_get_target_id() {
+        let args = CompileArgs {
+            file: String::from("team.dmi.yml"),
+            ..Default::default()
+        };
+        let red = CompileArgs {
+            define: vec![(String::from("TEAM_COLOR"), String::from("#ff0000"))],
+            file: String::from("team.dmi.yml"),
+            ..Default::default()
+        };
+        assert_eq!(get_target_id(&args), get_target_id(&args));
+        assert_ne!(get_target_id(&args), get_target_id(&red));
+        assert_eq!(64, get_target_id(&args).len());
+
+        // options that would run together without framing stay apart
+        let split_one = CompileArgs {
+            define: vec![(String::from("A"), String::from("B=C"))],
+            ..Default::default()
+        };
+        let split_two = CompileArgs {
+            define: vec![(String::from("A=B"), String::from("C"))],
+            ..Default::default()
+        };
+        assert_ne!(get_target_id(&split_one), get_target_id(&split_two));
+        let empty = CompileArgs {
+            output: Some(String::new()),
+            ..Default::default()
+        };
+        assert_ne!(
+            get_target_id(&CompileArgs::default()),
+            get_target_id(&empty)
+        );
+    }
+
+    #[test]
+    fn test_hash_source() {
+        let base = Path::new("tests/data/cache/source.out.d");
+        let _ = std::fs::remove_dir_all(base);
+        std::fs::create_dir_all(base).unwrap();
+        std::fs::write(base.join("one.dmi.yml"), "ab").unwrap();
+        std::fs::write(base.join("two.dmi.yml"), "ab").unwrap();
+        let one = hash_source(&base.join("one.dmi.yml")).unwrap();
+        let two = hash_source(&base.join("two.dmi.yml")).unwrap();
+        assert_eq!(64, one.len());
+        assert_ne!(one, two);
+        assert_eq!(None, hash_source(&base.join("three.dmi.yml")));
+    }
+}
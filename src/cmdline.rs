@@ -15,7 +15,12 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //---------------------------------------------------------------------------
 
-use clap::{crate_version, Args, Parser, Subcommand};
+use clap::{crate_version, Args, Parser, Subcommand, ValueEnum};
+
+use crate::compile::{Packing, PadWith};
+use crate::constant::CONFIG_FILE_NAME;
+use crate::dmi::MetadataChunk;
+use crate::sprite_test::parse_rgba;
 
 #[derive(Parser)]
 #[command(name = "icontool")]
@@ -23,179 +28,2837 @@ use clap::{crate_version, Args, Parser, Subcommand};
 #[command(about = "Tool for working with BYOND DreamMaker Icon (.dmi) files", long_about = None)]
 #[command(propagate_version = true)]
 pub struct Cli {
+    /// run a command on each .dmi or .png file written, like "oxipng -o4 {}"
+    #[arg(long, global = true, value_name = "COMMAND")]
+    pub post_process: Option<String>,
+
+    /// also write every diagnostic, with the time it was printed, to this file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log_file: Option<String>,
+
+    /// report how long each phase of compiling or decompiling each file took
+    #[arg(long, global = true)]
+    pub timings: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// shift the hue, saturation, and brightness of the frames of a .dmi file
+    Adjust(AdjustArgs),
+    /// remove stray semi-transparent pixels from the frames of a .dmi file
+    Alpha(AlphaArgs),
+    /// layer the icon_states of an overlay on top of a .dmi.yml file
+    Apply(ApplyArgs),
+    /// summarize the .dmi files in a directory
+    Audit(AuditArgs),
+    /// create a .dmi file with transparent frames for new icon_states
+    Blank(BlankArgs),
+    /// compile every target listed in an icontool.yml project manifest
+    Build(BuildArgs),
+    /// remove the files written by build, and its cache
+    Clean(CleanArgs),
+    /// report how many colors an icon_state uses and whether they fit a 256-color palette
+    Colors(ColorsArgs),
     /// convert a .dmi.yml file to a .dmi file
     Compile(CompileArgs),
+    /// change the icon size of a .dmi file, scaling or padding each icon_state
+    ConvertSize(ConvertSizeArgs),
     /// convert a .dmi file to a .dmi.yml file
     Decompile(DecompileArgs),
+    /// list the icon_states, and with --frames the frames, that changed between two .dmi files
+    Diff(DiffArgs),
+    /// diagnose common problems in .dmi files and suggest fixes
+    Doctor(DoctorArgs),
+    /// check whether two .dmi files look the same in DreamMaker
+    Equal(EqualArgs),
+    /// describe an error code, like ICT0009, and how to fix it; without a code, list them all
+    Explain(ExplainArgs),
+    /// cut the metadata of a .dmi down to the frames its image really has
+    FixMetadata(FixMetadataArgs),
     /// flatten metadata into .yml format
     Flat(FlatArgs),
+    /// mirror the frames of a .dmi file horizontally or vertically
+    Flip(FlipArgs),
+    /// insert, delete, or reorder the frames of an icon_state in a .dmi file
+    Frames(FramesArgs),
+    /// color greyscale icon_states using a GAGS config
+    Gags(GagsArgs),
+    /// generate the other directions of a single-direction icon_state
+    GenDirs(GenDirsArgs),
+    /// export the animation of an icon_state as a .gif file
+    Gif(GifArgs),
+    /// check .dmi files for common problems
+    Lint(LintArgs),
+    /// write the content hashes of .dmi files to a lockfile
+    Lock(LockArgs),
     /// output the metadata contained in a .dmi file
     Metadata(MetadataArgs),
+    /// list the icon_states that DM source code names but no .dmi file has
+    Missing(MissingArgs),
+    /// create a .dmi.yml file with blank frames for new icon_states
+    New(NewArgs),
+    /// merge repeated frames of animations, adding up their delays
+    OptimizeAnim(OptimizeAnimArgs),
+    /// draw an outline around the art in the frames of a .dmi file
+    Outline(OutlineArgs),
+    /// draw the frames of one icon_state on top of another
+    Overlay(OverlayArgs),
+    /// combine the icon_states of several .dmi files into one
+    Pack(PackArgs),
+    /// list the colors used by a .dmi file
+    Palette(PaletteArgs),
+    /// replace or add icon_states in a .dmi file from a partial .dmi.yml file
+    Patch(PatchArgs),
+    /// generate a placeholder .dmi file with a labeled sprite
+    Placeholder(PlaceholderArgs),
+    /// pad or crop every frame of a .dmi file to a new icon size
+    Repad(RepadArgs),
+    /// generate a browsable HTML catalog of .dmi files
+    Report(ReportArgs),
+    /// play the animation of an icon_state backwards
+    Reverse(ReverseArgs),
+    /// rotate the frames of a .dmi file clockwise
+    Rotate(RotateArgs),
+    /// check that .dmi files survive a decompile and compile unchanged
+    Roundtrip(RoundtripArgs),
+    /// preview .dmi files in a web browser, reloading when they change
+    Serve(ServeArgs),
+    /// change the animation delays of an icon_state in a .dmi file
+    SetDelay(SetDelayArgs),
+    /// change the loop and rewind flags of an icon_state in a .dmi file
+    SetFlag(SetFlagArgs),
+    /// add, move, or remove the hotspots of an icon_state in a .dmi file
+    SetHotspot(SetHotspotArgs),
+    /// scale up every frame of a .dmi file by a whole number
+    Scale(ScaleArgs),
+    /// move the pixels of each frame within its tile
+    Shift(ShiftArgs),
+    /// list the icon_states contained in .dmi files
+    States(StatesArgs),
+    /// export the first frame of each icon_state as a .png file
+    Stills(StillsArgs),
+    /// extract the metadata from a .yml file made by flat
+    Unflat(UnflatArgs),
+    /// list the icon_states that DM source code never names
+    Unused(UnusedArgs),
+    /// rewrite the metadata of a .dmi file in the current format
+    Upgrade(UpgradeArgs),
+    /// check .dmi files for damaged chunks, metadata, and image data
+    Verify(VerifyArgs),
+    /// check that the .dmi files in a lockfile haven't changed
+    VerifyLock(VerifyLockArgs),
+    /// build the targets of an icontool.yml project manifest as their sources change
+    Watch(WatchArgs),
+}
+
+impl Commands {
+    // true if the command may write .dmi or .png files, which the
+    // post_process setting of icontool.yml applies to
+    pub fn writes_images(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Audit(_)
+                | Commands::Clean(_)
+                | Commands::Colors(_)
+                | Commands::Diff(_)
+                | Commands::Doctor(_)
+                | Commands::Equal(_)
+                | Commands::Explain(_)
+                | Commands::Flat(_)
+                | Commands::Gif(_)
+                | Commands::Lint(_)
+                | Commands::Lock(_)
+                | Commands::Metadata(_)
+                | Commands::Missing(_)
+                | Commands::Palette(_)
+                | Commands::Report(_)
+                | Commands::Roundtrip(_)
+                | Commands::Serve(_)
+                | Commands::States(_)
+                | Commands::Unused(_)
+                | Commands::Verify(_)
+                | Commands::VerifyLock(_)
+        )
+    }
+}
+
+#[derive(Args)]
+pub struct AdjustArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to adjust; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// degrees to rotate the hue
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub hue: f64,
+
+    /// percentage points to add to the saturation
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub saturation: f64,
+
+    /// percentage points to add to the brightness
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub brightness: f64,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct AlphaArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to clean up; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// pixels with this alpha or less become fully transparent
+    #[arg(long, default_value_t = 16)]
+    pub threshold: u8,
+
+    #[arg(long, value_enum, default_value_t = AlphaMode::Zero)]
+    pub mode: AlphaMode,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum AlphaMode {
+    /// make nearly transparent pixels fully transparent
+    Zero,
+    /// also make nearly opaque pixels fully opaque
+    Round,
+}
+
+#[derive(Args)]
+pub struct ApplyArgs {
+    #[arg(short, long)]
+    pub output: String,
+
+    /// the .dmi.yml file to start from
+    pub base: String,
+
+    /// a .yml file with the icon_states to change or add
+    pub overlay: String,
+}
+
+#[derive(Args)]
+pub struct AuditArgs {
+    /// also audit the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+#[derive(Args)]
+pub struct BlankArgs {
+    /// the .dmi file to create
+    #[arg(short, long)]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_size, default_value = "32x32")]
+    pub size: (u32, u32),
+
+    /// the icon_states, separated by commas, like idle:4dirs or walk:4dirs:2frames;
+    /// by default, the .dmi file has no icon_states
+    #[arg(long, value_delimiter = ',', value_parser = parse_blank_state)]
+    pub states: Vec<BlankState>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlankState {
+    pub name: String,
+    pub dirs: u32,
+    pub frames: u32,
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
+    /// the project manifest listing the targets to compile
+    #[arg(long, default_value = CONFIG_FILE_NAME)]
+    pub manifest: String,
+
+    /// compile every target, even those unchanged since the last build
+    #[arg(long)]
+    pub force_rebuild: bool,
+}
+
+#[derive(Args)]
+pub struct CleanArgs {
+    /// the project manifest that was built
+    #[arg(long, default_value = CONFIG_FILE_NAME)]
+    pub manifest: String,
+}
+
+#[derive(Args)]
+pub struct ColorsArgs {
+    /// icon_state to examine; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// how many of the most common colors to list
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    pub file: String,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct CompileArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// write the output under this directory, keeping the relative path of the input
+    #[arg(long, conflicts_with = "output")]
+    pub output_dir: Option<String>,
+
+    /// remove this prefix from the input path before placing it under the output directory
+    #[arg(long, requires = "output_dir")]
+    pub strip_prefix: Option<String>,
+
+    /// write the .dmi to the __dmi_path recorded in the .dmi.yml by decompile
+    #[arg(long, conflicts_with_all = ["output", "output_dir"])]
+    pub use_recorded_path: bool,
+
+    /// the directory that the recorded path is relative to;
+    /// by default, the root of the repository holding the .dmi.yml
+    #[arg(long, requires = "use_recorded_path")]
+    pub base: Option<String>,
+
+    /// re-serialize the metadata instead of copying it verbatim
+    #[arg(long)]
+    pub normalize_metadata: bool,
+
+    /// always write 32-bit RGBA pixels, even if a palette would be smaller
+    #[arg(long)]
+    pub no_palette: bool,
+
+    /// the type of PNG chunk that holds the dmi metadata
+    #[arg(long, value_enum, default_value_t = MetadataChunk::Ztxt)]
+    pub metadata_chunk: MetadataChunk,
+
+    /// lay out the frames as square, row, or width=N frames per row;
+    /// by default, keep the image dimensions recorded in the .dmi.yml
+    #[arg(long, value_parser = parse_packing)]
+    pub pack: Option<Packing>,
+
+    /// fail instead of writing the .dmi if there are any warnings
+    #[arg(long)]
+    pub deny_warnings: bool,
+
+    /// check that the .dmi.yml compiles, without writing the .dmi
+    #[arg(long)]
+    pub check: bool,
+
+    /// pad icon_states with too few frames, with a warning, instead of failing
+    #[arg(long)]
+    pub pad_missing: bool,
+
+    /// what --pad-missing pads icon_states with
+    #[arg(long, value_enum, default_value_t = PadWith::Transparent, requires = "pad_missing")]
+    pub pad_with: PadWith,
+
+    /// refuse frames that decompress to more than this many bytes (16 MiB by default)
+    #[arg(long)]
+    pub max_frame_bytes: Option<usize>,
+
+    /// refuse metadata with more than this many icon_states (65536 by default)
+    #[arg(long)]
+    pub max_states: Option<usize>,
+
+    /// if the icon_states don't fit in one .dmi, write foo.1.dmi, foo.2.dmi, ...
+    /// and a foo.split.yml manifest listing the icon_states in each
+    #[arg(long)]
+    pub split: bool,
+
+    /// set a variable, as NAME=VALUE, for ${NAME} in the __transforms section
+    #[arg(long, value_parser = parse_define)]
+    pub define: Vec<(String, String)>,
+
+    /// write a Make depfile listing the files the .dmi was compiled from
+    #[arg(long, conflicts_with = "check")]
+    pub depfile: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct ConvertSizeArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// new size of the icons, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size)]
+    pub to: (u32, u32),
+
+    /// how to resize icon_states: nearest, bilinear, pad, or pad:ANCHOR like pad:bottom
+    #[arg(long, default_value = "nearest", value_parser = parse_size_strategy)]
+    pub default: SizeStrategy,
+
+    /// resize one icon_state differently, like humans=pad:center; may be repeated
+    #[arg(long, value_parser = parse_state_size_strategy)]
+    pub state: Vec<StateSizeStrategy>,
+
+    pub file: String,
+}
+
+#[derive(Args, Default)]
+pub struct DecompileArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// write the output under this directory, or into this .zip archive, keeping the relative path of the input
+    #[arg(long, conflicts_with = "output")]
+    pub output_dir: Option<String>,
+
+    /// remove this prefix from the input path before placing it under the output directory
+    #[arg(long, requires = "output_dir")]
+    pub strip_prefix: Option<String>,
+
+    /// fail instead of writing the .dmi.yml if there are any warnings
+    #[arg(long)]
+    pub deny_warnings: bool,
+
+    /// write repeated frames as references to their first appearance
+    #[arg(long)]
+    pub frame_refs: bool,
+
+    /// print the number of icon_states and frames, and the size of the pixel data
+    #[arg(long)]
+    pub stats: bool,
+
+    /// only decompile this icon_state; can be repeated
+    #[arg(long)]
+    pub state: Vec<String>,
+
+    /// if the image is missing frames, use transparent frames instead of failing
+    #[arg(long)]
+    pub lenient: bool,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// list each changed frame by icon_state, dir, and frame number
+    #[arg(long)]
+    pub frames: bool,
+
+    /// ignore small changes: N allows each color channel to be off by up to N,
+    /// while N% allows up to N percent of the pixels of a frame to differ
+    #[arg(long, default_value = "0", value_parser = parse_tolerance)]
+    pub tolerance: Tolerance,
+
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    #[arg(required = true)]
+    pub files: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct EqualArgs {
+    /// ignore small changes: N allows each color channel to be off by up to N,
+    /// while N% allows up to N percent of the pixels of a frame to differ
+    #[arg(long, default_value = "0", value_parser = parse_tolerance)]
+    pub tolerance: Tolerance,
+
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Args)]
+pub struct ExplainArgs {
+    pub code: Option<String>,
 }
 
-#[derive(Args)]
-pub struct CompileArgs {
-    #[arg(short, long)]
-    pub output: Option<String>,
+#[derive(Args)]
+pub struct FixMetadataArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct FlatArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("axis").required(true).multiple(true).args(["horizontal", "vertical"])))]
+pub struct FlipArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to flip; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// mirror left to right
+    #[arg(long)]
+    pub horizontal: bool,
+
+    /// mirror top to bottom
+    #[arg(long)]
+    pub vertical: bool,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInsert {
+    pub source: Option<u32>, // None for a blank frame
+    pub at: u32,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("action").required(true).args(["delete", "_move", "insert"])))]
+pub struct FramesArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    /// 1-based frame to delete
+    #[arg(long)]
+    pub delete: Option<u32>,
+
+    /// move a frame to a new position, as FROM:TO
+    #[arg(id = "_move", long = "move", value_parser = parse_frame_move)]
+    pub _move: Option<(u32, u32)>,
+
+    /// insert a frame, as blank@TO or FROM@TO to copy an existing frame
+    #[arg(long, value_parser = parse_frame_insert)]
+    pub insert: Option<FrameInsert>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct GagsArgs {
+    #[arg(short, long)]
+    pub output: String,
+
+    /// greyscale .dmi file; by default, the config path ending in .dmi
+    #[arg(long)]
+    pub icon: Option<String>,
+
+    /// colors for the color_ids of the config, as #RRGGBB,#RRGGBB,...
+    #[arg(long, value_parser = parse_rgba, value_delimiter = ',', required = true)]
+    pub colors: Vec<[u8; 4]>,
+
+    pub config: String,
+}
+
+#[derive(Args)]
+pub struct GenDirsArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    #[arg(long, value_enum, default_value_t = GenDirsMode::Rotate)]
+    pub mode: GenDirsMode,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum GenDirsMode {
+    /// rotate the south frame to face north, east, and west
+    Rotate,
+    /// reuse the south frame, mirrored for west
+    Mirror,
+}
+
+#[derive(Args)]
+pub struct GifArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to animate
+    #[arg(long)]
+    pub state: String,
+
+    /// animate every direction side by side, instead of only south
+    #[arg(long)]
+    pub all_dirs: bool,
+
+    /// write one .gif file per direction, instead of side by side
+    #[arg(long, requires = "all_dirs")]
+    pub separate: bool,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    #[arg(required = true)]
+    pub files: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct LockArgs {
+    #[arg(short, long)]
+    pub output: String,
+
+    /// also lock the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args, Default)]
+#[command(group(clap::ArgGroup::new("format").args(["parsed", "json"])))]
+pub struct MetadataArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// print a table of the icon size and icon_states instead of the raw text
+    #[arg(long)]
+    pub parsed: bool,
+
+    /// print the parsed metadata as JSON instead of the raw text
+    #[arg(long)]
+    pub json: bool,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct MissingArgs {
+    /// .dm files, or directories containing them
+    #[arg(long, required = true)]
+    pub code: Vec<String>,
+
+    /// .dmi files, or directories containing them
+    #[arg(long, required = true)]
+    pub icons: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct NewArgs {
+    /// the .dmi.yml file to create
+    pub file: String,
+
+    #[arg(long, value_parser = parse_size, default_value = "32x32")]
+    pub size: (u32, u32),
+
+    /// the names of the icon_states, separated by commas
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub states: Vec<String>,
+
+    #[arg(long, default_value_t = 1)]
+    pub dirs: u32,
+
+    #[arg(long, default_value_t = 1)]
+    pub frames: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum OnConflict {
+    /// stop without writing anything
+    Error,
+    /// keep the icon_state from the earlier file
+    Ours,
+    /// replace it with the icon_state from the later file
+    Theirs,
+    /// keep both, numbering the later one like walk_2
+    Rename,
+    /// show both icon_states in the terminal and ask
+    Interactive,
+}
+
+#[derive(Args)]
+pub struct OptimizeAnimArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to optimize; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct OutlineArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to outline; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// color of the outline, as #RRGGBB or #RRGGBBAA
+    #[arg(long, value_parser = parse_rgba, default_value = "#000000")]
+    pub color: [u8; 4],
+
+    /// width of the outline in pixels
+    #[arg(long, default_value_t = 1)]
+    pub thickness: u32,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct OverlayArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to draw on top of
+    #[arg(long)]
+    pub state: String,
+
+    /// icon_state to draw, as FILE:STATE
+    #[arg(long, value_parser = parse_overlay_source)]
+    pub with: OverlaySource,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct PackArgs {
+    #[arg(short, long)]
+    pub output: String,
+
+    /// also pack the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// put PREFIX before every icon_state name, or only those from one file
+    /// with FILE=PREFIX like blue.dmi=blue_; may be repeated
+    #[arg(long, value_parser = parse_pack_prefix)]
+    pub prefix: Vec<PackPrefix>,
+
+    /// what to do when two files have an icon_state with the same name
+    #[arg(long, value_enum, default_value_t = OnConflict::Error)]
+    pub on_conflict: OnConflict,
+
+    /// .dmi files, or directories containing them, in the order to pack them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackPrefix {
+    pub file: Option<String>, // None applies to every file
+    pub prefix: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverlaySource {
+    pub file: String,
+    pub state: String,
+}
+
+#[derive(Args)]
+pub struct PaletteArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to examine; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// by default, gpl when the output ends in .gpl, otherwise text
+    #[arg(long, value_enum)]
+    pub format: Option<PaletteFormat>,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum PaletteFormat {
+    /// each color with the number of pixels that use it
+    Text,
+    /// one hex color per line
+    Hex,
+    /// GIMP palette
+    Gpl,
+}
+
+#[derive(Args)]
+pub struct PatchArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// the .dmi file to patch
+    pub file: String,
+
+    /// a .dmi.yml file with the icon_states to replace or add
+    pub changes: String,
+}
+
+#[derive(Args)]
+pub struct PlaceholderArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long, value_parser = parse_size, default_value = "32x32")]
+    pub size: (u32, u32),
+
+    #[arg(long)]
+    pub label: String,
+
+    #[arg(long, default_value = "")]
+    pub state: String,
+}
+
+#[derive(Args)]
+pub struct RepadArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// new size of the icons, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size)]
+    pub size: (u32, u32),
+
+    /// where the existing art sits on the new canvas
+    #[arg(long, value_enum, default_value_t = Anchor::Center)]
+    pub anchor: Anchor,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// the directory to write the HTML pages to
+    #[arg(short, long)]
+    pub output: String,
+
+    /// also include the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ReverseArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    /// write the reversed animation to this icon_state instead
+    #[arg(id = "_as", long = "as")]
+    pub _as: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct RotateArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to rotate; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// clockwise rotation: 90, 180, or 270
+    #[arg(long, value_parser = parse_degrees)]
+    pub degrees: u32,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct RoundtripArgs {
+    /// ignore small changes: N allows each color channel to be off by up to N,
+    /// while N% allows up to N percent of the pixels of a frame to differ
+    #[arg(long, default_value = "0", value_parser = parse_tolerance)]
+    pub tolerance: Tolerance,
+
+    #[arg(required = true)]
+    pub files: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ScaleArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// how many times larger to make the icons
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub factor: u32,
+
+    #[arg(long, value_enum, default_value_t = ScaleFilter::Nearest)]
+    pub filter: ScaleFilter,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum ScaleFilter {
+    /// keep hard pixel edges
+    Nearest,
+    /// blend neighboring pixels
+    Bilinear,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SizeStrategy {
+    Scale(ScaleFilter),
+    Pad(Anchor),
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// the port to listen on, on localhost
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// also serve the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("timing").required(true).args(["delays", "fps"])))]
+pub struct SetDelayArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    /// delay of each frame, in ticks (1/10th of a second)
+    #[arg(long, value_delimiter = ',')]
+    pub delays: Vec<f64>,
+
+    /// use the same delay for every frame, from frames per second
+    #[arg(long)]
+    pub fps: Option<f64>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("flags").required(true).multiple(true).args(["_loop", "rewind"])))]
+pub struct SetFlagArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    /// number of times to play the animation, or "infinite"
+    #[arg(id = "_loop", long = "loop")]
+    pub _loop: Option<String>,
+
+    /// play the animation backwards after playing it forwards
+    #[arg(long)]
+    pub rewind: Option<Toggle>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("action").required(true).args(["xy", "remove"])))]
+pub struct SetHotspotArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    #[arg(long)]
+    pub state: String,
+
+    /// position of the hotspot, as X,Y
+    #[arg(long, value_parser = parse_point)]
+    pub xy: Option<(u32, u32)>,
+
+    /// 1-based frame to change; by default, every frame
+    #[arg(long)]
+    pub frame: Option<u32>,
+
+    /// remove the hotspot instead of setting it
+    #[arg(long)]
+    pub remove: bool,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("edge").args(["wrap", "clip"])))]
+pub struct ShiftArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// icon_state to shift; by default, every icon_state
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// distance to move the pixels, as X,Y; negative values move up or left
+    #[arg(long, value_parser = parse_offset, allow_hyphen_values = true)]
+    pub by: (i64, i64),
+
+    /// pixels pushed off one edge come back on the other
+    #[arg(long)]
+    pub wrap: bool,
+
+    /// pixels pushed off an edge are lost (the default)
+    #[arg(long)]
+    pub clip: bool,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateSizeStrategy {
+    pub name: String,
+    pub strategy: SizeStrategy,
+}
+
+#[derive(Args)]
+pub struct StatesArgs {
+    /// how to print the icon_states
+    #[arg(long, value_enum, default_value_t = StatesFormat::Table)]
+    pub format: StatesFormat,
+
+    /// also list the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum StatesFormat {
+    /// an aligned table for reading
+    Table,
+    /// comma-separated values, one row per file and icon_state
+    Csv,
+}
+
+#[derive(Args)]
+pub struct StillsArgs {
+    /// directory to write the .png files into
+    #[arg(long, default_value = ".")]
+    pub output_dir: String,
+
+    pub file: String,
+}
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum Toggle {
+    On,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tolerance {
+    // how far each color channel of a pixel may be off
+    Channel(u8),
+    // how many percent of the pixels of a frame may differ
+    Percent(f64),
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance::Channel(0)
+    }
+}
+
+#[derive(Args)]
+pub struct UnflatArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct UnusedArgs {
+    /// .dm files, or directories containing them
+    #[arg(long, required = true)]
+    pub code: Vec<String>,
+
+    /// .dmi files, or directories containing them
+    #[arg(long, required = true)]
+    pub icons: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct UpgradeArgs {
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// also verify the .dmi files in subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// .dmi files, or directories containing them
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct VerifyLockArgs {
+    /// the lockfile written by lock
+    pub file: String,
+}
+
+#[derive(Args)]
+pub struct WatchArgs {
+    /// the project manifest listing the targets to compile
+    #[arg(long, default_value = CONFIG_FILE_NAME)]
+    pub manifest: String,
+
+    /// how often to look for changes, in milliseconds
+    #[arg(long, default_value_t = 500)]
+    pub interval: u64,
+}
+
+pub fn parse_blank_state(value: &str) -> Result<BlankState, String> {
+    // blank icon_states are given as NAME[:Ndirs][:Nframes] like walk:4dirs:2frames
+    let mut parts = value.split(':');
+    let mut state = BlankState {
+        name: parts.next().unwrap_or_default().to_string(),
+        dirs: 1,
+        frames: 1,
+    };
+    for part in parts {
+        let (count, field) = if let Some(count) = part.strip_suffix("dirs") {
+            (count, &mut state.dirs)
+        } else if let Some(count) = part.strip_suffix("frames") {
+            (count, &mut state.frames)
+        } else {
+            return Err(format!("expected Ndirs or Nframes, found '{part}'"));
+        };
+        *field = match count.parse::<u32>() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(format!("invalid count '{count}' in '{value}'")),
+        };
+    }
+    Ok(state)
+}
+
+pub fn parse_degrees(value: &str) -> Result<u32, String> {
+    // only quarter turns keep the pixels on the grid
+    match value {
+        "90" | "180" | "270" => Ok(value.parse::<u32>().unwrap_or_default()),
+        _ => Err(format!("expected 90, 180, or 270, found '{value}'")),
+    }
+}
+
+pub fn parse_frame_insert(value: &str) -> Result<FrameInsert, String> {
+    // inserts are given as blank@2 or 3@2
+    let (source, at) = value
+        .split_once('@')
+        .ok_or_else(|| format!("expected blank@TO or FROM@TO, found '{value}'"))?;
+    let source = match source {
+        "blank" => None,
+        _ => Some(parse_frame_number(source)?),
+    };
+    let at = parse_frame_number(at)?;
+    Ok(FrameInsert { source, at })
+}
+
+pub fn parse_frame_move(value: &str) -> Result<(u32, u32), String> {
+    // moves are given as FROM:TO like 2:5
+    let (from, to) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected FROM:TO, found '{value}'"))?;
+    Ok((parse_frame_number(from)?, parse_frame_number(to)?))
+}
+
+fn parse_frame_number(value: &str) -> Result<u32, String> {
+    // frames are numbered from 1
+    match value.parse::<u32>() {
+        Ok(frame) if frame > 0 => Ok(frame),
+        _ => Err(format!("invalid frame '{value}'")),
+    }
+}
+
+pub fn parse_offset(value: &str) -> Result<(i64, i64), String> {
+    // offsets are given as X,Y like 0,-2
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected X,Y, found '{value}'"))?;
+    let x = x.parse::<i64>().map_err(|_| format!("invalid x '{x}'"))?;
+    let y = y.parse::<i64>().map_err(|_| format!("invalid y '{y}'"))?;
+    Ok((x, y))
+}
+
+pub fn parse_overlay_source(value: &str) -> Result<OverlaySource, String> {
+    // sources are given as FILE:STATE like accessories.dmi:collar; split
+    // after the .dmi so that icon_state names may contain colons
+    let (file, state) = match value.find(".dmi:") {
+        Some(index) => (&value[..index + 4], &value[index + 5..]),
+        None => value
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected FILE:STATE, found '{value}'"))?,
+    };
+    Ok(OverlaySource {
+        file: file.to_string(),
+        state: state.to_string(),
+    })
+}
+
+pub fn parse_define(value: &str) -> Result<(String, String), String> {
+    // defines are given as NAME=VALUE, like TEAM_COLOR=#ff0000
+    let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, found '{value}'"))?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid variable name '{name}'"));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+pub fn parse_pack_prefix(value: &str) -> Result<PackPrefix, String> {
+    // like overlay sources, split after the .dmi so prefixes may contain '='
+    match value.find(".dmi=") {
+        Some(index) => Ok(PackPrefix {
+            file: Some(value[..index + 4].to_string()),
+            prefix: value[index + 5..].to_string(),
+        }),
+        None => Ok(PackPrefix {
+            file: None,
+            prefix: value.to_string(),
+        }),
+    }
+}
+
+pub fn parse_packing(value: &str) -> Result<Packing, String> {
+    // packings are given as square, row, or width=N like width=16
+    match value {
+        "square" => Ok(Packing::Square),
+        "row" => Ok(Packing::Row),
+        _ => match value.strip_prefix("width=").map(|n| n.parse::<u32>()) {
+            Some(Ok(width)) if width > 0 => Ok(Packing::Width(width)),
+            _ => Err(format!("expected square, row, or width=N, found '{value}'")),
+        },
+    }
+}
+
+pub fn parse_point(value: &str) -> Result<(u32, u32), String> {
+    // points are given as X,Y like 16,16
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("expected X,Y, found '{value}'"))?;
+    let x = x.parse::<u32>().map_err(|_| format!("invalid x '{x}'"))?;
+    let y = y.parse::<u32>().map_err(|_| format!("invalid y '{y}'"))?;
+    Ok((x, y))
+}
+
+pub fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    // sizes are given as WIDTHxHEIGHT, like 32x32
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, found '{value}'"))?;
+    let width = width
+        .parse::<u32>()
+        .map_err(|_| format!("invalid width '{width}'"))?;
+    let height = height
+        .parse::<u32>()
+        .map_err(|_| format!("invalid height '{height}'"))?;
+    if width == 0 || height == 0 {
+        return Err(format!("size must not be zero, found '{value}'"));
+    }
+    Ok((width, height))
+}
+
+pub fn parse_size_strategy(value: &str) -> Result<SizeStrategy, String> {
+    // strategies are a scale filter, or pad with an optional anchor like pad:bottom
+    match value.split_once(':') {
+        None if value == "pad" => Ok(SizeStrategy::Pad(Anchor::Center)),
+        None => ScaleFilter::from_str(value, true)
+            .map(SizeStrategy::Scale)
+            .map_err(|_| format!("expected nearest, bilinear, or pad[:ANCHOR], found '{value}'")),
+        Some(("pad", anchor)) => Anchor::from_str(anchor, true)
+            .map(SizeStrategy::Pad)
+            .map_err(|_| format!("invalid anchor '{anchor}' in '{value}'")),
+        Some(_) => Err(format!(
+            "expected nearest, bilinear, or pad[:ANCHOR], found '{value}'"
+        )),
+    }
+}
+
+pub fn parse_state_size_strategy(value: &str) -> Result<StateSizeStrategy, String> {
+    // per-state strategies are given as NAME=STRATEGY, like humans=pad:center
+    let (name, strategy) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected NAME=STRATEGY, found '{value}'"))?;
+    Ok(StateSizeStrategy {
+        name: name.to_string(),
+        strategy: parse_size_strategy(strategy)?,
+    })
+}
+
+pub fn parse_tolerance(value: &str) -> Result<Tolerance, String> {
+    // tolerances are given as a channel delta like 2, or a percentage like 0.5%
+    if let Some(percent) = value.strip_suffix('%') {
+        return match percent.parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(Tolerance::Percent(percent)),
+            _ => Err(format!(
+                "expected a percentage from 0% to 100%, found '{value}'"
+            )),
+        };
+    }
+    value
+        .parse::<u8>()
+        .map(Tolerance::Channel)
+        .map_err(|_| format!("expected a channel delta from 0 to 255 or N%, found '{value}'"))
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_writes_images() {
+        let explain = Cli::parse_from(vec!["icontool", "explain", "ICT0001"]);
+        assert!(!explain.command.writes_images());
+        let metadata = Cli::parse_from(vec!["icontool", "metadata", "neck.dmi"]);
+        assert!(!metadata.command.writes_images());
+        let compile = Cli::parse_from(vec!["icontool", "compile", "neck.dmi.yml"]);
+        assert!(compile.command.writes_images());
+    }
+
+    #[test]
+    fn test_compile_default() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "icons/mob/clothing/neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi.yml", args.file);
+                assert_eq!(None, args.output);
+                assert!(!args.no_palette);
+                assert_eq!(MetadataChunk::Ztxt, args.metadata_chunk);
+            }
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+    }
+
+    #[test]
+    fn test_compile_output() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--output",
+            "icons/mob/clothing/neckbeard.dmi",
+            "icons/mob/clothing/neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi.yml", args.file);
+                assert_eq!(
+                    "icons/mob/clothing/neckbeard.dmi",
+                    args.output.as_ref().unwrap()
+                );
+            }
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+    }
+
+    #[test]
+    fn test_decompile_default() {
+        let cli = Cli::parse_from(vec!["icontool", "decompile", "icons/mob/clothing/neck.dmi"]);
+        match &cli.command {
+            Commands::Decompile(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+    }
+
+    #[test]
+    fn test_decompile_output() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "decompile",
+            "--output",
+            "icons/mob/clothing/neckbeard.dmi.yml",
+            "icons/mob/clothing/neck.dmi",
+        ]);
+        match &cli.command {
+            Commands::Decompile(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+                assert_eq!(
+                    "icons/mob/clothing/neckbeard.dmi.yml",
+                    args.output.as_ref().unwrap()
+                );
+            }
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+    }
+
+    #[test]
+    fn test_flat_default() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "flat",
+            "icons/mob/clothing/neck.dmi.metadata",
+        ]);
+        match &cli.command {
+            Commands::Flat(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi.metadata", args.file);
+            }
+            _ => panic!("Subcommand 'flat' was not parsed to Commands::Flat"),
+        }
+    }
+
+    #[test]
+    fn test_metadata_default() {
+        let cli = Cli::parse_from(vec!["icontool", "metadata", "icons/mob/clothing/neck.dmi"]);
+        match &cli.command {
+            Commands::Metadata(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'metadata' was not parsed to Commands::Metadata"),
+        }
+    }
+
+    #[test]
+    fn test_metadata_output() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "metadata",
+            "--output",
+            "icons/mob/clothing/neck.dmi.metadata",
+            "icons/mob/clothing/neck.dmi",
+        ]);
+        match &cli.command {
+            Commands::Metadata(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+                assert_eq!(
+                    "icons/mob/clothing/neck.dmi.metadata",
+                    args.output.as_ref().unwrap()
+                );
+            }
+            _ => panic!("Subcommand 'metadata' was not parsed to Commands::Metadata"),
+        }
+    }
+
+    #[test]
+    fn test_lint_files() {
+        let cli = Cli::parse_from(vec!["icontool", "lint", "a.dmi", "b.dmi"]);
+        match &cli.command {
+            Commands::Lint(args) => {
+                assert_eq!(vec!["a.dmi", "b.dmi"], args.files);
+            }
+            _ => panic!("Subcommand 'lint' was not parsed to Commands::Lint"),
+        }
+    }
+
+    #[test]
+    fn test_placeholder_default() {
+        let cli = Cli::parse_from(vec!["icontool", "placeholder", "--label", "AMMO"]);
+        match &cli.command {
+            Commands::Placeholder(args) => {
+                assert_eq!("AMMO", args.label);
+                assert_eq!((32, 32), args.size);
+                assert_eq!("", args.state);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'placeholder' was not parsed to Commands::Placeholder"),
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(Ok((48, 64)), parse_size("48x64"));
+        assert!(parse_size("48").is_err());
+        assert!(parse_size("0x32").is_err());
+        assert!(parse_size("axb").is_err());
+    }
+
+    #[test]
+    fn test_parse_size_strategy() {
+        assert_eq!(
+            Ok(SizeStrategy::Scale(ScaleFilter::Bilinear)),
+            parse_size_strategy("bilinear")
+        );
+        assert_eq!(
+            Ok(SizeStrategy::Pad(Anchor::Center)),
+            parse_size_strategy("pad")
+        );
+        assert_eq!(
+            Ok(SizeStrategy::Pad(Anchor::BottomLeft)),
+            parse_size_strategy("pad:bottom-left")
+        );
+        assert!(parse_size_strategy("pad:middle").is_err());
+        assert!(parse_size_strategy("crop").is_err());
+        assert_eq!(
+            Ok(StateSizeStrategy {
+                name: String::from("a=b"),
+                strategy: SizeStrategy::Scale(ScaleFilter::Nearest)
+            }),
+            parse_state_size_strategy("a=b=nearest")
+        );
+        assert!(parse_state_size_strategy("humans").is_err());
+    }
+
+    #[test]
+    fn test_parse_tolerance() {
+        assert_eq!(Ok(Tolerance::Channel(2)), parse_tolerance("2"));
+        assert_eq!(Ok(Tolerance::Percent(0.5)), parse_tolerance("0.5%"));
+        assert!(parse_tolerance("256").is_err());
+        assert!(parse_tolerance("101%").is_err());
+        assert!(parse_tolerance("x%").is_err());
+    }
+
+    #[test]
+    fn test_upgrade_default() {
+        let cli = Cli::parse_from(vec!["icontool", "upgrade", "icons/mob/clothing/neck.dmi"]);
+        match &cli.command {
+            Commands::Upgrade(args) => {
+                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'upgrade' was not parsed to Commands::Upgrade"),
+        }
+    }
+
+    #[test]
+    fn test_set_delay_delays() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "set-delay",
+            "foo.dmi",
+            "--state",
+            "burning",
+            "--delays",
+            "1,1,2,4",
+        ]);
+        match &cli.command {
+            Commands::SetDelay(args) => {
+                assert_eq!("foo.dmi", args.file);
+                assert_eq!("burning", args.state);
+                assert_eq!(vec![1.0, 1.0, 2.0, 4.0], args.delays);
+                assert_eq!(None, args.fps);
+            }
+            _ => panic!("Subcommand 'set-delay' was not parsed to Commands::SetDelay"),
+        }
+    }
+
+    #[test]
+    fn test_set_delay_requires_timing() {
+        let result = Cli::try_parse_from(vec!["icontool", "set-delay", "foo.dmi", "--state", "x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_set_flag() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "set-flag", "foo.dmi", "--state", "spin", "--loop", "3", "--rewind", "on",
+        ]);
+        match &cli.command {
+            Commands::SetFlag(args) => {
+                assert_eq!("foo.dmi", args.file);
+                assert_eq!("spin", args.state);
+                assert_eq!(Some(String::from("3")), args._loop);
+                assert_eq!(Some(Toggle::On), args.rewind);
+            }
+            _ => panic!("Subcommand 'set-flag' was not parsed to Commands::SetFlag"),
+        }
+    }
+
+    #[test]
+    fn test_set_hotspot() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "set-hotspot",
+            "foo.dmi",
+            "--state",
+            "crosshair",
+            "--xy",
+            "16,16",
+            "--frame",
+            "1",
+        ]);
+        match &cli.command {
+            Commands::SetHotspot(args) => {
+                assert_eq!("crosshair", args.state);
+                assert_eq!(Some((16, 16)), args.xy);
+                assert_eq!(Some(1), args.frame);
+                assert!(!args.remove);
+            }
+            _ => panic!("Subcommand 'set-hotspot' was not parsed to Commands::SetHotspot"),
+        }
+    }
+
+    #[test]
+    fn test_parse_point() {
+        assert_eq!(Ok((16, 8)), parse_point("16,8"));
+        assert!(parse_point("16").is_err());
+        assert!(parse_point("a,8").is_err());
+    }
+
+    #[test]
+    fn test_states_default() {
+        let cli = Cli::parse_from(vec!["icontool", "states", "icons/mob/clothing/neck.dmi"]);
+        match &cli.command {
+            Commands::States(args) => {
+                assert_eq!(StatesFormat::Table, args.format);
+                assert!(!args.recursive);
+                assert_eq!(vec!["icons/mob/clothing/neck.dmi"], args.paths);
+            }
+            _ => panic!("Subcommand 'states' was not parsed to Commands::States"),
+        }
+    }
+
+    #[test]
+    fn test_reverse_as() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "reverse",
+            "foo.dmi",
+            "--state",
+            "door_opening",
+            "--as",
+            "door_closing",
+        ]);
+        match &cli.command {
+            Commands::Reverse(args) => {
+                assert_eq!("foo.dmi", args.file);
+                assert_eq!("door_opening", args.state);
+                assert_eq!(Some(String::from("door_closing")), args._as);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'reverse' was not parsed to Commands::Reverse"),
+        }
+    }
+
+    #[test]
+    fn test_frames_move() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "frames", "foo.dmi", "--state", "anim", "--move", "2:5",
+        ]);
+        match &cli.command {
+            Commands::Frames(args) => {
+                assert_eq!("anim", args.state);
+                assert_eq!(Some((2, 5)), args._move);
+                assert_eq!(None, args.delete);
+                assert_eq!(None, args.insert);
+            }
+            _ => panic!("Subcommand 'frames' was not parsed to Commands::Frames"),
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_insert() {
+        let blank = FrameInsert {
+            source: None,
+            at: 2,
+        };
+        let copy = FrameInsert {
+            source: Some(3),
+            at: 1,
+        };
+        assert_eq!(Ok(blank), parse_frame_insert("blank@2"));
+        assert_eq!(Ok(copy), parse_frame_insert("3@1"));
+        assert!(parse_frame_insert("blank").is_err());
+        assert!(parse_frame_insert("blank@0").is_err());
+        assert!(parse_frame_move("2-5").is_err());
+    }
+
+    #[test]
+    fn test_stills_output_dir() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "stills",
+            "foo.dmi",
+            "--output-dir",
+            "out/",
+        ]);
+        match &cli.command {
+            Commands::Stills(args) => {
+                assert_eq!("foo.dmi", args.file);
+                assert_eq!("out/", args.output_dir);
+            }
+            _ => panic!("Subcommand 'stills' was not parsed to Commands::Stills"),
+        }
+    }
+
+    #[test]
+    fn test_gen_dirs_mode() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "gen-dirs", "foo.dmi", "--state", "arrow", "--mode", "mirror",
+        ]);
+        match &cli.command {
+            Commands::GenDirs(args) => {
+                assert_eq!("arrow", args.state);
+                assert_eq!(GenDirsMode::Mirror, args.mode);
+            }
+            _ => panic!("Subcommand 'gen-dirs' was not parsed to Commands::GenDirs"),
+        }
+    }
+
+    #[test]
+    fn test_flip_horizontal() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "flip",
+            "foo.dmi",
+            "--state",
+            "sword",
+            "--horizontal",
+        ]);
+        match &cli.command {
+            Commands::Flip(args) => {
+                assert_eq!(Some(String::from("sword")), args.state);
+                assert!(args.horizontal);
+                assert!(!args.vertical);
+            }
+            _ => panic!("Subcommand 'flip' was not parsed to Commands::Flip"),
+        }
+    }
+
+    #[test]
+    fn test_rotate_degrees() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "rotate",
+            "foo.dmi",
+            "--state",
+            "gear",
+            "--degrees",
+            "270",
+        ]);
+        match &cli.command {
+            Commands::Rotate(args) => {
+                assert_eq!(Some(String::from("gear")), args.state);
+                assert_eq!(270, args.degrees);
+            }
+            _ => panic!("Subcommand 'rotate' was not parsed to Commands::Rotate"),
+        }
+        assert!(parse_degrees("45").is_err());
+    }
+
+    #[test]
+    fn test_repad_anchor() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "repad",
+            "foo.dmi",
+            "--size",
+            "48x48",
+            "--anchor",
+            "bottom-left",
+        ]);
+        match &cli.command {
+            Commands::Repad(args) => {
+                assert_eq!((48, 48), args.size);
+                assert_eq!(Anchor::BottomLeft, args.anchor);
+            }
+            _ => panic!("Subcommand 'repad' was not parsed to Commands::Repad"),
+        }
+    }
+
+    #[test]
+    fn test_scale_factor() {
+        let cli = Cli::parse_from(vec!["icontool", "scale", "foo.dmi", "--factor", "3"]);
+        match &cli.command {
+            Commands::Scale(args) => {
+                assert_eq!(3, args.factor);
+                assert_eq!(ScaleFilter::Nearest, args.filter);
+            }
+            _ => panic!("Subcommand 'scale' was not parsed to Commands::Scale"),
+        }
+        assert!(
+            Cli::try_parse_from(vec!["icontool", "scale", "foo.dmi", "--factor", "0"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_shift_negative_offset() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "shift",
+            "foo.dmi",
+            "--state",
+            "held_item",
+            "--by",
+            "0,-2",
+            "--wrap",
+        ]);
+        match &cli.command {
+            Commands::Shift(args) => {
+                assert_eq!(Some(String::from("held_item")), args.state);
+                assert_eq!((0, -2), args.by);
+                assert!(args.wrap);
+                assert!(!args.clip);
+            }
+            _ => panic!("Subcommand 'shift' was not parsed to Commands::Shift"),
+        }
+    }
+
+    #[test]
+    fn test_palette_default() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "palette", "foo.dmi", "--state", "jumpsuit",
+        ]);
+        match &cli.command {
+            Commands::Palette(args) => {
+                assert_eq!(Some(String::from("jumpsuit")), args.state);
+                assert_eq!(None, args.format);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'palette' was not parsed to Commands::Palette"),
+        }
+    }
+
+    #[test]
+    fn test_adjust_negative() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "adjust",
+            "foo.dmi",
+            "--state",
+            "glow",
+            "--hue",
+            "30",
+            "--saturation",
+            "-10",
+        ]);
+        match &cli.command {
+            Commands::Adjust(args) => {
+                assert_eq!(Some(String::from("glow")), args.state);
+                assert_eq!(30.0, args.hue);
+                assert_eq!(-10.0, args.saturation);
+                assert_eq!(0.0, args.brightness);
+            }
+            _ => panic!("Subcommand 'adjust' was not parsed to Commands::Adjust"),
+        }
+    }
+
+    #[test]
+    fn test_alpha_default() {
+        let cli = Cli::parse_from(vec!["icontool", "alpha", "foo.dmi", "--mode", "round"]);
+        match &cli.command {
+            Commands::Alpha(args) => {
+                assert_eq!(16, args.threshold);
+                assert_eq!(AlphaMode::Round, args.mode);
+                assert_eq!(None, args.state);
+            }
+            _ => panic!("Subcommand 'alpha' was not parsed to Commands::Alpha"),
+        }
+    }
+
+    #[test]
+    fn test_overlay_with() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "overlay",
+            "base.dmi",
+            "--state",
+            "body",
+            "--with",
+            "accessories.dmi:collar",
+            "-o",
+            "out.dmi",
+        ]);
+        match &cli.command {
+            Commands::Overlay(args) => {
+                assert_eq!("base.dmi", args.file);
+                assert_eq!("body", args.state);
+                assert_eq!("accessories.dmi", args.with.file);
+                assert_eq!("collar", args.with.state);
+                assert_eq!(Some(String::from("out.dmi")), args.output);
+            }
+            _ => panic!("Subcommand 'overlay' was not parsed to Commands::Overlay"),
+        }
+        let source = parse_overlay_source("a.dmi:b:c").unwrap();
+        assert_eq!(
+            ("a.dmi", "b:c"),
+            (source.file.as_str(), source.state.as_str())
+        );
+        assert!(parse_overlay_source("accessories").is_err());
+    }
+
+    #[test]
+    fn test_outline_color() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "outline",
+            "foo.dmi",
+            "--state",
+            "item",
+            "--color",
+            "#FF000080",
+        ]);
+        match &cli.command {
+            Commands::Outline(args) => {
+                assert_eq!(Some(String::from("item")), args.state);
+                assert_eq!([255, 0, 0, 128], args.color);
+                assert_eq!(1, args.thickness);
+            }
+            _ => panic!("Subcommand 'outline' was not parsed to Commands::Outline"),
+        }
+        assert!(parse_rgba("#FF00").is_err());
+        assert!(parse_rgba("#FF0000FF00").is_err());
+    }
+
+    #[test]
+    fn test_gags_colors() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "gags",
+            "config.json",
+            "--colors",
+            "#ff0000,#00ff00",
+            "-o",
+            "out.dmi",
+        ]);
+        match &cli.command {
+            Commands::Gags(args) => {
+                assert_eq!("config.json", args.config);
+                assert_eq!(vec![[255, 0, 0, 255], [0, 255, 0, 255]], args.colors);
+                assert_eq!("out.dmi", args.output);
+                assert_eq!(None, args.icon);
+            }
+            _ => panic!("Subcommand 'gags' was not parsed to Commands::Gags"),
+        }
+    }
+
+    #[test]
+    fn test_compile_metadata_chunk() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--metadata-chunk",
+            "itxt",
+            "neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => {
+                assert_eq!(MetadataChunk::Itxt, args.metadata_chunk);
+            }
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+    }
+
+    #[test]
+    fn test_deny_warnings() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--deny-warnings",
+            "neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => assert!(args.deny_warnings),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "decompile", "neck.dmi"]);
+        match &cli.command {
+            Commands::Decompile(args) => assert!(!args.deny_warnings),
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+    }
+
+    #[test]
+    fn test_compile_pack() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--pack",
+            "width=16",
+            "neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => assert_eq!(Some(Packing::Width(16)), args.pack),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        assert_eq!(Ok(Packing::Row), parse_packing("row"));
+        assert!(parse_packing("width=0").is_err());
+        assert!(parse_packing("diagonal").is_err());
+    }
+
+    #[test]
+    fn test_metadata_format() {
+        let cli = Cli::parse_from(vec!["icontool", "metadata", "--json", "neck.dmi"]);
+        match &cli.command {
+            Commands::Metadata(args) => assert!(args.json && !args.parsed),
+            _ => panic!("Subcommand 'metadata' was not parsed to Commands::Metadata"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "icontool", "metadata", "--json", "--parsed", "neck.dmi",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unflat() {
+        let cli = Cli::parse_from(vec!["icontool", "unflat", "neck.yml", "-o", "metadata.txt"]);
+        match &cli.command {
+            Commands::Unflat(args) => {
+                assert_eq!("neck.yml", args.file);
+                assert_eq!(Some(String::from("metadata.txt")), args.output);
+            }
+            _ => panic!("Subcommand 'unflat' was not parsed to Commands::Unflat"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "flat", "-o", "neck.yml", "metadata.txt"]);
+        match &cli.command {
+            Commands::Flat(args) => assert_eq!(Some(String::from("neck.yml")), args.output),
+            _ => panic!("Subcommand 'flat' was not parsed to Commands::Flat"),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let cli = Cli::parse_from(vec!["icontool", "roundtrip", "a.dmi", "b.dmi"]);
+        match &cli.command {
+            Commands::Roundtrip(args) => assert_eq!(vec!["a.dmi", "b.dmi"], args.files),
+            _ => panic!("Subcommand 'roundtrip' was not parsed to Commands::Roundtrip"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "roundtrip"]).is_err());
+    }
+
+    #[test]
+    fn test_equal() {
+        let cli = Cli::parse_from(vec!["icontool", "equal", "a.dmi", "b.dmi"]);
+        match &cli.command {
+            Commands::Equal(args) => {
+                assert_eq!(Tolerance::Channel(0), args.tolerance);
+                assert_eq!("a.dmi", args.a);
+                assert_eq!("b.dmi", args.b);
+            }
+            _ => panic!("Subcommand 'equal' was not parsed to Commands::Equal"),
+        }
+    }
+
+    #[test]
+    fn test_audit() {
+        let cli = Cli::parse_from(vec!["icontool", "audit", "icons/", "-r", "--json"]);
+        match &cli.command {
+            Commands::Audit(args) => {
+                assert_eq!(vec!["icons/"], args.paths);
+                assert!(args.recursive);
+                assert!(args.json);
+            }
+            _ => panic!("Subcommand 'audit' was not parsed to Commands::Audit"),
+        }
+    }
+
+    #[test]
+    fn test_unused() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "unused", "--code", "code/", "--code", "modules/", "--icons", "icons/",
+        ]);
+        match &cli.command {
+            Commands::Unused(args) => {
+                assert_eq!(vec!["code/", "modules/"], args.code);
+                assert_eq!(vec!["icons/"], args.icons);
+            }
+            _ => panic!("Subcommand 'unused' was not parsed to Commands::Unused"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "unused", "--code", "code/"]).is_err());
+    }
+
+    #[test]
+    fn test_missing() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "missing", "--code", "code/", "--icons", "icons/",
+        ]);
+        match &cli.command {
+            Commands::Missing(args) => {
+                assert_eq!(vec!["code/"], args.code);
+                assert_eq!(vec!["icons/"], args.icons);
+            }
+            _ => panic!("Subcommand 'missing' was not parsed to Commands::Missing"),
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "new",
+            "door.dmi.yml",
+            "--size",
+            "32x32",
+            "--states",
+            "idle,open,broken",
+            "--dirs",
+            "4",
+        ]);
+        match &cli.command {
+            Commands::New(args) => {
+                assert_eq!("door.dmi.yml", args.file);
+                assert_eq!(vec!["idle", "open", "broken"], args.states);
+                assert_eq!(4, args.dirs);
+                assert_eq!(1, args.frames);
+            }
+            _ => panic!("Subcommand 'new' was not parsed to Commands::New"),
+        }
+    }
+
+    #[test]
+    fn test_gif() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "gif",
+            "mob.dmi",
+            "--state",
+            "walk",
+            "--all-dirs",
+        ]);
+        match &cli.command {
+            Commands::Gif(args) => {
+                assert_eq!("walk", args.state);
+                assert!(args.all_dirs);
+                assert!(!args.separate);
+            }
+            _ => panic!("Subcommand 'gif' was not parsed to Commands::Gif"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "gif",
+            "mob.dmi",
+            "--state",
+            "walk",
+            "--separate",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decompile_stats() {
+        let cli = Cli::parse_from(vec!["icontool", "decompile", "--stats", "neck.dmi"]);
+        match &cli.command {
+            Commands::Decompile(args) => assert!(args.stats),
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+    }
+
+    #[test]
+    fn test_compile_check() {
+        let cli = Cli::parse_from(vec!["icontool", "compile", "--check", "neck.dmi.yml"]);
+        match &cli.command {
+            Commands::Compile(args) => assert!(args.check),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+    }
+
+    #[test]
+    fn test_decompile_output_dir() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "decompile",
+            "--output-dir",
+            "build/",
+            "--strip-prefix",
+            "icons/",
+            "icons/mob/neck.dmi",
+        ]);
+        match &cli.command {
+            Commands::Decompile(args) => {
+                assert_eq!(Some(String::from("build/")), args.output_dir);
+                assert_eq!(Some(String::from("icons/")), args.strip_prefix);
+            }
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "compile",
+            "--strip-prefix",
+            "icons/",
+            "icons/mob/neck.dmi.yml",
+        ]);
+        assert!(result.is_err());
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "compile",
+            "--output-dir",
+            "build/",
+            "-o",
+            "neck.dmi",
+            "icons/mob/neck.dmi.yml",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_compile_use_recorded_path() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--use-recorded-path",
+            "--base",
+            "code/",
+            "neck.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => {
+                assert!(args.use_recorded_path);
+                assert_eq!(Some(String::from("code/")), args.base);
+            }
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "compile",
+            "--base",
+            "code/",
+            "neck.dmi.yml",
+        ]);
+        assert!(result.is_err());
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "compile",
+            "--use-recorded-path",
+            "-o",
+            "neck.dmi",
+            "neck.dmi.yml",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let cli = Cli::parse_from(vec!["icontool", "verify", "-r", "icons"]);
+        match &cli.command {
+            Commands::Verify(args) => {
+                assert!(args.recursive);
+                assert_eq!(vec!["icons"], args.paths);
+            }
+            _ => panic!("Subcommand 'verify' was not parsed to Commands::Verify"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "verify"]).is_err());
+    }
+
+    #[test]
+    fn test_blank() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "blank",
+            "-o",
+            "new.dmi",
+            "--size",
+            "48x48",
+            "--states",
+            "idle:4dirs,walk:4dirs:2frames,off",
+        ]);
+        match &cli.command {
+            Commands::Blank(args) => {
+                assert_eq!("new.dmi", args.output);
+                assert_eq!((48, 48), args.size);
+                assert_eq!(
+                    vec![
+                        BlankState {
+                            name: String::from("idle"),
+                            dirs: 4,
+                            frames: 1
+                        },
+                        BlankState {
+                            name: String::from("walk"),
+                            dirs: 4,
+                            frames: 2
+                        },
+                        BlankState {
+                            name: String::from("off"),
+                            dirs: 1,
+                            frames: 1
+                        },
+                    ],
+                    args.states
+                );
+            }
+            _ => panic!("Subcommand 'blank' was not parsed to Commands::Blank"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "blank"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_blank_state() {
+        assert_eq!(
+            Ok(BlankState {
+                name: String::new(),
+                dirs: 8,
+                frames: 1
+            }),
+            parse_blank_state(":8dirs")
+        );
+        assert!(parse_blank_state("idle:4").is_err());
+        assert!(parse_blank_state("idle:0frames").is_err());
+        assert!(parse_blank_state("idle:xdirs").is_err());
+    }
+
+    #[test]
+    fn test_doctor() {
+        let cli = Cli::parse_from(vec!["icontool", "doctor", "a.dmi", "b.dmi"]);
+        match &cli.command {
+            Commands::Doctor(args) => assert_eq!(vec!["a.dmi", "b.dmi"], args.files),
+            _ => panic!("Subcommand 'doctor' was not parsed to Commands::Doctor"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "doctor"]).is_err());
+    }
 
-    pub file: String,
-}
+    #[test]
+    fn test_decompile_state() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "decompile",
+            "--state",
+            "closed",
+            "--state",
+            "open",
+            "door.dmi",
+        ]);
+        match &cli.command {
+            Commands::Decompile(args) => assert_eq!(vec!["closed", "open"], args.state),
+            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+        }
+    }
 
-#[derive(Args)]
-pub struct DecompileArgs {
-    #[arg(short, long)]
-    pub output: Option<String>,
+    #[test]
+    fn test_patch() {
+        let cli = Cli::parse_from(vec!["icontool", "patch", "door.dmi", "changes.dmi.yml"]);
+        match &cli.command {
+            Commands::Patch(args) => {
+                assert_eq!("door.dmi", args.file);
+                assert_eq!("changes.dmi.yml", args.changes);
+                assert_eq!(None, args.output);
+            }
+            _ => panic!("Subcommand 'patch' was not parsed to Commands::Patch"),
+        }
+    }
 
-    pub file: String,
-}
+    #[test]
+    fn test_apply() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "apply",
+            "base.dmi.yml",
+            "winter.yml",
+            "-o",
+            "merged.dmi.yml",
+        ]);
+        match &cli.command {
+            Commands::Apply(args) => {
+                assert_eq!("base.dmi.yml", args.base);
+                assert_eq!("winter.yml", args.overlay);
+                assert_eq!("merged.dmi.yml", args.output);
+            }
+            _ => panic!("Subcommand 'apply' was not parsed to Commands::Apply"),
+        }
+        let result = Cli::try_parse_from(vec!["icontool", "apply", "base.dmi.yml", "winter.yml"]);
+        assert!(result.is_err());
+    }
 
-#[derive(Args)]
-pub struct FlatArgs {
-    pub file: String,
-}
+    #[test]
+    fn test_diff() {
+        let cli = Cli::parse_from(vec!["icontool", "diff", "--frames", "old.dmi", "new.dmi"]);
+        match &cli.command {
+            Commands::Diff(args) => {
+                assert!(args.frames);
+                assert_eq!("old.dmi", args.old);
+                assert_eq!("new.dmi", args.new);
+            }
+            _ => panic!("Subcommand 'diff' was not parsed to Commands::Diff"),
+        }
+    }
 
-#[derive(Args)]
-pub struct MetadataArgs {
-    #[arg(short, long)]
-    pub output: Option<String>,
+    #[test]
+    fn test_tolerance() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "diff",
+            "--tolerance",
+            "1%",
+            "a.dmi",
+            "b.dmi",
+        ]);
+        match &cli.command {
+            Commands::Diff(args) => assert_eq!(Tolerance::Percent(1.0), args.tolerance),
+            _ => panic!("Subcommand 'diff' was not parsed to Commands::Diff"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "roundtrip", "--tolerance", "3", "a.dmi"]);
+        match &cli.command {
+            Commands::Roundtrip(args) => assert_eq!(Tolerance::Channel(3), args.tolerance),
+            _ => panic!("Subcommand 'roundtrip' was not parsed to Commands::Roundtrip"),
+        }
+        assert!(
+            Cli::try_parse_from(vec!["icontool", "equal", "--tolerance", "x", "a", "b"]).is_err()
+        );
+    }
 
-    pub file: String,
-}
+    #[test]
+    fn test_report() {
+        let cli = Cli::parse_from(vec!["icontool", "report", "icons/", "-r", "-o", "report/"]);
+        match &cli.command {
+            Commands::Report(args) => {
+                assert_eq!("report/", args.output);
+                assert!(args.recursive);
+                assert_eq!(vec!["icons/"], args.paths);
+            }
+            _ => panic!("Subcommand 'report' was not parsed to Commands::Report"),
+        }
+        assert!(Cli::try_parse_from(vec!["icontool", "report", "icons/"]).is_err());
+    }
 
-//---------------------------------------------------------------------------
-//---------------------------------------------------------------------------
-//---------------------------------------------------------------------------
+    #[test]
+    fn test_serve() {
+        let cli = Cli::parse_from(vec!["icontool", "serve", "icons/"]);
+        match &cli.command {
+            Commands::Serve(args) => {
+                assert_eq!(8080, args.port);
+                assert!(!args.recursive);
+                assert_eq!(vec!["icons/"], args.paths);
+            }
+            _ => panic!("Subcommand 'serve' was not parsed to Commands::Serve"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "serve", "icons/", "-r", "--port", "9000"]);
+        match &cli.command {
+            Commands::Serve(args) => assert_eq!(9000, args.port),
+            _ => panic!("Subcommand 'serve' was not parsed to Commands::Serve"),
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_states_csv() {
+        let cli = Cli::parse_from(vec![
+            "icontool", "states", "--format", "csv", "icons/", "-r",
+        ]);
+        match &cli.command {
+            Commands::States(args) => {
+                assert_eq!(StatesFormat::Csv, args.format);
+                assert!(args.recursive);
+                assert_eq!(vec!["icons/"], args.paths);
+            }
+            _ => panic!("Subcommand 'states' was not parsed to Commands::States"),
+        }
+    }
 
     #[test]
-    fn test_always_succeed() {
-        assert!(true);
+    fn test_colors() {
+        let cli = Cli::parse_from(vec!["icontool", "colors", "foo.dmi", "--state", "jumpsuit"]);
+        match &cli.command {
+            Commands::Colors(args) => {
+                assert_eq!(Some(String::from("jumpsuit")), args.state);
+                assert_eq!(10, args.top);
+                assert_eq!("foo.dmi", args.file);
+            }
+            _ => panic!("Subcommand 'colors' was not parsed to Commands::Colors"),
+        }
     }
 
     #[test]
-    fn test_compile_default() {
+    fn test_optimize_anim() {
+        let cli = Cli::parse_from(vec!["icontool", "optimize-anim", "foo.dmi"]);
+        match &cli.command {
+            Commands::OptimizeAnim(args) => {
+                assert_eq!(None, args.output);
+                assert_eq!(None, args.state);
+                assert_eq!("foo.dmi", args.file);
+            }
+            _ => panic!("Subcommand 'optimize-anim' was not parsed to Commands::OptimizeAnim"),
+        }
+    }
+
+    #[test]
+    fn test_convert_size() {
         let cli = Cli::parse_from(vec![
             "icontool",
-            "compile",
-            "icons/mob/clothing/neck.dmi.yml",
+            "convert-size",
+            "foo.dmi",
+            "--to",
+            "64x64",
+            "--state",
+            "humans=pad:center",
         ]);
         match &cli.command {
-            Commands::Compile(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi.yml", args.file);
-                assert_eq!(None, args.output);
+            Commands::ConvertSize(args) => {
+                assert_eq!((64, 64), args.to);
+                assert_eq!(SizeStrategy::Scale(ScaleFilter::Nearest), args.default);
+                assert_eq!("humans", args.state[0].name);
+                assert_eq!(SizeStrategy::Pad(Anchor::Center), args.state[0].strategy);
             }
+            _ => panic!("Subcommand 'convert-size' was not parsed to Commands::ConvertSize"),
+        }
+    }
+
+    #[test]
+    fn test_compile_split() {
+        let cli = Cli::parse_from(vec!["icontool", "compile", "--split", "big.dmi.yml"]);
+        match &cli.command {
+            Commands::Compile(args) => assert!(args.split),
             _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
         }
     }
 
     #[test]
-    fn test_compile_output() {
+    fn test_pack() {
+        let cli = Cli::parse_from(vec!["icontool", "pack", "parts/", "-o", "combined.dmi"]);
+        match &cli.command {
+            Commands::Pack(args) => {
+                assert_eq!("combined.dmi", args.output);
+                assert!(!args.recursive);
+                assert!(args.prefix.is_empty());
+                assert_eq!(OnConflict::Error, args.on_conflict);
+                assert_eq!(vec![String::from("parts/")], args.paths);
+            }
+            _ => panic!("Subcommand 'pack' was not parsed to Commands::Pack"),
+        }
+        let result = Cli::try_parse_from(vec!["icontool", "pack", "parts/"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pack_prefix() {
         let cli = Cli::parse_from(vec![
             "icontool",
-            "compile",
-            "--output",
-            "icons/mob/clothing/neckbeard.dmi",
-            "icons/mob/clothing/neck.dmi.yml",
+            "pack",
+            "--prefix",
+            "pack_",
+            "--prefix",
+            "parts/blue.dmi=blue=",
+            "parts/",
+            "-o",
+            "combined.dmi",
         ]);
         match &cli.command {
-            Commands::Compile(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi.yml", args.file);
-                assert_eq!(
-                    "icons/mob/clothing/neckbeard.dmi",
-                    args.output.as_ref().unwrap()
-                );
+            Commands::Pack(args) => {
+                let expected = vec![
+                    PackPrefix {
+                        file: None,
+                        prefix: String::from("pack_"),
+                    },
+                    PackPrefix {
+                        file: Some(String::from("parts/blue.dmi")),
+                        prefix: String::from("blue="),
+                    },
+                ];
+                assert_eq!(expected, args.prefix);
             }
-            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+            _ => panic!("Subcommand 'pack' was not parsed to Commands::Pack"),
         }
     }
 
     #[test]
-    fn test_decompile_default() {
-        let cli = Cli::parse_from(vec!["icontool", "decompile", "icons/mob/clothing/neck.dmi"]);
+    fn test_pack_on_conflict() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "pack",
+            "--on-conflict",
+            "rename",
+            "parts/",
+            "-o",
+            "combined.dmi",
+        ]);
         match &cli.command {
-            Commands::Decompile(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
+            Commands::Pack(args) => assert_eq!(OnConflict::Rename, args.on_conflict),
+            _ => panic!("Subcommand 'pack' was not parsed to Commands::Pack"),
+        }
+    }
+
+    #[test]
+    fn test_fix_metadata() {
+        let cli = Cli::parse_from(vec!["icontool", "fix-metadata", "foo.dmi"]);
+        match &cli.command {
+            Commands::FixMetadata(args) => {
                 assert_eq!(None, args.output);
+                assert_eq!("foo.dmi", args.file);
             }
+            _ => panic!("Subcommand 'fix-metadata' was not parsed to Commands::FixMetadata"),
+        }
+    }
+
+    #[test]
+    fn test_decompile_lenient() {
+        let cli = Cli::parse_from(vec!["icontool", "decompile", "--lenient", "broken.dmi"]);
+        match &cli.command {
+            Commands::Decompile(args) => assert!(args.lenient),
             _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
         }
     }
 
     #[test]
-    fn test_decompile_output() {
+    fn test_compile_pad_missing() {
         let cli = Cli::parse_from(vec![
             "icontool",
-            "decompile",
-            "--output",
-            "icons/mob/clothing/neckbeard.dmi.yml",
-            "icons/mob/clothing/neck.dmi",
+            "compile",
+            "--pad-missing",
+            "--pad-with",
+            "checker",
+            "wip.dmi.yml",
         ]);
         match &cli.command {
-            Commands::Decompile(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
-                assert_eq!(
-                    "icons/mob/clothing/neckbeard.dmi.yml",
-                    args.output.as_ref().unwrap()
-                );
+            Commands::Compile(args) => {
+                assert!(args.pad_missing);
+                assert_eq!(PadWith::Checker, args.pad_with);
             }
-            _ => panic!("Subcommand 'decompile' was not parsed to Commands::Decompile"),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "compile", "--pad-missing", "wip.dmi.yml"]);
+        match &cli.command {
+            Commands::Compile(args) => assert_eq!(PadWith::Transparent, args.pad_with),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
         }
+        let args = vec![
+            "icontool",
+            "compile",
+            "--pad-with",
+            "checker",
+            "wip.dmi.yml",
+        ];
+        assert!(Cli::try_parse_from(args).is_err());
     }
 
     #[test]
-    fn test_flat_default() {
+    fn test_compile_limits() {
         let cli = Cli::parse_from(vec![
             "icontool",
-            "flat",
-            "icons/mob/clothing/neck.dmi.metadata",
+            "compile",
+            "--max-frame-bytes",
+            "4096",
+            "--max-states",
+            "10",
+            "untrusted.dmi.yml",
         ]);
         match &cli.command {
-            Commands::Flat(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi.metadata", args.file);
+            Commands::Compile(args) => {
+                assert_eq!(Some(4096), args.max_frame_bytes);
+                assert_eq!(Some(10), args.max_states);
             }
-            _ => panic!("Subcommand 'flat' was not parsed to Commands::Flat"),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
         }
     }
 
     #[test]
-    fn test_metadata_default() {
-        let cli = Cli::parse_from(vec!["icontool", "metadata", "icons/mob/clothing/neck.dmi"]);
+    fn test_lock() {
+        let cli = Cli::parse_from(vec!["icontool", "lock", "icons/", "-r", "-o", "icons.lock"]);
         match &cli.command {
-            Commands::Metadata(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
-                assert_eq!(None, args.output);
+            Commands::Lock(args) => {
+                assert_eq!("icons.lock", args.output);
+                assert!(args.recursive);
+                assert_eq!(vec![String::from("icons/")], args.paths);
             }
-            _ => panic!("Subcommand 'metadata' was not parsed to Commands::Metadata"),
+            _ => panic!("Subcommand 'lock' was not parsed to Commands::Lock"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "verify-lock", "icons.lock"]);
+        match &cli.command {
+            Commands::VerifyLock(args) => assert_eq!("icons.lock", args.file),
+            _ => panic!("Subcommand 'verify-lock' was not parsed to Commands::VerifyLock"),
         }
     }
 
     #[test]
-    fn test_metadata_output() {
+    fn test_post_process() {
         let cli = Cli::parse_from(vec![
             "icontool",
-            "metadata",
-            "--output",
-            "icons/mob/clothing/neck.dmi.metadata",
-            "icons/mob/clothing/neck.dmi",
+            "--post-process",
+            "oxipng {}",
+            "verify-lock",
+            "a.lock",
+        ]);
+        assert_eq!(Some(String::from("oxipng {}")), cli.post_process);
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "verify-lock",
+            "a.lock",
+            "--post-process",
+            "oxipng",
+        ]);
+        assert_eq!(Some(String::from("oxipng")), cli.post_process);
+        let cli = Cli::parse_from(vec!["icontool", "verify-lock", "a.lock"]);
+        assert_eq!(None, cli.post_process);
+    }
+
+    #[test]
+    fn test_log_file() {
+        let cli = Cli::parse_from(vec!["icontool", "--log-file", "run.log", "verify", "icons"]);
+        assert_eq!(Some(String::from("run.log")), cli.log_file);
+        let cli = Cli::parse_from(vec!["icontool", "verify", "icons", "--log-file", "a.log"]);
+        assert_eq!(Some(String::from("a.log")), cli.log_file);
+        let cli = Cli::parse_from(vec!["icontool", "verify", "icons"]);
+        assert_eq!(None, cli.log_file);
+    }
+
+    #[test]
+    fn test_timings() {
+        let cli = Cli::parse_from(vec!["icontool", "--timings", "build"]);
+        assert!(cli.timings);
+        let cli = Cli::parse_from(vec!["icontool", "compile", "a.dmi.yml", "--timings"]);
+        assert!(cli.timings);
+        let cli = Cli::parse_from(vec!["icontool", "build"]);
+        assert!(!cli.timings);
+    }
+
+    #[test]
+    fn test_compile_define() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "--define",
+            "TEAM_COLOR=#ff0000",
+            "--define",
+            "SIDE=a=b",
+            "team.dmi.yml",
         ]);
         match &cli.command {
-            Commands::Metadata(args) => {
-                assert_eq!("icons/mob/clothing/neck.dmi", args.file);
-                assert_eq!(
-                    "icons/mob/clothing/neck.dmi.metadata",
-                    args.output.as_ref().unwrap()
-                );
+            Commands::Compile(args) => assert_eq!(
+                vec![
+                    (String::from("TEAM_COLOR"), String::from("#ff0000")),
+                    (String::from("SIDE"), String::from("a=b")),
+                ],
+                args.define
+            ),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        assert!(parse_define("TEAM_COLOR").is_err());
+        assert!(parse_define("=red").is_err());
+        assert!(parse_define("TEAM COLOR=red").is_err());
+        assert_eq!(
+            Ok((String::from("EMPTY"), String::new())),
+            parse_define("EMPTY=")
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let cli = Cli::parse_from(vec!["icontool", "build"]);
+        match &cli.command {
+            Commands::Build(args) => {
+                assert_eq!("icontool.yml", args.manifest);
+                assert!(!args.force_rebuild);
             }
-            _ => panic!("Subcommand 'metadata' was not parsed to Commands::Metadata"),
+            _ => panic!("Subcommand 'build' was not parsed to Commands::Build"),
+        }
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "build",
+            "--manifest",
+            "icons/icontool.yml",
+            "--force-rebuild",
+        ]);
+        match &cli.command {
+            Commands::Build(args) => {
+                assert_eq!("icons/icontool.yml", args.manifest);
+                assert!(args.force_rebuild);
+            }
+            _ => panic!("Subcommand 'build' was not parsed to Commands::Build"),
+        }
+    }
+
+    #[test]
+    fn test_compile_depfile() {
+        let cli = Cli::parse_from(vec![
+            "icontool",
+            "compile",
+            "door.dmi.yml",
+            "-o",
+            "door.dmi",
+            "--depfile",
+            "door.d",
+        ]);
+        match &cli.command {
+            Commands::Compile(args) => assert_eq!(Some(String::from("door.d")), args.depfile),
+            _ => panic!("Subcommand 'compile' was not parsed to Commands::Compile"),
+        }
+        let result = Cli::try_parse_from(vec![
+            "icontool",
+            "compile",
+            "door.dmi.yml",
+            "--check",
+            "--depfile",
+            "door.d",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_clean() {
+        let cli = Cli::parse_from(vec!["icontool", "clean"]);
+        match &cli.command {
+            Commands::Clean(args) => assert_eq!("icontool.yml", args.manifest),
+            _ => panic!("Subcommand 'clean' was not parsed to Commands::Clean"),
+        }
+    }
+
+    #[test]
+    fn test_watch() {
+        let cli = Cli::parse_from(vec!["icontool", "watch", "--interval", "250"]);
+        match &cli.command {
+            Commands::Watch(args) => {
+                assert_eq!("icontool.yml", args.manifest);
+                assert_eq!(250, args.interval);
+            }
+            _ => panic!("Subcommand 'watch' was not parsed to Commands::Watch"),
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let cli = Cli::parse_from(vec!["icontool", "explain", "ICT0009"]);
+        match &cli.command {
+            Commands::Explain(args) => assert_eq!(Some(String::from("ICT0009")), args.code),
+            _ => panic!("Subcommand 'explain' was not parsed to Commands::Explain"),
+        }
+        let cli = Cli::parse_from(vec!["icontool", "explain"]);
+        match &cli.command {
+            Commands::Explain(args) => assert_eq!(None, args.code),
+            _ => panic!("Subcommand 'explain' was not parsed to Commands::Explain"),
         }
     }
 }
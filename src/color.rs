@@ -0,0 +1,216 @@
+// color.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use crate::cmdline::{AdjustArgs, AlphaArgs, AlphaMode};
+use crate::dmi_file::edit_dmi_file;
+use crate::edit::get_output_path;
+use crate::error::Result;
+use crate::log::diagnostic;
+use crate::transform::select_states;
+
+pub fn adjust(args: &AdjustArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        for index in select_states(sheet, &args.state)? {
+            for frame in &mut sheet.frames[index] {
+                for pixel in frame.pixels_mut() {
+                    let [r, g, b, a] = pixel.0;
+                    let [r, g, b] =
+                        adjust_rgb([r, g, b], args.hue, args.saturation, args.brightness);
+                    pixel.0 = [r, g, b, a];
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn alpha(args: &AlphaArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let mut changed = 0;
+        for index in select_states(sheet, &args.state)? {
+            for frame in &mut sheet.frames[index] {
+                for pixel in frame.pixels_mut() {
+                    let alpha = clean_alpha(pixel.0[3], args.threshold, &args.mode);
+                    if alpha != pixel.0[3] {
+                        changed += 1;
+                    }
+                    // fully transparent pixels carry no color
+                    pixel.0 = match alpha {
+                        0 => [0, 0, 0, 0],
+                        _ => [pixel.0[0], pixel.0[1], pixel.0[2], alpha],
+                    };
+                }
+            }
+        }
+        diagnostic(&format!(
+            "icontool: cleaned up the alpha of {changed} pixel(s)"
+        ));
+        Ok(())
+    })
+}
+
+// pixels at or below the threshold become transparent; in round mode,
+// pixels at or above 255 - threshold also become opaque
+pub fn clean_alpha(alpha: u8, threshold: u8, mode: &AlphaMode) -> u8 {
+    if alpha <= threshold {
+        return 0;
+    }
+    match mode {
+        AlphaMode::Round if alpha >= 255 - threshold => 255,
+        _ => alpha,
+    }
+}
+
+// shift the hue by degrees, and saturation and brightness by percentage points
+pub fn adjust_rgb(rgb: [u8; 3], hue: f64, saturation: f64, brightness: f64) -> [u8; 3] {
+    let (h, s, v) = rgb_to_hsv(rgb);
+    let h = (h + hue).rem_euclid(360.0);
+    let s = (s + saturation / 100.0).clamp(0.0, 1.0);
+    let v = (v + brightness / 100.0).clamp(0.0, 1.0);
+    hsv_to_rgb(h, s, v)
+}
+
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = value - chroma;
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let to_u8 = |c: f64| ((c + m) * 255.0).round() as u8;
+    [to_u8(r), to_u8(g), to_u8(b)]
+}
+
+pub fn rgb_to_hsv(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let [r, g, b] = rgb.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmi_file::DmiFile;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_hsv_round_trip() {
+        for rgb in [
+            [255, 0, 0],
+            [12, 200, 99],
+            [0, 0, 0],
+            [255, 255, 255],
+            [90, 10, 250],
+        ] {
+            let (h, s, v) = rgb_to_hsv(rgb);
+            assert_eq!(rgb, hsv_to_rgb(h, s, v));
+        }
+    }
+
+    #[test]
+    fn test_adjust_rgb() {
+        assert_eq!([0, 255, 0], adjust_rgb([255, 0, 0], 120.0, 0.0, 0.0));
+        assert_eq!([255, 0, 0], adjust_rgb([255, 0, 0], 360.0, 0.0, 0.0));
+        assert_eq!([128, 128, 128], adjust_rgb([128, 0, 0], 0.0, -100.0, 0.0));
+    }
+
+    #[test]
+    fn test_clean_alpha() {
+        assert_eq!(0, clean_alpha(16, 16, &AlphaMode::Zero));
+        assert_eq!(17, clean_alpha(17, 16, &AlphaMode::Zero));
+        assert_eq!(240, clean_alpha(240, 16, &AlphaMode::Zero));
+        assert_eq!(255, clean_alpha(240, 16, &AlphaMode::Round));
+        assert_eq!(128, clean_alpha(128, 16, &AlphaMode::Round));
+        assert_eq!(0, clean_alpha(3, 16, &AlphaMode::Round));
+    }
+
+    #[test]
+    fn test_alpha_leaves_no_stray_pixels() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = AlphaArgs {
+            output: Some(String::from("tests/data/edit/alpha.out.dmi")),
+            state: None,
+            threshold: 254,
+            mode: AlphaMode::Zero,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        alpha(&args).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/alpha.out.dmi")).unwrap();
+        for frame in sheet.frames.iter().flatten() {
+            for pixel in frame.pixels() {
+                assert!(pixel.0 == [0, 0, 0, 0] || pixel.0[3] == 255);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adjust_preserves_alpha() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = AdjustArgs {
+            output: Some(String::from("tests/data/edit/adjust.out.dmi")),
+            state: Some(String::from("walk")),
+            hue: 30.0,
+            saturation: -10.0,
+            brightness: 5.0,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        adjust(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/adjust.out.dmi")).unwrap();
+        let before = original.frames[1]
+            .iter()
+            .flat_map(|f| f.pixels().map(|p| p.0[3]));
+        let after = sheet.frames[1]
+            .iter()
+            .flat_map(|f| f.pixels().map(|p| p.0[3]));
+        assert!(before.eq(after));
+        assert_eq!(original.frames[0], sheet.frames[0]);
+    }
+}
@@ -0,0 +1,344 @@
+// compare.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use crate::cmdline::{DiffArgs, EqualArgs, Tolerance};
+use crate::constant::DIR_NAMES;
+use crate::dmi_file::{DmiFile, IconState};
+use crate::error::{IconToolError, Result};
+use crate::fetch::open_dmi;
+use crate::parser::DreamMakerIconState;
+
+pub fn equal(args: &EqualArgs) -> Result<()> {
+    // compare the two files
+    let a = open_dmi(&args.a)?;
+    let b = open_dmi(&args.b)?;
+    let differences = compare_dmi_files(&a, &b, args.tolerance);
+    for difference in &differences {
+        println!("{difference}");
+    }
+
+    // if they aren't functionally identical, tell the caller
+    if !differences.is_empty() {
+        return Err(IconToolError::NotEqual(differences.len()));
+    }
+    Ok(())
+}
+
+pub fn diff(args: &DiffArgs) -> Result<()> {
+    // list what changed between the two files; either may be a URL, to
+    // compare against a copy that was never checked out
+    let old = open_dmi(&args.old)?;
+    let new = open_dmi(&args.new)?;
+    let differences = diff_dmi_files(&old, &new, args.frames, args.tolerance);
+    for difference in &differences {
+        println!("{difference}");
+    }
+
+    // like equal, any change is a non-zero exit
+    if !differences.is_empty() {
+        return Err(IconToolError::NotEqual(differences.len()));
+    }
+    Ok(())
+}
+
+// list the icon_states that were removed, changed, or added between two
+// .dmi files, matching them by name; with frames, list each changed frame
+pub fn diff_dmi_files(
+    old: &DmiFile,
+    new: &DmiFile,
+    frames: bool,
+    tolerance: Tolerance,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    // if the icons aren't the same size, the frames can't be compared
+    let (old_size, new_size) = (
+        (old.metadata.width, old.metadata.height),
+        (new.metadata.width, new.metadata.height),
+    );
+    if old_size != new_size {
+        differences.push(format!(
+            "icon size changed from {}x{} to {}x{}",
+            old_size.0, old_size.1, new_size.0, new_size.1
+        ));
+        return differences;
+    }
+
+    // match the icon_states by name, so moving a state isn't a change
+    let (mut old_metadata, mut new_metadata) = (old.metadata.clone(), new.metadata.clone());
+    old_metadata.normalize();
+    new_metadata.normalize();
+    let (old_keys, new_keys) = (old.metadata.state_keys(), new.metadata.state_keys());
+    for key in old_keys.iter().filter(|x| !new_keys.contains(x)) {
+        differences.push(format!("icon_state '{key}' was removed"));
+    }
+    let old_states = old_metadata.states.iter().zip(old.states());
+    for (key, (old_state, old_frames)) in old_keys.iter().zip(old_states) {
+        let Some(index) = new_keys.iter().position(|x| x == key) else {
+            continue;
+        };
+        let new_state = &new_metadata.states[index];
+        let new_frames = new.states().nth(index).expect("icon_state without frames");
+        let properties = compare_properties(old_state, new_state);
+        let changed = match old_frames.frames.len() == new_frames.frames.len() {
+            true => changed_frames(&old_frames, &new_frames, tolerance),
+            false => Vec::new(),
+        };
+        if !frames {
+            if properties.is_some() || !changed.is_empty() {
+                differences.push(format!("icon_state '{key}' changed"));
+            }
+            continue;
+        }
+        if let Some((old_only, new_only)) = properties {
+            differences.push(format!(
+                "icon_state '{key}' changed from '{}' to '{}'",
+                old_only.join("; "),
+                new_only.join("; ")
+            ));
+        }
+        for (dir, frame, pixels) in changed {
+            differences.push(format!(
+                "icon_state '{key}' dir {} frame {} has {pixels} changed pixel(s)",
+                DIR_NAMES[dir as usize],
+                frame + 1
+            ));
+        }
+    }
+    for key in new_keys.iter().filter(|x| !old_keys.contains(x)) {
+        differences.push(format!("icon_state '{key}' was added"));
+    }
+
+    differences
+}
+
+// compare what DreamMaker would show for two .dmi files: icon size, the
+// icon_states and their properties, and the pixels of every frame
+pub fn compare_dmi_files(a: &DmiFile, b: &DmiFile, tolerance: Tolerance) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    // if the icons aren't the same size, the frames can't be compared
+    let (a_size, b_size) = (
+        (a.metadata.width, a.metadata.height),
+        (b.metadata.width, b.metadata.height),
+    );
+    if a_size != b_size {
+        differences.push(format!(
+            "icon size {}x{} differs from {}x{}",
+            a_size.0, a_size.1, b_size.0, b_size.1
+        ));
+        return differences;
+    }
+    if a.metadata.states.len() != b.metadata.states.len() {
+        differences.push(format!(
+            "expected {} icon_states, found {}",
+            a.metadata.states.len(),
+            b.metadata.states.len()
+        ));
+        return differences;
+    }
+
+    // compare the normalized properties and then the frames of each state
+    let (mut a_metadata, mut b_metadata) = (a.metadata.clone(), b.metadata.clone());
+    a_metadata.normalize();
+    b_metadata.normalize();
+    let states = a_metadata.states.iter().zip(&b_metadata.states);
+    for ((a_state, b_state), (a_frames, b_frames)) in states.zip(a.states().zip(b.states())) {
+        if let Some((a_only, b_only)) = compare_properties(a_state, b_state) {
+            differences.push(format!(
+                "icon_state '{}' differs: '{}' vs '{}'",
+                a_state.name,
+                a_only.join("; "),
+                b_only.join("; ")
+            ));
+        }
+        if a_frames.frames.len() == b_frames.frames.len() {
+            differences.extend(compare_frames(&a_frames, &b_frames, tolerance));
+        }
+    }
+
+    differences
+}
+
+// compare the properties of two normalized icon_states, returning the
+// lines that only one or the other has
+fn compare_properties(
+    a: &DreamMakerIconState,
+    b: &DreamMakerIconState,
+) -> Option<(Vec<String>, Vec<String>)> {
+    let (a_text, b_text) = (a.to_string(), b.to_string());
+    if a_text == b_text {
+        return None;
+    }
+    let only = |x: &str, y: &str| -> Vec<String> {
+        let theirs: Vec<&str> = y.lines().collect();
+        x.lines()
+            .filter(|line| !theirs.contains(line))
+            .map(|line| line.trim().to_string())
+            .collect()
+    };
+    Some((only(&a_text, &b_text), only(&b_text, &a_text)))
+}
+
+// compare the pixels of each frame of two icon_states
+pub fn compare_frames(a: &IconState, b: &IconState, tolerance: Tolerance) -> Vec<String> {
+    changed_frames(a, b, tolerance)
+        .into_iter()
+        .map(|(dir, frame, pixels)| {
+            format!(
+                "icon_state '{}' frame {} dir {} has {pixels} changed pixel(s)",
+                a.name(),
+                frame + 1,
+                dir + 1
+            )
+        })
+        .collect()
+}
+
+// find the frames that differ between two icon_states with the same number
+// of frames, as (dir index, frame index, number of changed pixels); changes
+// within the tolerance are ignored
+pub fn changed_frames(
+    a: &IconState,
+    b: &IconState,
+    tolerance: Tolerance,
+) -> Vec<(u32, u32, usize)> {
+    let mut changed = Vec::new();
+    let dirs = a.metadata.dirs;
+    let delta = match tolerance {
+        Tolerance::Channel(delta) => delta,
+        Tolerance::Percent(_) => 0,
+    };
+    for (index, (a_frame, b_frame)) in a.frames.iter().zip(b.frames).enumerate() {
+        let pixels = a_frame
+            .pixels()
+            .zip(b_frame.pixels())
+            .filter(|(x, y)| x.0.iter().zip(y.0).any(|(x, y)| x.abs_diff(y) > delta))
+            .count();
+        let allowed = match tolerance {
+            Tolerance::Channel(_) => 0.0,
+            Tolerance::Percent(percent) => a_frame.len() as f64 / 4.0 * percent / 100.0,
+        };
+        if pixels as f64 > allowed || a_frame.dimensions() != b_frame.dimensions() {
+            changed.push((index as u32 % dirs, index as u32 / dirs, pixels));
+        }
+    }
+    changed
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_equal_ignores_encoding() {
+        // chunks.dmi is spin.dmi with extra PNG chunks
+        let args = EqualArgs {
+            tolerance: Tolerance::default(),
+            a: String::from("tests/data/edit/spin.dmi"),
+            b: String::from("tests/data/compile/chunks.dmi"),
+        };
+        equal(&args).unwrap();
+    }
+
+    #[test]
+    fn test_compare_dmi_files() {
+        let a = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let mut b = a.clone();
+        b.metadata.states[0].delay = Some(vec![
+            String::from("1.0"),
+            String::from("2"),
+            String::from("3.00"),
+        ]);
+        assert!(compare_dmi_files(&a, &b, Tolerance::default()).is_empty());
+        b.metadata.states[1].delay = Some(vec![String::from("2"), String::from("3")]);
+        b.frames[2][0].put_pixel(0, 0, image::Rgba([1, 2, 3, 4]));
+        assert_eq!(
+            vec![
+                "icon_state 'walk' differs: 'delay = 2,2' vs 'delay = 2,3'",
+                "icon_state 'still' frame 1 dir 1 has 1 changed pixel(s)",
+            ],
+            compare_dmi_files(&a, &b, Tolerance::default())
+        );
+    }
+
+    #[test]
+    fn test_diff_dmi_files() {
+        let old = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let mut new = old.clone();
+        assert!(diff_dmi_files(&old, &new, true, Tolerance::default()).is_empty());
+
+        // touch the east frames of walk, and rename spin
+        new.metadata.states[1].delay = Some(vec![String::from("2"), String::from("3")]);
+        new.frames[1][2].put_pixel(0, 0, image::Rgba([1, 2, 3, 4]));
+        new.frames[1][6].put_pixel(0, 0, image::Rgba([1, 2, 3, 4]));
+        new.frames[1][6].put_pixel(1, 0, image::Rgba([1, 2, 3, 4]));
+        new.metadata.states[0].name = String::from("twirl");
+        assert_eq!(
+            vec![
+                "icon_state 'spin' was removed",
+                "icon_state 'walk' changed",
+                "icon_state 'twirl' was added",
+            ],
+            diff_dmi_files(&old, &new, false, Tolerance::default())
+        );
+        assert_eq!(
+            vec![
+                "icon_state 'spin' was removed",
+                "icon_state 'walk' changed from 'delay = 2,2' to 'delay = 2,3'",
+                "icon_state 'walk' dir east frame 1 has 1 changed pixel(s)",
+                "icon_state 'walk' dir east frame 2 has 2 changed pixel(s)",
+                "icon_state 'twirl' was added",
+            ],
+            diff_dmi_files(&old, &new, true, Tolerance::default())
+        );
+    }
+
+    #[test]
+    fn test_diff_size() {
+        let old = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let new = DmiFile::open(Path::new("tests/data/gags/scarf.dmi")).unwrap();
+        let differences = diff_dmi_files(&old, &new, true, Tolerance::default());
+        assert_eq!(1, differences.len());
+        assert!(differences[0].starts_with("icon size changed from 32x32"));
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let a = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let mut b = a.clone();
+        let mut pixel = *b.frames[2][0].get_pixel(0, 0);
+        pixel.0[0] = pixel.0[0].wrapping_add(2);
+        b.frames[2][0].put_pixel(0, 0, pixel);
+        assert_eq!(1, compare_dmi_files(&a, &b, Tolerance::Channel(1)).len());
+        assert!(compare_dmi_files(&a, &b, Tolerance::Channel(2)).is_empty());
+
+        // one pixel of a 32x32 frame is just under 0.1%
+        assert!(compare_dmi_files(&a, &b, Tolerance::Percent(0.1)).is_empty());
+        assert_eq!(1, compare_dmi_files(&a, &b, Tolerance::Percent(0.05)).len());
+    }
+}
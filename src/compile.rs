@@ -16,71 +16,560 @@
 //---------------------------------------------------------------------------
 
 use base64::prelude::*;
-use image::{DynamicImage, Rgba};
+use image::{DynamicImage, RgbaImage};
 use indexmap::IndexMap;
 use lz4_flex::block::decompress_size_prepended;
 use num_integer::Roots;
-use png::Encoder;
 use serde_yml::Value;
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::BufWriter;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "cli")]
+use std::io::Write;
+#[cfg(feature = "cli")]
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "cli")]
+use crate::atomic::write_atomic;
+#[cfg(feature = "cli")]
 use crate::cmdline::CompileArgs;
 use crate::constant::*;
+#[cfg(feature = "cli")]
+use crate::depfile::write_depfile;
+use crate::diagnostics::Diagnostics;
+use crate::dmi::{encode_dmi, MetadataChunk, PngChunk, WriteOptions};
 use crate::error::{IconToolError, Result};
+#[cfg(feature = "cli")]
+use crate::include::{find_includes, resolve_includes};
 use crate::indexmap_helper::IndexMapHelper;
-use crate::parser::{parse_metadata, DreamMakerIconMetadata};
+#[cfg(feature = "cli")]
+use crate::locate::locate_error;
+#[cfg(feature = "cli")]
+use crate::parser::parse_metadata;
+use crate::parser::{parse_metadata_with_max_states, DreamMakerIconMetadata};
+use crate::sprite_test::run_sprite_tests;
+use crate::suggest::did_you_mean;
+#[cfg(feature = "cli")]
+use crate::timings::{finish_file, start_file};
+use crate::timings::{time_phase, Phase};
+use crate::transform::{apply_frame_ops, parse_frame_ops, FrameOp};
+use crate::validate::{find_warnings, validate_metadata, warn_for_unknown_version};
+#[cfg(feature = "cli")]
+use crate::walk::{create_parent_dir, relocate};
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packing {
+    Square,
+    Row,
+    Width(u32), // frames per row
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PadWith {
+    /// transparent frames
+    #[default]
+    Transparent,
+    /// magenta and black checkerboard frames, which stand out in a preview
+    Checker,
+}
+
+// what compile_yaml needs to know to turn a .dmi.yml into a .dmi; the
+// command line fills these in from CompileArgs
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    pub normalize_metadata: bool,
+    pub no_palette: bool,
+    pub metadata_chunk: MetadataChunk,
+    pub pack: Option<Packing>,
+    pub pad_missing: bool,
+    pub pad_with: PadWith,
+    pub max_frame_bytes: Option<usize>,
+    pub max_states: Option<usize>,
+    pub define: Vec<(String, String)>,
+}
+
+#[cfg(feature = "cli")]
+impl From<&CompileArgs> for CompileOptions {
+    fn from(args: &CompileArgs) -> CompileOptions {
+        CompileOptions {
+            normalize_metadata: args.normalize_metadata,
+            no_palette: args.no_palette,
+            metadata_chunk: args.metadata_chunk.clone(),
+            pack: args.pack.clone(),
+            pad_missing: args.pad_missing,
+            pad_with: args.pad_with.clone(),
+            max_frame_bytes: args.max_frame_bytes,
+            max_states: args.max_states,
+            define: args.define.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
 pub fn compile(args: &CompileArgs) -> Result<()> {
+    // a depfile needs a file to name as the target
+    if args.depfile.is_some() && args.output.as_deref() == Some(STDOUT_PATH) {
+        return Err(IconToolError::InvalidArgument(
+            "--depfile can't be used when writing to stdout".to_string(),
+        ));
+    }
+    let outputs = compile_files(args)?;
+    if let Some(depfile) = &args.depfile {
+        let path = PathBuf::from(&args.file);
+        let mut prerequisites = find_includes(&path)?;
+        prerequisites.insert(0, path);
+        write_depfile(Path::new(depfile), &outputs, &prerequisites)?;
+    }
+    Ok(())
+}
+
+// compile, and tell the caller which files were written
+#[cfg(feature = "cli")]
+pub fn compile_files(args: &CompileArgs) -> Result<Vec<PathBuf>> {
     // determine the path to the provided .dmi.yml file
     let path = PathBuf::from(&args.file);
 
-    // read the yaml data from the provided file
-    let file = File::open(path)?;
-    let yaml_data: IndexMap<String, Value> = serde_yml::from_reader(file)?;
+    // compile the yaml data from the provided file, pointing any error
+    // about a key at the place in the file where the key is
+    let text = std::fs::read_to_string(&path)?;
+    start_file();
+    let result = compile_text(args, &path, &text).map_err(|x| locate_error(&args.file, &text, x));
+    finish_file(&args.file);
+    result
+}
+
+#[cfg(feature = "cli")]
+fn compile_text(args: &CompileArgs, path: &Path, text: &str) -> Result<Vec<PathBuf>> {
+    // add the keys of any files that the yaml includes
+    let (yaml, _) = resolve_includes(path, text)?;
+
+    // if requested, spread a sheet too big for one .dmi across several
+    if args.split {
+        let parts = split_yaml(&yaml, MAX_IMAGE_WIDTH, MAX_IMAGE_HEIGHT)?;
+        if parts.len() > 1 {
+            return compile_split(args, &yaml, &parts);
+        }
+    }
+
+    let mut diagnostics = Diagnostics::new();
+    let result = compile_yaml(&yaml, &args.into(), &mut diagnostics);
+    if matches!(result, Err(IconToolError::TooManyIconStates(..))) {
+        diagnostics
+            .warn("icontool: use --split to spread the icon_states across several .dmi files");
+    }
+
+    // tell the user about any warnings, even if compilation failed
+    diagnostics.report(args.deny_warnings && result.is_ok())?;
+    let dmi = result?;
+
+    // when only checking, everything has been done except the writing
+    if args.check {
+        return Ok(Vec::new());
+    }
+
+    // write the .dmi file, or pipe it to stdout
+    if args.output.as_deref() == Some(STDOUT_PATH) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&dmi)?;
+        stdout.flush()?;
+        return Ok(Vec::new());
+    }
+    let output_path = get_output_path(args, &yaml)?;
+    create_parent_dir(&output_path)?;
+    write_atomic(&output_path, dmi)?;
+
+    // return success to the caller
+    Ok(vec![output_path])
+}
+
+// one of the .dmi files that a split sheet is compiled into
+#[cfg(feature = "cli")]
+struct SplitPart {
+    states: Vec<String>,
+    pack: Packing,
+    yaml: String,
+}
+
+#[cfg(feature = "cli")]
+fn compile_split(args: &CompileArgs, yaml: &str, parts: &[SplitPart]) -> Result<Vec<PathBuf>> {
+    // several .dmi files can't share stdout
+    if args.output.as_deref() == Some(STDOUT_PATH) {
+        return Err(IconToolError::InvalidArgument(
+            "--split can't write to stdout".to_string(),
+        ));
+    }
+
+    // compile each part as a .dmi file of its own
+    let mut diagnostics = Diagnostics::new();
+    if yaml_has_tests(yaml) {
+        diagnostics.warn("icontool: sprite tests are not run on a split sheet");
+    }
+    let result: Result<Vec<Vec<u8>>> = parts
+        .iter()
+        .map(|part| {
+            let part_options = CompileOptions {
+                pack: Some(part.pack.clone()),
+                ..CompileOptions::from(args)
+            };
+            compile_yaml(&part.yaml, &part_options, &mut diagnostics)
+        })
+        .collect();
+
+    // tell the user about any warnings, even if compilation failed
+    diagnostics.report(args.deny_warnings && result.is_ok())?;
+    let dmis = result?;
+    if args.check {
+        return Ok(Vec::new());
+    }
+
+    // write foo.1.dmi, foo.2.dmi, ... next to where foo.dmi would go
+    let output_path = get_output_path(args, yaml)?;
+    create_parent_dir(&output_path)?;
+    let mut manifest_parts = Vec::new();
+    let mut written = Vec::new();
+    for (index, (part, dmi)) in parts.iter().zip(dmis).enumerate() {
+        let part_path = get_split_path(&output_path, &format!("{}.dmi", index + 1))?;
+        write_atomic(&part_path, dmi)?;
+        written.push(part_path.clone());
+        let file_name = part_path.file_name().unwrap_or_default().to_string_lossy();
+        let mut entry = IndexMap::new();
+        entry.insert("file", Value::from(file_name.to_string()));
+        entry.insert("states", Value::from(part.states.clone()));
+        manifest_parts.push(entry);
+    }
+
+    // describe the split in foo.split.yml, so tooling can find the parts
+    let mut manifest = IndexMap::new();
+    manifest.insert("source", Value::from(args.file.clone()));
+    manifest.insert("parts", serde_yml::to_value(manifest_parts)?);
+    let manifest_path = get_split_path(&output_path, "split.yml")?;
+    write_atomic(&manifest_path, serde_yml::to_string(&manifest)?)?;
+    written.push(manifest_path);
+    Ok(written)
+}
+
+// partition the icon_states, in order, into as few sheets of the given size
+// as will hold them; each part is a .dmi.yml of its own
+#[cfg(feature = "cli")]
+fn split_yaml(yaml: &str, sheet_width: u32, sheet_height: u32) -> Result<Vec<SplitPart>> {
+    check_duplicate_keys(yaml)?;
+    let yaml_data: IndexMap<String, Value> = serde_yml::from_str(yaml)?;
+    let dmi = parse_metadata(&yaml_data.get_string(DMI_METADATA_KEY)?)?;
+    validate_metadata(&dmi)?;
+
+    // measure how many frames fit on one sheet
+    let columns = sheet_width / dmi.width.max(1);
+    let rows = sheet_height / dmi.height.max(1);
+    if columns == 0 || rows == 0 {
+        return Err(IconToolError::TooManyIconStates(dmi.width, dmi.height));
+    }
+
+    // fill each sheet with as many whole icon_states as will fit
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut frames_used = 0;
+    for (index, state) in dmi.states.iter().enumerate() {
+        let frames = state.dirs * state.frames;
+        // an icon_state can't be split, so it must fit on a sheet by itself
+        if frames > columns * rows {
+            let height = frames.div_ceil(columns) * dmi.height;
+            return Err(IconToolError::TooManyIconStates(
+                columns * dmi.width,
+                height,
+            ));
+        }
+        if groups.is_empty() || frames_used + frames > columns * rows {
+            groups.push(Vec::new());
+            frames_used = 0;
+        }
+        groups
+            .last_mut()
+            .expect("a group was just pushed")
+            .push(index);
+        frames_used += frames;
+    }
+
+    // build a .dmi.yml for each sheet
+    let keys = dmi.state_keys();
+    let mut parts = Vec::new();
+    for group in groups {
+        let mut metadata = dmi.clone();
+        metadata.states = group.iter().map(|&x| dmi.states[x].clone()).collect();
+        let mut part = IndexMap::new();
+        part.insert(
+            DMI_METADATA_KEY.to_string(),
+            Value::from(metadata.to_string()),
+        );
+        if let Some(chunks) = yaml_data.get(PNG_CHUNKS_KEY) {
+            part.insert(PNG_CHUNKS_KEY.to_string(), chunks.clone());
+        }
+        // repeated names are numbered within the part, so the keys can change
+        let mut transforms = Vec::new();
+        for (&index, key) in group.iter().zip(metadata.state_keys()) {
+            let mut frames = Vec::new();
+            for frame in get_state_frames(&yaml_data, &keys[index])? {
+                // a reference may point into another part, so resolve it now
+                frames.push(resolve_frame_ref(&yaml_data, frame)?);
+            }
+            transforms.extend(get_split_transforms(&yaml_data, &keys[index], &key));
+            part.insert(key, Value::from(frames.join("\n")));
+        }
+        if !transforms.is_empty() {
+            part.insert(TRANSFORMS_KEY.to_string(), Value::Sequence(transforms));
+        }
+        // pack roughly square, without running past the bottom of the sheet
+        let frames: u32 = metadata.states.iter().map(|x| x.dirs * x.frames).sum();
+        let square = (frames as f64).sqrt().ceil() as u32;
+        let per_row = square.max(frames.div_ceil(rows)).clamp(1, columns);
+        parts.push(SplitPart {
+            states: metadata.states.iter().map(|x| x.name.clone()).collect(),
+            pack: Packing::Width(per_row),
+            yaml: serde_yml::to_string(&part)?,
+        });
+    }
+    Ok(parts)
+}
+
+// replace ${NAME} in every string of the value with the value of NAME
+fn interpolate_value(value: &Value, defines: &[(String, String)]) -> Result<Value> {
+    Ok(match value {
+        Value::String(text) => Value::from(interpolate(text, defines)?),
+        Value::Sequence(list) => Value::Sequence(
+            list.iter()
+                .map(|x| interpolate_value(x, defines))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Mapping(mapping) => {
+            let mut result = serde_yml::Mapping::new();
+            for (key, value) in mapping {
+                result.insert(
+                    interpolate_value(key, defines)?,
+                    interpolate_value(value, defines)?,
+                );
+            }
+            Value::Mapping(result)
+        }
+        _ => value.clone(),
+    })
+}
+
+fn interpolate(text: &str, defines: &[(String, String)]) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        // an unfinished ${ is left alone, for whatever reads it to complain about
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + end];
+        // a later --define of the same name wins
+        let value = defines
+            .iter()
+            .rev()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| IconToolError::UndefinedVariable(name.to_string()))?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+// the __transforms entries of an icon_state, under its key within a part
+#[cfg(feature = "cli")]
+fn get_split_transforms(yaml: &IndexMap<String, Value>, key: &str, part_key: &str) -> Vec<Value> {
+    let Some(entries) = yaml.get(TRANSFORMS_KEY).and_then(Value::as_sequence) else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .filter(|x| x.get("state").and_then(Value::as_str) == Some(key))
+        .map(|x| {
+            let mut entry = x.clone();
+            entry["state"] = Value::from(part_key);
+            entry
+        })
+        .collect()
+}
+
+// the path of a file alongside a split sheet; foo.dmi becomes foo.<suffix>
+#[cfg(feature = "cli")]
+fn get_split_path(output_path: &Path, suffix: &str) -> Result<PathBuf> {
+    let file_stem = output_path
+        .file_stem()
+        .ok_or_else(|| IconToolError::PathError("Failed to get file stem".to_string()))?
+        .to_string_lossy();
+    Ok(output_path.with_file_name(format!("{file_stem}.{suffix}")))
+}
+
+#[cfg(feature = "cli")]
+fn yaml_has_tests(yaml: &str) -> bool {
+    serde_yml::from_str::<IndexMap<String, Value>>(yaml).is_ok_and(|x| x.contains_key(TESTS_KEY))
+}
+
+// compile .dmi.yml text into the bytes of a .dmi file, without touching
+// the filesystem; any warnings are printed
+pub fn compile_bytes(yaml: &str) -> Result<Vec<u8>> {
+    let mut diagnostics = Diagnostics::new();
+    let result = compile_bytes_with_diagnostics(yaml, &mut diagnostics);
+    diagnostics.print();
+    result
+}
+
+// like compile_bytes, but the warnings are collected for the caller
+pub fn compile_bytes_with_diagnostics(
+    yaml: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<u8>> {
+    compile_yaml(yaml, &CompileOptions::default(), diagnostics)
+}
+
+fn compile_yaml(
+    yaml: &str,
+    options: &CompileOptions,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<u8>> {
+    // read the yaml data; serde_yml quietly keeps only one of a repeated
+    // key, so look for those first
+    let yaml_data: IndexMap<String, Value> = time_phase(Phase::YamlParse, || {
+        check_duplicate_keys(yaml)?;
+        Ok::<_, IconToolError>(serde_yml::from_str(yaml)?)
+    })?;
 
     // parse dmi metadata
-    let yaml_metadata = yaml_data.get_string(DMI_METADATA_KEY)?;
-    let dmi_metadata = parse_metadata(&yaml_metadata)?;
+    let mut yaml_metadata = yaml_data.get_string(DMI_METADATA_KEY)?;
+    let max_states = options.max_states.unwrap_or(DEFAULT_MAX_STATES);
+    let mut dmi_metadata = time_phase(Phase::MetadataParse, || {
+        parse_metadata_with_max_states(&yaml_metadata, max_states)
+    })?;
+    warn_for_unknown_version(&dmi_metadata, diagnostics);
+    validate_metadata(&dmi_metadata)?;
+    for warning in find_warnings(&dmi_metadata) {
+        diagnostics.warn_error(warning);
+    }
+
+    // if requested, regenerate the metadata from the parsed structure
+    if options.normalize_metadata {
+        dmi_metadata.normalize();
+        yaml_metadata = dmi_metadata.to_string();
+    }
 
     // measure the dimensions of the image to create our canvas
-    let (image_width, image_height) = get_image_dimensions(&yaml_data, &dmi_metadata)?;
+    let (image_width, image_height) =
+        get_image_dimensions(&yaml_data, &dmi_metadata, &options.pack, diagnostics)?;
     let mut image = DynamicImage::new_rgba8(image_width, image_height);
 
     // warn if any icon states specified in the yaml will not be used to paint
-    warn_for_unused_icon_states(&yaml_data, &dmi_metadata);
+    warn_for_unused_icon_states(&yaml_data, &dmi_metadata, diagnostics);
 
     // paint frames to the DynamicImage canvas
-    paint_frames(&yaml_data, &dmi_metadata, &mut image)?;
+    let paint_options = PaintOptions {
+        pad_with: options.pad_missing.then_some(&options.pad_with),
+        max_frame_bytes: options.max_frame_bytes.unwrap_or(DEFAULT_MAX_FRAME_BYTES),
+        transforms: get_transforms(&yaml_data, &dmi_metadata, &options.define)?,
+    };
+    time_phase(Phase::Paint, || {
+        paint_frames(
+            &yaml_data,
+            &dmi_metadata,
+            &paint_options,
+            &mut image,
+            diagnostics,
+        )
+    })?;
 
-    // write the .dmi file
-    let output_path = get_output_path(args)?;
-    write_dmi_file(&output_path, ZTXT_KEYWORD, &yaml_metadata, &image)?;
+    // evaluate any sprite tests declared in the yaml
+    if yaml_data.contains_key(TESTS_KEY) {
+        let tests = yaml_data.get_string_list(TESTS_KEY)?;
+        let failures = run_sprite_tests(&tests, &dmi_metadata, &image)?;
+        for failure in &failures {
+            diagnostics.warn(format!("icontool: sprite test failed: {failure}"));
+        }
+        if !failures.is_empty() {
+            return Err(IconToolError::SpriteTestFailed(failures.len()));
+        }
+    }
 
-    // return success to the caller
+    // bring along any extra PNG chunks from the original .dmi file
+    let mut chunks = Vec::new();
+    if yaml_data.contains_key(PNG_CHUNKS_KEY) {
+        for text in yaml_data.get_string_list(PNG_CHUNKS_KEY)? {
+            chunks.push(PngChunk::parse(&text)?);
+        }
+    }
+
+    // encode the .dmi file
+    let write_options = WriteOptions {
+        chunks,
+        metadata_chunk: options.metadata_chunk.clone(),
+        no_palette: options.no_palette,
+        ..Default::default()
+    };
+    time_phase(Phase::PngEncode, || {
+        encode_dmi(ZTXT_KEYWORD, &yaml_metadata, &image, &write_options)
+    })
+}
+
+// a bad merge can leave the same icon_state in the yaml twice; report the
+// first repeated top-level key along with the lines it appears on
+fn check_duplicate_keys(yaml: &str) -> Result<()> {
+    let mut lines: HashMap<String, usize> = HashMap::new();
+    for (index, line) in yaml.lines().enumerate() {
+        // only unindented lines can start a top-level key
+        if line.is_empty() || line.starts_with([' ', '\t', '#', '-', '.']) {
+            continue;
+        }
+        // let serde_yml deal with quoting; a block scalar on its own is empty
+        let Ok(entry) = serde_yml::from_str::<IndexMap<String, Value>>(line) else {
+            continue;
+        };
+        for key in entry.into_keys() {
+            if let Some(first) = lines.insert(key.clone(), index + 1) {
+                return Err(IconToolError::DuplicateKey(key, first, index + 1));
+            }
+        }
+    }
     Ok(())
 }
 
 fn get_image_dimensions(
     yaml: &IndexMap<String, Value>,
     dmi: &DreamMakerIconMetadata,
+    pack: &Option<Packing>,
+    diagnostics: &mut Diagnostics,
 ) -> Result<(u32, u32)> {
     // measure the dimensions of the icon
     let icon_width = dmi.width;
     let icon_height = dmi.height;
 
+    // determine how many frames we need; absurd counts saturate, so they
+    // fail the size check below instead of wrapping around to small ones
+    let mut frames_needed: u32 = 0;
+    for state in &dmi.states {
+        frames_needed = frames_needed.saturating_add(state.dirs.saturating_mul(state.frames));
+    }
+
+    // if the user asked for a particular layout, use it
+    if let Some(pack) = pack {
+        let frames = frames_needed.max(1);
+        let frames_per_row = match pack {
+            Packing::Square => (frames as f64).sqrt().ceil() as u32,
+            Packing::Row => frames,
+            Packing::Width(width) => *width,
+        };
+        let rows_per_image = frames.div_ceil(frames_per_row);
+        let image_width = frames_per_row.saturating_mul(icon_width);
+        let image_height = rows_per_image.saturating_mul(icon_height);
+        if image_width > MAX_IMAGE_WIDTH || image_height > MAX_IMAGE_HEIGHT {
+            return Err(IconToolError::TooManyIconStates(image_width, image_height));
+        }
+        return Ok((image_width, image_height));
+    }
+
     // measure the original width and height of the image
     let mut image_width = yaml.get_u32(IMAGE_WIDTH_KEY)?;
     let mut image_height = yaml.get_u32(IMAGE_HEIGHT_KEY)?;
 
-    // determine how many frames we need
-    let mut frames_needed = 0;
-    for state in &dmi.states {
-        frames_needed += state.dirs * state.frames;
-    }
-
     // determine how many frames we have available
     let frames_per_row = image_width / icon_width;
     let rows_per_image = image_height / icon_height;
@@ -88,20 +577,22 @@ fn get_image_dimensions(
 
     // if we need more frames than we've got available
     if frames_needed >= frames_available {
-        // emit a warning to the user
-        eprintln!("Image dimensions {image_width}x{image_height} are not sufficient for {frames_needed} frames of icons sized {icon_width}x{icon_height}");
+        // remember the original dimensions for the warning
+        let (original_width, original_height) = (image_width, image_height);
 
         // calculate the new dimensions of the image
-        let pixels_square_needed = icon_width * icon_height * frames_needed;
+        let pixels_square_needed = icon_width
+            .saturating_mul(icon_height)
+            .saturating_mul(frames_needed);
         let pixels_needed = pixels_square_needed.sqrt();
         let frames_needed_per_row = (pixels_needed / icon_width) + 1;
-        let pixels_needed_per_row = frames_needed_per_row * icon_width;
+        let pixels_needed_per_row = frames_needed_per_row.saturating_mul(icon_width);
         image_width = pixels_needed_per_row; // note: always a multiple of icon_width
         let rows_needed = (frames_needed / frames_needed_per_row) + 1;
-        image_height = rows_needed * icon_height; // note: always a multiple of icon_height
+        image_height = rows_needed.saturating_mul(icon_height); // note: always a multiple of icon_height
 
         // tell the user that we've increased the dimensions
-        eprintln!("Image dimensions increased to {image_width}x{image_height}");
+        diagnostics.warn(format!("icontool: image dimensions {original_width}x{original_height} are not sufficient for {frames_needed} frames of icons sized {icon_width}x{icon_height}; increased to {image_width}x{image_height}"));
     }
 
     // do a final sanity check
@@ -113,12 +604,24 @@ fn get_image_dimensions(
     Ok((image_width, image_height))
 }
 
-fn get_output_path(args: &CompileArgs) -> Result<PathBuf> {
+#[cfg(feature = "cli")]
+fn get_output_path(args: &CompileArgs, yaml: &str) -> Result<PathBuf> {
     // if we were provided an output, just use it
     if let Some(output) = &args.output {
         return Ok(PathBuf::from(output));
     }
 
+    // if requested, write back to the path that decompile recorded
+    if args.use_recorded_path {
+        let yaml_data: IndexMap<String, Value> = serde_yml::from_str(yaml)?;
+        let recorded_path = yaml_data.get_string(DMI_PATH_KEY)?;
+        let base = match &args.base {
+            Some(base) => PathBuf::from(base),
+            None => find_repo_root(Path::new(&args.file)),
+        };
+        return Ok(base.join(recorded_path));
+    }
+
     // otherwise, compute an output path based on the input path
     let file_stem = Path::new(&args.file)
         .file_stem()
@@ -134,14 +637,46 @@ fn get_output_path(args: &CompileArgs) -> Result<PathBuf> {
     file_path.push(file_stem);
     file_path.set_extension("dmi");
 
-    Ok(file_path)
+    // if we were provided an output directory, move the output under it
+    match &args.output_dir {
+        Some(output_dir) => relocate(&file_path, output_dir, &args.strip_prefix),
+        None => Ok(file_path),
+    }
+}
+
+// find the root of the repository holding the file, by looking for a .git
+// directory above it; outside of a repository, use the current directory
+#[cfg(feature = "cli")]
+fn find_repo_root(file: &Path) -> PathBuf {
+    if let Ok(path) = std::fs::canonicalize(file) {
+        for dir in path.ancestors().skip(1) {
+            if dir.join(".git").exists() {
+                return dir.to_path_buf();
+            }
+        }
+    }
+    PathBuf::from(".")
+}
+
+// how paint_frames treats frames that aren't what the metadata expects
+struct PaintOptions<'a> {
+    // pad an icon_state with too few frames, instead of failing
+    pad_with: Option<&'a PadWith>,
+    // refuse to decompress a frame to more than this many bytes
+    max_frame_bytes: usize,
+    // the ops declared under __transforms, by icon_state key
+    transforms: HashMap<String, Vec<FrameOp>>,
 }
 
 fn paint_frames(
     yaml: &IndexMap<String, Value>,
     dmi: &DreamMakerIconMetadata,
+    options: &PaintOptions,
     image: &mut DynamicImage,
+    diagnostics: &mut Diagnostics,
 ) -> Result<()> {
+    let pad_with = options.pad_with;
+
     // measure the dimensions of the image
     let image_width = image.width();
     let image_height = image.height();
@@ -149,31 +684,95 @@ fn paint_frames(
     // measure the dimensions of the icon
     let icon_width = dmi.width;
     let icon_height = dmi.height;
+    let row_bytes = icon_width as usize * 4;
 
     // as we iterate, we need to keep track of our position
     let mut cursor_x = 0;
     let mut cursor_y = 0;
 
     // for each icon_state in the dmi metadata
-    for state in &dmi.states {
+    for (state, key) in dmi.states.iter().zip(dmi.state_keys()) {
         // read the frame data from the yaml
-        let frames_base64 = yaml.get_icon_state_frames(&state.name)?;
+        let frames_base64 = get_state_frames(yaml, &key)?;
         // determine the number of frames we expect
         let expected_frames = (state.dirs * state.frames) as usize;
         // determine the number of frames we got
         let actual_frames = frames_base64.len();
         // if we didn't get what we expect
-        if expected_frames != actual_frames {
-            // tell the user which icon_state doesn't match between yaml and metadata
-            return Err(IconToolError::FrameCountMismatch(
-                state.name.to_string(),
-                expected_frames,
-                actual_frames,
-            ));
+        match pad_with {
+            Some(pad_with) if actual_frames < expected_frames => {
+                let pad = match pad_with {
+                    PadWith::Transparent => "transparent",
+                    PadWith::Checker => "checkerboard",
+                };
+                diagnostics.warn(format!("icontool: icon_state '{key}' has {actual_frames} of its {expected_frames} frame(s); padding it with {pad} frames"));
+            }
+            _ if expected_frames != actual_frames => {
+                // tell the user which icon_state doesn't match between yaml and metadata
+                return Err(IconToolError::FrameCountMismatch(
+                    key,
+                    expected_frames,
+                    actual_frames,
+                ));
+            }
+            _ => {}
+        }
+
+        // decode each frame
+        let mut frames_base64 = frames_base64.into_iter();
+        let mut frames = Vec::with_capacity(expected_frames);
+        for index in 0..expected_frames {
+            let frame_pixel_data = match (frames_base64.next(), pad_with) {
+                (Some(frame_base64), _) => {
+                    // follow a reference to the frame it repeats
+                    let frame_base64 = resolve_frame_ref(yaml, frame_base64)?;
+                    // decode the base64 to compressed pixel data
+                    let frame_pixel_data_compressed =
+                        time_phase(Phase::FrameDecode, || BASE64_STANDARD.decode(frame_base64))?;
+                    // lz4_flex allocates the size the data claims, so check it first
+                    let size = get_decompressed_size(&frame_pixel_data_compressed);
+                    if size > options.max_frame_bytes {
+                        let max = options.max_frame_bytes;
+                        return Err(IconToolError::FrameTooLarge(key, size, max));
+                    }
+                    // decompress pixel data to flat rgba pixel data
+                    let frame_pixel_data = time_phase(Phase::FrameDecode, || {
+                        decompress_size_prepended(&frame_pixel_data_compressed)
+                    })?;
+                    // the rows are copied out below, so the frame has to be exactly one icon
+                    let frame_bytes = row_bytes * icon_height as usize;
+                    if frame_pixel_data.len() != frame_bytes {
+                        return Err(IconToolError::FrameSizeMismatch(
+                            key,
+                            index,
+                            frame_bytes,
+                            frame_pixel_data.len(),
+                        ));
+                    }
+                    frame_pixel_data
+                }
+                // pad out an icon_state that is missing frames
+                (None, Some(PadWith::Checker)) => checker_frame(icon_width, icon_height),
+                (None, _) => vec![0; row_bytes * icon_height as usize],
+            };
+            frames.push(frame_pixel_data);
+        }
+
+        // apply any ops declared for the icon_state
+        if let Some(ops) = options.transforms.get(&key) {
+            let images = frames
+                .into_iter()
+                .map(|x| RgbaImage::from_raw(icon_width, icon_height, x))
+                .collect::<Option<Vec<RgbaImage>>>()
+                .expect("each frame is exactly one icon");
+            frames = apply_frame_ops(images, state.dirs as usize, ops)
+                .into_iter()
+                .map(RgbaImage::into_raw)
+                .collect();
         }
 
         // for each frame
-        for frame_base64 in frames_base64 {
+        for frame_pixel_data in frames {
             // if cursor_y has already reached the complete height of the image
             if cursor_y >= image_height {
                 // we have nowhere to paint this frame; so error out
@@ -182,23 +781,16 @@ fn paint_frames(
                 // frames, but it did not do so!
                 return Err(IconToolError::TooManyFrames());
             }
-            // decode the base64 to compressed pixel data
-            let frame_pixel_data_compressed = BASE64_STANDARD.decode(frame_base64)?;
-            // decompress pixel data to flat rgba pixel data
-            let frame_pixel_data = decompress_size_prepended(&frame_pixel_data_compressed)?;
-            // write the pixels of the frame to the image buffer
+            // copy the frame into the image buffer one row at a time
             let buffer = image.as_mut_rgba8().expect("Failed to convert to RGBA8");
-            for y in 0..icon_height {
-                for x in 0..icon_width {
-                    let index = ((y * icon_width + x) * 4) as usize;
-                    let pixel = Rgba([
-                        frame_pixel_data[index],
-                        frame_pixel_data[index + 1],
-                        frame_pixel_data[index + 2],
-                        frame_pixel_data[index + 3],
-                    ]);
-                    buffer.put_pixel(cursor_x + x, cursor_y + y, pixel);
-                }
+            let raw: &mut [u8] = buffer;
+            let rows = frame_pixel_data
+                .chunks_exact(row_bytes)
+                .take(icon_height as usize);
+            for (y, row) in rows.enumerate() {
+                let start =
+                    ((cursor_y as usize + y) * image_width as usize + cursor_x as usize) * 4;
+                raw[start..start + row_bytes].copy_from_slice(row);
             }
             // update the cursor
             cursor_x += icon_width;
@@ -213,7 +805,114 @@ fn paint_frames(
     Ok(())
 }
 
-fn warn_for_unused_icon_states(yaml: &IndexMap<String, Value>, dmi: &DreamMakerIconMetadata) {
+// the size of lz4 data once decompressed, which lz4_flex writes before it
+// as a little-endian u32; data too short to hold it is left to fail later
+fn get_decompressed_size(compressed: &[u8]) -> usize {
+    match compressed.first_chunk::<4>() {
+        Some(bytes) => u32::from_le_bytes(*bytes) as usize,
+        None => 0,
+    }
+}
+
+// a magenta and black checkerboard, like a missing texture in a game, so
+// padded frames stand out in a preview
+fn checker_frame(width: u32, height: u32) -> Vec<u8> {
+    let square = (width.min(height) / 4).max(1);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            match (x / square + y / square) % 2 {
+                0 => pixels.extend_from_slice(&[255, 0, 255, 255]),
+                _ => pixels.extend_from_slice(&[0, 0, 0, 255]),
+            }
+        }
+    }
+    pixels
+}
+
+// read the frames of an icon_state from the yaml; if the key is missing,
+// suggest the closest one, since a stray space or quote is the usual cause
+fn get_state_frames(yaml: &IndexMap<String, Value>, key: &str) -> Result<Vec<String>> {
+    if yaml.contains_key(key) {
+        return yaml.get_icon_state_frames(key);
+    }
+    let candidates = yaml
+        .keys()
+        .map(String::as_str)
+        .filter(|x| !ICONTOOL_KEYS.contains(x));
+    let message = match did_you_mean(key, candidates) {
+        Some(hint) => format!("Key {key:?} is missing; {hint}"),
+        None => format!("Key {key:?} is missing"),
+    };
+    Err(IconToolError::MissingKey(message))
+}
+
+// read the __transforms section: a list of icon_states, each with the ops
+// to apply to its frames while painting; ${NAME} is replaced by a --define
+fn get_transforms(
+    yaml: &IndexMap<String, Value>,
+    dmi: &DreamMakerIconMetadata,
+    defines: &[(String, String)],
+) -> Result<HashMap<String, Vec<FrameOp>>> {
+    let mut transforms: HashMap<String, Vec<FrameOp>> = HashMap::new();
+    let Some(entries) = yaml.get(TRANSFORMS_KEY) else {
+        return Ok(transforms);
+    };
+    let invalid = |reason: &str| IconToolError::InvalidTransform(reason.to_string());
+    let entries = entries
+        .as_sequence()
+        .ok_or_else(|| invalid("__transforms must be a list"))?;
+    let keys = dmi.state_keys();
+    for entry in entries {
+        let entry = interpolate_value(entry, defines)?;
+        let key = entry
+            .get("state")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("each of __transforms needs a state"))?;
+        if !keys.iter().any(|x| x == key) {
+            return Err(IconToolError::StateNotFound(key.to_string()));
+        }
+        let ops = entry
+            .get("ops")
+            .ok_or_else(|| invalid(&format!("the __transforms of '{key}' need ops")))?;
+        let ops = parse_frame_ops(ops, dmi.width, dmi.height)?;
+        transforms.entry(key.to_string()).or_default().extend(ops);
+    }
+    Ok(transforms)
+}
+
+// a frame written as "@ref:state[2]" repeats the third frame of "state"
+fn resolve_frame_ref(yaml: &IndexMap<String, Value>, frame: String) -> Result<String> {
+    let mut frame = frame;
+    let mut seen = HashSet::new();
+    while let Some(frame_ref) = frame.strip_prefix(FRAME_REF_PREFIX) {
+        let frame_ref = frame_ref.to_string();
+        let invalid = |reason: &str| IconToolError::InvalidFrameRef(format!("{frame}: {reason}"));
+        if !seen.insert(frame_ref.clone()) {
+            return Err(invalid("the references form a loop"));
+        }
+        let (key, index) = frame_ref
+            .strip_suffix(']')
+            .and_then(|x| x.rsplit_once('['))
+            .ok_or_else(|| invalid("expected @ref:state[index]"))?;
+        let index: usize = index
+            .parse()
+            .map_err(|_| invalid("the index is not a number"))?;
+        let frames = get_state_frames(yaml, key)?;
+        let next = frames
+            .get(index)
+            .ok_or_else(|| invalid(&format!("'{key}' has only {} frame(s)", frames.len())))?
+            .clone();
+        frame = next;
+    }
+    Ok(frame)
+}
+
+fn warn_for_unused_icon_states(
+    yaml: &IndexMap<String, Value>,
+    dmi: &DreamMakerIconMetadata,
+    diagnostics: &mut Diagnostics,
+) {
     // collect up all the keys from the yaml
     let mut keys: HashSet<String> = yaml.keys().cloned().collect();
     // remove keys used by icontool
@@ -221,42 +920,19 @@ fn warn_for_unused_icon_states(yaml: &IndexMap<String, Value>, dmi: &DreamMakerI
         keys.remove(key);
     }
     // remove keys referenced by the dmi metadata
-    for state in &dmi.states {
-        keys.remove(&state.name);
+    for key in dmi.state_keys() {
+        keys.remove(&key);
     }
     // if there is anything left in our list
     if !keys.is_empty() {
-        eprintln!(
+        diagnostics.warn(format!(
             "icontool: {} icon_state(s) in the yaml are unused in the .dmi metadata: {:?}",
             keys.len(),
             keys
-        );
+        ));
     }
 }
 
-fn write_dmi_file(path: &PathBuf, keyword: &str, text: &str, image: &DynamicImage) -> Result<()> {
-    // create the .dmi file
-    let file = File::create(path)?;
-    let bufwriter = BufWriter::new(file);
-
-    // use the PNG encoder to create the metadata
-    let width = image.width();
-    let height = image.height();
-    let mut encoder = Encoder::new(bufwriter, width, height);
-    encoder.set_color(png::ColorType::Rgba);
-    encoder.set_depth(png::BitDepth::Eight);
-    encoder.add_ztxt_chunk(keyword.to_string(), text.to_string())?;
-
-    // write the PNG header and image data
-    let mut writer = encoder.write_header()?;
-    writer.write_image_data(image.as_bytes())?;
-
-    // flush the correctness-verified PNG out to disk
-    writer.finish()?;
-
-    Ok(())
-}
-
 //---------------------------------------------------------------------------
 //---------------------------------------------------------------------------
 //---------------------------------------------------------------------------
@@ -264,6 +940,10 @@ fn write_dmi_file(path: &PathBuf, keyword: &str, text: &str, image: &DynamicImag
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "cli")]
+    use crate::dmi_file::DmiFile;
+    #[cfg(feature = "cli")]
+    use std::fs::File;
 
     #[test]
     fn test_always_succeed() {
@@ -271,36 +951,43 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_compile_default() {
         let args = CompileArgs {
             output: None,
             file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
         };
         let _ = compile(&args);
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_compile_output() {
         let args = CompileArgs {
             output: Some(String::from("tests/data/compile/neckbeard.dmi")),
             file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
         };
         let _ = compile(&args);
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_compile_failed_u32_conversion() {
         let args = CompileArgs {
             output: None,
             file: String::from("tests/data/compile/u33.dmi.yml"),
+            ..Default::default()
         };
         match compile(&args) {
             Err(x) => match x {
-                IconToolError::InvalidType(_) => {
-                    return;
+                IconToolError::AtLocation(path, 2, 1, x) => {
+                    assert_eq!("tests/data/compile/u33.dmi.yml", path);
+                    assert!(matches!(*x, IconToolError::InvalidType(_)));
                 }
                 _ => {
-                    panic!("test_compile_failed_u32_conversion: Expected InvalidType error")
+                    panic!("test_compile_failed_u32_conversion: Expected AtLocation error")
                 }
             },
             _ => {
@@ -308,4 +995,724 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_sprite_tests_pass() {
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/sprite_test.out.dmi")),
+            file: String::from("tests/data/compile/sprite_test.dmi.yml"),
+            ..Default::default()
+        };
+        assert!(compile(&args).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_sprite_tests_fail() {
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/sprite_test_fail.out.dmi")),
+            file: String::from("tests/data/compile/sprite_test_fail.dmi.yml"),
+            ..Default::default()
+        };
+        match compile(&args) {
+            Err(IconToolError::SpriteTestFailed(2)) => {}
+            _ => panic!("test_compile_sprite_tests_fail: Expected SpriteTestFailed error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_duplicate_state_names() {
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/duplicate.out.dmi")),
+            file: String::from("tests/data/compile/duplicate.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let path = PathBuf::from("tests/data/compile/duplicate.out.dmi");
+        let dmi = parse_metadata(&crate::dmi::read_metadata(&path).unwrap()).unwrap();
+        assert_eq!("bluetie", dmi.states[0].name);
+        assert_eq!("bluetie", dmi.states[1].name);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_no_palette() {
+        let path = PathBuf::from("tests/data/compile/palette.out.dmi");
+        let mut args = CompileArgs {
+            output: Some(path.to_string_lossy().to_string()),
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let indexed = crate::dmi::read_image(&path).unwrap().to_rgba8();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        assert_eq!(
+            png::ColorType::Indexed,
+            decoder.read_info().unwrap().info().color_type
+        );
+        args.no_palette = true;
+        compile(&args).unwrap();
+        let rgba = crate::dmi::read_image(&path).unwrap().to_rgba8();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        assert_eq!(
+            png::ColorType::Rgba,
+            decoder.read_info().unwrap().info().color_type
+        );
+        assert_eq!(indexed, rgba);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_preserves_png_chunks() {
+        let original = PathBuf::from("tests/data/compile/chunks.dmi");
+        let yaml = "tests/data/compile/chunks.out.dmi.yml";
+        let decompile_args = crate::cmdline::DecompileArgs {
+            output: Some(String::from(yaml)),
+            file: original.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        crate::decompile::decompile(&decompile_args).unwrap();
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/chunks.out.dmi")),
+            file: String::from(yaml),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let path = PathBuf::from("tests/data/compile/chunks.out.dmi");
+        let chunks = crate::dmi::read_png_chunks(&path).unwrap();
+        assert_eq!(3, chunks.len());
+        assert_eq!(crate::dmi::read_png_chunks(&original).unwrap(), chunks);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_deny_warnings() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap() + "leftover: ''\n";
+        let mut diagnostics = Diagnostics::new();
+        compile_bytes_with_diagnostics(&yaml, &mut diagnostics).unwrap();
+        assert_eq!(1, diagnostics.warnings().len());
+        assert!(diagnostics.warnings()[0].contains("leftover"));
+
+        let yaml_path = "tests/data/compile/leftover.out.dmi.yml";
+        let output = PathBuf::from("tests/data/compile/leftover.out.dmi");
+        std::fs::write(yaml_path, yaml).unwrap();
+        let _ = std::fs::remove_file(&output);
+        let mut args = CompileArgs {
+            output: Some(output.to_string_lossy().to_string()),
+            deny_warnings: true,
+            file: String::from(yaml_path),
+            ..Default::default()
+        };
+        match compile(&args) {
+            Err(IconToolError::WarningsDenied(1)) => {}
+            _ => panic!("test_compile_deny_warnings: Expected WarningsDenied error"),
+        }
+        assert!(!output.exists());
+        args.deny_warnings = false;
+        compile(&args).unwrap();
+        assert!(output.exists());
+    }
+
+    #[test]
+    fn test_resolve_frame_ref() {
+        let yaml: IndexMap<String, Value> =
+            serde_yml::from_str("a: \"AAAA\\n@ref:b[0]\"\nb: \"@ref:a[0]\"\nc: \"@ref:c[0]\"\n")
+                .unwrap();
+        let resolve = |text: &str| resolve_frame_ref(&yaml, text.to_string());
+        assert_eq!("AAAA", resolve("@ref:b[0]").unwrap());
+        assert_eq!("AAAA", resolve("@ref:a[1]").unwrap());
+        for text in ["@ref:c[0]", "@ref:a[2]", "@ref:a", "@ref:a[x]"] {
+            match resolve(text) {
+                Err(IconToolError::InvalidFrameRef(_)) => {}
+                _ => panic!("test_resolve_frame_ref: Expected InvalidFrameRef error"),
+            }
+        }
+        match resolve("@ref:missing[0]") {
+            Err(IconToolError::MissingKey(_)) => {}
+            _ => panic!("test_resolve_frame_ref: Expected MissingKey error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_normalize_metadata() {
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/normalize.out.dmi")),
+            normalize_metadata: true,
+            file: String::from("tests/data/compile/normalize.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let path = PathBuf::from("tests/data/compile/normalize.out.dmi");
+        let text = crate::dmi::read_metadata(&path).unwrap();
+        assert_eq!(
+            "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"bluetie\"\n\tdirs = 1\n\tframes = 2\n\tdelay = 1,1.5\n# END DMI\n",
+            text
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_pack() {
+        std::fs::create_dir_all("tests/data/compile").unwrap();
+        let path = PathBuf::from("tests/data/compile/pack.out.dmi");
+        let mut args = CompileArgs {
+            output: Some(path.to_string_lossy().to_string()),
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let original = DmiFile::open(&path).unwrap();
+        for (pack, width, height) in [
+            (Packing::Square, 256, 256),
+            (Packing::Row, 1856, 32),
+            (Packing::Width(16), 512, 128),
+        ] {
+            args.pack = Some(pack);
+            compile(&args).unwrap();
+            let image = crate::dmi::read_rgba_image(&path).unwrap();
+            assert_eq!((width, height), image.dimensions());
+            let packed = DmiFile::open(&path).unwrap();
+            for state in 0..58 {
+                assert_eq!(original.frame(state, 0, 0), packed.frame(state, 0, 0));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_check() {
+        let output = PathBuf::from("tests/data/compile/check.out.dmi");
+        let _ = std::fs::remove_file(&output);
+        let mut args = CompileArgs {
+            output: Some(output.to_string_lossy().to_string()),
+            check: true,
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        assert!(!output.exists());
+        args.file = String::from("tests/data/compile/sprite_test_fail.dmi.yml");
+        assert!(compile(&args).is_err());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_output_dir() {
+        let output = PathBuf::from("tests/data/compile/build.out.dir/neck.dmi");
+        let _ = std::fs::remove_file(&output);
+        let args = CompileArgs {
+            output_dir: Some(String::from("tests/data/compile/build.out.dir")),
+            strip_prefix: Some(String::from("tests/data/compile")),
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        assert_eq!(output, get_output_path(&args, "").unwrap());
+        compile(&args).unwrap();
+        assert!(output.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_use_recorded_path() {
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        let yaml = yaml.replace(
+            "__dmi_path: tests/data/decompile/neck.dmi",
+            "__dmi_path: recorded.out.dmi",
+        );
+        std::fs::create_dir_all("tests/data/compile").unwrap();
+        std::fs::write("tests/data/compile/recorded.out.dmi.yml", &yaml).unwrap();
+        let output = PathBuf::from("tests/data/compile/recorded.out.dmi");
+        let _ = std::fs::remove_file(&output);
+        let args = CompileArgs {
+            use_recorded_path: true,
+            base: Some(String::from("tests/data/compile")),
+            file: String::from("tests/data/compile/recorded.out.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        assert!(output.exists());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_output_path_recorded() {
+        let yaml = "__dmi_path: icons/mob/neck.dmi\n";
+        let args = CompileArgs {
+            use_recorded_path: true,
+            base: Some(String::from("code")),
+            file: String::from("yaml/neck.dmi.yml"),
+            ..Default::default()
+        };
+        assert_eq!(
+            PathBuf::from("code/icons/mob/neck.dmi"),
+            get_output_path(&args, yaml).unwrap()
+        );
+        let args = CompileArgs {
+            use_recorded_path: true,
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        let output_path = get_output_path(&args, yaml).unwrap();
+        assert!(output_path.ends_with("icons/mob/neck.dmi"));
+        assert!(get_output_path(&args, "__image_width: 32\n").is_err());
+    }
+
+    #[test]
+    fn test_get_state_frames_suggestion() {
+        let yaml = "__image_width: 32\n'opened ': AAAA\nclosed: AAAA\n";
+        let yaml: IndexMap<String, Value> = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(vec!["AAAA"], get_state_frames(&yaml, "closed").unwrap());
+        match get_state_frames(&yaml, "opened") {
+            Err(IconToolError::MissingKey(x)) => assert_eq!(
+                "Key \"opened\" is missing; did you mean 'opened '? note the trailing space",
+                x
+            ),
+            _ => panic!("test_get_state_frames_suggestion: Expected MissingKey error"),
+        }
+        match get_state_frames(&yaml, "__image_widt") {
+            Err(IconToolError::MissingKey(x)) => assert_eq!("Key \"__image_widt\" is missing", x),
+            _ => panic!("test_get_state_frames_suggestion: Expected MissingKey error"),
+        }
+    }
+
+    #[test]
+    fn test_check_duplicate_keys() {
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        assert!(check_duplicate_keys(&yaml).is_ok());
+
+        let yaml =
+            "__image_width: 32\nopen: |-\n  AAAA\n  AAAA\nclosed: AAAA\n'open': |-\n  BBBB\n";
+        match check_duplicate_keys(yaml) {
+            Err(IconToolError::DuplicateKey(key, first, second)) => {
+                assert_eq!("open", key);
+                assert_eq!(2, first);
+                assert_eq!(6, second);
+            }
+            _ => panic!("test_check_duplicate_keys: Expected DuplicateKey error"),
+        }
+        assert!(compile_bytes(yaml).is_err());
+
+        // the same name with different whitespace is a different key
+        let yaml = "open: AAAA\n'open ': AAAA\n";
+        assert!(check_duplicate_keys(yaml).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_split_yaml() {
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        let original = compile_bytes(&yaml).unwrap();
+        let original = crate::dmi_file::DmiFile::from_bytes(&original).unwrap();
+        // neck.dmi is 58 icon_states of 32x32 icons with a single frame each
+        let parts = split_yaml(&yaml, 160, 128).unwrap();
+        assert_eq!(3, parts.len());
+        assert_eq!(20, parts[0].states.len());
+        assert_eq!(20, parts[1].states.len());
+        assert_eq!(18, parts[2].states.len());
+        assert_eq!(Packing::Width(5), parts[0].pack);
+        let mut state = 0;
+        for part in &parts {
+            let options = CompileOptions {
+                pack: Some(part.pack.clone()),
+                ..Default::default()
+            };
+            let dmi = compile_yaml(&part.yaml, &options, &mut Diagnostics::new()).unwrap();
+            let image = crate::dmi::decode_image(&dmi).unwrap();
+            assert!(image.width() <= 160 && image.height() <= 128);
+            let dmi = crate::dmi_file::DmiFile::from_bytes(&dmi).unwrap();
+            for index in 0..part.states.len() {
+                assert_eq!(original.frame(state, 0, 0), dmi.frame(index, 0, 0));
+                state += 1;
+            }
+        }
+        assert_eq!(58, state);
+        // an icon_state bigger than a sheet can't be split
+        assert!(matches!(
+            split_yaml(&yaml, 32, 0),
+            Err(IconToolError::TooManyIconStates(..))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_split_path() {
+        let path = Path::new("build/foo.dmi");
+        assert_eq!(
+            PathBuf::from("build/foo.2.dmi"),
+            get_split_path(path, "2.dmi").unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("build/foo.split.yml"),
+            get_split_path(path, "split.yml").unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_split_fits() {
+        // a sheet that fits is compiled as usual
+        let output = PathBuf::from("tests/data/compile/split.out.dmi");
+        let args = CompileArgs {
+            output: Some(output.to_string_lossy().to_string()),
+            split: true,
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        assert!(output.exists());
+        assert!(!PathBuf::from("tests/data/compile/split.out.1.dmi").exists());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_split_options() {
+        // each part is compiled with the limits the user asked for
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        let parts = split_yaml(&yaml, 160, 128).unwrap();
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/split_options.out.dmi")),
+            max_frame_bytes: Some(4095),
+            split: true,
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        match compile_split(&args, &yaml, &parts) {
+            Err(IconToolError::FrameTooLarge(_, 4096, 4095)) => {}
+            _ => panic!("test_compile_split_options: Expected FrameTooLarge error"),
+        }
+        assert!(!PathBuf::from("tests/data/compile/split_options.out.1.dmi").exists());
+    }
+
+    #[test]
+    fn test_compile_pad_missing() {
+        // drop the last frame of the three frame "spin" animation
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let mut yaml_data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        let frames = yaml_data.get_icon_state_frames("spin").unwrap();
+        yaml_data.insert(String::from("spin"), Value::from(frames[..2].join("\n")));
+        let yaml = serde_yml::to_string(&yaml_data).unwrap();
+        let mut options = CompileOptions::default();
+        match compile_yaml(&yaml, &options, &mut Diagnostics::new()) {
+            Err(IconToolError::FrameCountMismatch(key, 3, 2)) => assert_eq!("spin", key),
+            _ => panic!("test_compile_pad_missing: Expected FrameCountMismatch error"),
+        }
+
+        let original = crate::dmi_file::DmiFile::from_bytes(&dmi).unwrap();
+        options.pad_missing = true;
+        for (pad_with, pixel) in [
+            (PadWith::Transparent, [0, 0, 0, 0]),
+            (PadWith::Checker, [255, 0, 255, 255]),
+        ] {
+            options.pad_with = pad_with;
+            let mut diagnostics = Diagnostics::new();
+            let padded = compile_yaml(&yaml, &options, &mut diagnostics).unwrap();
+            assert_eq!(1, diagnostics.warnings().len());
+            assert!(diagnostics.warnings()[0].contains("'spin' has 2 of its 3 frame(s)"));
+            let padded = crate::dmi_file::DmiFile::from_bytes(&padded).unwrap();
+            assert_eq!(original.frames[0][..2], padded.frames[0][..2]);
+            assert_eq!(&image::Rgba(pixel), padded.frames[0][2].get_pixel(0, 0));
+            assert_eq!(original.frames[1], padded.frames[1]);
+        }
+
+        // too many frames is still an error
+        let yaml = yaml.replace("frames = 3", "frames = 1");
+        assert!(compile_yaml(&yaml, &options, &mut Diagnostics::new()).is_err());
+    }
+
+    #[test]
+    fn test_checker_frame() {
+        let pixels = checker_frame(8, 8);
+        assert_eq!(8 * 8 * 4, pixels.len());
+        assert_eq!([255, 0, 255, 255], pixels[0..4]);
+        assert_eq!([0, 0, 0, 255], pixels[8..12]);
+        assert_eq!([0, 0, 0, 255], pixels[8 * 2 * 4..8 * 2 * 4 + 4]);
+    }
+
+    #[test]
+    fn test_compile_limits() {
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        let mut options = CompileOptions {
+            max_states: Some(10),
+            ..Default::default()
+        };
+        match compile_yaml(&yaml, &options, &mut Diagnostics::new()) {
+            Err(IconToolError::TooManyStates(58, 10)) => {}
+            _ => panic!("test_compile_limits: Expected TooManyStates error"),
+        }
+
+        // a 32x32 frame is 4096 bytes
+        options.max_states = None;
+        options.max_frame_bytes = Some(4096);
+        assert!(compile_yaml(&yaml, &options, &mut Diagnostics::new()).is_ok());
+        options.max_frame_bytes = Some(4095);
+        match compile_yaml(&yaml, &options, &mut Diagnostics::new()) {
+            Err(IconToolError::FrameTooLarge(_, 4096, 4095)) => {}
+            _ => panic!("test_compile_limits: Expected FrameTooLarge error"),
+        }
+    }
+
+    #[test]
+    fn test_get_decompressed_size() {
+        let compressed = lz4_flex::block::compress_prepend_size(&[0; 1000]);
+        assert_eq!(1000, get_decompressed_size(&compressed));
+        // a tiny blob can claim to be huge
+        assert_eq!(
+            u32::MAX as usize,
+            get_decompressed_size(&[255, 255, 255, 255, 0])
+        );
+        assert_eq!(0, get_decompressed_size(&[1, 2]));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_image_dimensions_absurd() {
+        let text = "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"big\"\n\tdirs = 8\n\tframes = 4000000000\n# END DMI\n";
+        let dmi = parse_metadata(text).unwrap();
+        let mut yaml = IndexMap::new();
+        yaml.insert(IMAGE_WIDTH_KEY.to_string(), Value::from(32));
+        yaml.insert(IMAGE_HEIGHT_KEY.to_string(), Value::from(32));
+        for pack in [None, Some(Packing::Square), Some(Packing::Row)] {
+            let result = get_image_dimensions(&yaml, &dmi, &pack, &mut Diagnostics::new());
+            assert!(matches!(result, Err(IconToolError::TooManyIconStates(..))));
+        }
+    }
+
+    #[test]
+    fn test_compile_frame_size_mismatch() {
+        // replace the second frame of "walk" with one that is too short
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let mut yaml_data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        let mut frames = yaml_data.get_icon_state_frames("walk").unwrap();
+        let short = lz4_flex::block::compress_prepend_size(&[0; 100]);
+        frames[1] = BASE64_STANDARD.encode(short);
+        yaml_data.insert(String::from("walk"), Value::from(frames.join("\n")));
+        let yaml = serde_yml::to_string(&yaml_data).unwrap();
+        match compile_bytes(&yaml) {
+            Err(IconToolError::FrameSizeMismatch(key, 1, 4096, 100)) => assert_eq!("walk", key),
+            _ => panic!("test_compile_frame_size_mismatch: Expected FrameSizeMismatch error"),
+        }
+    }
+
+    #[test]
+    fn test_compile_transforms() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let original = crate::dmi_file::DmiFile::from_bytes(&dmi).unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let transforms = "__transforms:\n- state: walk\n  ops: [flip_h, tint: \"#802020\"]\n";
+        let compiled = compile_bytes(&format!("{yaml}{transforms}")).unwrap();
+        let compiled = crate::dmi_file::DmiFile::from_bytes(&compiled).unwrap();
+        // the mirrored east frame now faces west, and is tinted
+        let expected = apply_frame_ops(
+            vec![original.frame(1, 1, 2).clone()],
+            1,
+            &[
+                FrameOp::Transform(crate::transform::Transform::FlipHorizontal),
+                FrameOp::Tint([0x80, 0x20, 0x20, 0xFF]),
+            ],
+        );
+        assert_eq!(&expected[0], compiled.frame(1, 1, 3));
+        assert_ne!(original.frame(1, 1, 3), compiled.frame(1, 1, 3));
+        // the other icon_states are untouched
+        assert_eq!(original.frames[0], compiled.frames[0]);
+        assert_eq!(original.frames[2], compiled.frames[2]);
+
+        let transforms = "__transforms:\n- state: run\n  ops: [flip_h]\n";
+        match compile_bytes(&format!("{yaml}{transforms}")) {
+            Err(IconToolError::StateNotFound(x)) => assert_eq!("run", x),
+            _ => panic!("test_compile_transforms: Expected StateNotFound error"),
+        }
+        let transforms = "__transforms:\n- state: walk\n  ops: [shear]\n";
+        match compile_bytes(&format!("{yaml}{transforms}")) {
+            Err(IconToolError::InvalidTransform(x)) => assert_eq!("unknown op 'shear'", x),
+            _ => panic!("test_compile_transforms: Expected InvalidTransform error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_split_yaml_transforms() {
+        let yaml = std::fs::read_to_string("tests/data/compile/neck.dmi.yml").unwrap();
+        let dmi = parse_metadata(
+            &serde_yml::from_str::<IndexMap<String, Value>>(&yaml)
+                .unwrap()
+                .get_string(DMI_METADATA_KEY)
+                .unwrap(),
+        )
+        .unwrap();
+        let last = dmi.state_keys().pop().unwrap();
+        let yaml = format!("{yaml}__transforms:\n- state: {last:?}\n  ops: [flip_v]\n");
+        let parts = split_yaml(&yaml, 160, 128).unwrap();
+        assert!(!parts[0].yaml.contains(TRANSFORMS_KEY));
+        let part: IndexMap<String, Value> = serde_yml::from_str(&parts[2].yaml).unwrap();
+        let entries = part[TRANSFORMS_KEY].as_sequence().unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(Some(last.as_str()), entries[0]["state"].as_str());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let defines = vec![
+            (String::from("TEAM_COLOR"), String::from("#ff0000")),
+            (String::from("SIDE"), String::from("red")),
+            (String::from("SIDE"), String::from("blue")),
+        ];
+        assert_eq!("#ff0000", interpolate("${TEAM_COLOR}", &defines).unwrap());
+        assert_eq!(
+            "sword_blue",
+            interpolate("sword_${SIDE}", &defines).unwrap()
+        );
+        assert_eq!(
+            "no variables",
+            interpolate("no variables", &defines).unwrap()
+        );
+        assert_eq!("${SIDE", interpolate("${SIDE", &defines).unwrap());
+        match interpolate("${TEAM}", &defines) {
+            Err(IconToolError::UndefinedVariable(x)) => assert_eq!("TEAM", x),
+            _ => panic!("test_interpolate: Expected UndefinedVariable error"),
+        }
+    }
+
+    #[test]
+    fn test_compile_define() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let yaml =
+            format!("{yaml}__transforms:\n- state: still\n  ops: [tint: \"${{TEAM_COLOR}}\"]\n");
+        let mut options = CompileOptions::default();
+        match compile_yaml(&yaml, &options, &mut Diagnostics::new()) {
+            Err(IconToolError::UndefinedVariable(x)) => assert_eq!("TEAM_COLOR", x),
+            _ => panic!("test_compile_define: Expected UndefinedVariable error"),
+        }
+        let mut teams = Vec::new();
+        for color in ["#ff0000", "#0000ff"] {
+            options.define = vec![(String::from("TEAM_COLOR"), color.to_string())];
+            let compiled = compile_yaml(&yaml, &options, &mut Diagnostics::new()).unwrap();
+            teams.push(crate::dmi_file::DmiFile::from_bytes(&compiled).unwrap());
+        }
+        assert_eq!(teams[0].frames[0], teams[1].frames[0]);
+        assert_ne!(teams[0].frames[2], teams[1].frames[2]);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_depfile() {
+        let args = CompileArgs {
+            output: Some(String::from("tests/data/compile/depfile.out.dmi")),
+            depfile: Some(String::from("tests/data/compile/depfile.out.d")),
+            file: String::from("tests/data/compile/neck.dmi.yml"),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let depfile = std::fs::read_to_string("tests/data/compile/depfile.out.d").unwrap();
+        assert_eq!(
+            "tests/data/compile/depfile.out.dmi: tests/data/compile/neck.dmi.yml\n\
+             tests/data/compile/neck.dmi.yml:\n",
+            depfile
+        );
+
+        let args = CompileArgs {
+            output: Some(String::from(STDOUT_PATH)),
+            ..args
+        };
+        match compile(&args) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_compile_depfile: Expected InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_includes() {
+        // move the "still" icon_state out to a shared file
+        let dir = Path::new("tests/data/compile/includes.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let mut yaml_data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        let mut shared = IndexMap::new();
+        shared.insert("still", yaml_data.shift_remove("still").unwrap());
+        std::fs::write(
+            dir.join("shared.yml"),
+            serde_yml::to_string(&shared).unwrap(),
+        )
+        .unwrap();
+        yaml_data.insert(String::from(INCLUDES_KEY), Value::from(vec!["shared.yml"]));
+        std::fs::write(
+            dir.join("spin.dmi.yml"),
+            serde_yml::to_string(&yaml_data).unwrap(),
+        )
+        .unwrap();
+
+        let args = CompileArgs {
+            output: Some(dir.join("spin.dmi").to_string_lossy().to_string()),
+            depfile: Some(dir.join("spin.d").to_string_lossy().to_string()),
+            file: dir.join("spin.dmi.yml").to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        compile(&args).unwrap();
+        let compiled = crate::dmi_file::DmiFile::open(&dir.join("spin.dmi")).unwrap();
+        let original = crate::dmi_file::DmiFile::from_bytes(&dmi).unwrap();
+        assert_eq!(original.frames, compiled.frames);
+        let depfile = std::fs::read_to_string(dir.join("spin.d")).unwrap();
+        assert!(depfile.starts_with(&format!(
+            "{}: {} {}\n",
+            dir.join("spin.dmi").display(),
+            dir.join("spin.dmi.yml").display(),
+            dir.join("shared.yml").display()
+        )));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_compile_error_location() {
+        let dir = Path::new("tests/data/compile/location.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = crate::decompile::decompile_bytes(&dmi).unwrap();
+        let line_of = |yaml: &str, prefix: &str| {
+            yaml.lines().position(|x| x.starts_with(prefix)).unwrap() + 1
+        };
+        let file = dir.join("spin.dmi.yml");
+        let args = CompileArgs {
+            output: Some(dir.join("spin.dmi").to_string_lossy().to_string()),
+            file: file.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        // an icon_state with a frame too few is pointed at by its key
+        let mut yaml_data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        let frames = yaml_data.get_string("spin").unwrap();
+        let frames = frames.rsplit_once('\n').unwrap().0;
+        yaml_data.insert(String::from("spin"), Value::from(frames));
+        let text = serde_yml::to_string(&yaml_data).unwrap();
+        std::fs::write(&file, &text).unwrap();
+        match compile(&args) {
+            Err(IconToolError::AtLocation(path, line, 1, x)) => {
+                assert_eq!(args.file, path);
+                assert_eq!(line_of(&text, "spin:"), line);
+                assert!(matches!(*x, IconToolError::FrameCountMismatch(..)));
+            }
+            _ => panic!("test_compile_error_location: Expected AtLocation error"),
+        }
+
+        // a missing icon_state is pointed at by the metadata that names it
+        let mut yaml_data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        yaml_data.shift_remove("walk");
+        let text = serde_yml::to_string(&yaml_data).unwrap();
+        std::fs::write(&file, &text).unwrap();
+        match compile(&args) {
+            Err(IconToolError::AtLocation(_, line, 1, x)) => {
+                assert_eq!(line_of(&text, DMI_METADATA_KEY), line);
+                assert!(matches!(*x, IconToolError::MissingKey(_)));
+            }
+            _ => panic!("test_compile_error_location: Expected AtLocation error"),
+        }
+    }
 }
@@ -0,0 +1,222 @@
+// composite.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{imageops, Rgba, RgbaImage};
+use std::path::PathBuf;
+
+use crate::cmdline::{OutlineArgs, OverlayArgs};
+use crate::dmi_file::{edit_dmi_file, DmiFile};
+use crate::edit::get_output_path;
+use crate::error::{IconToolError, Result};
+use crate::transform::select_states;
+
+pub fn outline(args: &OutlineArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        for index in select_states(sheet, &args.state)? {
+            for frame in &mut sheet.frames[index] {
+                *frame = outline_frame(frame, Rgba(args.color), args.thickness);
+            }
+        }
+        Ok(())
+    })
+}
+
+// grow the silhouette of the frame one pixel at a time, painting each
+// transparent pixel that touches it (up, down, left, or right)
+pub fn outline_frame(frame: &RgbaImage, color: Rgba<u8>, thickness: u32) -> RgbaImage {
+    let (width, height) = frame.dimensions();
+    let mut result = frame.clone();
+    let mut solid: Vec<bool> = frame.pixels().map(|pixel| pixel.0[3] != 0).collect();
+    for _ in 0..thickness {
+        let mut grown = solid.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if solid[index] {
+                    continue;
+                }
+                let touches = (x > 0 && solid[index - 1])
+                    || (x + 1 < width && solid[index + 1])
+                    || (y > 0 && solid[index - width as usize])
+                    || (y + 1 < height && solid[index + width as usize]);
+                if touches {
+                    result.put_pixel(x, y, color);
+                    grown[index] = true;
+                }
+            }
+        }
+        solid = grown;
+    }
+    result
+}
+
+pub fn overlay(args: &OverlayArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    // read the icon_state we're laying on top
+    let top = DmiFile::open(&PathBuf::from(&args.with.file))?;
+    let top_index = top
+        .metadata
+        .find_state(&args.with.state)
+        .ok_or_else(|| IconToolError::StateNotFound(args.with.state.clone()))?;
+    let top_state = &top.metadata.states[top_index];
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        // find the icon_state we're laying it onto
+        let index = sheet
+            .metadata
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &sheet.metadata.states[index];
+
+        // the overlay needs to line up with the base
+        if (top.metadata.width, top.metadata.height)
+            != (sheet.metadata.width, sheet.metadata.height)
+        {
+            return Err(IconToolError::InvalidOverlay(format!(
+                "icons are {}x{} in {}, but {}x{} in {}",
+                top.metadata.width,
+                top.metadata.height,
+                args.with.file,
+                sheet.metadata.width,
+                sheet.metadata.height,
+                args.file
+            )));
+        }
+        if top_state.dirs != 1 && top_state.dirs != state.dirs {
+            return Err(IconToolError::InvalidOverlay(format!(
+                "'{}' has {} dirs, but '{}' has {}",
+                args.with.state, top_state.dirs, args.state, state.dirs
+            )));
+        }
+        if top_state.frames != 1 && top_state.frames != state.frames {
+            return Err(IconToolError::InvalidOverlay(format!(
+                "'{}' has {} frames, but '{}' has {}",
+                args.with.state, top_state.frames, args.state, state.frames
+            )));
+        }
+
+        // composite each frame; a single dir or frame is used for all of them
+        for frame in 0..state.frames {
+            for dir in 0..state.dirs {
+                let top_frame = top.frame(
+                    top_index,
+                    frame.min(top_state.frames - 1),
+                    dir.min(top_state.dirs - 1),
+                );
+                let position = (frame * state.dirs + dir) as usize;
+                imageops::overlay(&mut sheet.frames[index][position], top_frame, 0, 0);
+            }
+        }
+        Ok(())
+    })
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmdline::OverlaySource;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_outline_frame() {
+        let black = Rgba([0, 0, 0, 255]);
+        let red = Rgba([255, 0, 0, 255]);
+        let mut frame = RgbaImage::new(5, 5);
+        frame.put_pixel(2, 2, red);
+        let result = outline_frame(&frame, black, 1);
+        assert_eq!(&red, result.get_pixel(2, 2));
+        for (x, y) in [(1, 2), (3, 2), (2, 1), (2, 3)] {
+            assert_eq!(&black, result.get_pixel(x, y));
+        }
+        assert_eq!(0, result.get_pixel(1, 1).0[3]);
+        let result = outline_frame(&frame, black, 2);
+        assert_eq!(&black, result.get_pixel(1, 1));
+        assert_eq!(&black, result.get_pixel(2, 0));
+        assert_eq!(0, result.get_pixel(0, 0).0[3]);
+    }
+
+    #[test]
+    fn test_outline_state() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = OutlineArgs {
+            output: Some(String::from("tests/data/edit/outline.out.dmi")),
+            state: Some(String::from("still")),
+            color: [0, 0, 0, 255],
+            thickness: 1,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        outline(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/outline.out.dmi")).unwrap();
+        let expected = outline_frame(&original.frames[2][0], Rgba([0, 0, 0, 255]), 1);
+        assert_eq!(expected, sheet.frames[2][0]);
+        assert_eq!(original.frames[1], sheet.frames[1]);
+    }
+
+    #[test]
+    fn test_overlay_broadcasts_still() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = OverlayArgs {
+            output: Some(String::from("tests/data/edit/overlay.out.dmi")),
+            state: String::from("walk"),
+            with: OverlaySource {
+                file: String::from("tests/data/edit/spin.dmi"),
+                state: String::from("still"),
+            },
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        overlay(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/overlay.out.dmi")).unwrap();
+        for position in 0..8 {
+            let mut expected = original.frames[1][position].clone();
+            imageops::overlay(&mut expected, &original.frames[2][0], 0, 0);
+            assert_eq!(expected, sheet.frames[1][position]);
+        }
+        assert_eq!(original.frames[0], sheet.frames[0]);
+    }
+
+    #[test]
+    fn test_overlay_mismatched_frames() {
+        let args = OverlayArgs {
+            output: Some(String::from("tests/data/edit/overlay_mismatch.out.dmi")),
+            state: String::from("walk"),
+            with: OverlaySource {
+                file: String::from("tests/data/edit/spin.dmi"),
+                state: String::from("spin"),
+            },
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        match overlay(&args) {
+            Err(IconToolError::InvalidOverlay(_)) => {}
+            _ => panic!("test_overlay_mismatched_frames: Expected InvalidOverlay error"),
+        }
+    }
+}
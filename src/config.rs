@@ -0,0 +1,262 @@
+// config.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use serde_yml::Value;
+use std::path::Path;
+
+use crate::cmdline::{parse_packing, CompileArgs};
+use crate::compile::PadWith;
+use crate::dmi::MetadataChunk;
+use crate::error::{IconToolError, Result};
+use crate::indexmap_helper::IndexMapHelper;
+use crate::suggest::did_you_mean;
+
+// the options a build target may set, named as on the compile command line
+const TARGET_KEYS: [&str; 11] = [
+    "define",
+    "deny_warnings",
+    "metadata_chunk",
+    "no_palette",
+    "normalize_metadata",
+    "output",
+    "pack",
+    "pad_missing",
+    "pad_with",
+    "source",
+    "split",
+];
+
+// settings that apply to every command, and the targets that build
+// compiles, read from icontool.yml
+#[derive(Default)]
+pub struct Config {
+    pub post_process: Option<String>,
+    pub targets: Vec<CompileArgs>,
+}
+
+// read the settings from the file; without one, everything is the default
+pub fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let yaml: IndexMap<String, Value> = serde_yml::from_str(&std::fs::read_to_string(path)?)?;
+    let post_process = match yaml.contains_key("post_process") {
+        true => Some(yaml.get_string("post_process")?),
+        false => None,
+    };
+    // the paths of a target are relative to the file that lists it
+    let base = path.parent().unwrap_or(Path::new(""));
+    let targets = match yaml.get("targets") {
+        Some(Value::Sequence(list)) => list
+            .iter()
+            .map(|x| parse_target(x, base))
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(invalid("targets must be a list")),
+        None => Vec::new(),
+    };
+    Ok(Config {
+        post_process,
+        targets,
+    })
+}
+
+// read a target, like { source: door.dmi.yml, output: door.dmi, pack: square },
+// as the arguments to compile it with
+fn parse_target(value: &Value, base: &Path) -> Result<CompileArgs> {
+    let target: IndexMap<String, Value> = serde_yml::from_value(value.clone())
+        .map_err(|_| invalid("each target must be a mapping"))?;
+    for key in target.keys() {
+        if !TARGET_KEYS.contains(&key.as_str()) {
+            let message = match did_you_mean(key, TARGET_KEYS) {
+                Some(hint) => format!("unknown target option '{key}'; {hint}"),
+                None => format!("unknown target option '{key}'"),
+            };
+            return Err(invalid(&message));
+        }
+    }
+    let source = target
+        .get("source")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("each target needs a source"))?;
+    let string = |key: &str| -> Result<Option<&str>> {
+        match target.get(key) {
+            None => Ok(None),
+            Some(Value::String(x)) => Ok(Some(x)),
+            Some(_) => Err(invalid(&format!("{key} of '{source}' must be a string"))),
+        }
+    };
+    let flag = |key: &str| -> Result<bool> {
+        match target.get(key) {
+            None => Ok(false),
+            Some(Value::Bool(x)) => Ok(*x),
+            Some(_) => Err(invalid(&format!(
+                "{key} of '{source}' must be true or false"
+            ))),
+        }
+    };
+    let choice = |key: &str, text: &str| invalid(&format!("{key} of '{source}' can't be '{text}'"));
+
+    let mut args = CompileArgs {
+        file: base.join(source).to_string_lossy().to_string(),
+        output: string("output")?.map(|x| base.join(x).to_string_lossy().to_string()),
+        deny_warnings: flag("deny_warnings")?,
+        no_palette: flag("no_palette")?,
+        normalize_metadata: flag("normalize_metadata")?,
+        pad_missing: flag("pad_missing")?,
+        split: flag("split")?,
+        ..Default::default()
+    };
+    if let Some(text) = string("metadata_chunk")? {
+        args.metadata_chunk =
+            MetadataChunk::from_str(text, true).map_err(|_| choice("metadata_chunk", text))?;
+    }
+    if let Some(text) = string("pack")? {
+        args.pack = Some(parse_packing(text).map_err(|_| choice("pack", text))?);
+    }
+    if let Some(text) = string("pad_with")? {
+        args.pad_with = PadWith::from_str(text, true).map_err(|_| choice("pad_with", text))?;
+    }
+    match target.get("define") {
+        None => {}
+        Some(Value::Mapping(defines)) => {
+            for (name, value) in defines {
+                let (Some(name), Some(value)) = (name.as_str(), get_scalar(value)) else {
+                    return Err(invalid(&format!(
+                        "define of '{source}' must map names to values"
+                    )));
+                };
+                args.define.push((name.to_string(), value));
+            }
+        }
+        Some(_) => return Err(invalid(&format!("define of '{source}' must be a mapping"))),
+    }
+    Ok(args)
+}
+
+// a define like SCALE: 2 is read as a number, but means the text "2"
+fn get_scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(x) => Some(x.clone()),
+        Value::Number(x) => Some(x.to_string()),
+        Value::Bool(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+fn invalid(reason: &str) -> IconToolError {
+    IconToolError::InvalidConfig(reason.to_string())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::Packing;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_load_config() {
+        let missing = load_config(Path::new("tests/data/config/missing.yml")).unwrap();
+        assert_eq!(None, missing.post_process);
+        assert!(missing.targets.is_empty());
+
+        std::fs::create_dir_all("tests/data/config").unwrap();
+        let path = Path::new("tests/data/config/icontool.out.yml");
+        std::fs::write(path, "post_process: oxipng -o4 {}\n").unwrap();
+        let config = load_config(path).unwrap();
+        assert_eq!(Some(String::from("oxipng -o4 {}")), config.post_process);
+
+        std::fs::write(path, "post_process: [oxipng]\n").unwrap();
+        assert!(load_config(path).is_err());
+    }
+
+    #[test]
+    fn test_load_config_targets() {
+        std::fs::create_dir_all("tests/data/config").unwrap();
+        let path = Path::new("tests/data/config/targets.out.yml");
+        std::fs::write(
+            path,
+            "targets:\n\
+             - source: icons/door.dmi.yml\n\
+             - source: icons/team.dmi.yml\n  \
+               output: build/red.dmi\n  \
+               pack: width=4\n  \
+               no_palette: true\n  \
+               metadata_chunk: itxt\n  \
+               define: {TEAM_COLOR: \"#ff0000\", SCALE: 2}\n",
+        )
+        .unwrap();
+        let config = load_config(path).unwrap();
+        assert_eq!(2, config.targets.len());
+        assert_eq!(
+            "tests/data/config/icons/door.dmi.yml",
+            config.targets[0].file
+        );
+        assert_eq!(None, config.targets[0].output);
+        let team = &config.targets[1];
+        assert_eq!(
+            Some(String::from("tests/data/config/build/red.dmi")),
+            team.output
+        );
+        assert_eq!(Some(Packing::Width(4)), team.pack);
+        assert!(team.no_palette);
+        assert!(!team.deny_warnings);
+        assert_eq!(MetadataChunk::Itxt, team.metadata_chunk);
+        assert_eq!(
+            vec![
+                (String::from("TEAM_COLOR"), String::from("#ff0000")),
+                (String::from("SCALE"), String::from("2")),
+            ],
+            team.define
+        );
+
+        for (yaml, reason) in [
+            ("targets: door.dmi.yml\n", "targets must be a list"),
+            (
+                "targets:\n- output: door.dmi\n",
+                "each target needs a source",
+            ),
+            (
+                "targets:\n- source: a.dmi.yml\n  pakc: row\n",
+                "did you mean 'pack'?",
+            ),
+            (
+                "targets:\n- source: a.dmi.yml\n  pack: round\n",
+                "pack of 'a.dmi.yml' can't be 'round'",
+            ),
+            (
+                "targets:\n- source: a.dmi.yml\n  split: yes please\n",
+                "must be true or false",
+            ),
+        ] {
+            std::fs::write(path, yaml).unwrap();
+            match load_config(path) {
+                Err(IconToolError::InvalidConfig(x)) => assert!(x.contains(reason), "{x}"),
+                _ => panic!("test_load_config_targets: Expected InvalidConfig error"),
+            }
+        }
+    }
+}
@@ -15,25 +15,78 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //---------------------------------------------------------------------------
 
+// build remembers what it compiled in this directory, beside the manifest
+pub const CACHE_DIR_NAME: &str = ".icontool-cache";
+
+pub const CACHE_FILE_NAME: &str = "build.yml";
+
+// settings for every command are read from this file in the current directory
+pub const CONFIG_FILE_NAME: &str = "icontool.yml";
+
+// the most bytes a frame in a .dmi.yml may decompress to, unless the user
+// raises it; enough for 2048x2048 icons
+pub const DEFAULT_MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+
+// the most icon_states metadata may have, unless the user raises it
+pub const DEFAULT_MAX_STATES: usize = 65536;
+
+// the directions of an icon_state, in the order BYOND stores them
+pub const DIR_NAMES: [&str; 8] = [
+    "south",
+    "north",
+    "east",
+    "west",
+    "southeast",
+    "southwest",
+    "northeast",
+    "northwest",
+];
+
 pub const DMI_METADATA_KEY: &str = "__dmi_metadata";
 
 pub const DMI_PATH_KEY: &str = "__dmi_path";
 
+pub const DUPLICATE_STATE_SEPARATOR: &str = "@";
+
+pub const FRAME_REF_PREFIX: &str = "@ref:";
+
 pub const IMAGE_HEIGHT_KEY: &str = "__image_height";
 
 pub const IMAGE_WIDTH_KEY: &str = "__image_width";
 
-pub const ICONTOOL_KEYS: [&str; 4] = [
+pub const INCLUDES_KEY: &str = "__includes";
+
+pub const ICONTOOL_KEYS: [&str; 8] = [
     DMI_METADATA_KEY,
     DMI_PATH_KEY,
     IMAGE_HEIGHT_KEY,
     IMAGE_WIDTH_KEY,
+    INCLUDES_KEY,
+    PNG_CHUNKS_KEY,
+    TESTS_KEY,
+    TRANSFORMS_KEY,
 ];
 
+pub const PLACEHOLDER_KEYWORD: &str = "icontool-placeholder";
+
+pub const PNG_CHUNKS_KEY: &str = "__png_chunks";
+
+pub const STDOUT_PATH: &str = "-";
+
+pub const TESTS_KEY: &str = "__tests";
+
+// the largest file icontool will download or unpack from an archive; a .dmi
+// at the largest image size, without any compression, still fits
+pub const MAX_INPUT_BYTES: usize = 256 * 1024 * 1024;
+
 pub const MAX_IMAGE_HEIGHT: u32 = 6144;
 
 pub const MAX_IMAGE_WIDTH: u32 = 6144;
 
+pub const TICKS_PER_SECOND: f64 = 10.0;
+
+pub const TRANSFORMS_KEY: &str = "__transforms";
+
 pub const ZTXT_KEYWORD: &str = "Description";
 
 //---------------------------------------------------------------------------
@@ -49,6 +102,34 @@ mod tests {
         assert!(true);
     }
 
+    #[test]
+    fn test_cache_dir_name() {
+        assert_eq!(".icontool-cache", CACHE_DIR_NAME);
+        assert_eq!("build.yml", CACHE_FILE_NAME);
+    }
+
+    #[test]
+    fn test_config_file_name() {
+        assert_eq!("icontool.yml", CONFIG_FILE_NAME);
+    }
+
+    #[test]
+    fn test_default_max_frame_bytes() {
+        assert_eq!(2048 * 2048 * 4, DEFAULT_MAX_FRAME_BYTES);
+    }
+
+    #[test]
+    fn test_default_max_states() {
+        assert_eq!(65536, DEFAULT_MAX_STATES);
+    }
+
+    #[test]
+    fn test_dir_names() {
+        assert_eq!("south", DIR_NAMES[0]);
+        assert_eq!("east", DIR_NAMES[2]);
+        assert_eq!("northwest", DIR_NAMES[7]);
+    }
+
     #[test]
     fn test_dmi_metadata_key() {
         assert_eq!("__dmi_metadata", DMI_METADATA_KEY);
@@ -59,6 +140,16 @@ mod tests {
         assert_eq!("__dmi_path", DMI_PATH_KEY);
     }
 
+    #[test]
+    fn test_duplicate_state_separator() {
+        assert_eq!("@", DUPLICATE_STATE_SEPARATOR);
+    }
+
+    #[test]
+    fn test_frame_ref_prefix() {
+        assert_eq!("@ref:", FRAME_REF_PREFIX);
+    }
+
     #[test]
     fn test_image_height_key() {
         assert_eq!("__image_height", IMAGE_HEIGHT_KEY);
@@ -69,6 +160,11 @@ mod tests {
         assert_eq!("__image_width", IMAGE_WIDTH_KEY);
     }
 
+    #[test]
+    fn test_includes_key() {
+        assert_eq!("__includes", INCLUDES_KEY);
+    }
+
     #[test]
     fn test_max_image_height() {
         assert_eq!(6144, MAX_IMAGE_HEIGHT);
@@ -79,6 +175,36 @@ mod tests {
         assert_eq!(6144, MAX_IMAGE_WIDTH);
     }
 
+    #[test]
+    fn test_placeholder_keyword() {
+        assert_eq!("icontool-placeholder", PLACEHOLDER_KEYWORD);
+    }
+
+    #[test]
+    fn test_png_chunks_key() {
+        assert_eq!("__png_chunks", PNG_CHUNKS_KEY);
+    }
+
+    #[test]
+    fn test_stdout_path() {
+        assert_eq!("-", STDOUT_PATH);
+    }
+
+    #[test]
+    fn test_tests_key() {
+        assert_eq!("__tests", TESTS_KEY);
+    }
+
+    #[test]
+    fn test_ticks_per_second() {
+        assert_eq!(10.0, TICKS_PER_SECOND);
+    }
+
+    #[test]
+    fn test_transforms_key() {
+        assert_eq!("__transforms", TRANSFORMS_KEY);
+    }
+
     #[test]
     fn test_ztxt_keyword() {
         assert_eq!("Description", ZTXT_KEYWORD);
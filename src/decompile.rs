@@ -16,80 +16,542 @@
 //---------------------------------------------------------------------------
 
 use base64::prelude::*;
-use image::{DynamicImage, GenericImageView, Pixel};
+#[cfg(feature = "cli")]
+use image::DynamicImage;
+use image::{imageops, ColorType, RgbaImage};
 use indexmap::IndexMap;
 use lz4_flex::block::compress_prepend_size;
+use png::{BitDepth, Transformations};
 use serde_yml::Value;
-use std::fs::File;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "cli")]
+use crate::archive::{get_entry_name, is_archive, list_entries, write_entries, Input};
+#[cfg(feature = "cli")]
+use crate::atomic::{create_atomic, write_atomic};
+#[cfg(feature = "cli")]
 use crate::cmdline::DecompileArgs;
-use crate::constant::{DMI_METADATA_KEY, DMI_PATH_KEY, IMAGE_HEIGHT_KEY, IMAGE_WIDTH_KEY};
-use crate::dmi::{read_image, read_metadata};
-use crate::error::Result;
+use crate::constant::{
+    DMI_METADATA_KEY, DMI_PATH_KEY, FRAME_REF_PREFIX, IMAGE_HEIGHT_KEY, IMAGE_WIDTH_KEY,
+    MAX_IMAGE_HEIGHT, MAX_IMAGE_WIDTH, PNG_CHUNKS_KEY,
+};
+use crate::diagnostics::Diagnostics;
+use crate::dmi::{
+    decode_image, decode_metadata, decode_png_chunks, to_rgba_image, warn_for_conversion, PngChunk,
+};
+#[cfg(feature = "cli")]
+use crate::dmi_file::DmiFile;
+#[cfg(feature = "cli")]
+use crate::error::get_error_message;
+use crate::error::{IconToolError, MissingMetadata, Result};
+#[cfg(feature = "cli")]
+use crate::fetch::{get_url_file_name, is_url, read_input};
+#[cfg(feature = "cli")]
+use crate::log::diagnostic;
 use crate::parser::{parse_metadata, DreamMakerIconMetadata};
+#[cfg(feature = "cli")]
+use crate::summary::BatchSummary;
+#[cfg(feature = "cli")]
+use crate::timings::{finish_file, start_file};
+use crate::timings::{time_phase, Phase};
+use crate::validate::warn_for_unknown_version;
+#[cfg(feature = "cli")]
+use crate::walk::{create_parent_dir, relocate};
 
-struct IconStatePixels {
-    key: String,
-    value: Value,
+// what decompile wrote, for --stats
+#[derive(Debug, Default)]
+pub struct DecompileStats {
+    pub states: usize,
+    pub frames: usize,
+    pub raw_bytes: usize,                  // rgba pixel data
+    pub compressed_bytes: usize,           // lz4 compressed pixel data
+    pub state_bytes: Vec<(String, usize)>, // compressed bytes of each icon_state
 }
 
+// the parts of a .dmi file that decompile needs to write the .dmi.yml
+struct DecodedDmi<'a> {
+    pixels: Pixels<'a>,
+    width: u32, // of the sheet, including any frames filled in
+    height: u32,
+    chunks: Vec<PngChunk>,
+    text: String,
+    metadata: DreamMakerIconMetadata,
+}
+
+// where the pixels of the sheet come from; a .dmi file is decoded a row of
+// icons at a time while the .dmi.yml is written, so a huge sheet never has
+// to be held in memory all at once
+enum Pixels<'a> {
+    Png(&'a [u8]),    // the bytes of the .dmi file
+    Image(RgbaImage), // decoded up front: 16-bit or interlaced, or repacked
+}
+
+// hands out the sheet one row of icons at a time, with transparent pixels
+// past the edges of the image
+struct IconRows<'a> {
+    source: RowSource<'a>,
+    width: u32,
+    icon_height: u32,
+    y: u32, // the top of the next row of icons
+}
+
+enum RowSource<'a> {
+    Png(Box<png::Reader<Cursor<&'a [u8]>>>),
+    Image(&'a RgbaImage),
+}
+
+#[cfg(feature = "cli")]
 pub fn decompile(args: &DecompileArgs) -> Result<()> {
+    // an archive has every .dmi file in it decompiled
+    if is_archive(Path::new(&args.file)) {
+        return decompile_archive(args);
+    }
+
     // determine the path to the provided dmi file
     let path = PathBuf::from(&args.file);
 
-    // read the image data from the provided dmi file
-    let image = read_image(&path)?;
-    // read the dmi metadata from the provided dmi file
-    let metadata_text = read_metadata(&path)?;
-    // parse dmi metadata
-    let dmi_metadata = parse_metadata(&metadata_text)?;
+    // decode the image and metadata of the icon, downloading it if need be
+    let dmi = read_input(&args.file)?;
+    start_file();
+    let result = decompile_dmi(args, &dmi, &path);
+    finish_file(&args.file);
+    result
+}
+
+#[cfg(feature = "cli")]
+fn decompile_dmi(args: &DecompileArgs, dmi: &[u8], path: &Path) -> Result<()> {
+    let decoded = decode_selected(args, dmi, path)?;
 
-    // decompile the icon to an indexmap
-    let data = decompile_icon(&path, &image, &metadata_text, &dmi_metadata);
+    // the .dmi path isn't recorded for a subset of the icon_states, so
+    // compiling the subset can't overwrite the whole icon; nor for a URL,
+    // which compile couldn't write back to
+    let recorded = args.state.is_empty() && !is_url(&args.file);
+    let recorded_path = recorded.then_some(path);
 
-    // output yaml to file
-    let output_path = get_output_path(args);
-    let file = File::create(output_path)?;
-    serde_yml::to_writer(file, &data)?;
+    let output_path = get_output_path(args)?;
+    let stats = match get_output_archive(args) {
+        // add the yaml to an archive
+        Some(archive) => {
+            let mut yaml = Vec::new();
+            let stats = write_icon(&mut yaml, recorded_path, &decoded, args.frame_refs)?;
+            write_entries(archive, &[(get_entry_name(archive, &output_path), yaml)])?;
+            stats
+        }
+        // stream the yaml to the output file as each row of icons is decoded
+        None => {
+            create_parent_dir(&output_path)?;
+            create_atomic(&output_path, |writer| {
+                write_icon(writer, recorded_path, &decoded, args.frame_refs)
+            })?
+        }
+    };
+
+    // if requested, tell the user what we wrote
+    print_stats(args, &stats);
 
     // return success to the caller
     Ok(())
 }
 
-fn decompile_icon(
-    path: &Path,
-    image: &DynamicImage,
-    text: &str,
+// decompile every .dmi file in an archive, keeping their paths under the
+// output directory, which may be another archive
+#[cfg(feature = "cli")]
+fn decompile_archive(args: &DecompileArgs) -> Result<()> {
+    let output_dir = args.output_dir.as_ref().ok_or_else(|| {
+        IconToolError::InvalidArgument(format!(
+            "use --output-dir to choose where the .dmi.yml files from {} go",
+            args.file
+        ))
+    })?;
+    let archive = PathBuf::from(&args.file);
+    let mut outputs = Vec::new();
+    let mut summary = BatchSummary::new();
+    for name in list_entries(&archive, ".dmi")? {
+        // one broken file shouldn't stop the others
+        let input = Input::Entry(archive.clone(), name.clone());
+        start_file();
+        let result = decompile_entry(args, &input);
+        finish_file(&input.name());
+        let yaml = match result {
+            Ok(yaml) => yaml,
+            Err(x) => {
+                let message = get_error_message(x);
+                let message = message.trim_start_matches("icontool: ");
+                diagnostic(&format!("{}: {message}", input.name()));
+                summary.fail(input.name(), message);
+                continue;
+            }
+        };
+        summary.succeed();
+        let mut path = PathBuf::from(name);
+        path.set_extension("dmi.yml");
+        outputs.push((relocate(&path, output_dir, &args.strip_prefix)?, yaml));
+    }
+
+    // add them all to an archive at once, or write them one by one
+    if let Some(output) = get_output_archive(args) {
+        let files: Vec<_> = outputs
+            .into_iter()
+            .map(|(path, yaml)| (get_entry_name(output, &path), yaml))
+            .collect();
+        write_entries(output, &files)?;
+    } else {
+        for (path, yaml) in outputs {
+            create_parent_dir(&path)?;
+            write_atomic(&path, yaml)?;
+        }
+    }
+
+    // if anything failed to decompile, tell the caller
+    summary.report();
+    if summary.num_failed() > 0 {
+        return Err(IconToolError::DecompileFailed(summary.num_failed()));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+fn decompile_entry(args: &DecompileArgs, input: &Input) -> Result<Vec<u8>> {
+    let dmi = input.read()?;
+    let decoded = decode_selected(args, &dmi, Path::new(&input.name()))?;
+    let mut yaml = Vec::new();
+    let stats = write_icon(&mut yaml, None, &decoded, args.frame_refs)?;
+    if args.stats {
+        println!("{}:", input.name());
+    }
+    print_stats(args, &stats);
+    Ok(yaml)
+}
+
+// decode a .dmi file, keeping only the icon_states asked for
+#[cfg(feature = "cli")]
+fn decode_selected<'a>(args: &DecompileArgs, dmi: &'a [u8], path: &Path) -> Result<DecodedDmi<'a>> {
+    let mut diagnostics = Diagnostics::new();
+    let result = decode_dmi(dmi, Some(path), args.lenient, &mut diagnostics);
+
+    // tell the user about any warnings, even if decompilation failed
+    diagnostics.report(args.deny_warnings && result.is_ok())?;
+    let decoded = result?;
+    if args.state.is_empty() {
+        return Ok(decoded);
+    }
+    select_states(decoded, &args.state)
+}
+
+// an --output-dir ending in .zip is an archive to add the output to
+#[cfg(feature = "cli")]
+fn get_output_archive(args: &DecompileArgs) -> Option<&Path> {
+    let output_dir = Path::new(args.output_dir.as_deref()?);
+    (args.output.is_none() && is_archive(output_dir)).then_some(output_dir)
+}
+
+#[cfg(feature = "cli")]
+fn print_stats(args: &DecompileArgs, stats: &DecompileStats) {
+    if args.stats {
+        for line in format_stats(stats) {
+            println!("{line}");
+        }
+    }
+}
+
+// decompile the bytes of a .dmi file into .dmi.yml text, without touching
+// the filesystem; any warnings are printed
+pub fn decompile_bytes(dmi: &[u8]) -> Result<String> {
+    let mut diagnostics = Diagnostics::new();
+    let result = decompile_bytes_with_diagnostics(dmi, &mut diagnostics);
+    diagnostics.print();
+    result
+}
+
+// like decompile_bytes, but the warnings are collected for the caller
+pub fn decompile_bytes_with_diagnostics(
+    dmi: &[u8],
+    diagnostics: &mut Diagnostics,
+) -> Result<String> {
+    let decoded = decode_dmi(dmi, None, false, diagnostics)?;
+    let mut yaml = Vec::new();
+    write_icon(&mut yaml, None, &decoded, false)?;
+    Ok(String::from_utf8_lossy(&yaml).into_owned())
+}
+
+fn decode_dmi<'a>(
+    dmi: &'a [u8],
+    path: Option<&Path>,
+    lenient: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<DecodedDmi<'a>> {
+    // read the header of the image; the pixels are decoded as they're
+    // written, unless they have to be decoded all at once (and a file that
+    // isn't a PNG at all is left to the image crate to describe)
+    let source = match path {
+        Some(path) => path.display().to_string(),
+        None => String::from("the .dmi data"),
+    };
+    let (pixels, width, height) = match open_rows(dmi).ok().flatten() {
+        Some(reader) => {
+            let (width, height) = reader.info().size();
+            let color = match reader.output_color_type().0 {
+                png::ColorType::Grayscale => Some(ColorType::L8),
+                png::ColorType::GrayscaleAlpha => Some(ColorType::La8),
+                png::ColorType::Rgb => Some(ColorType::Rgb8),
+                _ => None,
+            };
+            if let Some(color) = color {
+                warn_for_conversion(color, &source, diagnostics);
+            }
+            (Pixels::Png(dmi), width, height)
+        }
+        None => {
+            let image = time_phase(Phase::PngDecode, || decode_image(dmi))?;
+            let image = to_rgba_image(image, &source, diagnostics);
+            let (width, height) = image.dimensions();
+            (Pixels::Image(image), width, height)
+        }
+    };
+    // read the dmi metadata from the dmi file
+    let text = decode_metadata(dmi)?
+        .ok_or_else(|| MissingMetadata(path.map(PathBuf::from).unwrap_or_default()))?;
+    // parse dmi metadata
+    let metadata = time_phase(Phase::MetadataParse, || parse_metadata(&text))?;
+    warn_for_unknown_version(&metadata, diagnostics);
+    // read any other chunks that we'll need to write back out
+    let chunks = decode_png_chunks(dmi)?;
+    // make sure the sheet has every frame the metadata describes
+    let (width, height) = fill_missing_frames((width, height), &metadata, lenient, diagnostics)?;
+
+    // return the decoded icon to the caller
+    Ok(DecodedDmi {
+        pixels,
+        width,
+        height,
+        chunks,
+        text,
+        metadata,
+    })
+}
+
+// a reader that hands out the rows of the image as 8-bit pixels, or None
+// if the image has to be decoded all at once: 16-bit pixels are reduced the
+// way the image crate does it, and interlaced rows come out of order
+fn open_rows(dmi: &[u8]) -> Result<Option<png::Reader<Cursor<&[u8]>>>> {
+    let mut decoder = png::Decoder::new(Cursor::new(dmi));
+    decoder.set_transformations(Transformations::EXPAND);
+    let reader = decoder.read_info()?;
+    let info = reader.info();
+    if info.bit_depth == BitDepth::Sixteen || info.interlaced {
+        return Ok(None);
+    }
+    Ok(Some(reader))
+}
+
+impl DecodedDmi<'_> {
+    fn rows(&self) -> Result<IconRows<'_>> {
+        let source = match &self.pixels {
+            Pixels::Png(dmi) => match open_rows(dmi)? {
+                Some(reader) => RowSource::Png(Box::new(reader)),
+                None => unreachable!("the image was checked by decode_dmi"),
+            },
+            Pixels::Image(image) => RowSource::Image(image),
+        };
+        Ok(IconRows {
+            source,
+            width: self.width,
+            icon_height: self.metadata.height,
+            y: 0,
+        })
+    }
+}
+
+impl IconRows<'_> {
+    fn next_row(&mut self) -> Result<RgbaImage> {
+        let mut row = RgbaImage::new(self.width, self.icon_height);
+        match &mut self.source {
+            RowSource::Png(reader) => {
+                let color = reader.output_color_type().0;
+                let row_bytes = self.width as usize * 4;
+                for line in row.chunks_exact_mut(row_bytes) {
+                    match time_phase(Phase::PngDecode, || reader.next_row())? {
+                        Some(pixels) => copy_rgba(color, pixels.data(), line),
+                        None => break,
+                    }
+                }
+            }
+            RowSource::Image(image) => {
+                let view = imageops::crop_imm(*image, 0, self.y, image.width(), self.icon_height);
+                imageops::replace(&mut row, &*view, 0, 0);
+            }
+        }
+        self.y += self.icon_height;
+        Ok(row)
+    }
+}
+
+// copy a row of 8-bit pixels of any color type into RGBA
+fn copy_rgba(color: png::ColorType, pixels: &[u8], rgba: &mut [u8]) {
+    let samples = color.samples();
+    for (pixel, target) in pixels.chunks_exact(samples).zip(rgba.chunks_exact_mut(4)) {
+        let value = match *pixel {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => [0, 0, 0, 0],
+        };
+        target.copy_from_slice(&value);
+    }
+}
+
+// a truncated .dmi describes more frames than its image has; that's an
+// error, unless lenient, where transparent rows are added to the bottom of
+// the sheet so the frames that are there keep their places
+fn fill_missing_frames(
+    (width, height): (u32, u32),
     dmi: &DreamMakerIconMetadata,
-) -> IndexMap<String, Value> {
-    // this is the data structure that we'll build
-    let mut data = IndexMap::new();
+    lenient: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<(u32, u32)> {
+    let (icon_width, icon_height) = (dmi.width.max(1), dmi.height.max(1));
+    let columns = width / icon_width;
+    let available = columns.saturating_mul(height / icon_height);
+    // None if the metadata claims more frames than a u32 can count
+    let needed = dmi.states.iter().try_fold(0u32, |sum, x| {
+        x.dirs
+            .checked_mul(x.frames)
+            .and_then(|frames| sum.checked_add(frames))
+    });
+    if needed.is_some_and(|needed| needed <= available) {
+        return Ok((width, height));
+    }
+    if !lenient {
+        return Err(IconToolError::ImageTooSmall(
+            needed.map_or(usize::MAX, |x| x as usize),
+            available as usize,
+        ));
+    }
+
+    // grow the sheet to hold every frame, as long as BYOND could load it
+    let columns = columns.max(1);
+    let grown_width = width.max(icon_width);
+    let grown_height = needed
+        .map_or(u32::MAX, |x| {
+            x.div_ceil(columns).saturating_mul(icon_height)
+        })
+        .max(height);
+    if grown_width > MAX_IMAGE_WIDTH || grown_height > MAX_IMAGE_HEIGHT {
+        return Err(IconToolError::TooManyIconStates(grown_width, grown_height));
+    }
+
+    // tell the user about each icon_state that lost frames
+    let mut offset: u32 = 0;
+    for (state, key) in dmi.states.iter().zip(dmi.state_keys()) {
+        let frames = state.dirs * state.frames;
+        let missing = (offset + frames).saturating_sub(available).min(frames);
+        if missing > 0 {
+            diagnostics.warn(format!("icontool: icon_state '{key}' is missing {missing} of its {frames} frame(s) in the image; using transparent frames"));
+        }
+        offset += frames;
+    }
+    Ok((grown_width, grown_height))
+}
 
+// repack the selected icon_states into a smaller sheet, with metadata to
+// match; the metadata is regenerated, so it may be formatted differently
+#[cfg(feature = "cli")]
+fn select_states<'a>(decoded: DecodedDmi<'a>, keys: &[String]) -> Result<DecodedDmi<'a>> {
+    if let Some(key) = keys
+        .iter()
+        .find(|x| decoded.metadata.find_state(x).is_none())
+    {
+        return Err(IconToolError::StateNotFound(key.clone()));
+    }
+
+    // the subset is repacked, so the whole sheet is decoded first
+    let mut image = RgbaImage::new(decoded.width, decoded.height);
+    let mut rows = decoded.rows()?;
+    for y in (0..decoded.height).step_by(decoded.metadata.height.max(1) as usize) {
+        imageops::replace(&mut image, &rows.next_row()?, 0, y as i64);
+    }
+    drop(rows);
+
+    // keep the selected icon_states in the order they appear in the file
+    let image = DynamicImage::ImageRgba8(image);
+    let sheet = DmiFile::from_image(decoded.metadata, &image)?;
+    let state_keys = sheet.metadata.state_keys();
+    let mut selected = DmiFile {
+        metadata: DreamMakerIconMetadata {
+            states: Vec::new(),
+            ..sheet.metadata
+        },
+        frames: Vec::new(),
+    };
+    for ((state, frames), key) in sheet
+        .metadata
+        .states
+        .into_iter()
+        .zip(sheet.frames)
+        .zip(state_keys)
+    {
+        if keys.contains(&key) {
+            selected.metadata.states.push(state);
+            selected.frames.push(frames);
+        }
+    }
+
+    let image = selected.to_image().to_rgba8();
+    Ok(DecodedDmi {
+        width: image.width(),
+        height: image.height(),
+        pixels: Pixels::Image(image),
+        chunks: decoded.chunks,
+        text: selected.metadata.to_string(),
+        metadata: selected.metadata,
+    })
+}
+
+fn write_icon<W: Write>(
+    writer: &mut W,
+    path: Option<&Path>,
+    decoded: &DecodedDmi,
+    frame_refs: bool,
+) -> Result<DecompileStats> {
     // put the filename of the dmi at the top of the yaml
-    let path_str = path.to_str().expect("Failed to convert path to string");
-    data.insert(DMI_PATH_KEY.to_string(), Value::from(path_str));
+    if let Some(path) = path {
+        let path_str = path.to_str().expect("Failed to convert path to string");
+        write_yaml_entry(writer, DMI_PATH_KEY, Value::from(path_str))?;
+    }
 
     // save the image dimensions
-    data.insert(IMAGE_WIDTH_KEY.to_string(), Value::from(image.width()));
-    data.insert(IMAGE_HEIGHT_KEY.to_string(), Value::from(image.height()));
+    write_yaml_entry(writer, IMAGE_WIDTH_KEY, Value::from(decoded.width))?;
+    write_yaml_entry(writer, IMAGE_HEIGHT_KEY, Value::from(decoded.height))?;
 
-    // for each icon_state, add the name and pixels to the yaml
-    let icon_states = extract_icon_states(image, dmi);
-    for icon_state in icon_states {
-        data.insert(icon_state.key, icon_state.value);
+    // save any extra PNG chunks, so compile can write them back out
+    if !decoded.chunks.is_empty() {
+        let chunks = decoded.chunks.iter().map(|x| Value::from(x.to_string()));
+        write_yaml_entry(writer, PNG_CHUNKS_KEY, Value::Sequence(chunks.collect()))?;
     }
 
-    // put the dmi metadata at the bottom of the yaml
-    data.insert(DMI_METADATA_KEY.to_string(), Value::from(text));
+    // for each icon_state, add the name and pixels to the yaml
+    let stats = write_icon_states(writer, &mut decoded.rows()?, &decoded.metadata, frame_refs)?;
 
-    // return the indexmap to the caller
-    data
+    // put the dmi metadata at the bottom of the yaml
+    write_yaml_entry(writer, DMI_METADATA_KEY, Value::from(decoded.text.as_str()))?;
+    Ok(stats)
 }
 
-fn extract_icon_states(image: &DynamicImage, dmi: &DreamMakerIconMetadata) -> Vec<IconStatePixels> {
-    // build up a nice list for the caller
-    let mut icon_states = Vec::new();
+fn write_icon_states<W: Write>(
+    writer: &mut W,
+    rows: &mut IconRows,
+    dmi: &DreamMakerIconMetadata,
+    frame_refs: bool,
+) -> Result<DecompileStats> {
+    // keep track of what we've written
+    let mut stats = DecompileStats::default();
+
+    // if requested, repeated frames refer back to their first appearance;
+    // this remembers the pixels of every distinct frame
+    let mut first_seen: HashMap<Vec<u8>, String> = HashMap::new();
 
     // make some nice aliases
     let DreamMakerIconMetadata {
@@ -97,50 +559,66 @@ fn extract_icon_states(image: &DynamicImage, dmi: &DreamMakerIconMetadata) -> Ve
         height: icon_height,
         ..
     } = *dmi;
-    let (image_width, _image_height) = image.dimensions();
+    let image_width = rows.width;
 
-    // as we iterate, we need to keep track of our position
+    // as we iterate, we need to keep track of our position; only the row
+    // of icons under the cursor is decoded
     let mut cursor_x = 0;
-    let mut cursor_y = 0;
+    let mut row: Option<RgbaImage> = None;
 
     // for each icon_state in the icon
-    for state in &dmi.states {
-        // we'll collect up each frame of the icon here
-        let mut icon_frames = Vec::new();
+    for (state, key) in dmi.states.iter().zip(dmi.state_keys()) {
+        // each icon_state is a literal block scalar with one frame per line,
+        // so that a changed frame is a one-line diff
+        write_yaml_block_key(writer, &key)?;
+        let mut state_bytes = 0;
         // determine how many frames we need to extract
         let num_frames = state.frames * state.dirs;
         // for each frame we need to extract
-        for _ in 0..num_frames {
+        for index in 0..num_frames {
             // extract the pixel data
-            let pixel_data = extract_pixel_data(image, cursor_x, cursor_y, icon_width, icon_height);
-            // stringify the pixel data
-            let pixel_text = stringify_pixel_data(&pixel_data);
-            // add the pixel data to the icon_state
-            icon_frames.push(pixel_text);
-            // update the cursor
+            if row.is_none() {
+                row = Some(rows.next_row()?);
+            }
+            let image = row.as_ref().expect("the row was just decoded");
+            let pixel_data = extract_pixel_data(image, cursor_x, 0, icon_width, icon_height);
+            stats.raw_bytes += pixel_data.len();
+            // stringify the pixel data, or refer to an identical frame
+            let pixel_text = match first_seen.get(&pixel_data) {
+                Some(frame_ref) => frame_ref.clone(),
+                None => {
+                    if frame_refs {
+                        let frame_ref = format!("{FRAME_REF_PREFIX}{key}[{index}]");
+                        first_seen.insert(pixel_data.clone(), frame_ref);
+                    }
+                    time_phase(Phase::FrameEncode, || {
+                        let compressed = compress_prepend_size(&pixel_data);
+                        state_bytes += compressed.len();
+                        BASE64_STANDARD.encode(compressed)
+                    })
+                }
+            };
+            // write the frame out right away, so only one is held at a time
+            writeln!(writer, "  {pixel_text}")?;
+            // update the cursor; a partial icon at the right edge is skipped
             cursor_x += icon_width;
-            if cursor_x >= image_width {
-                cursor_y += icon_height;
+            if cursor_x + icon_width > image_width {
+                row = None;
                 cursor_x = 0;
             }
         }
-        // collect up all the frames into a single value
-        let frames = Value::String(icon_frames.join("\n"));
-        // turn this into an icon_state
-        let icon_state = IconStatePixels {
-            key: state.name.clone(),
-            value: frames,
-        };
-        // add it to our list of icon_states
-        icon_states.push(icon_state);
+        stats.states += 1;
+        stats.frames += num_frames as usize;
+        stats.compressed_bytes += state_bytes;
+        stats.state_bytes.push((key, state_bytes));
     }
 
-    // return the list of icon states to the caller
-    icon_states
+    // tell the caller what was written
+    Ok(stats)
 }
 
 fn extract_pixel_data(
-    image: &DynamicImage,
+    image: &RgbaImage,
     tile_x: u32,
     tile_y: u32,
     tile_width: u32,
@@ -150,36 +628,81 @@ fn extract_pixel_data(
     let num_bytes: usize = tile_width as usize * tile_height as usize * 4;
     let mut pixel_data = Vec::with_capacity(num_bytes);
 
-    // extract the RGBA values for each pixel in the requested region
+    // copy each row of RGBA values in the requested region
+    let image_width = image.width() as usize;
+    let row_bytes = tile_width as usize * 4;
+    let raw: &[u8] = image.as_raw();
     for y in tile_y..tile_y + tile_height {
-        for x in tile_x..tile_x + tile_width {
-            let pixel = image.get_pixel(x, y).to_rgba();
-            for i in 0..4 {
-                pixel_data.push(pixel[i]);
-            }
-        }
+        let start = (y as usize * image_width + tile_x as usize) * 4;
+        pixel_data.extend_from_slice(&raw[start..start + row_bytes]);
     }
 
     // return the RGBA pixel data to the caller
     pixel_data
 }
 
-fn get_output_path(args: &DecompileArgs) -> PathBuf {
+#[cfg(feature = "cli")]
+fn get_output_path(args: &DecompileArgs) -> Result<PathBuf> {
     match &args.output {
-        Some(output) => PathBuf::from(output),
+        Some(output) => Ok(PathBuf::from(output)),
         None => {
+            // a download is written to the current directory, by its name
             let mut file_path = PathBuf::from(&args.file);
+            if is_url(&args.file) {
+                let name = get_url_file_name(&args.file).ok_or_else(|| {
+                    IconToolError::InvalidArgument(format!(
+                        "{} doesn't end in a file name; use --output",
+                        args.file
+                    ))
+                })?;
+                file_path = PathBuf::from(name);
+            }
             file_path.set_extension("dmi.yml");
-            file_path
+            match &args.output_dir {
+                Some(output_dir) => relocate(&file_path, output_dir, &args.strip_prefix),
+                None => Ok(file_path),
+            }
         }
     }
 }
 
-fn stringify_pixel_data(pixel_data: &[u8]) -> String {
-    // compress the pixel data with lz4
-    let compressed = compress_prepend_size(pixel_data);
-    // encode the compressed data into a base64 string
-    BASE64_STANDARD.encode(compressed)
+pub fn format_stats(stats: &DecompileStats) -> Vec<String> {
+    let percent = match stats.raw_bytes {
+        0 => 0.0,
+        raw => stats.compressed_bytes as f64 * 100.0 / raw as f64,
+    };
+    let mut lines = vec![
+        format!("icon_states: {}", stats.states),
+        format!("frames: {}", stats.frames),
+        format!(
+            "pixel data: {} bytes raw, {} bytes compressed ({percent:.1}%)",
+            stats.raw_bytes, stats.compressed_bytes
+        ),
+    ];
+
+    // the five biggest icon_states are the ones worth looking at
+    let mut largest: Vec<&(String, usize)> = stats.state_bytes.iter().collect();
+    largest.sort_by_key(|x| std::cmp::Reverse(x.1));
+    if !largest.is_empty() {
+        lines.push(String::from("largest icon_states:"));
+    }
+    for (key, bytes) in largest.into_iter().take(5) {
+        lines.push(format!("  \"{key}\": {bytes} bytes"));
+    }
+    lines
+}
+
+fn write_yaml_block_key<W: Write>(writer: &mut W, key: &str) -> Result<()> {
+    // let serde_yml decide whether the key needs quoting
+    let key = serde_yml::to_string(&Value::from(key))?;
+    writeln!(writer, "{}: |-", key.trim_end())?;
+    Ok(())
+}
+
+fn write_yaml_entry<W: Write>(writer: &mut W, key: &str, value: Value) -> Result<()> {
+    let entry = IndexMap::from([(key, value)]);
+    serde_yml::to_writer(writer, &entry)?;
+    Ok(())
 }
 
 //---------------------------------------------------------------------------
@@ -196,30 +719,36 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_decompile_default() {
         let args = DecompileArgs {
             output: None,
             file: String::from("tests/data/decompile/neck.dmi"),
+            ..Default::default()
         };
         let _ = decompile(&args);
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_decompile_output() {
         let args = DecompileArgs {
             output: Some(String::from("tests/data/decompile/neckbeard.dmi.yml")),
             file: String::from("tests/data/decompile/neck.dmi"),
+            ..Default::default()
         };
         let _ = decompile(&args);
     }
 
     #[test]
+    #[cfg(feature = "cli")]
     fn test_get_output_path_default() {
         let args = DecompileArgs {
             output: None,
             file: String::from("tests/data/decompile/neck.dmi"),
+            ..Default::default()
         };
-        let output_path = get_output_path(&args);
+        let output_path = get_output_path(&args).unwrap();
         assert_eq!(
             PathBuf::from("tests/data/decompile/neck.dmi.yml"),
             output_path
@@ -227,15 +756,381 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_output_path_url() {
+        let args = DecompileArgs {
+            file: String::from("https://example.com/raw/neck.dmi?token=1"),
+            ..Default::default()
+        };
+        assert_eq!(
+            PathBuf::from("neck.dmi.yml"),
+            get_output_path(&args).unwrap()
+        );
+        let args = DecompileArgs {
+            output_dir: Some(String::from("build/")),
+            file: String::from("https://example.com/raw/neck.dmi"),
+            ..Default::default()
+        };
+        assert_eq!(
+            PathBuf::from("build/neck.dmi.yml"),
+            get_output_path(&args).unwrap()
+        );
+        let args = DecompileArgs {
+            file: String::from("https://example.com/raw/"),
+            ..Default::default()
+        };
+        match get_output_path(&args) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_get_output_path_url: Expected InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_output_path_output_dir() {
+        let args = DecompileArgs {
+            output_dir: Some(String::from("build/")),
+            strip_prefix: Some(String::from("icons/")),
+            file: String::from("icons/mob/neck.dmi"),
+            ..Default::default()
+        };
+        let output_path = get_output_path(&args).unwrap();
+        assert_eq!(PathBuf::from("build/mob/neck.dmi.yml"), output_path);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
     fn test_get_output_path_override() {
         let args = DecompileArgs {
             output: Some(String::from("tests/data/decompile/neckbeard.dmi.yml")),
             file: String::from("tests/data/decompile/neck.dmi"),
+            ..Default::default()
         };
-        let output_path = get_output_path(&args);
+        let output_path = get_output_path(&args).unwrap();
         assert_eq!(
             PathBuf::from("tests/data/decompile/neckbeard.dmi.yml"),
             output_path
         );
     }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_decompile_16_bit_grayscale() {
+        let args = DecompileArgs {
+            output: Some(String::from("tests/data/dmi/grey16.out.dmi.yml")),
+            file: String::from("tests/data/dmi/grey16.dmi"),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        let compile_args = crate::cmdline::CompileArgs {
+            output: Some(String::from("tests/data/dmi/grey16.out.dmi")),
+            file: String::from("tests/data/dmi/grey16.out.dmi.yml"),
+            ..Default::default()
+        };
+        crate::compile::compile(&compile_args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/dmi/grey16.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/dmi/grey16.out.dmi")).unwrap();
+        assert_eq!(original.frames, sheet.frames);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_decompile_frame_refs() {
+        // every frame of "twins" is the same, and matches "single"
+        let red = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+        let mut builder = crate::DmiBuilder::new(4, 4)
+            .state("single")
+            .push_frame(red.clone());
+        builder = builder.state("twins").dirs(4);
+        for _ in 0..4 {
+            builder = builder.push_frame(red.clone());
+        }
+        let path = PathBuf::from("tests/data/decompile/twins.out.dmi");
+        builder.build().unwrap().save(&path).unwrap();
+
+        let yaml_path = "tests/data/decompile/twins.out.dmi.yml";
+        let args = DecompileArgs {
+            output: Some(String::from(yaml_path)),
+            frame_refs: true,
+            file: path.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        let yaml = std::fs::read_to_string(yaml_path).unwrap();
+        assert_eq!(4, yaml.matches("@ref:single[0]").count());
+
+        let compile_args = crate::cmdline::CompileArgs {
+            output: Some(String::from("tests/data/decompile/twins.out.out.dmi")),
+            file: String::from(yaml_path),
+            ..Default::default()
+        };
+        crate::compile::compile(&compile_args).unwrap();
+        let original = DmiFile::open(&path).unwrap();
+        let compiled =
+            DmiFile::open(&PathBuf::from("tests/data/decompile/twins.out.out.dmi")).unwrap();
+        assert_eq!(original.frames, compiled.frames);
+    }
+
+    #[test]
+    fn test_write_icon_states_duplicate_names() {
+        let dmi = std::fs::read("tests/data/decompile/neck.dmi").unwrap();
+        let mut decoded = decode_dmi(&dmi, None, false, &mut Diagnostics::new()).unwrap();
+        decoded.metadata.states[1].name = String::from("bluetie");
+        let mut yaml = Vec::new();
+        let mut rows = decoded.rows().unwrap();
+        write_icon_states(&mut yaml, &mut rows, &decoded.metadata, false).unwrap();
+        let icon_states: IndexMap<String, Value> = serde_yml::from_slice(&yaml).unwrap();
+        assert_eq!("bluetie", icon_states.get_index(0).unwrap().0);
+        assert_eq!("bluetie@2", icon_states.get_index(1).unwrap().0);
+        assert_ne!(icon_states[0], icon_states[1]);
+    }
+
+    #[test]
+    fn test_decompile_bytes_block_scalars() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let yaml = decompile_bytes(&dmi).unwrap();
+        assert!(yaml.starts_with("__image_width: 128\n__image_height: 128\nspin: |-\n  "));
+        assert!(yaml.contains("\nstill: |-\n  ABAAAE+AQCD/"));
+        let data: IndexMap<String, Value> = serde_yml::from_str(&yaml).unwrap();
+        assert_eq!(3, data["spin"].as_str().unwrap().lines().count());
+        assert_eq!(8, data["walk"].as_str().unwrap().lines().count());
+        let mut key = Vec::new();
+        write_yaml_block_key(&mut key, "a: b").unwrap();
+        assert_eq!("'a: b': |-\n", String::from_utf8(key).unwrap());
+    }
+
+    // decompile a .dmi file row by row, and again from the whole image
+    fn decompile_both_ways(dmi: &[u8]) -> (String, String, Vec<String>) {
+        let mut diagnostics = Diagnostics::new();
+        let decoded = decode_dmi(dmi, None, false, &mut diagnostics).unwrap();
+        let mut streamed = Vec::new();
+        write_icon(&mut streamed, None, &decoded, true).unwrap();
+        let image = to_rgba_image(decode_image(dmi).unwrap(), "", &mut Diagnostics::new());
+        let decoded = DecodedDmi {
+            pixels: Pixels::Image(image),
+            ..decoded
+        };
+        let mut whole = Vec::new();
+        write_icon(&mut whole, None, &decoded, true).unwrap();
+        (
+            String::from_utf8(streamed).unwrap(),
+            String::from_utf8(whole).unwrap(),
+            diagnostics.warnings().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_decompile_rows() {
+        for path in [
+            "tests/data/decompile/neck.dmi",
+            "tests/data/dmi/grey16.dmi",
+            "tests/data/edit/spin.dmi",
+            "tests/data/gags/scarf.dmi",
+        ] {
+            let dmi = std::fs::read(path).unwrap();
+            let (streamed, whole, _) = decompile_both_ways(&dmi);
+            assert_eq!(whole, streamed, "{path}");
+        }
+
+        // 8-bit grayscale is converted row by row, with the same warning
+        let text = "# BEGIN DMI\nversion = 4.0\n\twidth = 2\n\theight = 2\nstate = \"grey\"\n\tdirs = 1\n\tframes = 3\n# END DMI\n";
+        let mut dmi = Vec::new();
+        let mut encoder = png::Encoder::new(&mut dmi, 5, 4);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder
+            .add_ztxt_chunk(String::from("Description"), String::from(text))
+            .unwrap();
+        let pixels: Vec<u8> = (0..20).map(|x| x * 12).collect();
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&pixels)
+            .unwrap();
+        let (streamed, whole, warnings) = decompile_both_ways(&dmi);
+        assert_eq!(whole, streamed);
+        assert!(streamed.contains("__image_width: 5\n"));
+        assert_eq!(
+            vec!["icontool: converting the .dmi data from L8 pixels to 8-bit RGBA"],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_decompile_stats() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let decoded = decode_dmi(&dmi, None, false, &mut Diagnostics::new()).unwrap();
+        let stats = write_icon(&mut Vec::new(), None, &decoded, false).unwrap();
+        assert_eq!(3, stats.states);
+        assert_eq!(12, stats.frames);
+        assert_eq!(12 * 32 * 32 * 4, stats.raw_bytes);
+        let total: usize = stats.state_bytes.iter().map(|x| x.1).sum();
+        assert_eq!(total, stats.compressed_bytes);
+        let lines = format_stats(&stats);
+        assert_eq!("icon_states: 3", lines[0]);
+        assert_eq!("largest icon_states:", lines[3]);
+        assert!(lines[4].starts_with("  \"walk\": "));
+        assert_eq!(7, lines.len());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_decompile_state() {
+        std::fs::create_dir_all("tests/data/decompile").unwrap();
+        let output = String::from("tests/data/decompile/spin.state.out.dmi.yml");
+        let mut args = DecompileArgs {
+            output: Some(output.clone()),
+            state: vec![String::from("still"), String::from("walk")],
+            file: String::from("tests/data/edit/spin.dmi"),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        let yaml = std::fs::read_to_string(&output).unwrap();
+        assert!(!yaml.contains(DMI_PATH_KEY));
+        assert!(!yaml.contains("spin:"));
+
+        // the subset compiles, and keeps the order of the original file
+        let original = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let subset = DmiFile::from_bytes(&crate::compile::compile_bytes(&yaml).unwrap()).unwrap();
+        assert_eq!(vec!["walk", "still"], subset.metadata.state_keys());
+        assert_eq!(original.frames[1], subset.frames[0]);
+        assert_eq!(original.frames[2], subset.frames[1]);
+
+        args.state = vec![String::from("run")];
+        match decompile(&args) {
+            Err(IconToolError::StateNotFound(x)) => assert_eq!("run", x),
+            _ => panic!("test_decompile_state: Expected StateNotFound error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_decompile_lenient() {
+        // spin.dmi has 12 frames on a 4x3 grid; keep only the first two rows
+        let original = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let image = original.to_image().crop_imm(0, 0, 128, 64);
+        let path = PathBuf::from("tests/data/decompile/truncated.out.dmi");
+        let text = original.metadata.to_string();
+        crate::dmi::write_dmi_file(&path, crate::constant::ZTXT_KEYWORD, &text, &image).unwrap();
+
+        let output = String::from("tests/data/decompile/truncated.out.dmi.yml");
+        let mut args = DecompileArgs {
+            output: Some(output.clone()),
+            file: path.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        match decompile(&args) {
+            Err(IconToolError::ImageTooSmall(12, 8)) => {}
+            _ => panic!("test_decompile_lenient: Expected ImageTooSmall error"),
+        }
+        args.lenient = true;
+        decompile(&args).unwrap();
+
+        // the frames that survived are kept, and the rest are transparent
+        let yaml = std::fs::read_to_string(&output).unwrap();
+        let sheet = DmiFile::from_bytes(&crate::compile::compile_bytes(&yaml).unwrap()).unwrap();
+        assert_eq!(original.frames[0], sheet.frames[0]);
+        assert_eq!(original.frames[1][..5], sheet.frames[1][..5]);
+        let blank = RgbaImage::new(32, 32);
+        assert!(sheet.frames[1][5..].iter().all(|x| *x == blank));
+        assert_eq!(vec![blank], sheet.frames[2]);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_fill_missing_frames_warnings() {
+        let original = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let mut diagnostics = Diagnostics::new();
+        let size = fill_missing_frames((128, 64), &original.metadata, true, &mut diagnostics);
+        assert_eq!((128, 96), size.unwrap());
+        assert_eq!(
+            vec![
+                "icontool: icon_state 'walk' is missing 3 of its 8 frame(s) in the image; using transparent frames",
+                "icontool: icon_state 'still' is missing 1 of its 1 frame(s) in the image; using transparent frames",
+            ],
+            diagnostics.warnings()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_fill_missing_frames_limits() {
+        let mut dmi = DmiFile::open(Path::new("tests/data/edit/spin.dmi"))
+            .unwrap()
+            .metadata;
+        dmi.states[1].frames = 1000;
+        let mut diagnostics = Diagnostics::new();
+        match fill_missing_frames((128, 64), &dmi, true, &mut diagnostics) {
+            Err(IconToolError::TooManyIconStates(128, 32032)) => {}
+            _ => panic!("test_fill_missing_frames_limits: Expected TooManyIconStates error"),
+        }
+        dmi.states[1].frames = u32::MAX;
+        match fill_missing_frames((128, 64), &dmi, false, &mut diagnostics) {
+            Err(IconToolError::ImageTooSmall(usize::MAX, 8)) => {}
+            _ => panic!("test_fill_missing_frames_limits: Expected ImageTooSmall error"),
+        }
+        match fill_missing_frames((128, 64), &dmi, true, &mut diagnostics) {
+            Err(IconToolError::TooManyIconStates(128, u32::MAX)) => {}
+            _ => panic!("test_fill_missing_frames_limits: Expected TooManyIconStates error"),
+        }
+        assert!(diagnostics.warnings().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_decompile_archive() {
+        let dir = Path::new("tests/data/decompile/archive.out.d");
+        let _ = std::fs::remove_dir_all(dir);
+        let archive = dir.join("icons.zip");
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let files = vec![
+            (String::from("icons/mob/spin.dmi"), dmi.clone()),
+            (String::from("icons/spin.dmi"), dmi),
+        ];
+        crate::archive::write_entries(&archive, &files).unwrap();
+
+        // every .dmi file in the archive goes into another archive
+        let output = dir.join("yaml.zip");
+        let args = DecompileArgs {
+            output_dir: Some(output.display().to_string()),
+            strip_prefix: Some(String::from("icons/")),
+            file: archive.display().to_string(),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        assert_eq!(
+            vec!["mob/spin.dmi.yml", "spin.dmi.yml"],
+            list_entries(&output, ".yml").unwrap()
+        );
+        let yaml = crate::archive::read_entry(&output, "spin.dmi.yml").unwrap();
+        let yaml = String::from_utf8(yaml).unwrap();
+        assert!(yaml.starts_with(IMAGE_WIDTH_KEY));
+
+        // or into a directory
+        let args = DecompileArgs {
+            output_dir: Some(dir.join("yaml").display().to_string()),
+            file: archive.display().to_string(),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        assert!(dir.join("yaml/icons/mob/spin.dmi.yml").exists());
+
+        // a single .dmi file can be added to an archive too
+        let args = DecompileArgs {
+            output_dir: Some(output.display().to_string()),
+            strip_prefix: Some(String::from("tests/data/")),
+            file: String::from("tests/data/edit/spin.dmi"),
+            ..Default::default()
+        };
+        decompile(&args).unwrap();
+        assert_eq!(3, list_entries(&output, ".yml").unwrap().len());
+
+        let args = DecompileArgs {
+            file: archive.display().to_string(),
+            ..Default::default()
+        };
+        match decompile(&args) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_decompile_archive: Expected InvalidArgument error"),
+        }
+    }
 }
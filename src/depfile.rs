@@ -0,0 +1,92 @@
+// depfile.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::{Path, PathBuf};
+
+use crate::atomic::write_atomic;
+use crate::error::Result;
+use crate::walk::create_parent_dir;
+
+// write a Make depfile, which Ninja also reads, so a build system knows to
+// run icontool again when any of the prerequisites change
+pub fn write_depfile(path: &Path, targets: &[PathBuf], prerequisites: &[PathBuf]) -> Result<()> {
+    create_parent_dir(path)?;
+    write_atomic(path, format_depfile(targets, prerequisites))
+}
+
+pub fn format_depfile(targets: &[PathBuf], prerequisites: &[PathBuf]) -> String {
+    let join = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .map(|x| escape_path(&x.to_string_lossy()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    let mut depfile = format!("{}: {}\n", join(targets), join(prerequisites));
+    // an empty rule for each prerequisite keeps make happy if one is deleted
+    for prerequisite in prerequisites {
+        depfile.push_str(&format!(
+            "{}:\n",
+            escape_path(&prerequisite.to_string_lossy())
+        ));
+    }
+    depfile
+}
+
+// make splits on spaces and treats $ and # specially, so escape them
+fn escape_path(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        match c {
+            ' ' => escaped.push_str("\\ "),
+            '#' => escaped.push_str("\\#"),
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_format_depfile() {
+        let targets = vec![PathBuf::from("icons/door.dmi")];
+        let prerequisites = vec![PathBuf::from("src/door.dmi.yml")];
+        assert_eq!(
+            "icons/door.dmi: src/door.dmi.yml\nsrc/door.dmi.yml:\n",
+            format_depfile(&targets, &prerequisites)
+        );
+        let targets = vec![PathBuf::from("a.1.dmi"), PathBuf::from("a.2.dmi")];
+        let prerequisites = vec![PathBuf::from("my door #2 ($5).dmi.yml")];
+        assert_eq!(
+            "a.1.dmi a.2.dmi: my\\ door\\ \\#2\\ ($$5).dmi.yml\nmy\\ door\\ \\#2\\ ($$5).dmi.yml:\n",
+            format_depfile(&targets, &prerequisites)
+        );
+    }
+}
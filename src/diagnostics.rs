@@ -0,0 +1,99 @@
+// diagnostics.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// Diagnostics collects the warnings found while compiling or decompiling,
+// so the caller decides what to do with them: the command line prints them
+// (and with --deny-warnings, fails), while library users can inspect them
+
+use crate::error::{get_error_message, IconToolError, Result};
+#[cfg(feature = "cli")]
+use crate::log::diagnostic;
+
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    warnings: Vec<String>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    // some problems are only worth a warning in context
+    pub fn warn_error(&mut self, error: IconToolError) {
+        self.warn(get_error_message(error));
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    // without the command line there is no --log-file to copy them to
+    pub fn print(&self) {
+        for warning in &self.warnings {
+            #[cfg(feature = "cli")]
+            diagnostic(warning);
+            #[cfg(not(feature = "cli"))]
+            eprintln!("{warning}");
+        }
+    }
+
+    // print the warnings; if they are denied, fail when there are any
+    pub fn report(&self, deny_warnings: bool) -> Result<()> {
+        self.print();
+        if deny_warnings && !self.is_empty() {
+            return Err(IconToolError::WarningsDenied(self.warnings.len()));
+        }
+        Ok(())
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut diagnostics = Diagnostics::new();
+        assert!(diagnostics.report(true).is_ok());
+        diagnostics.warn("icontool: something odd");
+        diagnostics.warn_error(IconToolError::MovementWithoutBase(String::from("walk")));
+        assert_eq!(2, diagnostics.warnings().len());
+        assert!(diagnostics.report(false).is_ok());
+        match diagnostics.report(true) {
+            Err(IconToolError::WarningsDenied(2)) => {}
+            _ => panic!("test_report: Expected WarningsDenied error"),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+// dm_code.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::cmdline::{MissingArgs, UnusedArgs};
+use crate::dmi::read_metadata;
+use crate::error::{IconToolError, Result};
+use crate::parser::parse_metadata;
+use crate::walk::find_files;
+
+// an icon_state named by DM source code
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateReference {
+    pub line: usize,          // 1-based line number in the .dm file
+    pub icon: Option<String>, // the icon assigned in the same definition
+    pub state: String,
+}
+
+// every icon_state named by the scanned DM source code; names built with
+// embedded expressions like "[base]_on" are kept as patterns
+#[derive(Debug, Default)]
+pub struct ReferencedStates {
+    pub names: HashSet<String>,
+    pub patterns: Vec<String>,
+}
+
+impl ReferencedStates {
+    pub fn contains(&self, state: &str) -> bool {
+        self.names.contains(state) || self.patterns.iter().any(|x| matches_pattern(x, state))
+    }
+}
+
+pub fn unused(args: &UnusedArgs) -> Result<()> {
+    // collect every icon_state named in the code
+    let referenced = scan_code(&args.code)?;
+
+    // report the icon_states that none of the code names
+    for path in find_files(&args.icons, true, ".dmi")? {
+        for state in find_unused_states(&path, &referenced)? {
+            println!("{}: \"{state}\"", path.display());
+        }
+    }
+    Ok(())
+}
+
+pub fn find_unused_states(path: &Path, referenced: &ReferencedStates) -> Result<Vec<String>> {
+    let dmi = parse_metadata(&read_metadata(path)?)?;
+    let mut unused = Vec::new();
+    for state in &dmi.states {
+        if !referenced.contains(&state.name) && !unused.contains(&state.name) {
+            unused.push(state.name.clone());
+        }
+    }
+    Ok(unused)
+}
+
+pub fn missing(args: &MissingArgs) -> Result<()> {
+    // collect the icon_states of every .dmi file
+    let mut icons = Vec::new();
+    for path in find_files(&args.icons, true, ".dmi")? {
+        let dmi = parse_metadata(&read_metadata(&path)?)?;
+        let states: HashSet<String> = dmi.states.into_iter().map(|x| x.name).collect();
+        icons.push((path, states));
+    }
+
+    // report each reference that doesn't resolve to an icon_state
+    let mut num_missing = 0;
+    for (path, reference) in scan_code_references(&args.code)? {
+        if let Some(problem) = resolve_reference(&reference, &icons) {
+            println!("{}:{}: {problem}", path.display(), reference.line);
+            num_missing += 1;
+        }
+    }
+
+    // if anything was missing, tell the caller
+    if num_missing > 0 {
+        return Err(IconToolError::UnresolvedStates(num_missing));
+    }
+    Ok(())
+}
+
+// check that a reference names an icon_state of its icon, or of any icon if
+// we don't know which icon it uses; returns the problem if it doesn't
+pub fn resolve_reference(
+    reference: &StateReference,
+    icons: &[(PathBuf, HashSet<String>)],
+) -> Option<String> {
+    // we can't check names built from expressions, or the empty default
+    let state = &reference.state;
+    if state.is_empty() || state.contains('[') {
+        return None;
+    }
+
+    // if the icon is one of ours, the state must be in it
+    let icon = reference.icon.as_ref().and_then(|icon| {
+        icons
+            .iter()
+            .find(|(path, _)| path.ends_with(Path::new(icon)))
+    });
+    match icon {
+        Some((_, states)) if states.contains(state) => None,
+        Some(_) => Some(format!(
+            "icon_state \"{state}\" not found in '{}'",
+            reference.icon.as_deref().unwrap_or_default()
+        )),
+        None if icons.iter().any(|(_, states)| states.contains(state)) => None,
+        None => Some(format!("icon_state \"{state}\" not found in any .dmi file")),
+    }
+}
+
+pub fn scan_code(paths: &[String]) -> Result<ReferencedStates> {
+    let mut referenced = ReferencedStates::default();
+    for (_, reference) in scan_code_references(paths)? {
+        if reference.state.contains('[') {
+            referenced.patterns.push(reference.state);
+        } else {
+            referenced.names.insert(reference.state);
+        }
+    }
+    Ok(referenced)
+}
+
+pub fn scan_code_references(paths: &[String]) -> Result<Vec<(PathBuf, StateReference)>> {
+    let mut references = Vec::new();
+    for path in find_files(paths, true, ".dm")? {
+        let text = std::fs::read_to_string(&path)?;
+        for reference in scan_dm_text(&text) {
+            references.push((path.clone(), reference));
+        }
+    }
+    Ok(references)
+}
+
+// find each icon_state = "..." assignment in some DM source code, along
+// with the icon = '...' assigned before it in the same definition
+pub fn scan_dm_text(text: &str) -> Vec<StateReference> {
+    let mut references = Vec::new();
+    let mut icon = None;
+    for (index, line) in text.lines().enumerate() {
+        // skip comments
+        let code = match line.find("//") {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        // an unindented line starts a new definition
+        if !code.trim().is_empty() && !code.starts_with(char::is_whitespace) {
+            icon = None;
+        }
+
+        // handle the assignments in the order they appear on the line
+        let mut assignments: Vec<(usize, bool, String)> = Vec::new();
+        for (offset, value) in find_assignments(code, "icon", '\'') {
+            assignments.push((offset, true, value));
+        }
+        for (offset, value) in find_assignments(code, "icon_state", '"') {
+            assignments.push((offset, false, value));
+        }
+        assignments.sort_by_key(|x| x.0);
+        for (_, is_icon, value) in assignments {
+            if is_icon {
+                icon = Some(value);
+            } else {
+                references.push(StateReference {
+                    line: index + 1,
+                    icon: icon.clone(),
+                    state: value,
+                });
+            }
+        }
+    }
+    references
+}
+
+// find each place the variable is assigned a quoted string
+fn find_assignments(code: &str, name: &str, quote: char) -> Vec<(usize, String)> {
+    let is_name_char = |x: char| x.is_alphanumeric() || x == '_';
+    let mut assignments = Vec::new();
+    for (offset, _) in code.match_indices(name) {
+        // make sure we found the whole variable name
+        let before = code[..offset].chars().next_back();
+        let rest = &code[offset + name.len()..];
+        if before.is_some_and(is_name_char) || rest.starts_with(is_name_char) {
+            continue;
+        }
+        if let Some(value) = read_assigned_string(rest, quote) {
+            assignments.push((offset, value));
+        }
+    }
+    assignments
+}
+
+// given the text after a variable name, read the string assigned to it
+fn read_assigned_string(rest: &str, quote: char) -> Option<String> {
+    let rest = rest.trim_start().strip_prefix('=')?;
+    if rest.starts_with('=') {
+        return None; // a comparison, not an assignment
+    }
+    let rest = rest.trim_start().strip_prefix(quote)?;
+    let mut value = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?),
+            c if c == quote => return Some(value),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+// a pattern like "[base]_on" matches any name where each [...] is replaced
+// by some text, like "light_on"
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    // split the pattern into the literal pieces between the expressions
+    let mut pieces = Vec::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('[') {
+        pieces.push(&rest[..start]);
+        rest = match rest[start..].find(']') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    pieces.push(rest);
+
+    // the first piece starts the name, the last ends it, and the others
+    // appear in order in between
+    let first = pieces[0];
+    let last = pieces[pieces.len() - 1];
+    if pieces.len() == 1 {
+        return name == first;
+    }
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+    let mut middle = &name[first.len()..name.len() - last.len()];
+    for piece in &pieces[1..pieces.len() - 1] {
+        match middle.find(piece) {
+            Some(x) => middle = &middle[x + piece.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_scan_dm_text() {
+        let text = "/obj/item/spinner\n\ticon_state = \"spin\"\n\t// icon_state = \"commented\"\n\tif(icon_state == \"walk\")\n\t\tnew /obj{icon_state=\"sti\\\"ll\"}\n\tmy_icon_state = \"nope\"\n";
+        let references = scan_dm_text(text);
+        assert_eq!(
+            vec![
+                StateReference {
+                    line: 2,
+                    icon: None,
+                    state: String::from("spin")
+                },
+                StateReference {
+                    line: 5,
+                    icon: None,
+                    state: String::from("sti\"ll")
+                },
+            ],
+            references
+        );
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("spin", "spin"));
+        assert!(!matches_pattern("spin", "spin2"));
+        assert!(matches_pattern("[base]_on", "light_on"));
+        assert!(matches_pattern("door_[state]", "door_open"));
+        assert!(matches_pattern("[a]_[b]_[c]", "x_y_z"));
+        assert!(!matches_pattern("[base]_on", "light_off"));
+        assert!(!matches_pattern("ab[x]ba", "aba"));
+    }
+
+    #[test]
+    fn test_find_unused_states() {
+        std::fs::create_dir_all("tests/data/dm_code").unwrap();
+        let code = "tests/data/dm_code/unused.out.dm";
+        std::fs::write(code, "/obj/spinner\n\ticon_state = \"spin\"\n\tvar/base = \"wa\"\n\n/obj/spinner/proc/walk()\n\ticon_state = \"[base]lk\"\n").unwrap();
+        let referenced = scan_code(&[String::from(code)]).unwrap();
+        let unused =
+            find_unused_states(Path::new("tests/data/edit/spin.dmi"), &referenced).unwrap();
+        assert_eq!(vec![String::from("still")], unused);
+    }
+
+    #[test]
+    fn test_scan_dm_text_icons() {
+        let text = "/obj/a\n\ticon = 'icons/a.dmi'\n\ticon_state = \"one\"\n/obj/b\n\ticon_state = \"two\"\n\tnew /obj{icon = 'icons/c.dmi'; icon_state = \"three\"}\n";
+        let icons: Vec<Option<String>> = scan_dm_text(text).into_iter().map(|x| x.icon).collect();
+        assert_eq!(
+            vec![
+                Some(String::from("icons/a.dmi")),
+                None,
+                Some(String::from("icons/c.dmi"))
+            ],
+            icons
+        );
+    }
+
+    #[test]
+    fn test_resolve_reference() {
+        let states: HashSet<String> = ["spin", "walk"].iter().map(|x| x.to_string()).collect();
+        let icons = vec![(PathBuf::from("project/icons/obj/spin.dmi"), states)];
+        let reference = |icon: Option<&str>, state: &str| StateReference {
+            line: 1,
+            icon: icon.map(String::from),
+            state: String::from(state),
+        };
+        let resolve = |icon, state| resolve_reference(&reference(icon, state), &icons);
+        assert_eq!(None, resolve(Some("icons/obj/spin.dmi"), "walk"));
+        assert_eq!(None, resolve(None, "spin"));
+        assert_eq!(None, resolve(Some("icons/other.dmi"), "spin"));
+        assert_eq!(None, resolve(None, "[base]_on"));
+        assert_eq!(
+            Some(String::from(
+                "icon_state \"run\" not found in 'icons/obj/spin.dmi'"
+            )),
+            resolve(Some("icons/obj/spin.dmi"), "run")
+        );
+        assert_eq!(
+            Some(String::from(
+                "icon_state \"run\" not found in any .dmi file"
+            )),
+            resolve(None, "run")
+        );
+    }
+}
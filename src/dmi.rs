@@ -15,42 +15,369 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //---------------------------------------------------------------------------
 
-use image::{DynamicImage, ImageFormat, ImageReader};
+use base64::prelude::*;
+use image::{ColorType, DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use png::Encoder;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Cursor;
 use std::path::Path;
-use std::{fs::File, io::BufReader};
 
+use crate::atomic::write_atomic;
 use crate::constant::ZTXT_KEYWORD;
+use crate::diagnostics::Diagnostics;
 use crate::error::{IconToolError, MissingMetadata, Result};
+use crate::parser::{parse_metadata, DreamMakerIconMetadata};
+
+pub fn edit_metadata<F>(path: &Path, output_path: &Path, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut DreamMakerIconMetadata) -> Result<()>,
+{
+    // read the image and metadata from the .dmi file
+    let image = read_rgba_image(path)?;
+    let metadata_text = read_metadata(path)?;
+    let mut dmi = parse_metadata(&metadata_text)?;
+
+    // let the caller change the metadata
+    edit(&mut dmi)?;
+
+    // write the pixels back out with the regenerated metadata
+    let image = DynamicImage::ImageRgba8(image);
+    write_dmi_file(output_path, ZTXT_KEYWORD, &dmi.to_string(), &image)
+}
+
+pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage> {
+    let reader = ImageReader::with_format(Cursor::new(bytes), ImageFormat::Png);
+    let image = reader.decode()?;
+    Ok(image)
+}
 
 pub fn read_image(path: &Path) -> Result<DynamicImage> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let image = ImageReader::with_format(reader, ImageFormat::Png).decode()?;
+    decode_image(&std::fs::read(path)?)
+}
+
+// icontool works with 8-bit RGBA pixels; grayscale, 16-bit, and paletted
+// images without transparency are converted when they are read
+pub fn to_rgba_image(
+    image: DynamicImage,
+    source: &str,
+    diagnostics: &mut Diagnostics,
+) -> RgbaImage {
+    if let DynamicImage::ImageRgba8(image) = image {
+        return image;
+    }
+    warn_for_conversion(image.color(), source, diagnostics);
+    image.to_rgba8()
+}
+
+pub fn warn_for_conversion(color: ColorType, source: &str, diagnostics: &mut Diagnostics) {
+    diagnostics.warn(format!(
+        "icontool: converting {source} from {color:?} pixels to 8-bit RGBA"
+    ));
+}
+
+pub fn read_rgba_image(path: &Path) -> Result<RgbaImage> {
+    let image = read_image(path)?;
+    let mut diagnostics = Diagnostics::new();
+    let image = to_rgba_image(image, &path.display().to_string(), &mut diagnostics);
+    diagnostics.print();
     Ok(image)
 }
 
+pub fn decode_metadata(bytes: &[u8]) -> Result<Option<String>> {
+    // read the png data from the .dmi file
+    let decoder = png::Decoder::new(Cursor::new(bytes));
+    let reader = decoder.read_info()?;
+
+    // DreamMaker writes the metadata to a zTXt chunk with keyword 'Description'
+    let info = reader.info();
+    for text_chunk in &info.compressed_latin1_text {
+        if text_chunk.keyword == ZTXT_KEYWORD {
+            return Ok(Some(text_chunk.get_text()?));
+        }
+    }
+
+    // but some tools write it uncompressed in a tEXt or iTXt chunk instead
+    for text_chunk in &info.uncompressed_latin1_text {
+        if text_chunk.keyword == ZTXT_KEYWORD {
+            return Ok(Some(text_chunk.text.clone()));
+        }
+    }
+    for text_chunk in &info.utf8_text {
+        if text_chunk.keyword == ZTXT_KEYWORD {
+            return Ok(Some(text_chunk.get_text()?));
+        }
+    }
+
+    // we didn't find a text chunk with dmi metadata
+    Ok(None)
+}
+
 pub fn read_metadata(path: &Path) -> Result<String> {
+    match decode_metadata(&std::fs::read(path)?)? {
+        Some(metadata) => Ok(metadata),
+        None => Err(IconToolError::MissingMetadata(MissingMetadata(path.into()))),
+    }
+}
+
+// ancillary chunks that describe the pixels as they were encoded; the
+// encoder writes its own versions of these, so they're never carried over
+const ENCODING_CHUNKS: [&[u8; 4]; 4] = [b"bKGD", b"hIST", b"sBIT", b"tRNS"];
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngChunk {
+    pub chunk_type: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl PngChunk {
+    // chunks are stored in the yaml as the chunk type and base64 data,
+    // separated by a space; for example: "pHYs AAALEwAACxMB"
+    pub fn parse(text: &str) -> Result<PngChunk> {
+        let invalid = || IconToolError::InvalidPngChunk(text.to_string());
+        let (chunk_type, data) = text.split_once(' ').ok_or_else(invalid)?;
+        let chunk_type: [u8; 4] = chunk_type.as_bytes().try_into().map_err(|_| invalid())?;
+        if !chunk_type.iter().all(u8::is_ascii_alphabetic) || !is_ancillary(&chunk_type) {
+            return Err(invalid());
+        }
+        let data = BASE64_STANDARD.decode(data.trim())?;
+        Ok(PngChunk { chunk_type, data })
+    }
+
+    fn is_description(&self) -> bool {
+        let text_chunk = matches!(&self.chunk_type, b"tEXt" | b"zTXt" | b"iTXt");
+        let keyword = self.data.split(|x| *x == 0).next().unwrap_or_default();
+        text_chunk && keyword == ZTXT_KEYWORD.as_bytes()
+    }
+}
+
+impl fmt::Display for PngChunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chunk_type = String::from_utf8_lossy(&self.chunk_type);
+        write!(f, "{chunk_type} {}", BASE64_STANDARD.encode(&self.data))
+    }
+}
+
+fn is_ancillary(chunk_type: &[u8; 4]) -> bool {
+    // a lowercase first letter marks a chunk the image doesn't depend on
+    chunk_type[0].is_ascii_lowercase()
+}
+
+pub fn read_png_chunks(path: &Path) -> Result<Vec<PngChunk>> {
+    decode_png_chunks(&std::fs::read(path)?)
+}
+
+pub fn decode_png_chunks(bytes: &[u8]) -> Result<Vec<PngChunk>> {
+    let truncated = || IconToolError::InvalidPngChunk(String::from("the PNG data is truncated"));
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return Err(IconToolError::InvalidPngChunk(String::from(
+            "the data is not a PNG file",
+        )));
+    }
+
+    // walk the chunks, keeping the ancillary ones we don't write ourselves
+    let mut chunks = Vec::new();
+    let mut cursor = PNG_SIGNATURE.len();
+    while cursor < bytes.len() {
+        let header = bytes.get(cursor..cursor + 8).ok_or_else(truncated)?;
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let chunk_type: [u8; 4] = header[4..8].try_into().unwrap();
+        let data = bytes
+            .get(cursor + 8..cursor + 8 + length)
+            .ok_or_else(truncated)?;
+        let chunk = PngChunk {
+            chunk_type,
+            data: data.to_vec(),
+        };
+        if is_ancillary(&chunk_type)
+            && !ENCODING_CHUNKS.contains(&&chunk_type)
+            && !chunk.is_description()
+        {
+            chunks.push(chunk);
+        }
+        // skip over the length, type, data, and crc
+        cursor += length + 12;
+    }
+
+    Ok(chunks)
+}
+
+pub fn read_text_chunk(path: &Path, keyword: &str) -> Result<Option<String>> {
     // read the png data from the .dmi file
     let dmi_file = File::open(path)?;
     let decoder = png::Decoder::new(dmi_file);
     let reader = decoder.read_info()?;
 
-    // for each zTXt chunk in the png file
-    for text_chunk in &reader.info().compressed_latin1_text {
-        // println!("{:?}", text_chunk.keyword);
-        // println!("zTXt: {}", text_chunk.get_text().unwrap());
+    // look for a tEXt chunk with the provided keyword
+    for text_chunk in &reader.info().uncompressed_latin1_text {
+        if text_chunk.keyword == keyword {
+            return Ok(Some(text_chunk.text.clone()));
+        }
+    }
 
-        // if the chunk has keyword 'Description'
-        if text_chunk.keyword == ZTXT_KEYWORD {
-            // extract the dmi metadata from the zTXt chunk
-            let metadata = text_chunk.get_text()?;
-            return Ok(metadata);
+    // the .dmi file didn't contain the chunk
+    Ok(None)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum MetadataChunk {
+    /// compressed text, as written by DreamMaker
+    #[default]
+    Ztxt,
+    /// uncompressed text
+    Text,
+    /// international (UTF-8) text
+    Itxt,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    pub chunks: Vec<PngChunk>, // copied verbatim after the IHDR chunk
+    pub metadata_chunk: MetadataChunk,
+    pub no_palette: bool, // always write 32-bit RGBA pixels
+    pub text_chunks: Vec<(String, String)>,
+}
+
+pub fn write_dmi_file(path: &Path, keyword: &str, text: &str, image: &DynamicImage) -> Result<()> {
+    write_dmi_file_with_options(path, keyword, text, image, &WriteOptions::default())
+}
+
+pub fn write_dmi_file_with_options(
+    path: &Path,
+    keyword: &str,
+    text: &str,
+    image: &DynamicImage,
+    options: &WriteOptions,
+) -> Result<()> {
+    let png_data = encode_dmi(keyword, text, image, options)?;
+    write_atomic(path, png_data)
+}
+
+pub fn encode_dmi(
+    keyword: &str,
+    text: &str,
+    image: &DynamicImage,
+    options: &WriteOptions,
+) -> Result<Vec<u8>> {
+    // use the PNG encoder to create the metadata
+    let mut png_data = Vec::new();
+    let image = image.to_rgba8();
+    let width = image.width();
+    let height = image.height();
+    let mut encoder = Encoder::new(&mut png_data, width, height);
+    match options.metadata_chunk {
+        MetadataChunk::Ztxt => encoder.add_ztxt_chunk(keyword.to_string(), text.to_string())?,
+        MetadataChunk::Text => encoder.add_text_chunk(keyword.to_string(), text.to_string())?,
+        MetadataChunk::Itxt => encoder.add_itxt_chunk(keyword.to_string(), text.to_string())?,
+    }
+    for (chunk_keyword, chunk_text) in &options.text_chunks {
+        encoder.add_text_chunk(chunk_keyword.to_string(), chunk_text.to_string())?;
+    }
+
+    // if the image has few enough colors, write it with a palette
+    let indexed = match options.no_palette {
+        true => None,
+        false => index_colors(&image),
+    };
+    let data = match indexed {
+        Some(indexed) => {
+            let depth = match indexed.palette.len() {
+                0..=2 => png::BitDepth::One,
+                3..=4 => png::BitDepth::Two,
+                5..=16 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            };
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(depth);
+            encoder.set_palette(
+                indexed
+                    .palette
+                    .iter()
+                    .flat_map(|c| [c[0], c[1], c[2]])
+                    .collect::<Vec<u8>>(),
+            );
+            encoder.set_trns(indexed.palette.iter().map(|c| c[3]).collect::<Vec<u8>>());
+            pack_indices(&indexed.indices, width, depth as u8)
+        }
+        None => {
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            image.into_raw()
         }
+    };
+
+    // write the PNG header and image data
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+
+    // finish the correctness-verified PNG
+    writer.finish()?;
+
+    // ancillary chunks are valid anywhere before the image data, so
+    // we put any extra chunks right after the header
+    let mut position = PNG_SIGNATURE.len() + 25;
+    for chunk in &options.chunks {
+        let mut bytes = Vec::with_capacity(chunk.data.len() + 12);
+        bytes.extend_from_slice(&(chunk.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&chunk.chunk_type);
+        bytes.extend_from_slice(&chunk.data);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&chunk.chunk_type);
+        hasher.update(&chunk.data);
+        bytes.extend_from_slice(&hasher.finalize().to_be_bytes());
+        png_data.splice(position..position, bytes.iter().cloned());
+        position += bytes.len();
     }
 
-    // if we didn't find a zTXt chunk with dmi metadata
-    let missing_metadata = MissingMetadata(path.into());
-    Err(IconToolError::MissingMetadata(missing_metadata))
+    Ok(png_data)
+}
+
+struct IndexedImage {
+    palette: Vec<[u8; 4]>,
+    indices: Vec<u8>,
+}
+
+fn index_colors(image: &RgbaImage) -> Option<IndexedImage> {
+    // build a palette of up to 256 colors, in the order they appear
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity((image.width() * image.height()) as usize);
+    for pixel in image.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(index) => *index,
+            None => {
+                if palette.len() == 256 {
+                    return None;
+                }
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                lookup.insert(pixel.0, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some(IndexedImage { palette, indices })
+}
+
+fn pack_indices(indices: &[u8], width: u32, depth: u8) -> Vec<u8> {
+    // each row of pixels starts on a fresh byte
+    let per_byte = (8 / depth) as usize;
+    let mut data = Vec::new();
+    for row in indices.chunks(width as usize) {
+        for group in row.chunks(per_byte) {
+            let mut byte = 0;
+            for (position, index) in group.iter().enumerate() {
+                byte |= index << (8 - depth as usize * (position + 1));
+            }
+            data.push(byte);
+        }
+    }
+    data
 }
 
 //---------------------------------------------------------------------------
@@ -59,10 +386,111 @@ pub fn read_metadata(path: &Path) -> Result<String> {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn test_always_succeed() {
         assert!(true);
     }
+
+    #[test]
+    fn test_pack_indices() {
+        assert_eq!(vec![0b1010_1000], pack_indices(&[1, 0, 1, 0, 1], 5, 1));
+        assert_eq!(
+            vec![0b1000_0000, 0b0100_0000],
+            pack_indices(&[1, 0, 0, 1], 2, 1)
+        );
+        assert_eq!(
+            vec![0x12, 0x30, 0x45, 0x60],
+            pack_indices(&[1, 2, 3, 4, 5, 6], 3, 4)
+        );
+        assert_eq!(vec![7, 8], pack_indices(&[7, 8], 2, 8));
+    }
+
+    #[test]
+    fn test_read_rgba_image_16_bit_grayscale() {
+        let path = PathBuf::from("tests/data/dmi/grey16.dmi");
+        assert_eq!(image::ColorType::La16, read_image(&path).unwrap().color());
+        let image = read_rgba_image(&path).unwrap();
+        assert_eq!(&image::Rgba([128, 128, 128, 255]), image.get_pixel(0, 0));
+        assert_eq!(0, image.get_pixel(2, 1).0[3]);
+        assert_eq!(&image::Rgba([255, 255, 255, 255]), image.get_pixel(3, 1));
+    }
+
+    #[test]
+    fn test_png_chunk_parse() {
+        let chunk = PngChunk::parse("gAMA AACxjw==").unwrap();
+        assert_eq!(b"gAMA", &chunk.chunk_type);
+        assert_eq!(vec![0, 0, 177, 143], chunk.data);
+        assert_eq!("gAMA AACxjw==", chunk.to_string());
+        for text in ["gAMA", "IDAT AACxjw==", "gAM AACxjw=="] {
+            match PngChunk::parse(text) {
+                Err(IconToolError::InvalidPngChunk(_)) => {}
+                _ => panic!("test_png_chunk_parse: Expected InvalidPngChunk error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_png_chunks() {
+        std::fs::create_dir_all("tests/data/dmi").unwrap();
+        let path = PathBuf::from("tests/data/dmi/chunks.out.dmi");
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+        let chunks = vec![
+            PngChunk::parse("gAMA AACxjw==").unwrap(),
+            PngChunk::parse("tEXt U29mdHdhcmUAdGVzdA==").unwrap(),
+        ];
+        let options = WriteOptions {
+            chunks: chunks.clone(),
+            ..Default::default()
+        };
+        write_dmi_file_with_options(&path, ZTXT_KEYWORD, "text", &image, &options).unwrap();
+        assert_eq!(chunks, read_png_chunks(&path).unwrap());
+        assert_eq!("text", read_metadata(&path).unwrap());
+        assert_eq!(image.to_rgba8(), read_image(&path).unwrap().to_rgba8());
+    }
+
+    #[test]
+    fn test_read_metadata_text_chunks() {
+        std::fs::create_dir_all("tests/data/dmi").unwrap();
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+        for (name, metadata_chunk) in [
+            ("ztxt", MetadataChunk::Ztxt),
+            ("text", MetadataChunk::Text),
+            ("itxt", MetadataChunk::Itxt),
+        ] {
+            let path = PathBuf::from(format!("tests/data/dmi/metadata_{name}.out.dmi"));
+            let options = WriteOptions {
+                metadata_chunk,
+                ..Default::default()
+            };
+            write_dmi_file_with_options(&path, ZTXT_KEYWORD, "text", &image, &options).unwrap();
+            assert_eq!("text", read_metadata(&path).unwrap());
+            assert!(read_png_chunks(&path).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_write_indexed() {
+        std::fs::create_dir_all("tests/data/dmi").unwrap();
+        let mut image = RgbaImage::new(5, 3);
+        image.put_pixel(1, 1, image::Rgba([255, 0, 0, 128]));
+        image.put_pixel(4, 2, image::Rgba([0, 0, 255, 255]));
+        let image = DynamicImage::ImageRgba8(image);
+        for (name, no_palette, color_type) in [
+            ("indexed", false, png::ColorType::Indexed),
+            ("rgba", true, png::ColorType::Rgba),
+        ] {
+            let path = PathBuf::from(format!("tests/data/dmi/{name}.out.dmi"));
+            let options = WriteOptions {
+                no_palette,
+                ..Default::default()
+            };
+            write_dmi_file_with_options(&path, ZTXT_KEYWORD, "text", &image, &options).unwrap();
+            let decoder = png::Decoder::new(File::open(&path).unwrap());
+            assert_eq!(color_type, decoder.read_info().unwrap().info().color_type);
+            assert_eq!(image.to_rgba8(), read_image(&path).unwrap().to_rgba8());
+        }
+    }
 }
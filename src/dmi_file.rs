@@ -0,0 +1,374 @@
+// dmi_file.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{imageops, DynamicImage, RgbaImage};
+use std::path::{Path, PathBuf};
+
+use crate::atomic::write_atomic;
+use crate::constant::{MAX_IMAGE_HEIGHT, MAX_IMAGE_WIDTH, ZTXT_KEYWORD};
+use crate::dmi::{
+    decode_image, decode_metadata, encode_dmi, read_metadata, read_rgba_image, WriteOptions,
+};
+use crate::error::{IconToolError, MissingMetadata, Result};
+use crate::parser::{parse_metadata, DreamMakerIconMetadata, DreamMakerIconState};
+use crate::validate::validate_metadata;
+
+// A .dmi file cut up into individual frames. Each icon_state holds its
+// frames in sheet order: every direction of frame 1, then every direction
+// of frame 2, and so on.
+#[derive(Clone, Debug)]
+pub struct DmiFile {
+    pub metadata: DreamMakerIconMetadata,
+    pub frames: Vec<Vec<RgbaImage>>,
+}
+
+// an icon_state of a DmiFile, along with its frames
+#[derive(Clone, Copy, Debug)]
+pub struct IconState<'a> {
+    pub metadata: &'a DreamMakerIconState,
+    pub frames: &'a [RgbaImage],
+}
+
+impl<'a> IconState<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.metadata.name
+    }
+
+    // the frame shown for the provided direction
+    pub fn frame(&self, frame: u32, dir: u32) -> &'a RgbaImage {
+        &self.frames[(frame * self.metadata.dirs + dir) as usize]
+    }
+
+    // true if no frame has a single visible pixel; DreamMaker's editor
+    // leaves these behind, and they show nothing in game
+    pub fn is_empty(&self) -> bool {
+        !self
+            .frames
+            .iter()
+            .any(|frame| frame.pixels().any(|pixel| pixel[3] != 0))
+    }
+}
+
+// an icon_state of a DmiFile that can be changed; if the dirs or frames
+// change, the frames must be changed to match before the file is saved
+#[derive(Debug)]
+pub struct IconStateMut<'a> {
+    pub metadata: &'a mut DreamMakerIconState,
+    pub frames: &'a mut Vec<RgbaImage>,
+}
+
+impl IconStateMut<'_> {
+    pub fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    // the frame shown for the provided direction
+    pub fn frame_mut(&mut self, frame: u32, dir: u32) -> &mut RgbaImage {
+        &mut self.frames[(frame * self.metadata.dirs + dir) as usize]
+    }
+}
+
+impl DmiFile {
+    pub fn open(path: &Path) -> Result<DmiFile> {
+        let image = DynamicImage::ImageRgba8(read_rgba_image(path)?);
+        let metadata = parse_metadata(&read_metadata(path)?)?;
+        DmiFile::from_image(metadata, &image)
+    }
+
+    // like open, but from the bytes of a .dmi file
+    pub fn from_bytes(bytes: &[u8]) -> Result<DmiFile> {
+        let image = decode_image(bytes)?;
+        let text = decode_metadata(bytes)?.ok_or_else(|| MissingMetadata(PathBuf::new()))?;
+        DmiFile::from_image(parse_metadata(&text)?, &image)
+    }
+
+    pub fn from_image(metadata: DreamMakerIconMetadata, image: &DynamicImage) -> Result<DmiFile> {
+        validate_metadata(&metadata)?;
+        DmiFile::from_image_unvalidated(metadata, image)
+    }
+
+    // like from_image, but the icon_states may have bad dirs, frames, or
+    // delays; doctor reports those itself, and still looks at the frames
+    pub fn from_image_unvalidated(
+        metadata: DreamMakerIconMetadata,
+        image: &DynamicImage,
+    ) -> Result<DmiFile> {
+        let image = image.to_rgba8();
+        let (width, height) = (metadata.width, metadata.height);
+
+        // make sure the image has room for every frame in the metadata
+        let frames_per_row = image.width() / width.max(1);
+        let rows = image.height() / height.max(1);
+        let available = u64::from(frames_per_row) * u64::from(rows);
+        let needed: u64 = metadata
+            .states
+            .iter()
+            .map(|state| u64::from(state.dirs) * u64::from(state.frames))
+            .sum();
+        if needed > available {
+            return Err(IconToolError::ImageTooSmall(
+                usize::try_from(needed).unwrap_or(usize::MAX),
+                usize::try_from(available).unwrap_or(usize::MAX),
+            ));
+        }
+
+        // cut the frames out of the image, left to right, top to bottom
+        let mut index = 0;
+        let mut frames = Vec::with_capacity(metadata.states.len());
+        for state in &metadata.states {
+            let mut state_frames = Vec::new();
+            for _ in 0..state.dirs * state.frames {
+                let x = (index % frames_per_row) * width;
+                let y = (index / frames_per_row) * height;
+                state_frames.push(imageops::crop_imm(&image, x, y, width, height).to_image());
+                index += 1;
+            }
+            frames.push(state_frames);
+        }
+
+        Ok(DmiFile { metadata, frames })
+    }
+
+    // the frame of an icon_state shown for the provided direction
+    pub fn frame(&self, state: usize, frame: u32, dir: u32) -> &RgbaImage {
+        let dirs = self.metadata.states[state].dirs;
+        &self.frames[state][(frame * dirs + dir) as usize]
+    }
+
+    // every icon_state in the file, in order
+    pub fn states(&self) -> impl Iterator<Item = IconState<'_>> {
+        self.metadata
+            .states
+            .iter()
+            .zip(&self.frames)
+            .map(|(metadata, frames)| IconState { metadata, frames })
+    }
+
+    // the first icon_state with the provided name
+    pub fn state(&self, name: &str) -> Option<IconState<'_>> {
+        let index = self.metadata.find_state(name)?;
+        Some(IconState {
+            metadata: &self.metadata.states[index],
+            frames: &self.frames[index],
+        })
+    }
+
+    pub fn state_mut(&mut self, name: &str) -> Option<IconStateMut<'_>> {
+        let index = self.metadata.find_state(name)?;
+        Some(IconStateMut {
+            metadata: &mut self.metadata.states[index],
+            frames: &mut self.frames[index],
+        })
+    }
+
+    // add an icon_state to the end of the file
+    pub fn push_state(&mut self, state: DreamMakerIconState, frames: Vec<RgbaImage>) -> Result<()> {
+        check_frames(&state, &frames)?;
+        self.metadata.states.push(state);
+        self.frames.push(frames);
+        Ok(())
+    }
+
+    // remove the first icon_state with the provided name
+    pub fn remove_state(&mut self, name: &str) -> Option<(DreamMakerIconState, Vec<RgbaImage>)> {
+        let index = self.metadata.find_state(name)?;
+        Some((
+            self.metadata.states.remove(index),
+            self.frames.remove(index),
+        ))
+    }
+
+    // pack the frames into a roughly square sheet, the way BYOND does
+    pub fn to_image(&self) -> DynamicImage {
+        let (width, height) = (self.metadata.width, self.metadata.height);
+        let total = self.frames.iter().map(Vec::len).sum::<usize>().max(1) as u32;
+        let columns = (1..=total).find(|c| c * c >= total).unwrap_or(1);
+        let rows = total.div_ceil(columns);
+
+        let mut image = RgbaImage::new(columns * width, rows * height);
+        for (index, frame) in self.frames.iter().flatten().enumerate() {
+            let index = index as u32;
+            let x = (index % columns) * width;
+            let y = (index / columns) * height;
+            imageops::replace(&mut image, frame, x as i64, y as i64);
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    // like save, but returns the bytes of the .dmi file
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        for (state, frames) in self.metadata.states.iter().zip(&self.frames) {
+            check_frames(state, frames)?;
+        }
+        let image = self.to_image();
+        if image.width() > MAX_IMAGE_WIDTH || image.height() > MAX_IMAGE_HEIGHT {
+            return Err(IconToolError::TooManyIconStates(
+                image.width(),
+                image.height(),
+            ));
+        }
+        let text = self.metadata.to_string();
+        encode_dmi(ZTXT_KEYWORD, &text, &image, &WriteOptions::default())
+    }
+}
+
+fn check_frames(state: &DreamMakerIconState, frames: &[RgbaImage]) -> Result<()> {
+    let expected = (state.dirs * state.frames) as usize;
+    if frames.len() != expected {
+        return Err(IconToolError::FrameCountMismatch(
+            state.name.clone(),
+            expected,
+            frames.len(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn edit_dmi_file<F>(path: &Path, output_path: &Path, edit: F) -> Result<()>
+where
+    F: FnOnce(&mut DmiFile) -> Result<()>,
+{
+    // read the frames and metadata from the .dmi file
+    let mut sheet = DmiFile::open(path)?;
+
+    // let the caller change the frames and metadata
+    edit(&mut sheet)?;
+
+    // pack everything back up into a .dmi file
+    sheet.save(output_path)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_read_and_repack() {
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        assert_eq!(3, sheet.frames.len());
+        assert_eq!(3, sheet.frames[0].len());
+        assert_eq!(8, sheet.frames[1].len());
+        let image = sheet.to_image();
+        assert_eq!(4 * sheet.metadata.width, image.width());
+        assert_eq!(3 * sheet.metadata.height, image.height());
+        let repacked = DmiFile::from_image(sheet.metadata.clone(), &image).unwrap();
+        assert_eq!(sheet.frames, repacked.frames);
+    }
+
+    #[test]
+    fn test_image_too_small() {
+        let mut metadata = DreamMakerIconMetadata::new(32, 32);
+        let state = crate::parser::DreamMakerIconState::new("big", 4, 2);
+        metadata.states.push(state);
+        match DmiFile::from_image(metadata, &DynamicImage::new_rgba8(64, 64)) {
+            Err(IconToolError::ImageTooSmall(8, 4)) => {}
+            _ => panic!("test_image_too_small: Expected ImageTooSmall error"),
+        }
+    }
+
+    #[test]
+    fn test_from_image_frame_overflow() {
+        let mut metadata = DreamMakerIconMetadata::new(32, 32);
+        let state = crate::parser::DreamMakerIconState::new("huge", 8, u32::MAX);
+        metadata.states.push(state);
+        match DmiFile::from_image(metadata, &DynamicImage::new_rgba8(64, 64)) {
+            Err(IconToolError::ImageTooSmall(_, 4)) => {}
+            _ => panic!("test_from_image_frame_overflow: Expected ImageTooSmall error"),
+        }
+    }
+
+    #[test]
+    fn test_from_image_invalid_metadata() {
+        let image = DynamicImage::new_rgba8(64, 64);
+        let mut metadata = DreamMakerIconMetadata::new(32, 32);
+        metadata
+            .states
+            .push(crate::parser::DreamMakerIconState::new("flat", 0, 1));
+        match DmiFile::from_image(metadata, &image) {
+            Err(IconToolError::InvalidDirs(name, 0)) => assert_eq!("flat", name),
+            _ => panic!("test_from_image_invalid_metadata: Expected InvalidDirs error"),
+        }
+        let mut metadata = DreamMakerIconMetadata::new(32, 32);
+        metadata
+            .states
+            .push(crate::parser::DreamMakerIconState::new("still", 1, 0));
+        match DmiFile::from_image(metadata, &image) {
+            Err(IconToolError::InvalidFrames(name, 0)) => assert_eq!("still", name),
+            _ => panic!("test_from_image_invalid_metadata: Expected InvalidFrames error"),
+        }
+    }
+
+    #[test]
+    fn test_state_views() {
+        let mut dmi = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let names: Vec<&str> = dmi.states().map(|state| state.name()).collect();
+        assert_eq!(vec!["spin", "walk", "still"], names);
+        let walk = dmi.state("walk").unwrap();
+        assert_eq!(dmi.frame(1, 1, 2), walk.frame(1, 2));
+        assert!(dmi.state("missing").is_none());
+
+        let red = image::Rgba([255, 0, 0, 255]);
+        let mut still = dmi.state_mut("still").unwrap();
+        still.frame_mut(0, 0).put_pixel(0, 0, red);
+        still.metadata.name = String::from("moved");
+        assert_eq!(
+            &red,
+            dmi.state("moved").unwrap().frame(0, 0).get_pixel(0, 0)
+        );
+
+        let (state, frames) = dmi.remove_state("moved").unwrap();
+        assert_eq!(2, dmi.states().count());
+        dmi.push_state(state, frames).unwrap();
+        assert_eq!("moved", dmi.metadata.states[2].name);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let mut dmi = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        assert!(!dmi.state("still").unwrap().is_empty());
+        let transparent = image::Rgba([255, 0, 0, 0]);
+        for pixel in dmi.state_mut("still").unwrap().frame_mut(0, 0).pixels_mut() {
+            *pixel = transparent;
+        }
+        assert!(dmi.state("still").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_save_frame_count_mismatch() {
+        let mut dmi = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        dmi.state_mut("walk").unwrap().metadata.frames = 3;
+        match dmi.save(&PathBuf::from("tests/data/edit/mismatch.out.dmi")) {
+            Err(IconToolError::FrameCountMismatch(name, 12, 8)) if name == "walk" => {}
+            _ => panic!("test_save_frame_count_mismatch: Expected FrameCountMismatch error"),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+// doctor.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cmdline::DoctorArgs;
+use crate::constant::{MAX_IMAGE_HEIGHT, MAX_IMAGE_WIDTH};
+use crate::dmi::{decode_image, decode_metadata};
+use crate::dmi_file::DmiFile;
+use crate::error::{get_error_message, IconToolError, Result};
+use crate::parser::{parse_metadata, DreamMakerIconMetadata};
+use crate::validate::{find_problems, find_warnings};
+
+// how urgently a finding needs attention; findings are listed in this order
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Error,   // BYOND will show the icon wrong, or not at all
+    Warning, // the icon works, but probably not the way it was meant to
+    Info,    // worth a look when cleaning up
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub problem: String,
+    pub advice: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, problem: String, advice: &str) -> Finding {
+        Finding {
+            severity,
+            problem,
+            advice: advice.to_string(),
+        }
+    }
+}
+
+pub fn doctor(args: &DoctorArgs) -> Result<()> {
+    // examine each of the provided files
+    let mut num_errors = 0;
+    for file in &args.files {
+        let findings = match std::fs::read(file) {
+            Ok(bytes) => examine_bytes(&bytes),
+            Err(x) => vec![Finding::new(
+                Severity::Error,
+                format!("can't be read: {x}"),
+                "check the path and permissions of the file",
+            )],
+        };
+        for finding in &findings {
+            println!("{file}: {}: {}", finding.severity, finding.problem);
+            println!("    {}", finding.advice);
+        }
+        num_errors += findings
+            .iter()
+            .filter(|x| x.severity == Severity::Error)
+            .count();
+    }
+
+    // only the serious findings fail the run
+    if num_errors > 0 {
+        return Err(IconToolError::DoctorFailed(num_errors));
+    }
+    Ok(())
+}
+
+pub fn examine_bytes(bytes: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    // without pixels or metadata, there's nothing else to examine
+    let image = match decode_image(bytes) {
+        Ok(image) => image,
+        Err(x) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("the image can't be decoded: {}", describe(x)),
+                "restore the file from version control; icontool verify shows which chunk is damaged",
+            ));
+            return findings;
+        }
+    };
+    let dmi = match decode_metadata(bytes).map(|x| x.map(|text| parse_metadata(&text))) {
+        Ok(Some(Ok(dmi))) => dmi,
+        Ok(None) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                String::from("there is no zTXt chunk with dmi metadata"),
+                "an image editor probably dropped it; restore it with icontool unflat, or rebuild the icon from its .dmi.yml",
+            ));
+            return findings;
+        }
+        Ok(Some(Err(x))) | Err(x) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("the metadata can't be read: {}", describe(x)),
+                "look at the text with icontool metadata, and fix it with icontool flat and unflat",
+            ));
+            return findings;
+        }
+    };
+
+    // problems in the metadata itself
+    for problem in find_problems(&dmi) {
+        let advice = match problem {
+            IconToolError::InvalidDirs(_, _) => {
+                "set dirs to 1, 4, or 8, and give the icon_state frames for each of them"
+            }
+            IconToolError::DelayCountMismatch(_, _, _) => {
+                "give the icon_state one delay per frame with icontool set-delay"
+            }
+            _ => "fix the icon_state in the metadata",
+        };
+        findings.push(Finding::new(Severity::Error, describe(problem), advice));
+    }
+    for warning in find_warnings(&dmi) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            describe(warning),
+            "add a regular icon_state with the same name, or drop the movement flag",
+        ));
+    }
+    examine_names(&dmi, &mut findings);
+
+    // the image should be a grid of icons big enough for every frame
+    let (width, height) = (image.width(), image.height());
+    if width % dmi.width.max(1) != 0 || height % dmi.height.max(1) != 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "the image is {width}x{height}, which isn't a whole number of {}x{} icons",
+                dmi.width, dmi.height
+            ),
+            "check the icon size in the metadata, or repad the sheet to fit it",
+        ));
+    }
+    if width > MAX_IMAGE_WIDTH || height > MAX_IMAGE_HEIGHT {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "the image is {width}x{height}, larger than the {MAX_IMAGE_WIDTH}x{MAX_IMAGE_HEIGHT} that BYOND handles well"
+            ),
+            "split the icon_states across several .dmi files",
+        ));
+    }
+    match DmiFile::from_image_unvalidated(dmi, &image) {
+        Ok(sheet) => examine_frames(&sheet, &mut findings),
+        Err(x) => findings.push(Finding::new(
+            Severity::Error,
+            describe(x),
+            "the icon was probably cropped or truncated; restore it from version control, or keep the frames that are left with icontool fix-metadata",
+        )),
+    }
+
+    // the most serious findings come first
+    findings.sort_by_key(|x| x.severity);
+    findings
+}
+
+fn examine_names(dmi: &DreamMakerIconMetadata, findings: &mut Vec<Finding>) {
+    // BYOND only ever shows the first icon_state with a name
+    let mut counts: HashMap<(&str, bool), usize> = HashMap::new();
+    for state in &dmi.states {
+        *counts.entry((&state.name, state.movement)).or_default() += 1;
+    }
+    let mut reported = Vec::new();
+    for state in &dmi.states {
+        let count = counts[&(state.name.as_str(), state.movement)];
+        if count > 1 && !reported.contains(&(&state.name, state.movement)) {
+            reported.push((&state.name, state.movement));
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{count} icon_states are named \"{}\"", state.name),
+                "BYOND only uses the first one; rename or remove the others",
+            ));
+        }
+    }
+}
+
+fn examine_frames(sheet: &DmiFile, findings: &mut Vec<Finding>) {
+    // icon_states without a single visible pixel are almost always mistakes,
+    // and silently show nothing in game
+    for state in sheet.states() {
+        if state.is_empty() {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("icon_state \"{}\" has no visible pixels", state.name()),
+                "draw the missing art, or remove the icon_state if nothing uses it; icontool unused can tell",
+            ));
+        }
+    }
+}
+
+fn describe(error: IconToolError) -> String {
+    let message = get_error_message(error);
+    message.trim_start_matches("icontool: ").to_string()
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::DmiBuilder;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_examine_healthy() {
+        let bytes = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        assert!(examine_bytes(&bytes).is_empty());
+    }
+
+    #[test]
+    fn test_examine_problems() {
+        let mut sheet = DmiBuilder::new(32, 32)
+            .state("open")
+            .push_frame(RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255])))
+            .state("blank")
+            .push_frame(RgbaImage::new(32, 32))
+            .state("open")
+            .push_frame(RgbaImage::from_pixel(32, 32, image::Rgba([0, 0, 255, 255])))
+            .build()
+            .unwrap();
+        sheet.metadata.states[1].delay = Some(vec![String::from("1"), String::from("2")]);
+        let bytes = crate::dmi::encode_dmi(
+            crate::constant::ZTXT_KEYWORD,
+            &sheet.metadata.to_string(),
+            &sheet.to_image(),
+            &crate::dmi::WriteOptions::default(),
+        )
+        .unwrap();
+
+        let findings = examine_bytes(&bytes);
+        let severities: Vec<Severity> = findings.iter().map(|x| x.severity).collect();
+        assert_eq!(
+            vec![Severity::Error, Severity::Warning, Severity::Warning],
+            severities
+        );
+        assert!(findings[0]
+            .problem
+            .contains("'blank' has 1 frame(s) but 2 delay(s)"));
+        assert_eq!("2 icon_states are named \"open\"", findings[1].problem);
+        assert_eq!(
+            "icon_state \"blank\" has no visible pixels",
+            findings[2].problem
+        );
+    }
+
+    #[test]
+    fn test_examine_missing_metadata() {
+        let mut bytes = Vec::new();
+        RgbaImage::new(32, 32)
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        let findings = examine_bytes(&bytes);
+        assert_eq!(1, findings.len());
+        assert_eq!(
+            "there is no zTXt chunk with dmi metadata",
+            findings[0].problem
+        );
+        assert!(examine_bytes(b"not a png")
+            .iter()
+            .all(|x| x.severity == Severity::Error));
+    }
+}
@@ -0,0 +1,772 @@
+// edit.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::RgbaImage;
+use std::path::PathBuf;
+
+use crate::cmdline::{
+    FramesArgs, OptimizeAnimArgs, ReverseArgs, SetDelayArgs, SetFlagArgs, SetHotspotArgs, Toggle,
+};
+use crate::dmi::edit_metadata;
+use crate::dmi_file::edit_dmi_file;
+use crate::error::{IconToolError, Result};
+use crate::parser::{fps_to_delay, normalize_number, DreamMakerIconHotspot, DreamMakerIconState};
+
+pub fn edit_frames(args: &FramesArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let (width, height) = (sheet.metadata.width, sheet.metadata.height);
+
+        // find the icon_state that we're changing
+        let index = sheet
+            .metadata
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &mut sheet.metadata.states[index];
+        let num_frames = state.frames;
+        let check = |frame: u32, last: u32| {
+            if frame < 1 || frame > last {
+                return Err(IconToolError::InvalidFrameEdit(format!(
+                    "frame {frame} is not between 1 and {last}"
+                )));
+            }
+            Ok(())
+        };
+
+        // work out the new order of the frames; None is a blank frame
+        let mut order: Vec<Option<u32>> = (0..num_frames).map(Some).collect();
+        if let Some(frame) = args.delete {
+            check(frame, num_frames)?;
+            if num_frames == 1 {
+                return Err(IconToolError::InvalidFrameEdit(format!(
+                    "cannot delete the only frame of icon_state '{}'",
+                    args.state
+                )));
+            }
+            order.remove(frame as usize - 1);
+        }
+        if let Some((from, to)) = args._move {
+            check(from, num_frames)?;
+            check(to, num_frames)?;
+            let frame = order.remove(from as usize - 1);
+            order.insert(to as usize - 1, frame);
+        }
+        if let Some(insert) = &args.insert {
+            if let Some(source) = insert.source {
+                check(source, num_frames)?;
+            }
+            check(insert.at, num_frames + 1)?;
+            order.insert(
+                insert.at as usize - 1,
+                insert.source.map(|source| source - 1),
+            );
+        }
+
+        // rearrange the pixels, keeping the directions of each frame together
+        let dirs = state.dirs;
+        let old_frames: Vec<&[RgbaImage]> = sheet.frames[index].chunks(dirs as usize).collect();
+        let blank = vec![RgbaImage::new(width, height); dirs as usize];
+        let frames = order
+            .iter()
+            .flat_map(|frame| match frame {
+                Some(frame) => old_frames[*frame as usize].to_vec(),
+                None => blank.clone(),
+            })
+            .collect();
+        sheet.frames[index] = frames;
+
+        // rearrange the delays; new blank frames last a single tick
+        if let Some(delay) = &state.delay {
+            let delay = order
+                .iter()
+                .map(|frame| {
+                    frame
+                        .and_then(|frame| delay.get(frame as usize).cloned())
+                        .unwrap_or_else(|| String::from("1"))
+                })
+                .collect();
+            state.delay = Some(delay);
+        }
+
+        // move the hotspots along with their frames
+        let mut hotspots = Vec::new();
+        for (position, frame) in order.iter().enumerate() {
+            let Some(frame) = frame else { continue };
+            for hotspot in &state.hotspots {
+                let Some(index) = hotspot.frame.checked_sub(1) else {
+                    continue;
+                };
+                if index / dirs == *frame {
+                    let frame = position as u32 * dirs + index % dirs + 1;
+                    hotspots.push(DreamMakerIconHotspot { frame, ..*hotspot });
+                }
+            }
+        }
+        state.hotspots = hotspots;
+        state.frames = order.len() as u32;
+
+        Ok(())
+    })
+}
+
+pub fn optimize_anim(args: &OptimizeAnimArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        // optimize the requested icon_state, or every icon_state
+        let indexes: Vec<usize> = match &args.state {
+            Some(name) => vec![sheet
+                .metadata
+                .find_state(name)
+                .ok_or_else(|| IconToolError::StateNotFound(name.clone()))?],
+            None => (0..sheet.metadata.states.len()).collect(),
+        };
+        for index in indexes {
+            let state = &mut sheet.metadata.states[index];
+            let removed = collapse_frames(state, &mut sheet.frames[index]);
+            if removed > 0 {
+                println!(
+                    "icon_state '{}': merged {removed} repeated frame(s)",
+                    state.name
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+// merge each run of identical frames into its first frame, adding up their
+// delays, so the animation plays the same with fewer frames; returns how many
+// frames were removed
+pub fn collapse_frames(state: &mut DreamMakerIconState, frames: &mut Vec<RgbaImage>) -> u32 {
+    // a delay that can't be read leaves the animation alone
+    let dirs = state.dirs.max(1);
+    let delays: Option<Vec<f64>> = match &state.delay {
+        Some(delay) => delay.iter().map(|x| x.parse::<f64>().ok()).collect(),
+        None => Some(vec![1.0; state.frames as usize]),
+    };
+    let Some(mut delays) = delays else {
+        return 0;
+    };
+    if state.frames < 2 || delays.len() != state.frames as usize {
+        return 0;
+    }
+
+    // frames only match if every direction and every hotspot does
+    let hotspots_of = |frame: u32| -> Vec<(u32, u32, u32)> {
+        state
+            .hotspots
+            .iter()
+            .filter_map(|x| Some((x, x.frame.checked_sub(1)?)))
+            .filter(|(_, index)| index / dirs == frame)
+            .map(|(x, index)| (x.x, x.y, index % dirs))
+            .collect()
+    };
+    let chunks: Vec<&[RgbaImage]> = frames.chunks(dirs as usize).collect();
+    let mut kept: Vec<u32> = vec![0];
+    for frame in 1..state.frames {
+        let last = *kept.last().expect("the first frame is always kept");
+        if chunks[frame as usize] == chunks[last as usize]
+            && hotspots_of(frame) == hotspots_of(last)
+        {
+            delays[last as usize] += delays[frame as usize];
+        } else {
+            kept.push(frame);
+        }
+    }
+    let removed = state.frames - kept.len() as u32;
+    if removed == 0 {
+        return 0;
+    }
+
+    // keep the surviving frames, their summed delays, and their hotspots
+    *frames = kept
+        .iter()
+        .flat_map(|frame| chunks[*frame as usize].to_vec())
+        .collect();
+    state.delay = Some(
+        kept.iter()
+            .map(|frame| normalize_number(&delays[*frame as usize].to_string()))
+            .collect(),
+    );
+    let mut hotspots = Vec::new();
+    for (position, frame) in kept.iter().enumerate() {
+        for hotspot in &state.hotspots {
+            let Some(index) = hotspot.frame.checked_sub(1) else {
+                continue;
+            };
+            if index / dirs == *frame {
+                let frame = position as u32 * dirs + index % dirs + 1;
+                hotspots.push(DreamMakerIconHotspot { frame, ..*hotspot });
+            }
+        }
+    }
+    state.hotspots = hotspots;
+    state.frames = kept.len() as u32;
+    removed
+}
+
+pub fn reverse(args: &ReverseArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        // find the icon_state that we're reversing
+        let index = sheet
+            .metadata
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let mut state = sheet.metadata.states[index].clone();
+        if state.frames < 2 {
+            return Err(IconToolError::NotAnimated(args.state.clone()));
+        }
+
+        // play the frames backwards, keeping the directions of each frame together
+        let dirs = state.dirs as usize;
+        let frames: Vec<_> = sheet.frames[index]
+            .chunks(dirs)
+            .rev()
+            .flatten()
+            .cloned()
+            .collect();
+
+        // mirror the delays and move the hotspots along with their frames
+        if let Some(delay) = &mut state.delay {
+            delay.reverse();
+        }
+        for hotspot in &mut state.hotspots {
+            let Some(index) = hotspot.frame.checked_sub(1) else {
+                continue;
+            };
+            let (frame, dir) = (index / state.dirs, index % state.dirs);
+            hotspot.frame = (state.frames - 1 - frame) * state.dirs + dir + 1;
+        }
+        state.hotspots.sort_by_key(|hotspot| hotspot.frame);
+
+        // replace the target icon_state, or add it after the original
+        let target = match &args._as {
+            None => Some(index),
+            Some(name) => match sheet.metadata.find_state(name) {
+                Some(target) => {
+                    state.name = sheet.metadata.states[target].name.clone();
+                    Some(target)
+                }
+                None => {
+                    state.name = name.clone();
+                    None
+                }
+            },
+        };
+        match target {
+            Some(target) => {
+                sheet.metadata.states[target] = state;
+                sheet.frames[target] = frames;
+            }
+            None => {
+                sheet.metadata.states.insert(index + 1, state);
+                sheet.frames.insert(index + 1, frames);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+pub fn set_delay(args: &SetDelayArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_metadata(&path, &output_path, |dmi| {
+        // find the icon_state that we're changing
+        let index = dmi
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &mut dmi.states[index];
+
+        // figure out the delay for each frame
+        let delays = match args.fps {
+            Some(fps) => vec![fps_to_delay(fps)?; state.frames as usize],
+            None => args.delays.clone(),
+        };
+        if delays.len() != state.frames as usize {
+            return Err(IconToolError::DelayCountMismatch(
+                args.state.clone(),
+                state.frames as usize,
+                delays.len(),
+            ));
+        }
+        if let Some(bad) = delays
+            .iter()
+            .find(|delay| !delay.is_finite() || **delay <= 0.0)
+        {
+            return Err(IconToolError::InvalidDelay(bad.to_string()));
+        }
+
+        // rewrite the delay list
+        let delays = delays
+            .iter()
+            .map(|delay| normalize_number(&delay.to_string()))
+            .collect();
+        state.delay = Some(delays);
+        Ok(())
+    })
+}
+
+pub fn set_flag(args: &SetFlagArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_metadata(&path, &output_path, |dmi| {
+        // find the icon_state that we're changing
+        let index = dmi
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &mut dmi.states[index];
+
+        // loop and rewind only mean something for animations
+        if state.frames < 2 {
+            return Err(IconToolError::NotAnimated(args.state.clone()));
+        }
+
+        // update the loop count; "infinite" removes the property
+        if let Some(_loop) = &args._loop {
+            state._loop = match _loop.as_str() {
+                "infinite" => None,
+                count => match count.parse::<u32>() {
+                    Ok(count) if count > 0 => Some(count.to_string()),
+                    _ => return Err(IconToolError::InvalidFlag(format!("loop = {count}"))),
+                },
+            };
+        }
+
+        // update the rewind flag
+        if let Some(rewind) = &args.rewind {
+            state.rewind = match rewind {
+                Toggle::On => Some(String::from("1")),
+                Toggle::Off => None,
+            };
+        }
+
+        Ok(())
+    })
+}
+
+pub fn set_hotspot(args: &SetHotspotArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_metadata(&path, &output_path, |dmi| {
+        let (width, height) = (dmi.width, dmi.height);
+
+        // find the icon_state that we're changing
+        let index = dmi
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &mut dmi.states[index];
+
+        // determine which frames we're changing; by default, all of them
+        let num_frames = state.dirs * state.frames;
+        let frames: Vec<u32> = match args.frame {
+            Some(frame) if frame < 1 || frame > num_frames => {
+                return Err(IconToolError::InvalidHotspot(format!(
+                    "frame {frame} is not between 1 and {num_frames}"
+                )));
+            }
+            Some(frame) => vec![frame],
+            None => (1..=num_frames).collect(),
+        };
+
+        // remove any existing hotspots from those frames
+        state
+            .hotspots
+            .retain(|hotspot| !frames.contains(&hotspot.frame));
+
+        // add the new hotspots, unless we were asked to remove them
+        if let Some((x, y)) = args.xy {
+            if x >= width || y >= height {
+                return Err(IconToolError::InvalidHotspot(format!(
+                    "({x},{y}) is outside the {width}x{height} icon"
+                )));
+            }
+            for frame in frames {
+                state.hotspots.push(DreamMakerIconHotspot { x, y, frame });
+            }
+            state.hotspots.sort_by_key(|hotspot| hotspot.frame);
+        }
+
+        Ok(())
+    })
+}
+
+pub fn get_output_path(file: &str, output: &Option<String>) -> PathBuf {
+    // by default, we edit the .dmi file in place
+    match output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(file),
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmdline::FrameInsert;
+    use crate::dmi::read_metadata;
+    use crate::dmi_file::DmiFile;
+    use crate::parser::parse_metadata;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_edit_frames() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let mut args = FramesArgs {
+            output: Some(String::from("tests/data/edit/frames.out.dmi")),
+            state: String::from("spin"),
+            delete: Some(1),
+            _move: None,
+            insert: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        edit_frames(&args).unwrap();
+        args.file = String::from("tests/data/edit/frames.out.dmi");
+        args.delete = None;
+        args.insert = Some(FrameInsert {
+            source: None,
+            at: 3,
+        });
+        edit_frames(&args).unwrap();
+        args.insert = None;
+        args._move = Some((3, 1));
+        edit_frames(&args).unwrap();
+        let path = PathBuf::from("tests/data/edit/frames.out.dmi");
+        let sheet = DmiFile::open(&path).unwrap();
+        let state = &sheet.metadata.states[0];
+        assert_eq!(3, state.frames);
+        let delays = vec![String::from("1"), String::from("2"), String::from("3")];
+        assert_eq!(Some(delays), state.delay);
+        assert_eq!(RgbaImage::new(32, 32), sheet.frames[0][0]);
+        assert_eq!(original.frames[0][1], sheet.frames[0][1]);
+        assert_eq!(original.frames[0][2], sheet.frames[0][2]);
+        assert_eq!(original.frames[1], sheet.frames[1]);
+    }
+
+    #[test]
+    fn test_edit_frames_out_of_range() {
+        let args = FramesArgs {
+            output: Some(String::from("tests/data/edit/frames_range.out.dmi")),
+            state: String::from("walk"),
+            delete: None,
+            _move: Some((1, 3)),
+            insert: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        match edit_frames(&args) {
+            Err(IconToolError::InvalidFrameEdit(_)) => {}
+            _ => panic!("test_edit_frames_out_of_range: Expected InvalidFrameEdit error"),
+        }
+    }
+
+    #[test]
+    fn test_reverse_as() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = ReverseArgs {
+            output: Some(String::from("tests/data/edit/reverse.out.dmi")),
+            state: String::from("spin"),
+            _as: Some(String::from("unspin")),
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        reverse(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let path = PathBuf::from("tests/data/edit/reverse.out.dmi");
+        let sheet = DmiFile::open(&path).unwrap();
+        assert_eq!("spin", sheet.metadata.states[0].name);
+        assert_eq!("unspin", sheet.metadata.states[1].name);
+        let delays = vec![String::from("3"), String::from("2"), String::from("1")];
+        assert_eq!(Some(delays), sheet.metadata.states[1].delay);
+        assert_eq!(original.frames[0][0], sheet.frames[1][2]);
+        assert_eq!(original.frames[0][2], sheet.frames[1][0]);
+        assert_eq!(original.frames[0], sheet.frames[0]);
+    }
+
+    #[test]
+    fn test_reverse_in_place_keeps_dirs() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = ReverseArgs {
+            output: Some(String::from("tests/data/edit/reverse_walk.out.dmi")),
+            state: String::from("walk"),
+            _as: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        reverse(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let path = PathBuf::from("tests/data/edit/reverse_walk.out.dmi");
+        let sheet = DmiFile::open(&path).unwrap();
+        assert_eq!(3, sheet.metadata.states.len());
+        assert_eq!(original.frames[1][4..8], sheet.frames[1][0..4]);
+        assert_eq!(original.frames[1][0..4], sheet.frames[1][4..8]);
+    }
+
+    #[test]
+    fn test_reverse_not_animated() {
+        let args = ReverseArgs {
+            output: Some(String::from("tests/data/edit/reverse_still.out.dmi")),
+            state: String::from("bluetie"),
+            _as: None,
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        match reverse(&args) {
+            Err(IconToolError::NotAnimated(_)) => {}
+            _ => panic!("test_reverse_not_animated: Expected NotAnimated error"),
+        }
+    }
+
+    #[test]
+    fn test_collapse_frames() {
+        let red = RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(32, 32, image::Rgba([0, 0, 255, 255]));
+        let mut sheet = crate::builder::DmiBuilder::new(32, 32)
+            .state("blink")
+            .frames(5)
+            .delay(&[1.0, 2.0, 3.0, 0.5, 4.0])
+            .push_frame(red.clone())
+            .push_frame(red.clone())
+            .push_frame(blue.clone())
+            .push_frame(blue.clone())
+            .push_frame(red.clone())
+            .build()
+            .unwrap();
+        let state = &mut sheet.metadata.states[0];
+        assert_eq!(2, collapse_frames(state, &mut sheet.frames[0]));
+        assert_eq!(3, state.frames);
+        let delays = vec![String::from("3"), String::from("3.5"), String::from("4")];
+        assert_eq!(Some(delays), state.delay);
+        assert_eq!(vec![red.clone(), blue, red], sheet.frames[0]);
+
+        // nothing left to merge
+        let state = &mut sheet.metadata.states[0];
+        assert_eq!(0, collapse_frames(state, &mut sheet.frames[0]));
+    }
+
+    #[test]
+    fn test_collapse_frames_keeps_hotspots() {
+        let red = RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255]));
+        let mut sheet = crate::builder::DmiBuilder::new(32, 32)
+            .state("aim")
+            .frames(3)
+            .push_frame(red.clone())
+            .push_frame(red.clone())
+            .push_frame(red)
+            .build()
+            .unwrap();
+        let state = &mut sheet.metadata.states[0];
+        state.hotspots = vec![
+            DreamMakerIconHotspot {
+                x: 1,
+                y: 1,
+                frame: 1,
+            },
+            DreamMakerIconHotspot {
+                x: 1,
+                y: 1,
+                frame: 2,
+            },
+            DreamMakerIconHotspot {
+                x: 5,
+                y: 5,
+                frame: 3,
+            },
+        ];
+
+        // a frame with a different hotspot isn't a repeat
+        assert_eq!(1, collapse_frames(state, &mut sheet.frames[0]));
+        assert_eq!(
+            Some(vec![String::from("2"), String::from("1")]),
+            state.delay
+        );
+        assert_eq!(
+            vec![
+                DreamMakerIconHotspot {
+                    x: 1,
+                    y: 1,
+                    frame: 1
+                },
+                DreamMakerIconHotspot {
+                    x: 5,
+                    y: 5,
+                    frame: 2
+                },
+            ],
+            state.hotspots
+        );
+    }
+
+    #[test]
+    fn test_optimize_anim() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = OptimizeAnimArgs {
+            output: Some(String::from("tests/data/edit/optimize.out.dmi")),
+            state: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        optimize_anim(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/optimize.out.dmi")).unwrap();
+        assert_eq!(original.metadata.states.len(), sheet.metadata.states.len());
+    }
+
+    #[test]
+    fn test_set_delay() {
+        let args = SetDelayArgs {
+            output: Some(String::from("tests/data/edit/set_delay.out.dmi")),
+            state: String::from("bluetie"),
+            delays: vec![2.0],
+            fps: None,
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        set_delay(&args).unwrap();
+        let path = PathBuf::from("tests/data/edit/set_delay.out.dmi");
+        let dmi = parse_metadata(&read_metadata(&path).unwrap()).unwrap();
+        assert_eq!(Some(vec![String::from("2")]), dmi.states[0].delay);
+        assert_eq!(None, dmi.states[1].delay);
+    }
+
+    #[test]
+    fn test_set_delay_mismatch() {
+        let args = SetDelayArgs {
+            output: Some(String::from("tests/data/edit/set_delay_mismatch.out.dmi")),
+            state: String::from("bluetie"),
+            delays: vec![1.0, 2.0],
+            fps: None,
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        match set_delay(&args) {
+            Err(IconToolError::DelayCountMismatch(_, 1, 2)) => {}
+            _ => panic!("test_set_delay_mismatch: Expected DelayCountMismatch error"),
+        }
+    }
+
+    #[test]
+    fn test_set_delay_missing_state() {
+        let args = SetDelayArgs {
+            output: Some(String::from("tests/data/edit/set_delay_missing.out.dmi")),
+            state: String::from("nosuchtie"),
+            delays: vec![],
+            fps: Some(10.0),
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        match set_delay(&args) {
+            Err(IconToolError::StateNotFound(_)) => {}
+            _ => panic!("test_set_delay_missing_state: Expected StateNotFound error"),
+        }
+    }
+
+    #[test]
+    fn test_set_flag_not_animated() {
+        let args = SetFlagArgs {
+            output: Some(String::from("tests/data/edit/set_flag.out.dmi")),
+            state: String::from("bluetie"),
+            _loop: Some(String::from("3")),
+            rewind: Some(Toggle::On),
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        match set_flag(&args) {
+            Err(IconToolError::NotAnimated(_)) => {}
+            _ => panic!("test_set_flag_not_animated: Expected NotAnimated error"),
+        }
+    }
+
+    #[test]
+    fn test_set_flag() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let delay_args = SetDelayArgs {
+            output: Some(String::from("tests/data/edit/set_flag_delay.out.dmi")),
+            state: String::from("spin"),
+            delays: vec![1.0, 1.0, 1.0],
+            fps: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        set_delay(&delay_args).unwrap();
+        let args = SetFlagArgs {
+            output: Some(String::from("tests/data/edit/set_flag.out.dmi")),
+            state: String::from("spin"),
+            _loop: Some(String::from("3")),
+            rewind: Some(Toggle::On),
+            file: String::from("tests/data/edit/set_flag_delay.out.dmi"),
+        };
+        set_flag(&args).unwrap();
+        let path = PathBuf::from("tests/data/edit/set_flag.out.dmi");
+        let dmi = parse_metadata(&read_metadata(&path).unwrap()).unwrap();
+        assert_eq!(Some(String::from("3")), dmi.states[0]._loop);
+        assert_eq!(Some(String::from("1")), dmi.states[0].rewind);
+        assert_eq!(Some(vec![String::from("1"); 3]), dmi.states[0].delay);
+    }
+
+    #[test]
+    fn test_set_hotspot() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = SetHotspotArgs {
+            output: Some(String::from("tests/data/edit/set_hotspot.out.dmi")),
+            state: String::from("spin"),
+            xy: Some((16, 16)),
+            frame: None,
+            remove: false,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        set_hotspot(&args).unwrap();
+        let args = SetHotspotArgs {
+            output: Some(String::from("tests/data/edit/set_hotspot.out.dmi")),
+            state: String::from("spin"),
+            xy: None,
+            frame: Some(2),
+            remove: true,
+            file: String::from("tests/data/edit/set_hotspot.out.dmi"),
+        };
+        set_hotspot(&args).unwrap();
+        let path = PathBuf::from("tests/data/edit/set_hotspot.out.dmi");
+        let dmi = parse_metadata(&read_metadata(&path).unwrap()).unwrap();
+        let frames: Vec<u32> = dmi.states[0].hotspots.iter().map(|h| h.frame).collect();
+        assert_eq!(vec![1, 3], frames);
+    }
+
+    #[test]
+    fn test_set_hotspot_outside() {
+        let args = SetHotspotArgs {
+            output: Some(String::from("tests/data/edit/set_hotspot_outside.out.dmi")),
+            state: String::from("spin"),
+            xy: Some((32, 0)),
+            frame: Some(1),
+            remove: false,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        match set_hotspot(&args) {
+            Err(IconToolError::InvalidHotspot(_)) => {}
+            _ => panic!("test_set_hotspot_outside: Expected InvalidHotspot error"),
+        }
+    }
+}
@@ -24,22 +24,65 @@ pub struct MissingMetadata(pub PathBuf);
 
 #[derive(Debug)]
 pub enum IconToolError {
+    AtLocation(String, usize, usize, Box<IconToolError>),
+    BuildFailed(usize),
     DecodeError(base64::DecodeError),
     DecodingError(png::DecodingError),
+    DecompileFailed(usize),
     DecompressError(lz4_flex::block::DecompressError),
+    DelayCountMismatch(String, usize, usize),
+    DoctorFailed(usize),
+    DuplicateKey(String, usize, usize),
     EncodingError(png::EncodingError),
+    FetchFailed(String),
     FrameCountMismatch(String, usize, usize),
+    FrameSizeMismatch(String, usize, usize, usize),
+    FrameTooLarge(String, usize, usize),
+    IconSizeMismatch(String),
     ImageError(image::ImageError),
+    ImageTooSmall(usize, usize),
     IncompleteParseError(String),
+    InvalidArchive(String),
+    InvalidArgument(String),
+    InvalidConfig(String),
+    InvalidDelay(String),
+    InvalidDirs(String, u32),
+    InvalidFlag(String),
+    InvalidFrameEdit(String),
+    InvalidFrameRef(String),
+    InvalidFrames(String, u32),
+    InvalidGagsConfig(String),
+    InvalidHotspot(String),
+    InvalidInclude(String),
+    InvalidOverlay(String),
+    InvalidPngChunk(String),
+    InvalidSpriteTest(String),
+    InvalidTransform(String),
     InvalidType(String),
     Io(std::io::Error),
+    LintFailed(usize),
+    LockMismatch(usize),
     MissingKey(String),
     MissingMetadata(MissingMetadata),
+    MovementWithoutBase(String),
+    NotAnimated(String),
+    NotEqual(usize),
+    OverlayConflict(String, String),
     ParseError(String),
     PathError(String),
+    PostProcessFailed(String),
+    RoundTripFailed(usize),
     Serialize(serde_yml::Error),
+    SpriteTestFailed(usize),
+    StateConflict(String),
+    StateNotFound(String),
     TooManyFrames(),
     TooManyIconStates(u32, u32),
+    TooManyStates(usize, usize),
+    UndefinedVariable(String),
+    UnresolvedStates(usize),
+    VerifyFailed(usize),
+    WarningsDenied(usize),
 }
 
 impl From<base64::DecodeError> for IconToolError {
@@ -90,6 +133,12 @@ impl From<nom::Err<nom::error::Error<&str>>> for IconToolError {
     }
 }
 
+impl From<zip::result::ZipError> for IconToolError {
+    fn from(error: zip::result::ZipError) -> Self {
+        IconToolError::InvalidArchive(error.to_string())
+    }
+}
+
 impl From<serde_yml::Error> for IconToolError {
     fn from(error: serde_yml::Error) -> Self {
         IconToolError::Serialize(error)
@@ -98,55 +147,257 @@ impl From<serde_yml::Error> for IconToolError {
 
 pub type Result<T> = std::result::Result<T, IconToolError>;
 
+// every error has a code that stays the same from release to release, so
+// it can be looked up with `icontool explain`; a new error takes the next
+// unused number, and the number of a removed error is never used again
+pub fn get_error_code(e: &IconToolError) -> &'static str {
+    match e {
+        IconToolError::AtLocation(_, _, _, x) => get_error_code(x),
+        IconToolError::BuildFailed(..) => "ICT0001",
+        IconToolError::DecodeError(..) => "ICT0002",
+        IconToolError::DecodingError(..) => "ICT0003",
+        IconToolError::DecompressError(..) => "ICT0004",
+        IconToolError::DelayCountMismatch(..) => "ICT0005",
+        IconToolError::DoctorFailed(..) => "ICT0006",
+        IconToolError::DuplicateKey(..) => "ICT0007",
+        IconToolError::EncodingError(..) => "ICT0008",
+        IconToolError::FrameCountMismatch(..) => "ICT0009",
+        IconToolError::FrameSizeMismatch(..) => "ICT0010",
+        IconToolError::FrameTooLarge(..) => "ICT0011",
+        IconToolError::IconSizeMismatch(..) => "ICT0012",
+        IconToolError::ImageError(..) => "ICT0013",
+        IconToolError::ImageTooSmall(..) => "ICT0014",
+        IconToolError::IncompleteParseError(..) => "ICT0015",
+        IconToolError::InvalidArgument(..) => "ICT0016",
+        IconToolError::InvalidConfig(..) => "ICT0017",
+        IconToolError::InvalidDelay(..) => "ICT0018",
+        IconToolError::InvalidDirs(..) => "ICT0019",
+        IconToolError::InvalidFlag(..) => "ICT0020",
+        IconToolError::InvalidFrameEdit(..) => "ICT0021",
+        IconToolError::InvalidFrameRef(..) => "ICT0022",
+        IconToolError::InvalidFrames(..) => "ICT0023",
+        IconToolError::InvalidGagsConfig(..) => "ICT0024",
+        IconToolError::InvalidHotspot(..) => "ICT0025",
+        IconToolError::InvalidInclude(..) => "ICT0026",
+        IconToolError::InvalidOverlay(..) => "ICT0027",
+        IconToolError::InvalidPngChunk(..) => "ICT0028",
+        IconToolError::InvalidSpriteTest(..) => "ICT0029",
+        IconToolError::InvalidTransform(..) => "ICT0030",
+        IconToolError::InvalidType(..) => "ICT0031",
+        IconToolError::Io(..) => "ICT0032",
+        IconToolError::LintFailed(..) => "ICT0033",
+        IconToolError::LockMismatch(..) => "ICT0034",
+        IconToolError::MissingKey(..) => "ICT0035",
+        IconToolError::MissingMetadata(..) => "ICT0036",
+        IconToolError::MovementWithoutBase(..) => "ICT0037",
+        IconToolError::NotAnimated(..) => "ICT0038",
+        IconToolError::NotEqual(..) => "ICT0039",
+        IconToolError::OverlayConflict(..) => "ICT0040",
+        IconToolError::ParseError(..) => "ICT0041",
+        IconToolError::PathError(..) => "ICT0042",
+        IconToolError::PostProcessFailed(..) => "ICT0043",
+        IconToolError::RoundTripFailed(..) => "ICT0044",
+        IconToolError::Serialize(..) => "ICT0045",
+        IconToolError::SpriteTestFailed(..) => "ICT0046",
+        IconToolError::StateConflict(..) => "ICT0047",
+        IconToolError::StateNotFound(..) => "ICT0048",
+        IconToolError::TooManyFrames(..) => "ICT0049",
+        IconToolError::TooManyIconStates(..) => "ICT0050",
+        IconToolError::TooManyStates(..) => "ICT0051",
+        IconToolError::UndefinedVariable(..) => "ICT0052",
+        IconToolError::UnresolvedStates(..) => "ICT0053",
+        IconToolError::VerifyFailed(..) => "ICT0054",
+        IconToolError::WarningsDenied(..) => "ICT0055",
+        IconToolError::FetchFailed(..) => "ICT0056",
+        IconToolError::InvalidArchive(..) => "ICT0057",
+        IconToolError::DecompileFailed(..) => "ICT0058",
+    }
+}
+
 pub fn get_error_message(e: IconToolError) -> String {
+    let code = get_error_code(&e);
+    format!("icontool: [{code}] {}", get_error_text(e))
+}
+
+// the message of an error, without the name of the program or the code
+pub fn get_error_text(e: IconToolError) -> String {
     match e {
+        IconToolError::AtLocation(path, line, column, x) => {
+            format!("{path}:{line}:{column}: {}", get_error_text(*x))
+        }
+        IconToolError::BuildFailed(x) => {
+            format!("{x} target(s) failed to build")
+        }
         IconToolError::DecodeError(x) => {
-            format!("icontool: Unable to decode base64 data: {x}")
+            format!("Unable to decode base64 data: {x}")
         }
         IconToolError::DecodingError(x) => {
-            format!("icontool: Unable to decode .dmi file: {x}")
+            format!("Unable to decode .dmi file: {x}")
+        }
+        IconToolError::DecompileFailed(x) => {
+            format!("{x} file(s) failed to decompile")
         }
         IconToolError::DecompressError(x) => {
-            format!("icontool: Unable to decompress LZ4 data: {x}")
+            format!("Unable to decompress LZ4 data: {x}")
+        }
+        IconToolError::DelayCountMismatch(name, expected, actual) => {
+            format!("icon_state '{name}' has {expected} frame(s) but {actual} delay(s) in the dmi metadata.")
+        }
+        IconToolError::DoctorFailed(x) => {
+            format!("doctor found {x} serious problem(s)")
+        }
+        IconToolError::DuplicateKey(x, y, z) => {
+            format!("Key {x:?} appears twice in the YAML data, on lines {y} and {z}")
         }
         IconToolError::EncodingError(x) => {
-            format!("icontool: Unable to encode .dmi file: {x}")
+            format!("Unable to encode .dmi file: {x}")
+        }
+        IconToolError::FetchFailed(x) => {
+            format!("Unable to download {x}")
         }
         IconToolError::FrameCountMismatch(name, expected, actual) => {
-            format!("icontool: icon_state '{name}' has a mismatched number of frames. Expected {expected} frame(s) from the dmi metadata. Found {actual} frame(s) in the YAML data.")
+            format!("icon_state '{name}' has a mismatched number of frames. Expected {expected} frame(s) from the dmi metadata. Found {actual} frame(s) in the YAML data.")
+        }
+        IconToolError::FrameSizeMismatch(name, index, expected, actual) => {
+            format!("frame {index} of icon_state '{name}' has {actual} bytes of pixel data. Expected {expected} bytes for the icon size in the dmi metadata.")
+        }
+        IconToolError::FrameTooLarge(key, size, max) => {
+            format!("a frame of icon_state '{key}' decompresses to {size} bytes, more than the limit of {max}; raise it with --max-frame-bytes")
+        }
+        IconToolError::IconSizeMismatch(x) => {
+            format!("Icon sizes don't match: {x}")
         }
         IconToolError::ImageError(x) => {
-            format!("icontool: Error decoding .dmi image: {x}")
+            format!("Error decoding .dmi image: {x}")
+        }
+        IconToolError::ImageTooSmall(expected, actual) => {
+            format!("the metadata describes {expected} frame(s), but the image only has room for {actual}")
         }
         IconToolError::IncompleteParseError(x) => {
-            format!("icontool: Incomplete parse of .dmi metadata: {x}")
+            format!("Incomplete parse of .dmi metadata: {x}")
+        }
+        IconToolError::InvalidArchive(x) => {
+            format!("Unable to use .zip archive: {x}")
+        }
+        IconToolError::InvalidArgument(x) => {
+            format!("Invalid argument: {x}")
+        }
+        IconToolError::InvalidConfig(x) => {
+            format!("Invalid {CONFIG_FILE_NAME}: {x}")
+        }
+        IconToolError::InvalidDelay(x) => {
+            format!("Invalid animation delay: {x}")
+        }
+        IconToolError::InvalidDirs(name, dirs) => {
+            format!("icon_state '{name}' has dirs = {dirs} in the dmi metadata. Expected 1, 4, or 8.")
+        }
+        IconToolError::InvalidFlag(x) => {
+            format!("Invalid animation flag: {x}")
+        }
+        IconToolError::InvalidFrameEdit(x) => {
+            format!("Invalid frame edit: {x}")
+        }
+        IconToolError::InvalidFrameRef(x) => {
+            format!("Invalid frame reference: {x}")
+        }
+        IconToolError::InvalidFrames(name, frames) => {
+            format!("icon_state '{name}' has frames = {frames} in the dmi metadata. Expected at least 1.")
+        }
+        IconToolError::InvalidGagsConfig(x) => {
+            format!("Invalid GAGS config: {x}")
+        }
+        IconToolError::InvalidHotspot(x) => {
+            format!("Invalid hotspot: {x}")
+        }
+        IconToolError::InvalidInclude(x) => {
+            format!("Invalid include: {x}")
+        }
+        IconToolError::InvalidOverlay(x) => {
+            format!("Unable to overlay icon_states: {x}")
+        }
+        IconToolError::InvalidPngChunk(x) => {
+            format!("Invalid PNG chunk: {x}")
+        }
+        IconToolError::InvalidSpriteTest(x) => {
+            format!("Unable to understand sprite test: {x}")
+        }
+        IconToolError::InvalidTransform(x) => {
+            format!("Invalid transformation: {x}")
         }
         IconToolError::InvalidType(x) => {
-            format!("icontool: Type mismatch in YAML data: {x}")
+            format!("Type mismatch in YAML data: {x}")
         }
         IconToolError::Io(x) => {
-            format!("icontool: I/O error: {x}")
+            format!("I/O error: {x}")
+        }
+        IconToolError::LintFailed(x) => {
+            format!("lint found {x} problem(s)")
+        }
+        IconToolError::LockMismatch(x) => {
+            format!("verify-lock found {x} change(s) since the lock was made")
         }
         IconToolError::MissingKey(x) => {
-            format!("icontool: Expected key missing from YAML data: {x}")
+            format!("Expected key missing from YAML data: {x}")
         }
         IconToolError::MissingMetadata(x) => {
-            format!("icontool: Unable to read metadata from .dmi file: {x:?}")
+            format!("Unable to read metadata from .dmi file: {x:?}")
+        }
+        IconToolError::MovementWithoutBase(x) => {
+            format!("movement icon_state '{x}' has no regular icon_state with the same name")
+        }
+        IconToolError::NotAnimated(x) => {
+            format!("icon_state '{x}' is not animated; it has only one frame")
+        }
+        IconToolError::NotEqual(x) => {
+            format!("found {x} difference(s)")
+        }
+        IconToolError::OverlayConflict(x, y) => {
+            format!("the overlay can't be applied to icon_state '{x}': {y}")
         }
         IconToolError::ParseError(x) => {
-            format!("icontool: Error parsing .dmi metadata: {x}")
+            format!("Error parsing .dmi metadata: {x}")
         }
         IconToolError::PathError(x) => {
-            format!("icontool: Error handling paths: {x}")
+            format!("Error handling paths: {x}")
+        }
+        IconToolError::PostProcessFailed(x) => {
+            format!("post-process command failed: {x}")
+        }
+        IconToolError::RoundTripFailed(x) => {
+            format!("round trip found {x} problem(s)")
         }
         IconToolError::Serialize(x) => {
-            format!("icontool: Unable to serialize YAML data: {x}")
+            format!("Unable to serialize YAML data: {x}")
+        }
+        IconToolError::SpriteTestFailed(x) => {
+            format!("{x} sprite test(s) failed")
+        }
+        IconToolError::StateConflict(x) => {
+            format!("Conflicting icon_states: {x}; use --on-conflict to choose")
+        }
+        IconToolError::StateNotFound(x) => {
+            format!("icon_state '{x}' was not found in the dmi metadata")
         }
         IconToolError::TooManyFrames() => {
-            "icontool: YAML contains too many frames to paint.\nThis is a bug in icontool, please report it to the author of icontool.".to_string()
+            "YAML contains too many frames to paint.\nThis is a bug in icontool, please report it to the author of icontool.".to_string()
         }
         IconToolError::TooManyIconStates(w, h) => {
-            format!("icontool: Attempted to resize image to {w}x{h} which is larger than the allowed {MAX_IMAGE_WIDTH}x{MAX_IMAGE_HEIGHT}.")
+            format!("Attempted to resize image to {w}x{h} which is larger than the allowed {MAX_IMAGE_WIDTH}x{MAX_IMAGE_HEIGHT}.")
+        }
+        IconToolError::TooManyStates(count, max) => {
+            format!("the metadata has {count} icon_states, more than the limit of {max}; raise it with --max-states")
+        }
+        IconToolError::UndefinedVariable(x) => {
+            format!("${{{x}}} is not defined; use --define {x}=VALUE")
+        }
+        IconToolError::UnresolvedStates(x) => {
+            format!("found {x} icon_state reference(s) that don't resolve")
+        }
+        IconToolError::VerifyFailed(x) => {
+            format!("{x} file(s) failed verification")
+        }
+        IconToolError::WarningsDenied(x) => {
+            format!("{x} warning(s) were treated as errors")
         }
     }
 }
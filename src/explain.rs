@@ -0,0 +1,700 @@
+// explain.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use crate::cmdline::ExplainArgs;
+use crate::error::{IconToolError, Result};
+
+// the code, a one line summary, and a longer description of every error,
+// in the order of their codes
+type Explanation = (&'static str, &'static str, &'static str);
+
+const EXPLANATIONS: &[Explanation] = &[
+    (
+        "ICT0001",
+        "some targets of the manifest failed to build",
+        "icontool build compiles every target listed in icontool.yml, and keeps\n\
+         going when one of them fails. The error of each failed target is printed\n\
+         above this one; this error only says how many there were.\n\
+         \n\
+         Typical fixes:\n\
+         - read the errors printed above, and fix each target they name\n\
+         - compile a single target with icontool compile to work on it alone",
+    ),
+    (
+        "ICT0002",
+        "frame data is not valid base64",
+        "Every frame in a .dmi.yml file is written as a line of base64 text. A line\n\
+         that isn't base64 can't be turned back into pixels; usually it was cut\n\
+         short, or something other than a frame ended up under an icon_state.\n\
+         \n\
+         Typical fixes:\n\
+         - check the icon_state for a line that was edited or wrapped by hand\n\
+         - decompile the .dmi file again to get the frames back",
+    ),
+    (
+        "ICT0003",
+        "the .dmi file could not be decoded",
+        "A .dmi file is a PNG image. This error comes from the PNG decoder, when the\n\
+         file isn't a PNG, or the PNG is damaged, for example by a download that\n\
+         stopped early or a merge that treated the file as text.\n\
+         \n\
+         Typical fixes:\n\
+         - check that the file really is a .dmi, and not a text file or .dmi.yml\n\
+         - run icontool verify on it to see what is damaged\n\
+         - restore the file from version control",
+    ),
+    (
+        "ICT0004",
+        "frame data could not be decompressed",
+        "The base64 of each frame holds LZ4 compressed pixels. The text decoded as\n\
+         base64, but what it held wasn't valid LZ4 data, so the frame was changed\n\
+         or cut short after it was written.\n\
+         \n\
+         Typical fixes:\n\
+         - check the icon_state for a line that was edited by hand\n\
+         - decompile the .dmi file again to get the frames back",
+    ),
+    (
+        "ICT0005",
+        "an icon_state has a different number of delays than frames",
+        "An animated icon_state needs one delay for each of its frames. The dmi\n\
+         metadata lists more or fewer delays than the icon_state has frames.\n\
+         \n\
+         Typical fixes:\n\
+         - add or remove delays in the metadata so there is one per frame\n\
+         - use icontool set-delay to write a delay for every frame",
+    ),
+    (
+        "ICT0006",
+        "doctor found serious problems",
+        "icontool doctor prints every problem it finds, and fails when any of them\n\
+         are serious enough to break the file in DreamMaker. The problems, and the\n\
+         suggested fixes, are printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - follow the suggestions printed for each problem",
+    ),
+    (
+        "ICT0007",
+        "a key appears twice in the YAML",
+        "YAML keeps only one of two keys with the same name, so the frames under\n\
+         one of them would be lost without a word. icontool refuses to compile a\n\
+         file where an icon_state, or any other key, is written twice.\n\
+         \n\
+         Typical fixes:\n\
+         - remove one of the keys on the lines named in the error\n\
+         - if both are wanted, rename one of them, in the YAML and in the metadata",
+    ),
+    (
+        "ICT0008",
+        "the .dmi file could not be encoded",
+        "This error comes from the PNG encoder, while writing the image of the .dmi\n\
+         file. It usually means the image would be larger than PNG allows.\n\
+         \n\
+         Typical fixes:\n\
+         - check __image_width and __image_height in the YAML\n\
+         - use compile --split to spread a very large sheet over several files",
+    ),
+    (
+        "ICT0009",
+        "an icon_state has the wrong number of frames",
+        "The dmi metadata says how many frames each icon_state has: dirs times\n\
+         frames. The YAML under that icon_state has a different number of lines\n\
+         of frame data, so the frames can't be placed in the image.\n\
+         \n\
+         Typical fixes:\n\
+         - add or remove frames in the YAML, one line per frame\n\
+         - change dirs or frames of the icon_state in the metadata to match\n\
+         - while drawing, use compile --pad-missing to fill in missing frames",
+    ),
+    (
+        "ICT0010",
+        "a frame has the wrong number of pixels",
+        "Each frame holds width times height pixels of the icon size in the dmi\n\
+         metadata. The frame named in the error has a different amount of pixel\n\
+         data, usually because it was copied from a .dmi file of another size.\n\
+         \n\
+         Typical fixes:\n\
+         - check width and height in the metadata\n\
+         - resize the source .dmi with icontool convert-size, and decompile it again",
+    ),
+    (
+        "ICT0011",
+        "a frame is larger than the limit",
+        "To protect against damaged or hostile files, icontool refuses to\n\
+         decompress a frame into more bytes than a limit. The frame named in the\n\
+         error would decompress to more than that.\n\
+         \n\
+         Typical fixes:\n\
+         - if the file is trusted, raise the limit with --max-frame-bytes\n\
+         - otherwise, decompile the .dmi file again to get sane frames back",
+    ),
+    (
+        "ICT0012",
+        "icon sizes don't match",
+        "Every icon_state in a .dmi file has the same width and height. The files\n\
+         being combined have different icon sizes, so their icon_states can't be\n\
+         put in one file.\n\
+         \n\
+         Typical fixes:\n\
+         - resize one of the files with icontool convert-size first",
+    ),
+    (
+        "ICT0013",
+        "the image could not be read",
+        "This error comes from the image library, while reading the pixels of a\n\
+         .dmi or .png file. The file may be damaged, or use a format the library\n\
+         can't read.\n\
+         \n\
+         Typical fixes:\n\
+         - open the file in an image editor, and save it again as a PNG\n\
+         - restore the file from version control",
+    ),
+    (
+        "ICT0014",
+        "the image is too small for the metadata",
+        "The dmi metadata describes more frames than fit in the image. The image\n\
+         was probably cropped, or the metadata was copied from another file.\n\
+         \n\
+         Typical fixes:\n\
+         - use decompile --lenient to recover the frames that are still there\n\
+         - use icontool fix-metadata to cut the metadata down to the image",
+    ),
+    (
+        "ICT0015",
+        "the dmi metadata has text that could not be parsed",
+        "The metadata was read up to a point, and the rest of it didn't make sense.\n\
+         The error shows the text that was left over, which is where to look.\n\
+         \n\
+         Typical fixes:\n\
+         - check the line at the start of the leftover text for a typo\n\
+         - keep the metadata between # BEGIN DMI and # END DMI",
+    ),
+    (
+        "ICT0016",
+        "an argument is not valid",
+        "One of the arguments on the command line can't be used, or can't be used\n\
+         together with the others. The error says which one, and why.\n\
+         \n\
+         Typical fixes:\n\
+         - run the command with --help to see what it accepts",
+    ),
+    (
+        "ICT0017",
+        "the icontool.yml manifest is not valid",
+        "The manifest lists the targets used by build, clean, and watch. Part of it\n\
+         isn't understood, for example an unknown key or a value of the wrong type.\n\
+         \n\
+         Typical fixes:\n\
+         - check the key named in the error; a suggestion is given for a typo\n\
+         - see the Projects section of the README for the keys a target can have",
+    ),
+    (
+        "ICT0018",
+        "an animation delay is not valid",
+        "A delay is how long a frame shows, in ticks of 1/10 of a second. It has to\n\
+         be a positive number.\n\
+         \n\
+         Typical fixes:\n\
+         - write the delays as positive numbers, like 1 or 0.5\n\
+         - when giving frames per second, give a positive number",
+    ),
+    (
+        "ICT0019",
+        "an icon_state has an unsupported number of dirs",
+        "DreamMaker icon_states face 1, 4, or 8 directions. The metadata gives the\n\
+         icon_state another number.\n\
+         \n\
+         Typical fixes:\n\
+         - set dirs to 1, 4, or 8 in the metadata\n\
+         - use icontool gen-dirs to make the directions from one of them",
+    ),
+    (
+        "ICT0020",
+        "an animation flag is not valid",
+        "The loop flag of an animation is how many times it plays. The count\n\
+         given isn't a number of 1 or more.\n\
+         \n\
+         Typical fixes:\n\
+         - give loop a count of 1 or more, or infinite to loop forever",
+    ),
+    (
+        "ICT0021",
+        "a frame edit is not valid",
+        "icontool frames inserts, deletes, and moves frames by number. A number in\n\
+         the edit is past the end of the icon_state, or the edit would delete the\n\
+         only frame.\n\
+         \n\
+         Typical fixes:\n\
+         - count frames from 1, and check the number of frames with icontool states\n\
+         - run icontool frames --help to see how edits are written",
+    ),
+    (
+        "ICT0022",
+        "a frame reference is not valid",
+        "decompile --frame-refs writes a repeated frame as a reference, like\n\
+         @ref:open[2] for the third frame of open. The reference names an\n\
+         icon_state or frame that isn't there, isn't written that way, or the\n\
+         references form a loop.\n\
+         \n\
+         Typical fixes:\n\
+         - check that the icon_state exists and has that many frames\n\
+         - replace the reference with the frame data it should point to",
+    ),
+    (
+        "ICT0023",
+        "an icon_state has no frames",
+        "Every icon_state has at least one frame. The metadata gives this one\n\
+         frames = 0.\n\
+         \n\
+         Typical fixes:\n\
+         - set frames to 1 or more in the metadata, or remove the icon_state",
+    ),
+    (
+        "ICT0024",
+        "the GAGS config is not valid",
+        "A GAGS config is a JSON file describing the layers of each icon_state. It\n\
+         isn't valid JSON, or is missing something icontool needs.\n\
+         \n\
+         Typical fixes:\n\
+         - check the JSON for a syntax error at the place named in the error\n\
+         - give every icon_state at least one layer",
+    ),
+    (
+        "ICT0025",
+        "a hotspot is not valid",
+        "A hotspot is a pixel of a frame, counted from the top left corner. The one\n\
+         given is outside the icon, or names a frame that isn't there.\n\
+         \n\
+         Typical fixes:\n\
+         - keep x and y within the icon size in the metadata\n\
+         - count frames from 1, and check the number of frames with icontool states",
+    ),
+    (
+        "ICT0026",
+        "an include is not valid",
+        "__includes lists files whose keys are shared into a .dmi.yml file. One of\n\
+         them is missing, isn't a YAML mapping, or the includes form a loop.\n\
+         \n\
+         Typical fixes:\n\
+         - write include paths relative to the file that includes them\n\
+         - break a loop by moving the shared keys to a file that includes nothing",
+    ),
+    (
+        "ICT0027",
+        "the icon_states can't be overlaid",
+        "Overlaying draws one icon_state on top of another, frame by frame. They\n\
+         need the same dirs and number of frames, and the same icon size.\n\
+         \n\
+         Typical fixes:\n\
+         - check dirs and frames of both icon_states with icontool states\n\
+         - pick icon_states that match, or make them match first",
+    ),
+    (
+        "ICT0028",
+        "a PNG chunk is not valid",
+        "A .dmi file keeps its metadata in a text chunk of the PNG, and other\n\
+         chunks are kept under __png_chunks. A chunk named in the error is damaged,\n\
+         cut short, or not written the expected way.\n\
+         \n\
+         Typical fixes:\n\
+         - run icontool verify on the file to see what is damaged\n\
+         - write each entry of __png_chunks as the chunk type, a space, and base64",
+    ),
+    (
+        "ICT0029",
+        "a sprite test could not be understood",
+        "Sprite tests in __sprite_tests check pixels of the compiled icon. One of\n\
+         them isn't written the way icontool expects.\n\
+         \n\
+         Typical fixes:\n\
+         - see the Sprite Tests section of the README for how tests are written",
+    ),
+    (
+        "ICT0030",
+        "a transformation is not valid",
+        "A transformation flips, rotates, tints, or otherwise changes frames, from\n\
+         the command line or from __transforms. The one named in the error is\n\
+         unknown, or can't be done on frames of this size.\n\
+         \n\
+         Typical fixes:\n\
+         - check the spelling of the transformation\n\
+         - only turn frames by 90 or 270 degrees when they are square",
+    ),
+    (
+        "ICT0031",
+        "a value in the YAML has the wrong type",
+        "A key in the YAML holds a value of the wrong type, like a number where\n\
+         frame data is expected, or a number too large to be an image size.\n\
+         \n\
+         Typical fixes:\n\
+         - check the value at the line and column given in the error\n\
+         - quote values that should be text",
+    ),
+    (
+        "ICT0032",
+        "a file could not be read or written",
+        "The operating system refused to read or write a file. The error includes\n\
+         its reason, like a file that doesn't exist or permission being denied.\n\
+         \n\
+         Typical fixes:\n\
+         - check the path for a typo\n\
+         - check that the directory exists and can be written to",
+    ),
+    (
+        "ICT0033",
+        "lint found problems",
+        "icontool lint prints every problem it finds in the .dmi files, and fails\n\
+         when there are any, so it can be used in CI. The problems are printed\n\
+         above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - fix each problem printed above\n\
+         - run icontool doctor on a file for suggestions",
+    ),
+    (
+        "ICT0034",
+        "the files changed since the lock was made",
+        "A lockfile records the icon_states of .dmi files. verify-lock compares the\n\
+         files against it, and found changes, printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - if the changes were meant, run icontool lock again to update the lockfile\n\
+         - otherwise, restore the changed files",
+    ),
+    (
+        "ICT0035",
+        "a key is missing from the YAML",
+        "The YAML is missing a key icontool needs, like __image_width, or an\n\
+         icon_state that the metadata lists but the YAML doesn't have. When the\n\
+         name is close to a key that is there, the error suggests it.\n\
+         \n\
+         Typical fixes:\n\
+         - add the key, or fix the spelling of the one suggested\n\
+         - check icon_state names for trailing spaces, in the YAML and metadata",
+    ),
+    (
+        "ICT0036",
+        "the .dmi file has no metadata",
+        "A .dmi file is a PNG with the dmi metadata in a text chunk. This file has\n\
+         no such chunk, so it is a plain PNG, or the metadata was stripped by an\n\
+         image optimizer.\n\
+         \n\
+         Typical fixes:\n\
+         - restore the file from version control\n\
+         - tell the image optimizer to keep text chunks",
+    ),
+    (
+        "ICT0037",
+        "a movement icon_state has no regular icon_state",
+        "DreamMaker uses a movement icon_state while an atom moves, and the regular\n\
+         icon_state with the same name the rest of the time. This movement\n\
+         icon_state has no regular one to go with it.\n\
+         \n\
+         Typical fixes:\n\
+         - add a regular icon_state with the same name\n\
+         - or drop the movement flag from the icon_state",
+    ),
+    (
+        "ICT0038",
+        "the icon_state is not animated",
+        "The command works on the frames of an animation, but this icon_state has\n\
+         only one frame.\n\
+         \n\
+         Typical fixes:\n\
+         - check the name of the icon_state with icontool states",
+    ),
+    (
+        "ICT0039",
+        "the files are different",
+        "icontool equal and diff fail when the files differ, so they can be used in\n\
+         scripts. The differences are printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - if small changes don't matter, use equal --tolerance",
+    ),
+    (
+        "ICT0040",
+        "the overlay can't be applied to an icon_state",
+        "icontool apply changes icon_states from an overlay file. A change doesn't\n\
+         fit the icon_state it names, like new frames without new pixels.\n\
+         \n\
+         Typical fixes:\n\
+         - give pixels along with any change to dirs or frames\n\
+         - check the overlay against the base with icontool states",
+    ),
+    (
+        "ICT0041",
+        "the dmi metadata could not be parsed",
+        "The metadata of the .dmi file, or under __dmi_metadata, isn't written the\n\
+         way DreamMaker writes it.\n\
+         \n\
+         Typical fixes:\n\
+         - check the metadata for a typo near the place named in the error\n\
+         - keep the metadata between # BEGIN DMI and # END DMI",
+    ),
+    (
+        "ICT0042",
+        "a path could not be used",
+        "A path given to icontool, or one it made, can't be used, for example one\n\
+         with no file name.\n\
+         \n\
+         Typical fixes:\n\
+         - give the full path of a file, not of a directory",
+    ),
+    (
+        "ICT0043",
+        "the post-process command failed",
+        "--post-process, or post_process in icontool.yml, runs a command on every\n\
+         .dmi or .png file written. The command couldn't be run, or exited with an\n\
+         error.\n\
+         \n\
+         Typical fixes:\n\
+         - check that the command is installed and on the PATH\n\
+         - run the command by hand on the file to see its error",
+    ),
+    (
+        "ICT0044",
+        "the round trip found problems",
+        "icontool roundtrip decompiles and compiles each file again, and checks\n\
+         that nothing changed. The problems are printed above this error, and\n\
+         probably are a bug in icontool.\n\
+         \n\
+         Typical fixes:\n\
+         - please report the problems, with the file, to the author of icontool",
+    ),
+    (
+        "ICT0045",
+        "the YAML could not be read or written",
+        "This error comes from the YAML library. The .dmi.yml file isn't valid\n\
+         YAML, usually because of indentation, or a value that needs quotes.\n\
+         \n\
+         Typical fixes:\n\
+         - check the line and column given in the error\n\
+         - quote values that contain a colon or start with a special character",
+    ),
+    (
+        "ICT0046",
+        "sprite tests failed",
+        "The sprite tests in __sprite_tests check pixels of the compiled icon. Some\n\
+         of them failed, and are printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - fix the sprites, or update the tests if the change was meant",
+    ),
+    (
+        "ICT0047",
+        "the files have icon_states with the same name",
+        "icontool pack combines several .dmi files, and two of them have an\n\
+         icon_state with the same name.\n\
+         \n\
+         Typical fixes:\n\
+         - rename one of the icon_states\n\
+         - use --on-conflict ours, theirs, or rename to choose which to keep",
+    ),
+    (
+        "ICT0048",
+        "an icon_state was not found",
+        "The icon_state named on the command line, or in a sprite test, isn't in\n\
+         the dmi metadata.\n\
+         \n\
+         Typical fixes:\n\
+         - list the icon_states with icontool states, and check the spelling",
+    ),
+    (
+        "ICT0049",
+        "there are too many frames to paint",
+        "The frames didn't fit in the image that was made for them. This is a bug\n\
+         in icontool.\n\
+         \n\
+         Typical fixes:\n\
+         - please report it, with the .dmi.yml file, to the author of icontool",
+    ),
+    (
+        "ICT0050",
+        "the image would be too large",
+        "The frames of all the icon_states don't fit in an image of the largest\n\
+         size icontool makes.\n\
+         \n\
+         Typical fixes:\n\
+         - use compile --split to spread the icon_states over several files\n\
+         - move some icon_states to another .dmi file",
+    ),
+    (
+        "ICT0051",
+        "the metadata has more icon_states than the limit",
+        "To protect against damaged or hostile files, icontool refuses metadata\n\
+         with more icon_states than a limit.\n\
+         \n\
+         Typical fixes:\n\
+         - if the file is trusted, raise the limit with --max-states",
+    ),
+    (
+        "ICT0052",
+        "a variable is not defined",
+        "A value in the YAML uses ${NAME}, but NAME wasn't given a value.\n\
+         \n\
+         Typical fixes:\n\
+         - give it one with compile --define NAME=VALUE\n\
+         - in icontool.yml, add it under define of the target",
+    ),
+    (
+        "ICT0053",
+        "some icon_state references don't resolve",
+        "icontool missing looks for icon_states named in DM code that no .dmi file\n\
+         has. The ones it found are printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - add the icon_states, or fix their names in the code",
+    ),
+    (
+        "ICT0054",
+        "some files failed verification",
+        "icontool verify checks that .dmi files can be read completely. The files\n\
+         that can't, and why, are printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - restore the damaged files from version control\n\
+         - use decompile --lenient to recover what is left of a file",
+    ),
+    (
+        "ICT0055",
+        "warnings were treated as errors",
+        "With --deny-warnings, any warning fails the command. The warnings are\n\
+         printed above this error.\n\
+         \n\
+         Typical fixes:\n\
+         - fix what each warning points out\n\
+         - or leave out --deny-warnings",
+    ),
+    (
+        "ICT0056",
+        "a URL could not be downloaded",
+        "An input given as an http:// or https:// URL is downloaded with curl. It\n\
+         couldn't be run, or the download failed for the reason in the error.\n\
+         URLs only work in a build of icontool with the fetch feature.\n\
+         \n\
+         Typical fixes:\n\
+         - rebuild icontool with --features fetch\n\
+         - install curl, and make sure it is on the PATH\n\
+         - open the URL in a browser to check it\n\
+         - for a file on GitHub, use its raw URL",
+    ),
+    (
+        "ICT0057",
+        "a .zip archive could not be used",
+        "icontool reads .dmi files straight out of .zip archives, and can write\n\
+         what it converts into one. The archive is damaged, isn't a .zip, uses a\n\
+         compression method other than deflate, or doesn't have the file named in\n\
+         the error. A file that unpacks to more than 256 MiB is refused too.\n\
+         \n\
+         Typical fixes:\n\
+         - check that the archive opens with another tool\n\
+         - repack the archive with plain deflate compression",
+    ),
+    (
+        "ICT0058",
+        "some files of the archive failed to decompile",
+        "Decompiling a .zip archive decompiles every .dmi file in it, and keeps\n\
+         going when one of them fails. The error of each failed file is printed\n\
+         above this one, and listed again in the summary at the end.\n\
+         \n\
+         Typical fixes:\n\
+         - fix or remove the files listed in the summary\n\
+         - run icontool verify on the archive to see which files are damaged",
+    ),
+];
+
+pub fn explain(args: &ExplainArgs) -> Result<()> {
+    match &args.code {
+        Some(code) => {
+            let (code, summary, details) = get_explanation(code)?;
+            println!("{code}: {summary}\n\n{details}");
+        }
+        // without a code, list all of them
+        None => {
+            for (code, summary, _) in EXPLANATIONS {
+                println!("{code}  {summary}");
+            }
+        }
+    }
+    Ok(())
+}
+
+// look up an error code; ICT0009, ict0009, and 9 are all the same code
+pub fn get_explanation(code: &str) -> Result<&'static Explanation> {
+    let number = code
+        .get(..3)
+        .filter(|x| x.eq_ignore_ascii_case("ICT"))
+        .map_or(code, |_| &code[3..]);
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|x| x.checked_sub(1))
+        .and_then(|x| EXPLANATIONS.get(x))
+        .ok_or_else(|| {
+            IconToolError::InvalidArgument(format!(
+                "{code} is not an icontool error code; run icontool explain to list them"
+            ))
+        })
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::get_error_code;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_codes_in_order() {
+        for (index, (code, _, _)) in EXPLANATIONS.iter().enumerate() {
+            assert_eq!(format!("ICT{:04}", index + 1), *code);
+        }
+        // the last error has the last explanation
+        let error = IconToolError::DecompileFailed(1);
+        assert_eq!(EXPLANATIONS.last().unwrap().0, get_error_code(&error));
+    }
+
+    #[test]
+    fn test_get_explanation() {
+        let error = IconToolError::FrameCountMismatch(String::from("open"), 1, 2);
+        let (code, summary, _) = get_explanation(get_error_code(&error)).unwrap();
+        assert_eq!("ICT0009", *code);
+        assert_eq!("an icon_state has the wrong number of frames", *summary);
+        assert_eq!("ICT0009", get_explanation("ict0009").unwrap().0);
+        assert_eq!("ICT0009", get_explanation("9").unwrap().0);
+        assert!(get_explanation("ICT0000").is_err());
+        assert!(get_explanation("ICT9999").is_err());
+        assert!(get_explanation("E0308").is_err());
+        assert!(get_explanation("").is_err());
+    }
+
+    #[test]
+    fn test_located_error_code() {
+        let error = IconToolError::InvalidType(String::from("Under key open"));
+        let error = IconToolError::AtLocation(String::from("a.dmi.yml"), 2, 1, Box::new(error));
+        assert_eq!("ICT0031", get_error_code(&error));
+    }
+}
@@ -0,0 +1,185 @@
+// fetch.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+#[cfg(feature = "fetch")]
+use std::process::Command;
+
+#[cfg(feature = "fetch")]
+use crate::constant::MAX_INPUT_BYTES;
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+
+// true if the input is an http:// or https:// URL rather than a file
+pub fn is_url(input: &str) -> bool {
+    let scheme = input.split_once("://").map(|x| x.0.to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https"))
+}
+
+// read a file, or download it if the input is a URL
+pub fn read_input(input: &str) -> Result<Vec<u8>> {
+    if is_url(input) {
+        return fetch(input);
+    }
+    Ok(std::fs::read(input)?)
+}
+
+// like DmiFile::open, but the input may be a URL
+pub fn open_dmi(input: &str) -> Result<DmiFile> {
+    if is_url(input) {
+        return DmiFile::from_bytes(&fetch(input)?);
+    }
+    DmiFile::open(input.as_ref())
+}
+
+// download a URL with curl, which is nearly everywhere and handles TLS and
+// proxies, instead of making every build of icontool carry an HTTP client;
+// redirects are followed, but only to other http or https URLs
+#[cfg(feature = "fetch")]
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--proto", "=http,https", "--proto-redir", "=http,https"])
+        .args(["--max-filesize", &MAX_INPUT_BYTES.to_string()])
+        .arg(url)
+        .output()
+        .map_err(|x| IconToolError::FetchFailed(format!("{url}: curl can't be run: {x}")))?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr);
+        let reason = reason.trim().trim_start_matches("curl: ");
+        return Err(IconToolError::FetchFailed(format!("{url}: {reason}")));
+    }
+    Ok(output.stdout)
+}
+
+// without the fetch feature, icontool never runs curl
+#[cfg(not(feature = "fetch"))]
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    Err(IconToolError::FetchFailed(format!(
+        "{url}: built without URL support"
+    )))
+}
+
+// the file name at the end of a URL, without any query or fragment; for
+// example, neck.dmi from https://example.com/raw/neck.dmi?token=1
+pub fn get_url_file_name(url: &str) -> Option<&str> {
+    let url = url.split(['?', '#']).next()?;
+    let (_, path) = url.split_once("://")?.1.split_once('/')?;
+    path.rsplit('/').next().filter(|x| !x.is_empty())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "fetch")]
+    use std::io::{Read, Write};
+    #[cfg(feature = "fetch")]
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/raw/neck.dmi"));
+        assert!(is_url("HTTP://example.com/neck.dmi"));
+        assert!(!is_url("ftp://example.com/neck.dmi"));
+        assert!(!is_url("icons/mob/neck.dmi"));
+        assert!(!is_url("C:\\icons\\neck.dmi"));
+    }
+
+    #[test]
+    fn test_get_url_file_name() {
+        assert_eq!(
+            Some("neck.dmi"),
+            get_url_file_name("https://example.com/raw/neck.dmi")
+        );
+        assert_eq!(
+            Some("neck.dmi"),
+            get_url_file_name("https://example.com/neck.dmi?token=1#x")
+        );
+        assert_eq!(None, get_url_file_name("https://example.com/raw/"));
+        assert_eq!(None, get_url_file_name("https://example.com"));
+    }
+
+    #[test]
+    fn test_read_input() {
+        let expected = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        assert_eq!(expected, read_input("tests/data/edit/spin.dmi").unwrap());
+        assert!(open_dmi("tests/data/edit/spin.dmi").is_ok());
+    }
+
+    // answer one request on a local port with the given status and body
+    #[cfg(feature = "fetch")]
+    fn serve_once(status: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/raw/spin.dmi", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let count = stream.read(&mut buffer).unwrap();
+                if count == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..count]);
+            }
+            let head = format!(
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+        url
+    }
+
+    #[test]
+    #[cfg(not(feature = "fetch"))]
+    fn test_fetch_without_url_support() {
+        match open_dmi("https://example.com/raw/neck.dmi") {
+            Err(IconToolError::FetchFailed(x)) => assert!(x.contains("without URL support")),
+            _ => panic!("test_fetch_without_url_support: Expected FetchFailed error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fetch")]
+    fn test_fetch() {
+        // the download is done by curl, so there's nothing to test without it
+        if Command::new("curl").arg("--version").output().is_err() {
+            return;
+        }
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let url = serve_once("200 OK", dmi.clone());
+        assert_eq!(dmi, fetch(&url).unwrap());
+        let url = serve_once("200 OK", dmi);
+        let sheet = open_dmi(&url).unwrap();
+        assert_eq!(3, sheet.metadata.states.len());
+        let url = serve_once("404 Not Found", Vec::new());
+        match read_input(&url) {
+            Err(IconToolError::FetchFailed(x)) => assert!(x.contains("404"), "{x}"),
+            _ => panic!("test_fetch: Expected FetchFailed error"),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+// ffi.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// the C API, for linking icontool into tools written in other languages;
+// build with the ffi feature to get a shared library and include/icontool.h
+//
+// every function takes its input as a pointer and length, and returns an
+// IconToolBuffer allocated by icontool, which must be released with
+// icontool_free. if something goes wrong, the returned buffer is empty and
+// the error code is written to the error out-parameter; a message is
+// available from icontool_last_error until the next call on that thread.
+// a panic inside icontool is caught and reported as ICONTOOL_ERROR_OTHER,
+// because unwinding into the caller's frames is undefined behavior
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::null;
+
+use crate::dmi::decode_metadata;
+use crate::error::{get_error_message, IconToolError, MissingMetadata};
+
+/// The call succeeded.
+pub const ICONTOOL_OK: i32 = 0;
+/// A pointer was null, or text was not valid UTF-8.
+pub const ICONTOOL_ERROR_ARGUMENT: i32 = 1;
+/// The .dmi file could not be decoded or encoded as a PNG.
+pub const ICONTOOL_ERROR_PNG: i32 = 2;
+/// The .dmi file has no metadata.
+pub const ICONTOOL_ERROR_MISSING_METADATA: i32 = 3;
+/// The .dmi metadata could not be parsed, or describes an invalid icon.
+pub const ICONTOOL_ERROR_METADATA: i32 = 4;
+/// The .dmi.yml text could not be read.
+pub const ICONTOOL_ERROR_YAML: i32 = 5;
+/// Any other error, including a bug inside icontool.
+pub const ICONTOOL_ERROR_OTHER: i32 = 6;
+
+/// Bytes allocated by icontool; release them with icontool_free.
+#[repr(C)]
+pub struct IconToolBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Compile .dmi.yml text into the bytes of a .dmi file.
+///
+/// # Safety
+/// `yaml` must point to `yaml_len` readable bytes, and `error` must be null
+/// or point to a writable int32_t.
+#[no_mangle]
+pub unsafe extern "C" fn icontool_compile(
+    yaml: *const u8,
+    yaml_len: usize,
+    error: *mut i32,
+) -> IconToolBuffer {
+    guard(error, || {
+        let yaml = read_input(yaml, yaml_len)?;
+        let yaml = std::str::from_utf8(yaml).map_err(|_| {
+            IconToolError::InvalidArgument(String::from("the .dmi.yml text is not valid UTF-8"))
+        })?;
+        crate::compile_bytes(yaml)
+    })
+}
+
+/// Decompile the bytes of a .dmi file into .dmi.yml text (UTF-8, not
+/// nul-terminated).
+///
+/// # Safety
+/// `dmi` must point to `dmi_len` readable bytes, and `error` must be null
+/// or point to a writable int32_t.
+#[no_mangle]
+pub unsafe extern "C" fn icontool_decompile(
+    dmi: *const u8,
+    dmi_len: usize,
+    error: *mut i32,
+) -> IconToolBuffer {
+    guard(error, || {
+        read_input(dmi, dmi_len)
+            .and_then(crate::decompile_bytes)
+            .map(String::into_bytes)
+    })
+}
+
+/// Read the metadata text (UTF-8, not nul-terminated) of a .dmi file.
+///
+/// # Safety
+/// `dmi` must point to `dmi_len` readable bytes, and `error` must be null
+/// or point to a writable int32_t.
+#[no_mangle]
+pub unsafe extern "C" fn icontool_metadata(
+    dmi: *const u8,
+    dmi_len: usize,
+    error: *mut i32,
+) -> IconToolBuffer {
+    guard(error, || {
+        decode_metadata(read_input(dmi, dmi_len)?)?
+            .map(String::into_bytes)
+            .ok_or_else(|| IconToolError::MissingMetadata(MissingMetadata(Default::default())))
+    })
+}
+
+/// Release a buffer returned by icontool.
+///
+/// # Safety
+/// `buffer` must have been returned by icontool, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn icontool_free(buffer: IconToolBuffer) {
+    if !buffer.data.is_null() {
+        let slice = std::ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+        drop(Box::from_raw(slice));
+    }
+}
+
+/// The message for the last error on this thread, or null if the last call
+/// succeeded. The message is valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn icontool_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => null(),
+    })
+}
+
+pub fn error_code(error: &IconToolError) -> i32 {
+    match error {
+        IconToolError::AtLocation(_, _, _, x) => error_code(x),
+        IconToolError::InvalidArgument(_) => ICONTOOL_ERROR_ARGUMENT,
+        IconToolError::DecodingError(_)
+        | IconToolError::EncodingError(_)
+        | IconToolError::ImageError(_)
+        | IconToolError::ImageTooSmall(_, _)
+        | IconToolError::InvalidPngChunk(_) => ICONTOOL_ERROR_PNG,
+        IconToolError::MissingMetadata(_) => ICONTOOL_ERROR_MISSING_METADATA,
+        IconToolError::DelayCountMismatch(_, _, _)
+        | IconToolError::IncompleteParseError(_)
+        | IconToolError::InvalidDelay(_)
+        | IconToolError::InvalidDirs(_, _)
+        | IconToolError::InvalidFrames(_, _)
+        | IconToolError::InvalidHotspot(_)
+        | IconToolError::ParseError(_)
+        | IconToolError::TooManyIconStates(_, _)
+        | IconToolError::TooManyStates(_, _) => ICONTOOL_ERROR_METADATA,
+        IconToolError::DecodeError(_)
+        | IconToolError::DecompressError(_)
+        | IconToolError::DuplicateKey(_, _, _)
+        | IconToolError::FrameCountMismatch(_, _, _)
+        | IconToolError::FrameSizeMismatch(_, _, _, _)
+        | IconToolError::FrameTooLarge(_, _, _)
+        | IconToolError::InvalidInclude(_)
+        | IconToolError::InvalidType(_)
+        | IconToolError::MissingKey(_)
+        | IconToolError::Serialize(_)
+        | IconToolError::SpriteTestFailed(_)
+        | IconToolError::UndefinedVariable(_) => ICONTOOL_ERROR_YAML,
+        _ => ICONTOOL_ERROR_OTHER,
+    }
+}
+
+unsafe fn read_input<'a>(data: *const u8, len: usize) -> Result<&'a [u8], IconToolError> {
+    if data.is_null() {
+        return Err(IconToolError::InvalidArgument(String::from(
+            "the input pointer is null",
+        )));
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+// run an entry point, turning a panic into ICONTOOL_ERROR_OTHER
+unsafe fn guard(
+    error: *mut i32,
+    f: impl FnOnce() -> Result<Vec<u8>, IconToolError>,
+) -> IconToolBuffer {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => finish(result, error),
+        Err(payload) => {
+            let message = format!("icontool: internal error: {}", panic_text(&*payload));
+            fail(ICONTOOL_ERROR_OTHER, message, error)
+        }
+    }
+}
+
+fn panic_text(payload: &(dyn Any + Send)) -> &str {
+    if let Some(text) = payload.downcast_ref::<&str>() {
+        text
+    } else if let Some(text) = payload.downcast_ref::<String>() {
+        text
+    } else {
+        "panic"
+    }
+}
+
+unsafe fn finish(result: Result<Vec<u8>, IconToolError>, error: *mut i32) -> IconToolBuffer {
+    match result {
+        Ok(bytes) => {
+            let len = bytes.len();
+            let data = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+            report(ICONTOOL_OK, None, error);
+            IconToolBuffer { data, len }
+        }
+        Err(x) => fail(error_code(&x), get_error_message(x), error),
+    }
+}
+
+unsafe fn fail(code: i32, message: String, error: *mut i32) -> IconToolBuffer {
+    report(code, CString::new(message).ok(), error);
+    IconToolBuffer {
+        data: std::ptr::null_mut(),
+        len: 0,
+    }
+}
+
+unsafe fn report(code: i32, message: Option<CString>, error: *mut i32) {
+    if !error.is_null() {
+        *error = code;
+    }
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_ffi_round_trip() {
+        let dmi = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let mut error = -1;
+        unsafe {
+            let yaml = icontool_decompile(dmi.as_ptr(), dmi.len(), &mut error);
+            assert_eq!(ICONTOOL_OK, error);
+            assert!(icontool_last_error().is_null());
+            let compiled = icontool_compile(yaml.data, yaml.len, &mut error);
+            assert_eq!(ICONTOOL_OK, error);
+            let metadata = icontool_metadata(compiled.data, compiled.len, &mut error);
+            assert_eq!(ICONTOOL_OK, error);
+            let text = std::slice::from_raw_parts(metadata.data, metadata.len);
+            assert!(text.starts_with(b"# BEGIN DMI"));
+            icontool_free(yaml);
+            icontool_free(compiled);
+            icontool_free(metadata);
+        }
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let mut error = -1;
+        unsafe {
+            let buffer = icontool_decompile(std::ptr::null(), 0, &mut error);
+            assert_eq!(ICONTOOL_ERROR_ARGUMENT, error);
+            assert!(buffer.data.is_null());
+            let garbage = b"not a png";
+            let buffer = icontool_metadata(garbage.as_ptr(), garbage.len(), &mut error);
+            assert_eq!(ICONTOOL_ERROR_PNG, error);
+            let message = CStr::from_ptr(icontool_last_error()).to_str().unwrap();
+            assert!(message.starts_with("icontool: "));
+            icontool_free(buffer);
+        }
+    }
+
+    #[test]
+    fn test_ffi_panic() {
+        let mut error = -1;
+        unsafe {
+            let buffer = guard(&mut error, || panic!("test_ffi_panic"));
+            assert_eq!(ICONTOOL_ERROR_OTHER, error);
+            assert!(buffer.data.is_null());
+            let message = CStr::from_ptr(icontool_last_error()).to_str().unwrap();
+            assert_eq!("icontool: internal error: test_ffi_panic", message);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// fix_metadata.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::path::PathBuf;
+
+use crate::cmdline::FixMetadataArgs;
+use crate::constant::ZTXT_KEYWORD;
+use crate::dmi::{read_metadata, read_rgba_image, write_dmi_file};
+use crate::edit::get_output_path;
+use crate::error::Result;
+use crate::log::diagnostic;
+use crate::parser::{parse_metadata, DreamMakerIconMetadata, DreamMakerIconState};
+use crate::validate::validate_metadata;
+
+pub fn fix_metadata(args: &FixMetadataArgs) -> Result<()> {
+    // read the image and metadata from the provided dmi file
+    let path = PathBuf::from(&args.file);
+    let image = read_rgba_image(&path)?;
+    let mut dmi_metadata = parse_metadata(&read_metadata(&path)?)?;
+    validate_metadata(&dmi_metadata)?;
+
+    // cut the icon_states down to the frames the image really has
+    let changes = fit_metadata(&mut dmi_metadata, &image);
+    let output_path = get_output_path(&args.file, &args.output);
+    if changes.is_empty() && output_path == path {
+        diagnostic(&format!(
+            "icontool: {} already matches its image",
+            args.file
+        ));
+        return Ok(());
+    }
+    for change in &changes {
+        diagnostic(&format!("icontool: {}: {change}", args.file));
+    }
+
+    // write the image back untouched, with the fixed metadata
+    let image = DynamicImage::ImageRgba8(image);
+    write_dmi_file(
+        &output_path,
+        ZTXT_KEYWORD,
+        &dmi_metadata.to_string(),
+        &image,
+    )
+}
+
+// if the metadata declares more frames than the image has, keep the frames
+// the image does have; returns a description of each change
+pub fn fit_metadata(dmi: &mut DreamMakerIconMetadata, image: &RgbaImage) -> Vec<String> {
+    let (width, height) = (dmi.width.max(1), dmi.height.max(1));
+    let cells = u64::from(image.width() / width) * u64::from(image.height() / height);
+    let needed: u64 = dmi.states.iter().map(frame_count).sum();
+    if needed <= cells {
+        return Vec::new();
+    }
+
+    // frames are laid out in order, so once an icon_state runs out of
+    // cells, every icon_state after it is gone too
+    let mut available = count_used_cells(image, width, height);
+    let mut changes = Vec::new();
+    let mut kept = Vec::new();
+    for mut state in dmi.states.drain(..) {
+        let dirs = state.dirs.max(1);
+        let needed = frame_count(&state);
+        if needed <= u64::from(available) {
+            available -= needed as u32;
+            kept.push(state);
+            continue;
+        }
+        let frames = available / dirs;
+        available = 0;
+        if frames == 0 {
+            changes.push(format!(
+                "removed icon_state '{}', which has no frames in the image",
+                state.name
+            ));
+            continue;
+        }
+        changes.push(format!(
+            "icon_state '{}' has {frames} of its {} frame(s) in the image",
+            state.name, state.frames
+        ));
+        state.frames = frames;
+        if let Some(delay) = &mut state.delay {
+            delay.truncate(frames as usize);
+        }
+        state.hotspots.retain(|x| x.frame <= frames * dirs);
+        kept.push(state);
+    }
+    dmi.states = kept;
+    changes
+}
+
+// the frames of every direction, which may not fit in a u32
+fn frame_count(state: &DreamMakerIconState) -> u64 {
+    u64::from(state.dirs) * u64::from(state.frames)
+}
+
+// count the cells of the grid up to the last one with a visible pixel; the
+// transparent cells after it are padding, not frames
+fn count_used_cells(image: &RgbaImage, width: u32, height: u32) -> u32 {
+    let columns = image.width() / width;
+    let cells = columns * (image.height() / height);
+    (0..cells)
+        .rev()
+        .find(|cell| {
+            let (x, y) = ((cell % columns) * width, (cell / columns) * height);
+            image
+                .view(x, y, width, height)
+                .pixels()
+                .any(|(_, _, pixel)| pixel[3] != 0)
+        })
+        .map_or(0, |cell| cell + 1)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::DmiBuilder;
+    use crate::dmi_file::DmiFile;
+    use image::Rgba;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_fix_metadata() {
+        // three frames on a 2x2 grid, then claim more frames than that
+        let red = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255]));
+        let sheet = DmiBuilder::new(8, 8)
+            .state("still")
+            .push_frame(red.clone())
+            .state("blink")
+            .frames(2)
+            .delay(&[1.0, 2.0])
+            .push_frame(red.clone())
+            .push_frame(blue.clone())
+            .build()
+            .unwrap();
+        let mut dmi = sheet.metadata.clone();
+        dmi.states[1].frames = 4;
+        dmi.states[1].delay = Some(vec![String::from("1"); 4]);
+        dmi.states.push(dmi.states[0].clone());
+        dmi.states[2].name = String::from("lost");
+        std::fs::create_dir_all("tests/data/fix_metadata").unwrap();
+        let path = PathBuf::from("tests/data/fix_metadata/mangled.out.dmi");
+        write_dmi_file(&path, ZTXT_KEYWORD, &dmi.to_string(), &sheet.to_image()).unwrap();
+        assert!(DmiFile::open(&path).is_err());
+
+        let output = PathBuf::from("tests/data/fix_metadata/fixed.out.dmi");
+        let args = FixMetadataArgs {
+            output: Some(output.to_string_lossy().to_string()),
+            file: path.to_string_lossy().to_string(),
+        };
+        fix_metadata(&args).unwrap();
+        let fixed = DmiFile::open(&output).unwrap();
+        let names: Vec<&str> = fixed.states().map(|x| x.name()).collect();
+        assert_eq!(vec!["still", "blink"], names);
+        let blink = fixed.state("blink").unwrap();
+        assert_eq!(2, blink.metadata.frames);
+        assert_eq!(Some(vec![String::from("1"); 2]), blink.metadata.delay);
+        assert_eq!(&blue, blink.frame(1, 0));
+    }
+
+    #[test]
+    fn test_fix_metadata_invalid_dirs() {
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let mut dmi = sheet.metadata.clone();
+        dmi.states[0].dirs = 0;
+        std::fs::create_dir_all("tests/data/fix_metadata").unwrap();
+        let path = PathBuf::from("tests/data/fix_metadata/no_dirs.out.dmi");
+        write_dmi_file(&path, ZTXT_KEYWORD, &dmi.to_string(), &sheet.to_image()).unwrap();
+        let args = FixMetadataArgs {
+            output: Some(String::from("tests/data/fix_metadata/no_dirs.out.out.dmi")),
+            file: path.to_string_lossy().to_string(),
+        };
+        match fix_metadata(&args) {
+            Err(crate::error::IconToolError::InvalidDirs(name, 0)) => assert_eq!("spin", name),
+            _ => panic!("test_fix_metadata_invalid_dirs: Expected InvalidDirs error"),
+        }
+    }
+
+    #[test]
+    fn test_fit_metadata_unchanged() {
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let mut dmi = sheet.metadata.clone();
+        let image = sheet.to_image().to_rgba8();
+        assert!(fit_metadata(&mut dmi, &image).is_empty());
+        assert_eq!(sheet.metadata.to_string(), dmi.to_string());
+    }
+
+    #[test]
+    fn test_fit_metadata_frame_overflow() {
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let mut dmi = sheet.metadata.clone();
+        dmi.states[1].frames = u32::MAX;
+        dmi.states[1].delay = None;
+        let image = sheet.to_image().to_rgba8();
+        assert_eq!(
+            vec![
+                "icon_state 'walk' has 2 of its 4294967295 frame(s) in the image",
+                "removed icon_state 'still', which has no frames in the image",
+            ],
+            fit_metadata(&mut dmi, &image)
+        );
+        assert_eq!(2, dmi.states[1].frames);
+    }
+
+    #[test]
+    fn test_count_used_cells() {
+        let mut image = RgbaImage::new(16, 16);
+        assert_eq!(0, count_used_cells(&image, 8, 8));
+        image.put_pixel(9, 1, Rgba([0, 0, 0, 1]));
+        assert_eq!(2, count_used_cells(&image, 8, 8));
+        image.put_pixel(15, 15, Rgba([0, 0, 0, 1]));
+        assert_eq!(4, count_used_cells(&image, 8, 8));
+    }
+}
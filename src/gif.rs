@@ -0,0 +1,187 @@
+// gif.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, RgbaImage};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::atomic::create_atomic;
+use crate::cmdline::GifArgs;
+use crate::constant::DIR_NAMES;
+use crate::dmi_file::{DmiFile, IconState};
+use crate::error::{IconToolError, Result};
+
+pub fn gif(args: &GifArgs) -> Result<()> {
+    // find the icon_state to animate
+    let path = PathBuf::from(&args.file);
+    let sheet = DmiFile::open(&path)?;
+    let state = sheet
+        .state(&args.state)
+        .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+    let output_path = match &args.output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(format!("{}.gif", args.state)),
+    };
+
+    // one animation per direction, all directions side by side, or south
+    if args.separate {
+        for dir in 0..state.metadata.dirs {
+            let frames = animate(&state, |frame| state.frame(frame, dir).clone());
+            write_gif(&get_dir_path(&output_path, dir), &state, frames)?;
+        }
+    } else if args.all_dirs {
+        let frames = animate(&state, |frame| side_by_side(&state, frame));
+        write_gif(&output_path, &state, frames)?;
+    } else {
+        let frames = animate(&state, |frame| state.frame(frame, 0).clone());
+        write_gif(&output_path, &state, frames)?;
+    }
+
+    Ok(())
+}
+
+// encode an icon_state as an animated GIF in memory, with all of its
+// directions side by side
+pub fn encode_gif(state: &IconState) -> Result<Vec<u8>> {
+    let frames = animate(state, |frame| side_by_side(state, frame));
+    let mut bytes = Vec::new();
+    encode_frames(&mut bytes, state, frames)?;
+    Ok(bytes)
+}
+
+// the frames of the animation in the order they play, with their delays
+fn animate<F>(state: &IconState, render: F) -> Vec<Frame>
+where
+    F: Fn(u32) -> RgbaImage,
+{
+    // a rewinding animation plays forwards and then backwards
+    let count = state.metadata.frames;
+    let mut order: Vec<u32> = (0..count).collect();
+    if state.metadata.rewind.is_some() && count > 2 {
+        order.extend((1..count - 1).rev());
+    }
+
+    // delays are in ticks, which are a tenth of a second
+    let delays = state.metadata.delay.clone().unwrap_or_default();
+    order
+        .into_iter()
+        .map(|frame| {
+            let ticks = delays
+                .get(frame as usize)
+                .and_then(|x| x.parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let delay = Delay::from_numer_denom_ms((ticks * 100.0).round() as u32, 1);
+            Frame::from_parts(render(frame), 0, 0, delay)
+        })
+        .collect()
+}
+
+fn side_by_side(state: &IconState, frame: u32) -> RgbaImage {
+    let first = state.frame(frame, 0);
+    let (width, height) = first.dimensions();
+    let mut image = RgbaImage::new(width * state.metadata.dirs, height);
+    for dir in 0..state.metadata.dirs {
+        let x = (dir * width) as i64;
+        imageops::replace(&mut image, state.frame(frame, dir), x, 0);
+    }
+    image
+}
+
+fn write_gif(path: &Path, state: &IconState, frames: Vec<Frame>) -> Result<()> {
+    create_atomic(path, |writer| encode_frames(writer, state, frames))
+}
+
+fn encode_frames<W: Write>(writer: W, state: &IconState, frames: Vec<Frame>) -> Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    // BYOND's loop count is how many times it plays, while a GIF's is how
+    // many times it repeats; without one, a GIF plays once
+    match state
+        .metadata
+        ._loop
+        .as_ref()
+        .and_then(|x| x.parse::<u16>().ok())
+    {
+        Some(0) | None => encoder.set_repeat(Repeat::Infinite)?,
+        Some(1) => {}
+        Some(count) => encoder.set_repeat(Repeat::Finite(count - 1))?,
+    }
+    encoder.encode_frames(frames)?;
+    Ok(())
+}
+
+fn get_dir_path(path: &Path, dir: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}_{}.gif", DIR_NAMES[dir as usize]))
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn read_gif(path: &str) -> Vec<Frame> {
+        let reader = BufReader::new(File::open(path).unwrap());
+        let decoder = GifDecoder::new(reader).unwrap();
+        decoder.into_frames().collect_frames().unwrap()
+    }
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_gif_all_dirs() {
+        std::fs::create_dir_all("tests/data/gif").unwrap();
+        let mut args = GifArgs {
+            output: Some(String::from("tests/data/gif/walk.out.gif")),
+            state: String::from("walk"),
+            all_dirs: true,
+            separate: false,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        gif(&args).unwrap();
+        let frames = read_gif("tests/data/gif/walk.out.gif");
+        assert_eq!(2, frames.len());
+        assert_eq!((128, 32), frames[0].buffer().dimensions());
+        assert_eq!((200, 1), frames[0].delay().numer_denom_ms());
+
+        args.output = Some(String::from("tests/data/gif/walk.out.dir.gif"));
+        args.separate = true;
+        gif(&args).unwrap();
+        for name in DIR_NAMES.iter().take(4) {
+            let frames = read_gif(&format!("tests/data/gif/walk.out.dir_{name}.gif"));
+            assert_eq!((32, 32), frames[1].buffer().dimensions());
+        }
+    }
+
+    #[test]
+    fn test_get_dir_path() {
+        assert_eq!(
+            PathBuf::from("out/walk_northeast.gif"),
+            get_dir_path(Path::new("out/walk.gif"), 6)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// hook.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::error::{IconToolError, Result};
+
+// the command to run on each .dmi or .png file that is written, like
+// "oxipng -o4 {}"; set once, by main, from the command line or icontool.yml
+static POST_PROCESS: OnceLock<String> = OnceLock::new();
+
+pub fn set_post_process(command: &str) {
+    let _ = POST_PROCESS.set(command.to_string());
+}
+
+// run the post-process command, if there is one, on a file just written
+pub fn after_write(path: &Path) -> Result<()> {
+    let is_image = path
+        .extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("dmi") || x.eq_ignore_ascii_case("png"));
+    match POST_PROCESS.get() {
+        Some(command) if is_image => post_process(command, path),
+        _ => Ok(()),
+    }
+}
+
+pub fn post_process(command: &str, path: &Path) -> Result<()> {
+    let words = expand_command(command, path);
+    let (program, args) = words.split_first().ok_or_else(|| {
+        IconToolError::InvalidArgument(String::from("the post-process command is empty"))
+    })?;
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|x| IconToolError::PostProcessFailed(format!("'{program}' can't be run: {x}")))?;
+    if !status.success() {
+        let command = words.join(" ");
+        return Err(IconToolError::PostProcessFailed(format!(
+            "'{command}' exited with {status}"
+        )));
+    }
+    Ok(())
+}
+
+// split the command into words, without a shell, so a path with spaces or
+// quotes in it stays one argument; {} is replaced with the path, which goes
+// on the end if there is no {}
+pub fn expand_command(command: &str, path: &Path) -> Vec<String> {
+    let path = path.to_string_lossy();
+    let mut words: Vec<String> = command
+        .split_whitespace()
+        .map(|x| x.replace("{}", &path))
+        .collect();
+    if !command.contains("{}") {
+        words.push(path.to_string());
+    }
+    words
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_expand_command() {
+        let path = Path::new("icons/my door.dmi");
+        assert_eq!(
+            vec!["oxipng", "-o4", "icons/my door.dmi"],
+            expand_command("oxipng -o4 {}", path)
+        );
+        assert_eq!(
+            vec!["oxipng", "icons/my door.dmi"],
+            expand_command("oxipng", path)
+        );
+        assert_eq!(
+            vec!["cp", "icons/my door.dmi", "icons/my door.dmi.bak"],
+            expand_command("cp {} {}.bak", path)
+        );
+        assert!(expand_command("", path).len() == 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_process() {
+        std::fs::create_dir_all("tests/data/hook").unwrap();
+        let path = Path::new("tests/data/hook/icon.out.dmi");
+        std::fs::write(path, "pixels").unwrap();
+        post_process("cp {} {}.out.copy", path).unwrap();
+        let copy = std::fs::read_to_string("tests/data/hook/icon.out.dmi.out.copy").unwrap();
+        assert_eq!("pixels", copy);
+        match post_process("false", path) {
+            Err(IconToolError::PostProcessFailed(x)) => assert!(x.starts_with("'false "), "{x}"),
+            _ => panic!("test_post_process: Expected PostProcessFailed error"),
+        }
+        match post_process("icontool-no-such-program {}", path) {
+            Err(IconToolError::PostProcessFailed(x)) => assert!(x.contains("can't be run"), "{x}"),
+            _ => panic!("test_post_process: Expected PostProcessFailed error"),
+        }
+    }
+}
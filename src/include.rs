@@ -0,0 +1,197 @@
+// include.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use indexmap::IndexMap;
+use serde_yml::Value;
+use std::path::{Path, PathBuf};
+
+use crate::constant::{ICONTOOL_KEYS, INCLUDES_KEY};
+use crate::error::{IconToolError, Result};
+use crate::indexmap_helper::IndexMapHelper;
+
+// pull the icon_states of the files listed under __includes into the yaml;
+// paths are relative to the file that lists them, and an icon_state the
+// file has itself, or that an earlier include has, is not replaced
+pub fn resolve_includes(path: &Path, yaml: &str) -> Result<(String, Vec<PathBuf>)> {
+    // most files include nothing, so don't parse them twice to find out
+    if !yaml.contains(INCLUDES_KEY) {
+        return Ok((yaml.to_string(), Vec::new()));
+    }
+    let data: IndexMap<String, Value> = serde_yml::from_str(yaml)?;
+    let mut included = IndexMap::new();
+    let mut files = Vec::new();
+    let mut chain = vec![(std::fs::canonicalize(path)?, path.display().to_string())];
+    collect_includes(path, &data, &mut chain, &mut included, &mut files)?;
+
+    // append what was included, so the lines of the file stay where they are
+    included.retain(|key, _| !data.contains_key(key));
+    if included.is_empty() {
+        return Ok((yaml.to_string(), files));
+    }
+    let mut yaml = yaml.to_string();
+    if !yaml.ends_with('\n') {
+        yaml.push('\n');
+    }
+    yaml.push_str(&serde_yml::to_string(&included)?);
+    Ok((yaml, files))
+}
+
+// every file the .dmi.yml includes, directly or through another include
+pub fn find_includes(path: &Path) -> Result<Vec<PathBuf>> {
+    let yaml = std::fs::read_to_string(path)?;
+    Ok(resolve_includes(path, &yaml)?.1)
+}
+
+fn collect_includes(
+    path: &Path,
+    data: &IndexMap<String, Value>,
+    chain: &mut Vec<(PathBuf, String)>,
+    included: &mut IndexMap<String, Value>,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if !data.contains_key(INCLUDES_KEY) {
+        return Ok(());
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for include in data.get_string_list(INCLUDES_KEY)? {
+        let include_path = dir.join(&include);
+        let describe = |chain: &[(PathBuf, String)]| {
+            let mut names: Vec<&str> = chain.iter().map(|(_, name)| name.as_str()).collect();
+            names.push(&include);
+            names.join(" -> ")
+        };
+        let invalid = |chain: &[(PathBuf, String)], reason: String| {
+            IconToolError::InvalidInclude(format!("{}: {reason}", describe(chain)))
+        };
+
+        // a file that includes itself, however indirectly, would never end
+        let canonical =
+            std::fs::canonicalize(&include_path).map_err(|x| invalid(chain, x.to_string()))?;
+        if chain.iter().any(|(x, _)| *x == canonical) {
+            return Err(invalid(chain, String::from("the includes form a loop")));
+        }
+        // one reached by two paths only needs reading once
+        if files
+            .iter()
+            .any(|x| std::fs::canonicalize(x).ok() == Some(canonical.clone()))
+        {
+            continue;
+        }
+
+        let text =
+            std::fs::read_to_string(&include_path).map_err(|x| invalid(chain, x.to_string()))?;
+        let include_data: IndexMap<String, Value> =
+            serde_yml::from_str(&text).map_err(|x| invalid(chain, x.to_string()))?;
+        files.push(include_path.clone());
+        for (key, value) in &include_data {
+            if !ICONTOOL_KEYS.contains(&key.as_str()) && !included.contains_key(key) {
+                included.insert(key.clone(), value.clone());
+            }
+        }
+        chain.push((canonical, include.clone()));
+        collect_includes(&include_path, &include_data, chain, included, files)?;
+        chain.pop();
+    }
+    Ok(())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_resolve_includes() {
+        let dir = Path::new("tests/data/include/resolve.out.d");
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(
+            dir.join("shared/broken.yml"),
+            "broken: BBBB\nopen: XXXX\n__includes:\n- sparks.yml\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("shared/sparks.yml"),
+            "sparks: SSSS\nbroken: YYYY\n",
+        )
+        .unwrap();
+        let path = dir.join("door.dmi.yml");
+        let yaml = "__includes:\n- shared/broken.yml\n- shared/sparks.yml\nopen: OOOO\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let (resolved, files) = resolve_includes(&path, yaml).unwrap();
+        assert_eq!(
+            vec![dir.join("shared/broken.yml"), dir.join("shared/sparks.yml")],
+            files
+        );
+        // the lines of the file are untouched, and its own icon_states win
+        assert!(resolved.starts_with(yaml));
+        let data: IndexMap<String, Value> = serde_yml::from_str(&resolved).unwrap();
+        assert_eq!(Some("OOOO"), data["open"].as_str());
+        assert_eq!(Some("BBBB"), data["broken"].as_str());
+        assert_eq!(Some("SSSS"), data["sparks"].as_str());
+        assert_eq!(files, find_includes(&path).unwrap());
+
+        // without includes, the yaml is left as it is
+        let plain = "open: OOOO";
+        assert_eq!(
+            (plain.to_string(), Vec::new()),
+            resolve_includes(&path, plain).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resolve_includes_errors() {
+        let dir = Path::new("tests/data/include/errors.out.d");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("a.yml"), "__includes: [b.yml]\n").unwrap();
+        std::fs::write(dir.join("b.yml"), "__includes: [a.yml]\n").unwrap();
+        let path = dir.join("door.dmi.yml");
+        std::fs::write(&path, "").unwrap();
+        let door = path.display();
+        for (yaml, expected) in [
+            (
+                "__includes: [a.yml]\n",
+                format!("{door} -> a.yml -> b.yml -> a.yml: the includes form a loop"),
+            ),
+            (
+                "__includes: [door.dmi.yml]\n",
+                format!("{door} -> door.dmi.yml: the includes form a loop"),
+            ),
+            (
+                "__includes: [missing.yml]\n",
+                format!("{door} -> missing.yml: "),
+            ),
+        ] {
+            match resolve_includes(&path, yaml) {
+                Err(IconToolError::InvalidInclude(x)) => assert!(x.starts_with(&expected), "{x}"),
+                _ => panic!("test_resolve_includes_errors: Expected InvalidInclude error"),
+            }
+        }
+        match resolve_includes(&path, "__includes: a.yml\n") {
+            Err(IconToolError::InvalidType(_)) => {}
+            _ => panic!("test_resolve_includes_errors: Expected InvalidType error"),
+        }
+    }
+}
@@ -26,6 +26,7 @@ use crate::error::{IconToolError, Result};
 pub trait IndexMapHelper {
     fn get_icon_state_frames(&self, key: &str) -> Result<Vec<String>>;
     fn get_string(&self, key: &str) -> Result<String>;
+    fn get_string_list(&self, key: &str) -> Result<Vec<String>>;
     fn get_u32(&self, key: &str) -> Result<u32>;
 }
 
@@ -67,6 +68,34 @@ impl IndexMapHelper for IndexMap<String, Value> {
         Err(IconToolError::MissingKey(format!("Key {key} is missing")))
     }
 
+    fn get_string_list(&self, key: &str) -> Result<Vec<String>> {
+        // if there is a Value stored under the provided key
+        if let Some(value) = self.get(key) {
+            // and we can convert it to a sequence
+            if let Some(value_seq) = value.as_sequence() {
+                let mut strings = Vec::with_capacity(value_seq.len());
+                // and every item in the sequence is a &str
+                for item in value_seq {
+                    match item.as_str() {
+                        Some(item_str) => strings.push(item_str.to_string()),
+                        None => {
+                            return Err(IconToolError::InvalidType(format!(
+                                "Under key {key}, Value {item:?} cannot be converted to a String"
+                            )))
+                        }
+                    }
+                }
+                return Ok(strings);
+            }
+            // return an error if we couldn't convert it to a sequence
+            return Err(IconToolError::InvalidType(format!(
+                "Under key {key}, Value {value:?} cannot be converted to a list of String"
+            )));
+        }
+        // return an error if the key was missing
+        Err(IconToolError::MissingKey(format!("Key {key} is missing")))
+    }
+
     fn get_u32(&self, key: &str) -> Result<u32> {
         // if there is a Value stored under the provided key
         if let Some(value) = self.get(key) {
@@ -0,0 +1,123 @@
+// lint.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::Path;
+
+use crate::cmdline::LintArgs;
+use crate::constant::PLACEHOLDER_KEYWORD;
+use crate::dmi::{read_metadata, read_text_chunk};
+use crate::dmi_file::DmiFile;
+use crate::error::{get_error_message, IconToolError, Result};
+use crate::parser::parse_metadata;
+use crate::summary::BatchSummary;
+use crate::validate::{find_problems, find_warnings};
+
+pub fn lint(args: &LintArgs) -> Result<()> {
+    // lint each of the provided files
+    let mut num_findings = 0;
+    let mut summary = BatchSummary::new();
+    for file in &args.files {
+        let findings = lint_file(Path::new(file))?;
+        for finding in &findings {
+            println!("{file}: {finding}");
+        }
+        match findings.len() {
+            0 => summary.succeed(),
+            count => summary.fail(file, format!("{count} problem(s)")),
+        }
+        num_findings += findings.len();
+    }
+
+    // if we found anything, tell the caller
+    summary.report();
+    if num_findings > 0 {
+        return Err(IconToolError::LintFailed(num_findings));
+    }
+    Ok(())
+}
+
+pub fn lint_file(path: &Path) -> Result<Vec<String>> {
+    let mut findings = Vec::new();
+
+    // check the dmi metadata for problems
+    let metadata_text = read_metadata(path)?;
+    let dmi = parse_metadata(&metadata_text)?;
+    for problem in find_problems(&dmi).into_iter().chain(find_warnings(&dmi)) {
+        let message = get_error_message(problem);
+        findings.push(message.trim_start_matches("icontool: ").to_string());
+    }
+
+    // check for icon_states that show nothing; if the frames can't be cut
+    // out of the sheet, the metadata problems above already say why
+    if let Ok(sheet) = DmiFile::open(path) {
+        for state in sheet.states().filter(|x| x.is_empty()) {
+            findings.push(format!(
+                "icon_state \"{}\" has no visible pixels",
+                state.name()
+            ));
+        }
+    }
+
+    // check for placeholder art that should have been replaced
+    if let Some(label) = read_text_chunk(path, PLACEHOLDER_KEYWORD)? {
+        findings.push(format!("contains placeholder art '{label}'"));
+    }
+
+    Ok(findings)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::DmiBuilder;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_lint_clean() {
+        let args = LintArgs {
+            files: vec![String::from("tests/data/decompile/neck.dmi")],
+        };
+        assert!(lint(&args).is_ok());
+    }
+
+    #[test]
+    fn test_lint_empty_state() {
+        let sheet = DmiBuilder::new(32, 32)
+            .state("open")
+            .push_frame(RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255])))
+            .state("blank")
+            .push_frame(RgbaImage::new(32, 32))
+            .build()
+            .unwrap();
+        std::fs::create_dir_all("tests/data/lint").unwrap();
+        let path = Path::new("tests/data/lint/empty.out.dmi");
+        sheet.save(path).unwrap();
+        assert_eq!(
+            vec!["icon_state \"blank\" has no visible pixels"],
+            lint_file(path).unwrap()
+        );
+    }
+}
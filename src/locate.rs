@@ -0,0 +1,197 @@
+// locate.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use indexmap::IndexMap;
+use serde_yml::Value;
+
+use crate::constant::DMI_METADATA_KEY;
+use crate::error::IconToolError;
+use crate::indexmap_helper::IndexMapHelper;
+use crate::parser::parse_metadata;
+
+// point an error about a key of a .dmi.yml at the line and column where the
+// key is, or where the metadata names the icon_state it belongs to; an
+// error that can't be placed is returned as it was
+pub fn locate_error(path: &str, yaml: &str, error: IconToolError) -> IconToolError {
+    let location = get_error_key(&error)
+        .and_then(|key| find_key(yaml, &key).or_else(|| find_state(yaml, &key)));
+    match location {
+        Some((line, column)) => {
+            IconToolError::AtLocation(path.to_string(), line, column, Box::new(error))
+        }
+        None => error,
+    }
+}
+
+// the key an error is about, if it is one that has a key
+fn get_error_key(error: &IconToolError) -> Option<String> {
+    match error {
+        IconToolError::FrameCountMismatch(key, _, _)
+        | IconToolError::FrameSizeMismatch(key, _, _, _)
+        | IconToolError::FrameTooLarge(key, _, _) => Some(key.clone()),
+        // "Under key open, Value ... cannot be converted to ..."
+        IconToolError::InvalidType(message) => message
+            .strip_prefix("Under key ")?
+            .split_once(", Value ")
+            .map(|(key, _)| key.to_string()),
+        // "Key open is missing", or "Key \"open\" is missing; did you mean ..."
+        IconToolError::MissingKey(message) => {
+            let rest = message.strip_prefix("Key ")?;
+            match rest.strip_prefix('"') {
+                Some(quoted) => get_quoted(quoted),
+                None => rest
+                    .split_once(" is missing")
+                    .map(|(key, _)| key.to_string()),
+            }
+        }
+        _ => None,
+    }
+}
+
+// the text of a string written with {:?}, given everything after its quote
+fn get_quoted(text: &str) -> Option<String> {
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '"' if !escaped => {
+                let quoted = &text[..index];
+                return Some(
+                    serde_json::from_str(&format!("\"{quoted}\"")).unwrap_or(quoted.to_string()),
+                );
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+// the line of a top-level key, which is always in the first column
+fn find_key(yaml: &str, key: &str) -> Option<(usize, usize)> {
+    yaml.lines()
+        .position(|line| is_key_line(line, key))
+        .map(|index| (index + 1, 1))
+}
+
+fn is_key_line(line: &str, key: &str) -> bool {
+    if line.is_empty() || line.starts_with([' ', '\t', '#', '-', '.']) {
+        return false;
+    }
+    // a line like `open: |-` is a mapping by itself; one that starts a
+    // string running onto the next line isn't, so compare the text
+    match serde_yml::from_str::<IndexMap<String, Value>>(line) {
+        Ok(mapping) => mapping.contains_key(key),
+        Err(_) => line
+            .split_once(':')
+            .is_some_and(|(name, _)| name.trim().trim_matches(['"', '\'']) == key),
+    }
+}
+
+// the `state = "..."` line of an icon_state, when the metadata is written
+// as a block, one line of metadata to a line of yaml; otherwise the line of
+// the metadata itself
+fn find_state(yaml: &str, key: &str) -> Option<(usize, usize)> {
+    let data: IndexMap<String, Value> = serde_yml::from_str(yaml).ok()?;
+    let dmi = parse_metadata(&data.get_string(DMI_METADATA_KEY).ok()?).ok()?;
+    let index = dmi.state_keys().iter().position(|x| x == key)?;
+    let start = yaml
+        .lines()
+        .position(|line| is_key_line(line, DMI_METADATA_KEY))?;
+    yaml.lines()
+        .enumerate()
+        .skip(start + 1)
+        .take_while(|(_, line)| line.is_empty() || line.starts_with([' ', '\t']))
+        .filter(|(_, line)| line.trim_start().starts_with("state ="))
+        .nth(index)
+        .map(|(number, line)| (number + 1, line.len() - line.trim_start().len() + 1))
+        .or(Some((start + 1, 1)))
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "__dmi_metadata: |-\n  # BEGIN DMI\n  version = 4.0\n  \twidth = 32\n  \theight = 32\n  state = \"open\"\n  \tdirs = 1\n  \tframes = 1\n  state = \"closed\"\n  \tdirs = 1\n  \tframes = 1\n  # END DMI\nopen: AAAA\n'closed ': AAAA\n";
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_locate_error() {
+        let error = IconToolError::FrameCountMismatch(String::from("open"), 1, 2);
+        match locate_error("door.dmi.yml", YAML, error) {
+            IconToolError::AtLocation(path, 13, 1, error) => {
+                assert_eq!("door.dmi.yml", path);
+                assert!(matches!(*error, IconToolError::FrameCountMismatch(..)));
+            }
+            _ => panic!("test_locate_error: Expected AtLocation error"),
+        }
+        // a missing icon_state is found where the metadata names it
+        let error = IconToolError::MissingKey(String::from(
+            "Key \"closed\" is missing; did you mean 'closed '? note the trailing space",
+        ));
+        match locate_error("door.dmi.yml", YAML, error) {
+            IconToolError::AtLocation(_, 9, 3, _) => {}
+            _ => panic!("test_locate_error: Expected AtLocation error"),
+        }
+        let error = IconToolError::InvalidType(String::from(
+            "Under key closed , Value Number(1) cannot be converted to a String",
+        ));
+        match locate_error("door.dmi.yml", YAML, error) {
+            IconToolError::AtLocation(_, 14, 1, _) => {}
+            _ => panic!("test_locate_error: Expected AtLocation error"),
+        }
+        // an error without a key is left alone
+        match locate_error("door.dmi.yml", YAML, IconToolError::TooManyFrames()) {
+            IconToolError::TooManyFrames() => {}
+            _ => panic!("test_locate_error: Expected TooManyFrames error"),
+        }
+        // metadata written as one quoted string can only be pointed at as a whole
+        let yaml = "open: AAAA\n__dmi_metadata: \"# BEGIN DMI\\nversion = 4.0\\n\\twidth = 32\\n\\theight = 32\\nstate = \\\"open\\\"\\n\\tdirs = 1\\n\\tframes = 1\\n# END DMI\"\n";
+        let error = IconToolError::FrameCountMismatch(String::from("open"), 1, 2);
+        match locate_error("door.dmi.yml", yaml, error) {
+            IconToolError::AtLocation(_, 1, 1, _) => {}
+            _ => panic!("test_locate_error: Expected AtLocation error"),
+        }
+        let error = IconToolError::MissingKey(String::from("Key \"open\" is missing"));
+        match locate_error("door.dmi.yml", &yaml[11..], error) {
+            IconToolError::AtLocation(_, 1, 1, _) => {}
+            _ => panic!("test_locate_error: Expected AtLocation error"),
+        }
+        let error = IconToolError::MissingKey(String::from("Key __dmi_metadata is missing"));
+        match locate_error("door.dmi.yml", "open: AAAA\n", error) {
+            IconToolError::MissingKey(_) => {}
+            _ => panic!("test_locate_error: Expected MissingKey error"),
+        }
+    }
+
+    #[test]
+    fn test_get_quoted() {
+        assert_eq!(Some(String::from("open")), get_quoted("open\" is missing"));
+        assert_eq!(
+            Some(String::from("say \"hi\"")),
+            get_quoted("say \\\"hi\\\"\" is missing")
+        );
+        assert_eq!(None, get_quoted("open is missing"));
+    }
+}
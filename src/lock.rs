@@ -0,0 +1,323 @@
+// lock.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use indexmap::IndexMap;
+use serde_yml::{Mapping, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use crate::atomic::write_atomic;
+use crate::cmdline::{LockArgs, VerifyLockArgs};
+use crate::dmi_file::{DmiFile, IconState};
+use crate::error::{IconToolError, Result};
+use crate::walk::find_files;
+
+// the format of the lockfile; version 1 used CRC32 hashes, which are too easy
+// to collide to trust, so those lockfiles have to be written again
+pub const LOCK_VERSION: u64 = 2;
+
+// the content hashes of a set of .dmi files, as written to a lockfile
+#[derive(Debug, PartialEq)]
+pub struct Lock {
+    pub recursive: bool,
+    pub roots: Vec<String>,
+    pub files: IndexMap<String, LockedFile>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LockedFile {
+    pub hash: String,                     // the bytes of the whole file
+    pub states: IndexMap<String, String>, // the metadata and pixels of each icon_state
+}
+
+pub fn lock(args: &LockArgs) -> Result<()> {
+    let lock = create_lock(&args.paths, args.recursive)?;
+    write_atomic(
+        Path::new(&args.output),
+        serde_yml::to_string(&to_yaml(&lock))?,
+    )
+}
+
+pub fn verify_lock(args: &VerifyLockArgs) -> Result<()> {
+    // hash the same files again, and compare them to the lock
+    let text = std::fs::read_to_string(&args.file)?;
+    let locked = from_yaml(&serde_yml::from_str(&text)?)?;
+    let current = create_lock(&locked.roots, locked.recursive)?;
+    let changes = compare_locks(&locked, &current);
+    for change in &changes {
+        println!("{change}");
+    }
+
+    // if anything changed, tell the caller
+    if !changes.is_empty() {
+        return Err(IconToolError::LockMismatch(changes.len()));
+    }
+    Ok(())
+}
+
+pub fn create_lock(roots: &[String], recursive: bool) -> Result<Lock> {
+    let mut files = IndexMap::new();
+    for path in find_files(roots, recursive, ".dmi")? {
+        let bytes = std::fs::read(&path)?;
+        // a file that isn't a readable .dmi is still locked by its bytes
+        let mut states = IndexMap::new();
+        if let Ok(sheet) = DmiFile::from_bytes(&bytes) {
+            for (state, key) in sheet.states().zip(sheet.metadata.state_keys()) {
+                states.insert(key, hash_state(&state));
+            }
+        }
+        let hash = to_hex(&Sha256::digest(&bytes));
+        let path = path.to_string_lossy().to_string();
+        files.insert(path, LockedFile { hash, states });
+    }
+    Ok(Lock {
+        recursive,
+        roots: roots.to_vec(),
+        files,
+    })
+}
+
+// describe every difference between a lock and the files as they are now
+pub fn compare_locks(locked: &Lock, current: &Lock) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (path, old) in &locked.files {
+        let Some(new) = current.files.get(path) else {
+            changes.push(format!("{path}: removed"));
+            continue;
+        };
+        if old.hash == new.hash {
+            continue;
+        }
+        let num_changes = changes.len();
+        for (key, hash) in &old.states {
+            match new.states.get(key) {
+                None => changes.push(format!("{path}: icon_state '{key}' removed")),
+                Some(x) if x != hash => changes.push(format!("{path}: icon_state '{key}' changed")),
+                Some(_) => {}
+            }
+        }
+        for key in new.states.keys() {
+            if !old.states.contains_key(key) {
+                changes.push(format!("{path}: icon_state '{key}' added"));
+            }
+        }
+        // the icon_states are the same, but something else in the file isn't
+        if changes.len() == num_changes {
+            changes.push(format!("{path}: changed outside of its icon_states"));
+        }
+    }
+    for path in current.files.keys() {
+        if !locked.files.contains_key(path) {
+            changes.push(format!("{path}: added"));
+        }
+    }
+    changes
+}
+
+fn hash_state(state: &IconState) -> String {
+    // the metadata fixes the size of every frame, so only it needs a length
+    let metadata = state.metadata.to_string();
+    let mut hasher = Sha256::new();
+    hasher.update((metadata.len() as u64).to_le_bytes());
+    hasher.update(metadata.as_bytes());
+    for frame in state.frames {
+        hasher.update(frame.as_raw());
+    }
+    to_hex(&hasher.finalize())
+}
+
+// a digest as lowercase hexadecimal
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn to_yaml(lock: &Lock) -> Value {
+    let mut files = Mapping::new();
+    for (path, file) in &lock.files {
+        let mut states = Mapping::new();
+        for (key, hash) in &file.states {
+            states.insert(Value::from(key.as_str()), Value::from(hash.as_str()));
+        }
+        let mut entry = Mapping::new();
+        entry.insert(Value::from("hash"), Value::from(file.hash.as_str()));
+        entry.insert(Value::from("states"), Value::Mapping(states));
+        files.insert(Value::from(path.as_str()), Value::Mapping(entry));
+    }
+    let mut yaml = Mapping::new();
+    yaml.insert(Value::from("version"), Value::from(LOCK_VERSION));
+    yaml.insert(Value::from("recursive"), Value::from(lock.recursive));
+    yaml.insert(Value::from("roots"), Value::from(lock.roots.clone()));
+    yaml.insert(Value::from("files"), Value::Mapping(files));
+    Value::Mapping(yaml)
+}
+
+fn from_yaml(yaml: &Value) -> Result<Lock> {
+    let invalid = |what: &str| IconToolError::InvalidType(format!("the lockfile {what}"));
+    // a lockfile without a version is from before there were versions
+    let version = yaml.get("version").and_then(Value::as_u64).unwrap_or(1);
+    if version != LOCK_VERSION {
+        return Err(invalid(&format!(
+            "is version {version}, but this icontool needs version {LOCK_VERSION}; run icontool lock again"
+        )));
+    }
+    let recursive = yaml
+        .get("recursive")
+        .and_then(Value::as_bool)
+        .ok_or_else(|| invalid("needs recursive: true or false"))?;
+    let roots = yaml
+        .get("roots")
+        .and_then(Value::as_sequence)
+        .and_then(|x| x.iter().map(|x| x.as_str().map(String::from)).collect())
+        .ok_or_else(|| invalid("needs a list of roots"))?;
+    let mut files = IndexMap::new();
+    let entries = yaml
+        .get("files")
+        .and_then(Value::as_mapping)
+        .ok_or_else(|| invalid("needs a mapping of files"))?;
+    for (path, entry) in entries {
+        let path = path
+            .as_str()
+            .ok_or_else(|| invalid("has a file path that isn't text"))?;
+        let bad_entry = || invalid(&format!("has a bad entry for {path}"));
+        let hash = entry
+            .get("hash")
+            .and_then(Value::as_str)
+            .ok_or_else(bad_entry)?;
+        let mut states = IndexMap::new();
+        for (key, hash) in entry
+            .get("states")
+            .and_then(Value::as_mapping)
+            .ok_or_else(bad_entry)?
+        {
+            match (key.as_str(), hash.as_str()) {
+                (Some(key), Some(hash)) => states.insert(key.to_string(), hash.to_string()),
+                _ => return Err(bad_entry()),
+            };
+        }
+        let hash = hash.to_string();
+        files.insert(path.to_string(), LockedFile { hash, states });
+    }
+    Ok(Lock {
+        recursive,
+        roots,
+        files,
+    })
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_lock_round_trip() {
+        let roots = vec![String::from("tests/data/edit/spin.dmi")];
+        let lock = create_lock(&roots, false).unwrap();
+        let file = &lock.files["tests/data/edit/spin.dmi"];
+        let keys: Vec<&str> = file.states.keys().map(String::as_str).collect();
+        assert_eq!(vec!["spin", "walk", "still"], keys);
+        let yaml = serde_yml::to_string(&to_yaml(&lock)).unwrap();
+        let parsed = from_yaml(&serde_yml::from_str(&yaml).unwrap()).unwrap();
+        assert_eq!(lock, parsed);
+        assert!(from_yaml(&serde_yml::from_str("files: []").unwrap()).is_err());
+        assert_eq!(64, file.hash.len());
+        assert!(file.states.values().all(|x| x.len() == 64));
+    }
+
+    #[test]
+    fn test_lock_old_version() {
+        let crc_lock = "recursive: false\nroots: [spin.dmi]\nfiles:\n  spin.dmi:\n    hash: 0a1b2c3d\n    states: {}\n";
+        match from_yaml(&serde_yml::from_str(crc_lock).unwrap()) {
+            Err(IconToolError::InvalidType(x)) => assert!(x.contains("version 1"), "{x}"),
+            _ => panic!("test_lock_old_version: Expected InvalidType error"),
+        }
+        let future_lock = format!("version: 3\n{crc_lock}");
+        match from_yaml(&serde_yml::from_str(&future_lock).unwrap()) {
+            Err(IconToolError::InvalidType(x)) => assert!(x.contains("version 3"), "{x}"),
+            _ => panic!("test_lock_old_version: Expected InvalidType error"),
+        }
+    }
+
+    #[test]
+    fn test_verify_lock() {
+        let dir = Path::new("tests/data/lock/icons.out.dir");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::copy("tests/data/edit/spin.dmi", dir.join("spin.dmi")).unwrap();
+        std::fs::copy("tests/data/gags/scarf.dmi", dir.join("scarf.dmi")).unwrap();
+        let output = String::from("tests/data/lock/icons.out.lock");
+        let args = LockArgs {
+            output: output.clone(),
+            recursive: false,
+            paths: vec![dir.to_string_lossy().to_string()],
+        };
+        lock(&args).unwrap();
+        let verify_args = VerifyLockArgs { file: output };
+        verify_lock(&verify_args).unwrap();
+
+        // change one icon_state, and add a file
+        let mut sheet = DmiFile::open(&dir.join("spin.dmi")).unwrap();
+        sheet.state_mut("still").unwrap().metadata.movement = true;
+        sheet.save(&dir.join("spin.dmi")).unwrap();
+        std::fs::copy("tests/data/compile/neck.dmi", dir.join("neck.dmi")).unwrap();
+        match verify_lock(&verify_args) {
+            Err(IconToolError::LockMismatch(2)) => {}
+            _ => panic!("test_verify_lock: Expected LockMismatch error"),
+        }
+    }
+
+    #[test]
+    fn test_compare_locks() {
+        let roots = vec![String::from("tests/data/edit/spin.dmi")];
+        let locked = create_lock(&roots, false).unwrap();
+        let mut current = create_lock(&roots, false).unwrap();
+        assert!(compare_locks(&locked, &current).is_empty());
+
+        let file = current.files.get_mut("tests/data/edit/spin.dmi").unwrap();
+        file.hash = String::from("00000000");
+        assert_eq!(
+            vec!["tests/data/edit/spin.dmi: changed outside of its icon_states"],
+            compare_locks(&locked, &current)
+        );
+        let file = current.files.get_mut("tests/data/edit/spin.dmi").unwrap();
+        file.states.shift_remove("walk");
+        file.states
+            .insert(String::from("run"), String::from("00000000"));
+        file.states
+            .insert(String::from("spin"), String::from("00000000"));
+        assert_eq!(
+            vec![
+                "tests/data/edit/spin.dmi: icon_state 'spin' changed",
+                "tests/data/edit/spin.dmi: icon_state 'walk' removed",
+                "tests/data/edit/spin.dmi: icon_state 'run' added",
+            ],
+            compare_locks(&locked, &current)
+        );
+        assert_eq!(
+            vec!["tests/data/edit/spin.dmi: removed"],
+            compare_locks(&locked, &create_lock(&[], false).unwrap())
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// log.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::Result;
+
+// the file that a copy of every diagnostic goes to, with the time it was
+// printed; set once, by main, from --log-file
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+// the log is appended to, so the runs of a CI job can share one file
+pub fn set_log_file(path: &Path) -> Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+// print a diagnostic on stderr, and copy it to the log file if there is one
+pub fn diagnostic(message: &str) {
+    eprintln!("{message}");
+    write_log(message);
+}
+
+pub fn write_log(message: &str) {
+    let Some(file) = LOG_FILE.get() else {
+        return;
+    };
+    let timestamp = format_timestamp(SystemTime::now());
+    let mut file = file.lock().unwrap_or_else(|x| x.into_inner());
+    for line in message.lines() {
+        // a log that can't be written shouldn't stop the work it describes
+        let _ = writeln!(file, "{timestamp} {line}");
+    }
+}
+
+// RFC 3339 in UTC, to the millisecond, like 2024-03-01T01:02:03.250Z
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = get_date(seconds / 86400);
+    let (hour, minute, second) = (seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60);
+    let millis = since_epoch.subsec_millis();
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{millis:03}Z")
+}
+
+// the calendar date of a number of days since 1970-01-01; this is the
+// civil_from_days algorithm of Howard Hinnant, for dates after 1970
+fn get_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let (era, day_of_era) = (days / 146097, days % 146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // the year is counted from March, so a leap day is at the end of it
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let at = |seconds: u64, millis: u64| {
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(seconds * 1000 + millis))
+        };
+        assert_eq!("1970-01-01T00:00:00.000Z", at(0, 0));
+        assert_eq!("2000-02-29T00:00:00.000Z", at(951782400, 0));
+        assert_eq!("2024-03-01T01:02:03.250Z", at(1709254923, 250));
+        assert_eq!("2100-02-28T23:59:59.999Z", at(4107542399, 999));
+    }
+
+    #[test]
+    fn test_log_file() {
+        std::fs::create_dir_all("tests/data/log").unwrap();
+        let path = Path::new("tests/data/log/run.out.log");
+        let _ = std::fs::remove_file(path);
+        set_log_file(path).unwrap();
+        diagnostic("icontool: 1 succeeded, 1 failed\n  icon.dmi: bad CRC");
+        let log = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines
+            .iter()
+            .any(|x| x.ends_with("Z icontool: 1 succeeded, 1 failed")));
+        assert!(lines.iter().any(|x| x.ends_with("Z   icon.dmi: bad CRC")));
+        assert!(lines.iter().all(|x| x.as_bytes()[4] == b'-'));
+    }
+}
@@ -15,46 +15,208 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 //---------------------------------------------------------------------------
 
-pub mod cmdline;
-pub mod compile;
-pub mod constant;
-pub mod decompile;
-pub mod dmi;
-pub mod error;
-pub mod indexmap_helper;
-pub mod metadata;
-pub mod parser;
-
 use clap::Parser;
+use std::path::Path;
 use std::process::ExitCode;
 
-use crate::cmdline::{Cli, Commands};
-use crate::compile::compile;
-use crate::decompile::decompile;
-use crate::error::get_error_message;
-use crate::metadata::{flatten_metadata, output_metadata};
+use icontool::apply::apply;
+use icontool::audit::audit;
+use icontool::build::{build, clean, watch};
+use icontool::cmdline::{Cli, Commands};
+use icontool::color::{adjust, alpha};
+use icontool::compare::{diff, equal};
+use icontool::compile::compile;
+use icontool::composite::{outline, overlay};
+use icontool::config::load_config;
+use icontool::constant::CONFIG_FILE_NAME;
+use icontool::decompile::decompile;
+use icontool::dm_code::{missing, unused};
+use icontool::doctor::doctor;
+use icontool::edit::{edit_frames, optimize_anim, reverse, set_delay, set_flag, set_hotspot};
+use icontool::error::get_error_message;
+use icontool::explain::explain;
+use icontool::fix_metadata::fix_metadata;
+use icontool::gags::gags;
+use icontool::gif::gif;
+use icontool::hook::set_post_process;
+use icontool::lint::lint;
+use icontool::lock::{lock, verify_lock};
+use icontool::log::{diagnostic, set_log_file, write_log};
+use icontool::metadata::{flatten_metadata, output_metadata, unflatten_metadata};
+use icontool::pack::pack;
+use icontool::palette::{colors, palette};
+use icontool::patch::patch;
+use icontool::placeholder::placeholder;
+use icontool::report::report;
+use icontool::roundtrip::roundtrip;
+use icontool::scaffold::{blank, new_dmi_yaml};
+use icontool::serve::serve;
+use icontool::states::list_states;
+use icontool::stills::stills;
+use icontool::timings::{report_timings, set_timings};
+use icontool::transform::{convert_size, flip, gen_dirs, repad, rotate, scale, shift};
+use icontool::upgrade::upgrade;
+use icontool::verify::verify;
 
 #[cfg(not(tarpaulin_include))]
 fn main() -> ExitCode {
     // parse what the user provided on the command line
     let cli = Cli::parse();
 
+    // keep a copy of every diagnostic, if the user asked for one
+    if let Some(path) = &cli.log_file {
+        if let Err(x) = set_log_file(Path::new(path)) {
+            eprintln!("{}", get_error_message(x));
+            return ExitCode::FAILURE;
+        }
+        let args: Vec<String> = std::env::args().collect();
+        write_log(&args.join(" "));
+    }
+
+    // measure how long the work takes, if the user asked
+    if cli.timings {
+        set_timings();
+    }
+
+    // read the settings that apply to every command; a broken icontool.yml
+    // only matters to the commands that write the files it post-processes
+    let config = match load_config(Path::new(CONFIG_FILE_NAME)) {
+        Ok(config) => config,
+        Err(x) if !cli.command.writes_images() => {
+            diagnostic(&get_error_message(x));
+            diagnostic(&format!(
+                "icontool: ignoring {CONFIG_FILE_NAME}; this command doesn't write .dmi or .png files"
+            ));
+            Default::default()
+        }
+        Err(x) => {
+            diagnostic(&get_error_message(x));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // the command line overrides icontool.yml
+    if let Some(command) = cli.post_process.as_ref().or(config.post_process.as_ref()) {
+        set_post_process(command);
+    }
+
     // depending on what subcommand the user provided
     let result = match &cli.command {
+        // shift the colors of the frames of a .dmi
+        Commands::Adjust(args) => adjust(args),
+        // remove stray semi-transparent pixels from a .dmi
+        Commands::Alpha(args) => alpha(args),
+        // layer an overlay on top of a .dmi.yml
+        Commands::Apply(args) => apply(args),
+        // summarize the .dmi files in a directory
+        Commands::Audit(args) => audit(args),
+        // create a .dmi with transparent frames
+        Commands::Blank(args) => blank(args),
+        // compile the targets of a project manifest
+        Commands::Build(args) => build(args),
+        // remove the files written by build
+        Commands::Clean(args) => clean(args),
+        // report the color usage of an icon_state
+        Commands::Colors(args) => colors(args),
         // compile a .dmi.yml -> .dmi
         Commands::Compile(args) => compile(args),
+        // change the icon size of a .dmi
+        Commands::ConvertSize(args) => convert_size(args),
         // decompile a .dmi -> .dmi.yml
         Commands::Decompile(args) => decompile(args),
+        // list what changed between two .dmi files
+        Commands::Diff(args) => diff(args),
+        // diagnose common problems in .dmi files
+        Commands::Doctor(args) => doctor(args),
+        // check whether two .dmi files look the same
+        Commands::Equal(args) => equal(args),
+        // describe an error code
+        Commands::Explain(args) => explain(args),
+        // cut .dmi metadata down to the frames in the image
+        Commands::FixMetadata(args) => fix_metadata(args),
         // flatten metadata into .yml format
         Commands::Flat(args) => flatten_metadata(args),
+        // mirror the frames of a .dmi
+        Commands::Flip(args) => flip(args),
+        // insert, delete, or reorder the frames of an icon_state
+        Commands::Frames(args) => edit_frames(args),
+        // color greyscale icon_states using a GAGS config
+        Commands::Gags(args) => gags(args),
+        // generate the other directions of an icon_state
+        Commands::GenDirs(args) => gen_dirs(args),
+        // export the animation of an icon_state as a .gif
+        Commands::Gif(args) => gif(args),
+        // check .dmi files for common problems
+        Commands::Lint(args) => lint(args),
+        // write the content hashes of .dmi files
+        Commands::Lock(args) => lock(args),
         // output metadata for a .dmi
         Commands::Metadata(args) => output_metadata(args),
+        // list the icon_states that the code names but no .dmi has
+        Commands::Missing(args) => missing(args),
+        // create a .dmi.yml with blank frames
+        Commands::New(args) => new_dmi_yaml(args),
+        // merge repeated frames of animations
+        Commands::OptimizeAnim(args) => optimize_anim(args),
+        // draw an outline around the art in a .dmi
+        Commands::Outline(args) => outline(args),
+        // draw the frames of one icon_state on top of another
+        Commands::Overlay(args) => overlay(args),
+        // combine several .dmi files into one
+        Commands::Pack(args) => pack(args),
+        // list the colors used by a .dmi
+        Commands::Palette(args) => palette(args),
+        // replace or add icon_states from a partial .dmi.yml
+        Commands::Patch(args) => patch(args),
+        // generate placeholder art as a .dmi
+        Commands::Placeholder(args) => placeholder(args),
+        // pad or crop the frames of a .dmi to a new icon size
+        Commands::Repad(args) => repad(args),
+        // generate an HTML catalog of .dmi files
+        Commands::Report(args) => report(args),
+        // play the animation of an icon_state backwards
+        Commands::Reverse(args) => reverse(args),
+        // rotate the frames of a .dmi
+        Commands::Rotate(args) => rotate(args),
+        // check that .dmi files survive a decompile and compile
+        Commands::Roundtrip(args) => roundtrip(args),
+        // preview .dmi files in a web browser
+        Commands::Serve(args) => serve(args),
+        // change the animation delays of an icon_state
+        Commands::SetDelay(args) => set_delay(args),
+        // change the loop and rewind flags of an icon_state
+        Commands::SetFlag(args) => set_flag(args),
+        // add, move, or remove the hotspots of an icon_state
+        Commands::SetHotspot(args) => set_hotspot(args),
+        // scale up the frames of a .dmi
+        Commands::Scale(args) => scale(args),
+        // move the pixels of each frame within its tile
+        Commands::Shift(args) => shift(args),
+        // list the icon_states in a .dmi
+        Commands::States(args) => list_states(args),
+        // export the first frame of each icon_state as a .png
+        Commands::Stills(args) => stills(args),
+        // extract metadata from .yml format
+        Commands::Unflat(args) => unflatten_metadata(args),
+        // list the icon_states that the code never names
+        Commands::Unused(args) => unused(args),
+        // rewrite .dmi metadata in the current format
+        Commands::Upgrade(args) => upgrade(args),
+        // check .dmi files for corruption
+        Commands::Verify(args) => verify(args),
+        // check .dmi files against a lockfile
+        Commands::VerifyLock(args) => verify_lock(args),
+        // build the targets of a project manifest as their sources change
+        Commands::Watch(args) => watch(args),
     };
 
+    // a batch of files ends with the timings of the whole run
+    report_timings();
+
     // if the operation failed for some reason
     if let Err(x) = result {
         // print a friendly message on stderr
-        eprintln!("{}", get_error_message(x));
+        diagnostic(&get_error_message(x));
         // exit (with non-zero to indicate an error)
         return ExitCode::FAILURE;
     }
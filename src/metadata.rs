@@ -16,15 +16,20 @@
 //---------------------------------------------------------------------------
 
 use indexmap::IndexMap;
+use serde_json::json;
 use serde_yml::Value;
 use std::fs::File;
-use std::io::{BufWriter, Read, Write};
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-use crate::cmdline::{FlatArgs, MetadataArgs};
+use crate::atomic::write_atomic;
+use crate::cmdline::{FlatArgs, MetadataArgs, UnflatArgs};
 use crate::constant::DMI_METADATA_KEY;
 use crate::dmi::read_metadata;
 use crate::error::Result;
+use crate::indexmap_helper::IndexMapHelper;
+use crate::parser::{parse_metadata, DreamMakerIconMetadata};
+use crate::states::format_states;
 
 pub fn flatten_metadata(args: &FlatArgs) -> Result<()> {
     // read the metadata from the file
@@ -37,24 +42,39 @@ pub fn flatten_metadata(args: &FlatArgs) -> Result<()> {
     let mut data = IndexMap::new();
     data.insert(DMI_METADATA_KEY.to_string(), Value::from(contents));
     let yaml = serde_yml::to_string(&data)?;
-    println!("{}", yaml);
+    write_text(&args.output, &yaml)
+}
 
-    Ok(())
+pub fn unflatten_metadata(args: &UnflatArgs) -> Result<()> {
+    // read the yml from the file
+    let yaml = std::fs::read_to_string(&args.file)?;
+    let data: IndexMap<String, Value> = serde_yml::from_str(&yaml)?;
+
+    // pull the metadata back out of it
+    let contents = data.get_string(DMI_METADATA_KEY)?;
+    write_text(&args.output, &contents)
 }
 
 pub fn output_metadata(args: &MetadataArgs) -> Result<()> {
     let metadata_path = PathBuf::from(&args.file);
-    let metadata_text = read_metadata(&metadata_path)?;
+    let mut metadata_text = read_metadata(&metadata_path)?;
+
+    // if requested, replace the raw text with a parsed view of it
+    if args.parsed {
+        let dmi = parse_metadata(&metadata_text)?;
+        metadata_text = format_parsed(&dmi).join("\n") + "\n";
+    } else if args.json {
+        let dmi = parse_metadata(&metadata_text)?;
+        metadata_text = serde_json::to_string_pretty(&to_json(&dmi))
+            .expect("Failed to serialize metadata to JSON")
+            + "\n";
+    }
 
     // if the user provided an output file
     if let Some(output) = &args.output {
         // if the user provided an output file
         let output_path = PathBuf::from(output);
-        let file = File::create(output_path)?;
-        let mut writer = BufWriter::new(file);
-        let _written_amount = writer.write(metadata_text.as_bytes())?;
-        writer.flush()?;
-        return Ok(());
+        return write_atomic(&output_path, metadata_text);
     }
 
     // otherwise, just print it to the console
@@ -62,16 +82,125 @@ pub fn output_metadata(args: &MetadataArgs) -> Result<()> {
     Ok(())
 }
 
+fn write_text(output: &Option<String>, text: &str) -> Result<()> {
+    // write to the output file if there is one, otherwise the console
+    match output {
+        Some(output) => write_atomic(Path::new(output), text)?,
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn format_parsed(dmi: &DreamMakerIconMetadata) -> Vec<String> {
+    let mut lines = vec![
+        format!("version: {}", dmi.version),
+        format!("icon size: {}x{}", dmi.width, dmi.height),
+        format!("icon_states: {}", dmi.states.len()),
+        String::new(),
+    ];
+    lines.extend(format_states(dmi));
+    lines
+}
+
+fn to_json(dmi: &DreamMakerIconMetadata) -> serde_json::Value {
+    // numbers in the metadata are kept as text; give them back as numbers
+    let number = |text: &String| match text.parse::<f64>() {
+        Ok(value) => json!(value),
+        Err(_) => json!(text),
+    };
+    let states: Vec<serde_json::Value> = dmi
+        .states
+        .iter()
+        .map(|state| {
+            let hotspots: Vec<serde_json::Value> = state
+                .hotspots
+                .iter()
+                .map(|x| json!({ "x": x.x, "y": x.y, "frame": x.frame }))
+                .collect();
+            json!({
+                "name": state.name,
+                "dirs": state.dirs,
+                "frames": state.frames,
+                "delay": state.delay.as_ref().map(|x| x.iter().map(number).collect::<Vec<_>>()),
+                "loop": state._loop.as_ref().map(number),
+                "rewind": state.rewind.is_some(),
+                "movement": state.movement,
+                "hotspots": hotspots,
+                "unknown": state.unknown,
+            })
+        })
+        .collect();
+    json!({
+        "version": dmi.version.to_string(),
+        "width": dmi.width,
+        "height": dmi.height,
+        "states": states,
+    })
+}
+
 //---------------------------------------------------------------------------
 //---------------------------------------------------------------------------
 //---------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn test_always_succeed() {
         assert!(true);
     }
+
+    #[test]
+    fn test_format_parsed() {
+        let text = read_metadata(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let lines = format_parsed(&parse_metadata(&text).unwrap());
+        assert_eq!("version: 4.0", lines[0]);
+        assert_eq!("icon size: 32x32", lines[1]);
+        assert_eq!("icon_states: 3", lines[2]);
+        assert!(lines[4].starts_with("STATE"));
+        assert_eq!(8, lines.len());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let text = "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"walk\"\n\tdirs = 4\n\tframes = 2\n\tdelay = 1,1.5\n\tloop = 3\n\tmovement = 1\n# END DMI\n";
+        let value = to_json(&parse_metadata(text).unwrap());
+        assert_eq!(json!("4.0"), value["version"]);
+        assert_eq!(json!(32), value["width"]);
+        let state = &value["states"][0];
+        assert_eq!(json!("walk"), state["name"]);
+        assert_eq!(json!([1.0, 1.5]), state["delay"]);
+        assert_eq!(json!(3.0), state["loop"]);
+        assert_eq!(json!(true), state["movement"]);
+        assert_eq!(json!(false), state["rewind"]);
+    }
+
+    #[test]
+    fn test_flat_unflat_round_trip() {
+        std::fs::create_dir_all("tests/data/metadata").unwrap();
+        let metadata = "tests/data/metadata/spin.out.metadata";
+        let flat = "tests/data/metadata/spin.out.yml";
+        let unflat = "tests/data/metadata/spin.out.unflat.metadata";
+        let args = MetadataArgs {
+            output: Some(String::from(metadata)),
+            file: String::from("tests/data/edit/spin.dmi"),
+            ..Default::default()
+        };
+        output_metadata(&args).unwrap();
+        let args = FlatArgs {
+            output: Some(String::from(flat)),
+            file: String::from(metadata),
+        };
+        flatten_metadata(&args).unwrap();
+        let args = UnflatArgs {
+            output: Some(String::from(unflat)),
+            file: String::from(flat),
+        };
+        unflatten_metadata(&args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(metadata).unwrap(),
+            std::fs::read_to_string(unflat).unwrap()
+        );
+    }
 }
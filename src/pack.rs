@@ -0,0 +1,416 @@
+// pack.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{Rgba, RgbaImage};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cmdline::{OnConflict, PackArgs, PackPrefix};
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+use crate::walk::{create_parent_dir, find_files};
+
+pub fn pack(args: &PackArgs) -> Result<()> {
+    // find the .dmi files to pack; a previous output is not one of them
+    let output_path = PathBuf::from(&args.output);
+    let paths: Vec<PathBuf> = find_files(&args.paths, args.recursive, ".dmi")?
+        .into_iter()
+        .filter(|path| !is_same_file(path, &output_path))
+        .collect();
+
+    // combine them into one .dmi file
+    let sheet = pack_files(&paths, &args.prefix, &args.on_conflict)?;
+    create_parent_dir(&output_path)?;
+    sheet.save(&output_path)
+}
+
+// append the icon_states of each .dmi file, in order, to the first one;
+// the icon_states keep their delays, hotspots, and other properties
+pub fn pack_files(
+    paths: &[PathBuf],
+    prefixes: &[PackPrefix],
+    on_conflict: &OnConflict,
+) -> Result<DmiFile> {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stderr();
+    pack_files_with(paths, prefixes, on_conflict, &mut input, &mut output)
+}
+
+// like pack_files, but interactive conflicts are asked about on the provided
+// input and output instead of the terminal
+fn pack_files_with(
+    paths: &[PathBuf],
+    prefixes: &[PackPrefix],
+    on_conflict: &OnConflict,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<DmiFile> {
+    let mut files = paths.iter();
+    let first = files
+        .next()
+        .ok_or_else(|| IconToolError::InvalidArgument(String::from("no .dmi files to pack")))?;
+    let mut sheet = open_prefixed(first, prefixes)?;
+    // remember which file each icon_state came from
+    let mut origins = vec![first; sheet.metadata.states.len()];
+
+    for path in files {
+        let part = open_prefixed(path, prefixes)?;
+        // every icon has to be the same size to share a sheet
+        let size = (part.metadata.width, part.metadata.height);
+        if size != (sheet.metadata.width, sheet.metadata.height) {
+            return Err(IconToolError::IconSizeMismatch(format!(
+                "icons are {}x{} in {}, but {}x{} in {}",
+                sheet.metadata.width,
+                sheet.metadata.height,
+                first.display(),
+                size.0,
+                size.1,
+                path.display()
+            )));
+        }
+        // only icon_states from different files conflict; a movement state
+        // doesn't conflict with the icon_state of the same name it goes with
+        let existing = sheet.metadata.states.len();
+        for (mut state, frames) in part.metadata.states.into_iter().zip(part.frames) {
+            let conflict = sheet.metadata.states[..existing]
+                .iter()
+                .position(|x| x.name == state.name && x.movement == state.movement);
+            let Some(index) = conflict else {
+                sheet.push_state(state, frames)?;
+                origins.push(path);
+                continue;
+            };
+            let choice = match on_conflict {
+                OnConflict::Interactive => {
+                    let ours = (origins[index].as_path(), &sheet.frames[index][0]);
+                    let theirs = (path.as_path(), &frames[0]);
+                    ask_conflict(&state.name, ours, theirs, input, output)?
+                }
+                choice => choice.clone(),
+            };
+            match choice {
+                OnConflict::Ours => {}
+                OnConflict::Theirs => {
+                    sheet.metadata.states[index] = state;
+                    sheet.frames[index] = frames;
+                    origins[index] = path;
+                }
+                OnConflict::Rename => {
+                    state.name = get_unique_name(&sheet, &state.name, state.movement);
+                    sheet.push_state(state, frames)?;
+                    origins.push(path);
+                }
+                _ => {
+                    return Err(IconToolError::StateConflict(format!(
+                        "icon_state '{}' is in both {} and {}",
+                        state.name,
+                        origins[index].display(),
+                        path.display()
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(sheet)
+}
+
+// show the first frame of both icon_states, and ask which one to keep
+fn ask_conflict(
+    name: &str,
+    ours: (&Path, &RgbaImage),
+    theirs: (&Path, &RgbaImage),
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<OnConflict> {
+    writeln!(output, "icontool: icon_state '{name}' is in both files")?;
+    writeln!(output, "ours: {}", ours.0.display())?;
+    write!(output, "{}", render_thumbnail(ours.1, THUMBNAIL_WIDTH))?;
+    writeln!(output, "theirs: {}", theirs.0.display())?;
+    write!(output, "{}", render_thumbnail(theirs.1, THUMBNAIL_WIDTH))?;
+    loop {
+        write!(
+            output,
+            "keep [o]urs, use [t]heirs, [r]ename theirs, or [e]rror? "
+        )?;
+        output.flush()?;
+        let mut answer = String::new();
+        // if nobody is there to answer, stop instead of guessing
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(OnConflict::Error);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "o" | "ours" => return Ok(OnConflict::Ours),
+            "t" | "theirs" => return Ok(OnConflict::Theirs),
+            "r" | "rename" => return Ok(OnConflict::Rename),
+            "e" | "error" => return Ok(OnConflict::Error),
+            _ => {}
+        }
+    }
+}
+
+// the widest a thumbnail gets, in terminal columns
+const THUMBNAIL_WIDTH: u32 = 32;
+
+// draw a frame with 24-bit terminal colors, two pixels to a character; big
+// frames are sampled down to fit, and transparent pixels are left blank
+pub fn render_thumbnail(frame: &RgbaImage, max_width: u32) -> String {
+    let step = frame.width().div_ceil(max_width.max(1)).max(1);
+    let visible = |x: u32, y: u32| {
+        let pixel = frame.get_pixel_checked(x, y)?;
+        (pixel[3] != 0).then_some(pixel)
+    };
+    let mut text = String::new();
+    for y in (0..frame.height()).step_by(step as usize * 2) {
+        for x in (0..frame.width()).step_by(step as usize) {
+            let cell = match (visible(x, y), visible(x, y + step)) {
+                (Some(top), Some(bottom)) => {
+                    format!("{}{}\u{2580}", ansi_color(38, top), ansi_color(48, bottom))
+                }
+                (Some(top), None) => format!("\x1b[49m{}\u{2580}", ansi_color(38, top)),
+                (None, Some(bottom)) => format!("\x1b[49m{}\u{2584}", ansi_color(38, bottom)),
+                (None, None) => String::from("\x1b[0m "),
+            };
+            text.push_str(&cell);
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+fn ansi_color(layer: u8, pixel: &Rgba<u8>) -> String {
+    format!("\x1b[{layer};2;{};{};{}m", pixel[0], pixel[1], pixel[2])
+}
+
+// number an icon_state name until it doesn't clash, like walk_2
+fn get_unique_name(sheet: &DmiFile, name: &str, movement: bool) -> String {
+    let taken = |name: &str| {
+        sheet
+            .metadata
+            .states
+            .iter()
+            .any(|x| x.name == name && x.movement == movement)
+    };
+    (2..)
+        .map(|number| format!("{name}_{number}"))
+        .find(|x| !taken(x))
+        .expect("some number is free")
+}
+
+// open a .dmi file, renaming its icon_states with the prefix that applies to
+// it; every icon_state gets the same prefix, so movement states still pair up
+fn open_prefixed(path: &Path, prefixes: &[PackPrefix]) -> Result<DmiFile> {
+    let mut sheet = DmiFile::open(path)?;
+    if let Some(prefix) = find_prefix(path, prefixes) {
+        for state in &mut sheet.metadata.states {
+            state.name = format!("{prefix}{}", state.name);
+        }
+    }
+    Ok(sheet)
+}
+
+// a prefix for the file itself wins over one for every file; if several
+// apply, the last one given wins
+fn find_prefix<'a>(path: &Path, prefixes: &'a [PackPrefix]) -> Option<&'a str> {
+    let for_file = prefixes.iter().rev().find(|x| match &x.file {
+        Some(file) => path.ends_with(file),
+        None => false,
+    });
+    let for_all = prefixes.iter().rev().find(|x| x.file.is_none());
+    for_file.or(for_all).map(|x| x.prefix.as_str())
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::DmiBuilder;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_pack() {
+        let dir = PathBuf::from("tests/data/pack/parts.out.dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("tests/data/edit/spin.dmi", dir.join("a.dmi")).unwrap();
+        let red = RgbaImage::from_pixel(32, 32, Rgba([255, 0, 0, 255]));
+        DmiBuilder::new(32, 32)
+            .state("blink")
+            .frames(2)
+            .delay(&[1.0, 4.0])
+            .loop_count(3)
+            .push_frame(red.clone())
+            .push_frame(RgbaImage::new(32, 32))
+            .build()
+            .unwrap()
+            .save(&dir.join("b.dmi"))
+            .unwrap();
+
+        // packing twice shouldn't pick up the first output
+        let output = dir.join("combined.dmi");
+        let args = PackArgs {
+            output: output.to_string_lossy().to_string(),
+            recursive: false,
+            prefix: Vec::new(),
+            on_conflict: OnConflict::Error,
+            paths: vec![dir.to_string_lossy().to_string()],
+        };
+        pack(&args).unwrap();
+        pack(&args).unwrap();
+
+        let sheet = DmiFile::open(&output).unwrap();
+        let names: Vec<&str> = sheet.states().map(|x| x.name()).collect();
+        assert_eq!(vec!["spin", "walk", "still", "blink"], names);
+        let blink = sheet.state("blink").unwrap();
+        let delay = Some(vec![String::from("1"), String::from("4")]);
+        assert_eq!(delay, blink.metadata.delay);
+        assert_eq!(Some(String::from("3")), blink.metadata._loop);
+        assert_eq!(&red, blink.frame(0, 0));
+        let spin = sheet.state("spin").unwrap();
+        assert_eq!(3, spin.metadata.frames);
+        assert!(spin.metadata.delay.is_some());
+    }
+
+    #[test]
+    fn test_pack_size_mismatch() {
+        let paths = [
+            PathBuf::from("tests/data/edit/spin.dmi"),
+            PathBuf::from("tests/data/gags/scarf.dmi"),
+        ];
+        match pack_files(&paths, &[], &OnConflict::Error) {
+            Err(IconToolError::IconSizeMismatch(x)) => assert!(x.contains("8x8 in"), "{x}"),
+            _ => panic!("test_pack_size_mismatch: Expected IconSizeMismatch error"),
+        }
+        assert!(pack_files(&[], &[], &OnConflict::Error).is_err());
+    }
+
+    #[test]
+    fn test_pack_prefix() {
+        let paths = [
+            PathBuf::from("tests/data/edit/spin.dmi"),
+            PathBuf::from("tests/data/compile/neck.dmi"),
+        ];
+        let prefixes = [
+            PackPrefix {
+                file: None,
+                prefix: String::from("pack_"),
+            },
+            PackPrefix {
+                file: Some(String::from("edit/spin.dmi")),
+                prefix: String::from("spin_"),
+            },
+        ];
+        let sheet = pack_files(&paths, &prefixes, &OnConflict::Error).unwrap();
+        let neck = DmiFile::open(&paths[1]).unwrap();
+        let names: Vec<&str> = sheet.states().map(|x| x.name()).collect();
+        assert_eq!(vec!["spin_spin", "spin_walk", "spin_still"], names[..3]);
+        assert_eq!(3 + neck.metadata.states.len(), names.len());
+        for (name, state) in names[3..].iter().zip(neck.states()) {
+            assert_eq!(format!("pack_{}", state.name()), *name);
+        }
+        // the keys a decompile would write follow the new names
+        assert_eq!("spin_walk", sheet.metadata.state_keys()[1]);
+    }
+
+    #[test]
+    fn test_pack_on_conflict() {
+        let spin = PathBuf::from("tests/data/edit/spin.dmi");
+        let paths = [spin.clone(), spin.clone()];
+        let pack = |on_conflict| pack_files(&paths, &[], &on_conflict);
+        match pack(OnConflict::Error) {
+            Err(IconToolError::StateConflict(x)) => assert!(x.contains("'spin'"), "{x}"),
+            _ => panic!("test_pack_on_conflict: Expected StateConflict error"),
+        }
+        let names = |sheet: DmiFile| -> Vec<String> {
+            sheet.states().map(|x| x.name().to_string()).collect()
+        };
+        assert_eq!(
+            vec!["spin", "walk", "still"],
+            names(pack(OnConflict::Ours).unwrap())
+        );
+        assert_eq!(
+            vec!["spin", "walk", "still"],
+            names(pack(OnConflict::Theirs).unwrap())
+        );
+        let renamed = vec!["spin", "walk", "still", "spin_2", "walk_2", "still_2"];
+        assert_eq!(renamed, names(pack(OnConflict::Rename).unwrap()));
+    }
+
+    #[test]
+    fn test_pack_interactive() {
+        let spin = PathBuf::from("tests/data/edit/spin.dmi");
+        let paths = [spin.clone(), spin.clone()];
+        // a bad answer is asked again; running out of answers is an error
+        let mut input = Cursor::new("x\nours\nr\n");
+        let mut output = Vec::new();
+        let result = pack_files_with(
+            &paths,
+            &[],
+            &OnConflict::Interactive,
+            &mut input,
+            &mut output,
+        );
+        assert!(matches!(result, Err(IconToolError::StateConflict(_))));
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(4, output.matches("keep [o]urs").count());
+        assert!(output.contains("icon_state 'walk' is in both files"));
+
+        let mut input = Cursor::new("o\nt\nrename\n");
+        let sheet = pack_files_with(
+            &paths,
+            &[],
+            &OnConflict::Interactive,
+            &mut input,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let names: Vec<&str> = sheet.states().map(|x| x.name()).collect();
+        assert_eq!(vec!["spin", "walk", "still", "still_2"], names);
+    }
+
+    #[test]
+    fn test_render_thumbnail() {
+        let mut frame = RgbaImage::new(4, 4);
+        frame.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        frame.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+        frame.put_pixel(1, 1, Rgba([0, 255, 0, 255]));
+        let text = render_thumbnail(&frame, 4);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}"));
+        assert!(lines[0].contains("\x1b[49m\x1b[38;2;0;255;0m\u{2584}"));
+        assert!(lines[1].ends_with("\x1b[0m \x1b[0m"));
+        // a wide frame is sampled down to fit
+        let text = render_thumbnail(&RgbaImage::new(64, 64), 16);
+        assert_eq!(8, text.lines().count());
+        assert_eq!(16, text.lines().next().unwrap().matches(' ').count());
+    }
+}
@@ -0,0 +1,259 @@
+// palette.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::atomic::write_atomic;
+use crate::cmdline::{ColorsArgs, PaletteArgs, PaletteFormat};
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+
+// the most colors an indexed PNG can hold
+const PALETTE_SIZE: usize = 256;
+
+pub fn colors(args: &ColorsArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let sheet = DmiFile::open(&path)?;
+    let frames = select_frames(&sheet, &args.state)?;
+    let colors = count_colors(&frames);
+    let transparent = frames
+        .iter()
+        .any(|frame| frame.pixels().any(|pixel| pixel.0[3] == 0));
+
+    let name = match &args.state {
+        Some(state) => format!("{} icon_state '{state}'", args.file),
+        None => args.file.clone(),
+    };
+    for line in format_color_stats(&name, &colors, transparent, args.top) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+// summarize the colors of an icon_state for planning palette work
+pub fn format_color_stats(
+    name: &str,
+    colors: &[([u8; 4], usize)],
+    transparent: bool,
+    top: usize,
+) -> Vec<String> {
+    // transparency takes a palette entry of its own
+    let entries = colors.len() + usize::from(transparent);
+    let fits = match entries <= PALETTE_SIZE {
+        true => String::from("yes"),
+        false => format!("no, {} too many", entries - PALETTE_SIZE),
+    };
+    let mut lines = vec![
+        format!("{name}: {} unique color(s)", colors.len()),
+        format!("fits a {PALETTE_SIZE}-color palette: {fits}"),
+    ];
+    if !colors.is_empty() && top > 0 {
+        lines.push(String::from("most common:"));
+        for (rgba, count) in colors.iter().take(top) {
+            lines.push(format!("  {:9}  {count}", format_hex(rgba)));
+        }
+    }
+    lines
+}
+
+pub fn palette(args: &PaletteArgs) -> Result<()> {
+    // cut the .dmi file up into frames
+    let path = PathBuf::from(&args.file);
+    let sheet = DmiFile::open(&path)?;
+
+    // count the colors of the requested icon_state, or the whole file
+    let colors = count_colors(&select_frames(&sheet, &args.state)?);
+
+    // write the palette in the requested format
+    let format = match (&args.format, &args.output) {
+        (Some(format), _) => format.clone(),
+        (None, Some(output)) if output.ends_with(".gpl") => PaletteFormat::Gpl,
+        _ => PaletteFormat::Text,
+    };
+    let name = match &args.state {
+        Some(state) => format!("{} {state}", args.file),
+        None => args.file.clone(),
+    };
+    let text = format_palette(&colors, &format, &name);
+    match &args.output {
+        Some(output) => write_atomic(Path::new(output), text)?,
+        None => print!("{text}"),
+    }
+
+    Ok(())
+}
+
+// the frames of the requested icon_state, or of the whole file
+fn select_frames<'a>(sheet: &'a DmiFile, state: &Option<String>) -> Result<Vec<&'a RgbaImage>> {
+    match state {
+        Some(name) => {
+            let index = sheet
+                .metadata
+                .find_state(name)
+                .ok_or_else(|| IconToolError::StateNotFound(name.clone()))?;
+            Ok(sheet.frames[index].iter().collect())
+        }
+        None => Ok(sheet.frames.iter().flatten().collect()),
+    }
+}
+
+// count the pixels of each color, most common first
+pub fn count_colors(frames: &[&RgbaImage]) -> Vec<([u8; 4], usize)> {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for frame in frames {
+        for pixel in frame.pixels() {
+            // fully transparent pixels aren't part of the palette
+            if pixel.0[3] != 0 {
+                *counts.entry(pixel.0).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut colors: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+    colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    colors
+}
+
+pub fn format_palette(colors: &[([u8; 4], usize)], format: &PaletteFormat, name: &str) -> String {
+    let mut lines = Vec::new();
+    match format {
+        PaletteFormat::Text => {
+            for (rgba, count) in colors {
+                lines.push(format!("{}  {count}", format_hex(rgba)));
+            }
+        }
+        PaletteFormat::Hex => {
+            for (rgba, _) in colors {
+                lines.push(format_hex(rgba));
+            }
+        }
+        PaletteFormat::Gpl => {
+            // GIMP palettes have no alpha; translucent colors are noted in the name
+            lines.push(String::from("GIMP Palette"));
+            lines.push(format!("Name: {name}"));
+            lines.push(String::from("Columns: 0"));
+            lines.push(String::from("#"));
+            for (rgba, count) in colors {
+                lines.push(format!(
+                    "{:3} {:3} {:3}\t{} ({count})",
+                    rgba[0],
+                    rgba[1],
+                    rgba[2],
+                    format_hex(rgba)
+                ));
+            }
+        }
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+fn format_hex(rgba: &[u8; 4]) -> String {
+    // opaque colors are written without alpha
+    let rgb = format!("#{:02X}{:02X}{:02X}", rgba[0], rgba[1], rgba[2]);
+    match rgba[3] {
+        255 => rgb,
+        alpha => format!("{rgb}{alpha:02X}"),
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_format_palette() {
+        let colors = vec![([255, 0, 0, 255], 10), ([0, 0, 255, 128], 2)];
+        assert_eq!(
+            "#FF0000  10\n#0000FF80  2\n",
+            format_palette(&colors, &PaletteFormat::Text, "x")
+        );
+        assert_eq!(
+            "#FF0000\n#0000FF80\n",
+            format_palette(&colors, &PaletteFormat::Hex, "x")
+        );
+        assert_eq!(
+            "GIMP Palette\nName: x\nColumns: 0\n#\n255   0   0\t#FF0000 (10)\n  0   0 255\t#0000FF80 (2)\n",
+            format_palette(&colors, &PaletteFormat::Gpl, "x")
+        );
+    }
+
+    #[test]
+    fn test_palette_gpl() {
+        std::fs::create_dir_all("tests/data/palette").unwrap();
+        let args = PaletteArgs {
+            output: Some(String::from("tests/data/palette/spin.out.gpl")),
+            state: Some(String::from("spin")),
+            format: None,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        palette(&args).unwrap();
+        let text = std::fs::read_to_string("tests/data/palette/spin.out.gpl").unwrap();
+        assert!(text.starts_with("GIMP Palette\nName: tests/data/edit/spin.dmi spin\n"));
+    }
+
+    #[test]
+    fn test_format_color_stats() {
+        let colors = vec![([255, 0, 0, 255], 10), ([0, 0, 255, 128], 2)];
+        assert_eq!(
+            vec![
+                "walk: 2 unique color(s)",
+                "fits a 256-color palette: yes",
+                "most common:",
+                "  #FF0000    10",
+                "  #0000FF80  2",
+            ],
+            format_color_stats("walk", &colors, true, 5)
+        );
+
+        // transparency needs an entry, so 256 colors plus transparency don't fit
+        let colors: Vec<([u8; 4], usize)> = (0..=255).map(|x| ([x, 0, 0, 255], 1)).collect();
+        assert_eq!(
+            "fits a 256-color palette: yes",
+            format_color_stats("x", &colors, false, 0)[1]
+        );
+        assert_eq!(
+            "fits a 256-color palette: no, 1 too many",
+            format_color_stats("x", &colors, true, 0)[1]
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let sheet = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let frames = select_frames(&sheet, &Some(String::from("walk"))).unwrap();
+        assert_eq!(8, frames.len());
+        assert!(!count_colors(&frames).is_empty());
+        assert!(select_frames(&sheet, &Some(String::from("run"))).is_err());
+
+        let args = ColorsArgs {
+            state: Some(String::from("walk")),
+            top: 3,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        colors(&args).unwrap();
+    }
+}
@@ -27,37 +27,64 @@
 // those fields for my purpose. If you care about the field and improve
 // the code, I am happy to accept a pull request on GitHub.
 
+use indexmap::IndexMap;
 use nom::{
-    branch::alt,
     bytes::complete::{is_not, tag},
     character::complete::{digit1, multispace0},
-    combinator::{fail, success},
+    combinator::{map_res, opt},
     error::ParseError,
     multi::many0,
     sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-use crate::error::{IconToolError::IncompleteParseError, Result};
+use crate::constant::{DEFAULT_MAX_STATES, DUPLICATE_STATE_SEPARATOR, TICKS_PER_SECOND};
+use crate::error::{
+    IconToolError::{self, IncompleteParseError, TooManyStates},
+    Result,
+};
 
-#[derive(Debug)]
+// the version of the dmi metadata format written by BYOND today
+pub const CURRENT_DMI_VERSION: DreamMakerIconVersion = DreamMakerIconVersion { major: 4, minor: 0 };
+
+// the versions of the dmi metadata format that this parser understands
+pub const KNOWN_DMI_VERSIONS: [DreamMakerIconVersion; 1] = [CURRENT_DMI_VERSION];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DreamMakerIconVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[derive(Clone, Debug)]
 pub struct DreamMakerIconMetadata {
-    pub version: String,
+    pub version: DreamMakerIconVersion,
     pub width: u32,
     pub height: u32,
     pub states: Vec<DreamMakerIconState>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct DreamMakerIconState {
     pub name: String,
     pub delay: Option<Vec<String>>,
     pub dirs: u32,
     pub frames: u32,
-    pub hotspot: Option<Vec<String>>,
+    pub hotspots: Vec<DreamMakerIconHotspot>,
     pub _loop: Option<String>, // 'loop' is a Rust keyword
-    pub movement: Option<String>,
+    pub movement: bool,
     pub rewind: Option<String>,
+    pub unknown: IndexMap<String, String>, // properties we don't recognize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DreamMakerIconHotspot {
+    pub x: u32,
+    pub y: u32,
+    pub frame: u32, // 1-based index of the frame within the icon_state
 }
 
 #[derive(Debug)]
@@ -67,8 +94,36 @@ struct DreamMakerIconStateProperty {
 }
 
 pub fn parse_metadata(input: &str) -> Result<DreamMakerIconMetadata> {
-    // parse the provided metadata
-    let (input, dmi_metadata) = nomify_metadata(input)?;
+    parse_metadata_with_max_states(input, DEFAULT_MAX_STATES)
+}
+
+// like parse_metadata, but refuse metadata with more than max_states
+// icon_states; they are counted before any of them are parsed
+pub fn parse_metadata_with_max_states(
+    input: &str,
+    max_states: usize,
+) -> Result<DreamMakerIconMetadata> {
+    let num_states = input
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("state"))
+        .filter(|rest| rest.trim_start().starts_with('='))
+        .count();
+    if num_states > max_states {
+        return Err(TooManyStates(num_states, max_states));
+    }
+    // metadata edited on Windows may start with a BOM and end its lines
+    // with CRLF; the grammar below only knows about LF
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let input = match input.contains('\r') {
+        true => Cow::Owned(input.replace("\r\n", "\n").replace('\r', "\n")),
+        false => Cow::Borrowed(input),
+    };
+    // parse the provided metadata; a bad number is reported before anything
+    // else, since it names the icon_state to fix
+    let (input, dmi_metadata) = nomify_metadata(&input)?;
+    let dmi_metadata = dmi_metadata?;
+    // whitespace and NUL padding after the end marker is harmless
+    let input = input.trim_end_matches(|c: char| c.is_whitespace() || c == '\0');
     // if we didn't parse all of the provided input
     if !input.is_empty() {
         // you get to drink from the firehose...
@@ -78,7 +133,147 @@ pub fn parse_metadata(input: &str) -> Result<DreamMakerIconMetadata> {
     Ok(dmi_metadata)
 }
 
-fn nomify_metadata(input: &str) -> IResult<&str, DreamMakerIconMetadata> {
+impl DreamMakerIconMetadata {
+    pub fn new(width: u32, height: u32) -> DreamMakerIconMetadata {
+        DreamMakerIconMetadata {
+            version: CURRENT_DMI_VERSION,
+            width,
+            height,
+            states: Vec::new(),
+        }
+    }
+
+    // put the metadata in canonical form: numbers in delay lists are
+    // written without trailing zeros and unknown properties are sorted
+    pub fn normalize(&mut self) {
+        for state in &mut self.states {
+            if let Some(delay) = &mut state.delay {
+                for value in delay.iter_mut() {
+                    *value = normalize_number(value);
+                }
+            }
+            state.unknown.sort_keys();
+        }
+    }
+
+    // find the index of the icon_state with the provided YAML key
+    pub fn find_state(&self, key: &str) -> Option<usize> {
+        self.state_keys().iter().position(|k| k == key)
+    }
+
+    // BYOND allows several icon_states to share a name, so the YAML keys
+    // for repeated names are disambiguated: "name", "name@2", "name@3", ...
+    pub fn state_keys(&self) -> Vec<String> {
+        let mut seen: HashMap<&str, u32> = HashMap::new();
+        let mut keys = Vec::with_capacity(self.states.len());
+        for state in &self.states {
+            let count = seen.entry(&state.name).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                keys.push(state.name.clone());
+            } else {
+                keys.push(format!("{}{DUPLICATE_STATE_SEPARATOR}{count}", state.name));
+            }
+        }
+        keys
+    }
+}
+
+impl DreamMakerIconState {
+    pub fn new(name: &str, dirs: u32, frames: u32) -> DreamMakerIconState {
+        DreamMakerIconState {
+            name: name.to_string(),
+            delay: None,
+            dirs,
+            frames,
+            hotspots: Vec::new(),
+            _loop: None,
+            movement: false,
+            rewind: None,
+            unknown: IndexMap::new(),
+        }
+    }
+}
+
+impl DreamMakerIconVersion {
+    pub fn is_known(&self) -> bool {
+        KNOWN_DMI_VERSIONS.contains(self)
+    }
+}
+
+impl fmt::Display for DreamMakerIconVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl fmt::Display for DreamMakerIconMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# BEGIN DMI")?;
+        writeln!(f, "version = {}", self.version)?;
+        writeln!(f, "\twidth = {}", self.width)?;
+        writeln!(f, "\theight = {}", self.height)?;
+        for state in &self.states {
+            write!(f, "{state}")?;
+        }
+        writeln!(f, "# END DMI")
+    }
+}
+
+impl fmt::Display for DreamMakerIconState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "state = {}", quote_string(&self.name))?;
+        writeln!(f, "\tdirs = {}", self.dirs)?;
+        writeln!(f, "\tframes = {}", self.frames)?;
+        if let Some(delay) = &self.delay {
+            writeln!(f, "\tdelay = {}", delay.join(","))?;
+        }
+        if let Some(_loop) = &self._loop {
+            writeln!(f, "\tloop = {_loop}")?;
+        }
+        if let Some(rewind) = &self.rewind {
+            writeln!(f, "\trewind = {rewind}")?;
+        }
+        if self.movement {
+            writeln!(f, "\tmovement = 1")?;
+        }
+        for hotspot in &self.hotspots {
+            writeln!(
+                f,
+                "\thotspot = {},{},{}",
+                hotspot.x, hotspot.y, hotspot.frame
+            )?;
+        }
+        // re-emit anything we didn't recognize verbatim
+        for (name, value) in &self.unknown {
+            writeln!(f, "\t{name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn normalize_number(value: &str) -> String {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.fract() == 0.0 => format!("{}", number as i64),
+        Ok(number) => format!("{number}"),
+        Err(_) => value.to_string(),
+    }
+}
+
+pub fn fps_to_delay(fps: f64) -> Result<f64> {
+    // delays are measured in ticks; 1/10th of a second
+    if !fps.is_finite() || fps <= 0.0 {
+        return Err(IconToolError::InvalidDelay(format!("{fps} fps")));
+    }
+    Ok(TICKS_PER_SECOND / fps)
+}
+
+pub fn delay_to_fps(delay: f64) -> f64 {
+    // the frame rate of a delay in ticks, to two decimal places
+    (TICKS_PER_SECOND / delay * 100.0).round() / 100.0
+}
+
+fn nomify_metadata(input: &str) -> IResult<&str, Result<DreamMakerIconMetadata>> {
     let (input, _) = ws(tag("# BEGIN DMI"))(input)?;
     let (input, version) = parse_version(input)?;
     let (input, width) = parse_optional_width(input)?;
@@ -86,63 +281,93 @@ fn nomify_metadata(input: &str) -> IResult<&str, DreamMakerIconMetadata> {
     let (input, states) = parse_states(input)?;
     let (input, _) = ws(tag("# END DMI"))(input)?;
 
-    Ok((
-        input,
-        DreamMakerIconMetadata {
+    // the grammar matched, but the numbers in it may still be bad
+    let metadata = || {
+        Ok(DreamMakerIconMetadata {
             version,
-            width,
-            height,
-            states,
-        },
-    ))
+            width: width?,
+            height: height?,
+            states: states.into_iter().collect::<Result<_>>()?,
+        })
+    };
+    Ok((input, metadata()))
 }
 
-fn parse_version(input: &str) -> IResult<&str, String> {
+fn parse_version(input: &str) -> IResult<&str, DreamMakerIconVersion> {
     let (input, _) = tag("version = ")(input)?;
-    let (input, (major_version, _, minor_version)) = tuple((digit1, tag("."), digit1))(input)?;
+    let (input, (major, _, minor)) = tuple((parse_u32, tag("."), parse_u32))(input)?;
     let (input, _) = tag("\n")(input)?;
-    Ok((input, format!("{}.{}", major_version, minor_version)))
+    Ok((input, DreamMakerIconVersion { major, minor }))
 }
 
-fn parse_optional_width(input: &str) -> IResult<&str, u32> {
-    let (input, width) = alt((parse_width, success(32)))(input)?;
-    Ok((input, width))
+fn parse_u32(input: &str) -> IResult<&str, u32> {
+    map_res(digit1, |digits: &str| digits.parse::<u32>())(input)
 }
 
-fn parse_optional_height(input: &str) -> IResult<&str, u32> {
-    let (input, height) = alt((parse_height, success(32)))(input)?;
-    Ok((input, height))
+fn parse_optional_width(input: &str) -> IResult<&str, Result<u32>> {
+    let (input, width) = opt(parse_width)(input)?;
+    Ok((input, width.map_or(Ok(32), |x| parse_number("width", x))))
+}
+
+fn parse_optional_height(input: &str) -> IResult<&str, Result<u32>> {
+    let (input, height) = opt(parse_height)(input)?;
+    Ok((input, height.map_or(Ok(32), |x| parse_number("height", x))))
 }
 
-fn parse_width(input: &str) -> IResult<&str, u32> {
+fn parse_width(input: &str) -> IResult<&str, &str> {
     let (input, _) = tag("\twidth = ")(input)?;
     let (input, width) = digit1(input)?;
     let (input, _) = tag("\n")(input)?;
-    Ok((input, width.parse::<u32>().unwrap()))
+    Ok((input, width))
 }
 
-fn parse_height(input: &str) -> IResult<&str, u32> {
+fn parse_height(input: &str) -> IResult<&str, &str> {
     let (input, _) = tag("\theight = ")(input)?;
     let (input, height) = digit1(input)?;
     let (input, _) = tag("\n")(input)?;
-    Ok((input, height.parse::<u32>().unwrap()))
+    Ok((input, height))
+}
+
+// a number from the metadata, like "width = 32"; the digits may still be
+// too big for a u32
+fn parse_number(property: &str, value: &str) -> Result<u32> {
+    value.trim().parse::<u32>().map_err(|_| {
+        IconToolError::ParseError(format!(
+            "{property} = {value}; expected a number up to {}",
+            u32::MAX
+        ))
+    })
 }
 
-fn parse_states(input: &str) -> IResult<&str, Vec<DreamMakerIconState>> {
+// the dirs or frames of an icon_state; the error names the icon_state, so
+// the bad line can be found
+fn parse_count(name: &str, property: &str, value: Option<String>) -> Result<u32> {
+    let value = value.ok_or_else(|| {
+        IconToolError::ParseError(format!("icon_state '{name}' has no {property}"))
+    })?;
+    parse_number(property, &value).map_err(|_| {
+        IconToolError::ParseError(format!(
+            "icon_state '{name}' has {property} = {value}; expected a number"
+        ))
+    })
+}
+
+fn parse_states(input: &str) -> IResult<&str, Vec<Result<DreamMakerIconState>>> {
     let (input, states) = many0(parse_state)(input)?;
     Ok((input, states))
 }
 
-fn parse_state(input: &str) -> IResult<&str, DreamMakerIconState> {
+fn parse_state(input: &str) -> IResult<&str, Result<DreamMakerIconState>> {
     let (input, name) = parse_state_name(input)?;
 
     let mut delay: Option<Vec<String>> = None;
-    let mut dirs: Option<u32> = None;
-    let mut frames: Option<u32> = None;
-    let mut hotspot: Option<Vec<String>> = None;
+    let mut dirs: Option<String> = None;
+    let mut frames: Option<String> = None;
+    let mut hotspots: Vec<DreamMakerIconHotspot> = Vec::new();
     let mut _loop: Option<String> = None;
-    let mut movement: Option<String> = None;
+    let mut movement = false;
     let mut rewind: Option<String> = None;
+    let mut unknown: IndexMap<String, String> = IndexMap::new();
 
     let (input, props) = parse_state_properties(input)?;
 
@@ -154,48 +379,71 @@ fn parse_state(input: &str) -> IResult<&str, DreamMakerIconState> {
             }
             // dirs = 4
             "dirs" => {
-                dirs = Some(prop.value.parse::<u32>().unwrap());
+                dirs = Some(prop.value);
             }
             // frames = 1
             "frames" => {
-                frames = Some(prop.value.parse::<u32>().unwrap());
-            }
-            // hotspot = 8,8,8,8,8,8,8,8,8,8,8,8,8,8,8,8,8,8,8,8
-            "hotspot" => {
-                hotspot = Some(prop.value.split(",").map(|x| x.to_string()).collect());
+                frames = Some(prop.value);
             }
+            // hotspot = 16,16,1
+            "hotspot" => match parse_hotspot(&prop.value) {
+                Some(hotspot) => hotspots.push(hotspot),
+                // keep a hotspot we don't understand for round trips
+                None => {
+                    unknown.insert(prop.name, prop.value);
+                }
+            },
             // loop = 1
             "loop" => {
                 _loop = Some(prop.value.clone());
             }
             // movement = 1
             "movement" => {
-                movement = Some(prop.value.clone());
+                movement = prop.value.trim() != "0";
             }
             // rewind = 1
             "rewind" => {
                 rewind = Some(prop.value.clone());
             }
-            // this is an unknown property keyword
+            // this is an unknown property keyword; keep it for round trips
             _ => {
-                return fail(input);
+                unknown.insert(prop.name, prop.value);
             }
         }
     }
 
-    Ok((
-        input,
-        DreamMakerIconState {
+    let state = || {
+        Ok(DreamMakerIconState {
+            dirs: parse_count(&name, "dirs", dirs)?,
+            frames: parse_count(&name, "frames", frames)?,
             name,
             delay,
-            dirs: dirs.unwrap(),
-            frames: frames.unwrap(),
-            hotspot,
+            hotspots,
             _loop,
             movement,
             rewind,
-        },
-    ))
+            unknown,
+        })
+    };
+    Ok((input, state()))
+}
+
+fn parse_hotspot(value: &str) -> Option<DreamMakerIconHotspot> {
+    let parts: Vec<u32> = value
+        .split(',')
+        .map(|x| x.trim().parse::<u32>())
+        .collect::<std::result::Result<_, _>>()
+        .ok()?;
+    match parts.as_slice() {
+        // frames are counted from 1, so frame 0 doesn't point at any of them
+        [_, _, 0] => None,
+        [x, y, frame] => Some(DreamMakerIconHotspot {
+            x: *x,
+            y: *y,
+            frame: *frame,
+        }),
+        _ => None,
+    }
 }
 
 fn parse_state_name(input: &str) -> IResult<&str, String> {
@@ -230,6 +478,19 @@ fn in_quotes(input: &str) -> IResult<&str, String> {
 }
 //------------------------------------------------------------------------------------------------------------------------
 
+fn quote_string(input: &str) -> String {
+    let mut ret = String::from("\"");
+    for ch in input.chars() {
+        if ch == '\\' || ch == '"' {
+            ret.push('\\');
+        }
+        ret.push(ch);
+    }
+    ret.push('"');
+    ret
+}
+//------------------------------------------------------------------------------------------------------------------------
+
 fn parse_state_properties(input: &str) -> IResult<&str, Vec<DreamMakerIconStateProperty>> {
     let (input, props) = many0(parse_state_property)(input)?;
     Ok((input, props))
@@ -273,3 +534,218 @@ where
     delimited(multispace0, inner, multispace0)
 }
 //------------------------------------------------------------------------------------------------------------------------
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNKNOWN_PROPERTY_METADATA: &str = "# BEGIN DMI
+version = 4.0
+\twidth = 32
+\theight = 32
+state = \"blinky\"
+\tdirs = 1
+\tframes = 2
+\tdelay = 1,2
+\tsparkle = 3
+state = \"quote \\\"me\\\"\"
+\tdirs = 4
+\tframes = 1
+# END DMI
+";
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_parse_unknown_property() {
+        let dmi = parse_metadata(UNKNOWN_PROPERTY_METADATA).unwrap();
+        assert_eq!(2, dmi.states.len());
+        assert_eq!("3", dmi.states[0].unknown["sparkle"]);
+        assert_eq!("quote \"me\"", dmi.states[1].name);
+        assert!(dmi.states[1].unknown.is_empty());
+    }
+
+    #[test]
+    fn test_parse_windows_line_endings() {
+        let expected = parse_metadata(UNKNOWN_PROPERTY_METADATA)
+            .unwrap()
+            .to_string();
+        let crlf = UNKNOWN_PROPERTY_METADATA.replace('\n', "\r\n");
+        assert_eq!(expected, parse_metadata(&crlf).unwrap().to_string());
+        let bom = format!("\u{feff}{crlf}");
+        assert_eq!(expected, parse_metadata(&bom).unwrap().to_string());
+
+        // a file touched by several editors can mix its line endings
+        let mixed: String = UNKNOWN_PROPERTY_METADATA
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(index, line)| match index % 3 {
+                0 => line.replace('\n', "\r\n"),
+                1 => line.replace('\n', "\r"),
+                _ => line.to_string(),
+            })
+            .collect();
+        assert_eq!(expected, parse_metadata(&mixed).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parse_max_states() {
+        assert!(parse_metadata_with_max_states(UNKNOWN_PROPERTY_METADATA, 2).is_ok());
+        match parse_metadata_with_max_states(UNKNOWN_PROPERTY_METADATA, 1) {
+            Err(TooManyStates(2, 1)) => {}
+            _ => panic!("test_parse_max_states: Expected TooManyStates error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_junk() {
+        let padded = format!("{UNKNOWN_PROPERTY_METADATA} \t\r\n\0\0");
+        assert!(parse_metadata(&padded).is_ok());
+        let junk = format!("{UNKNOWN_PROPERTY_METADATA}state = \"late\"\n");
+        match parse_metadata(&junk) {
+            Err(IncompleteParseError(x)) => assert_eq!("state = \"late\"", x),
+            _ => panic!("test_parse_trailing_junk: Expected IncompleteParseError"),
+        }
+    }
+
+    #[test]
+    fn test_parse_bad_counts() {
+        let bad_dirs = UNKNOWN_PROPERTY_METADATA.replace("\tdirs = 4", "\tdirs = abc");
+        match parse_metadata(&bad_dirs) {
+            Err(IconToolError::ParseError(x)) => assert_eq!(
+                "icon_state 'quote \"me\"' has dirs = abc; expected a number",
+                x
+            ),
+            _ => panic!("test_parse_bad_counts: Expected ParseError"),
+        }
+        let no_frames = UNKNOWN_PROPERTY_METADATA.replace("\tframes = 2\n", "");
+        match parse_metadata(&no_frames) {
+            Err(IconToolError::ParseError(x)) => {
+                assert_eq!("icon_state 'blinky' has no frames", x)
+            }
+            _ => panic!("test_parse_bad_counts: Expected ParseError"),
+        }
+        let big_frames = UNKNOWN_PROPERTY_METADATA.replace("frames = 2", "frames = 4294967296");
+        match parse_metadata(&big_frames) {
+            Err(IconToolError::ParseError(x)) => assert!(x.contains("frames = 4294967296"), "{x}"),
+            _ => panic!("test_parse_bad_counts: Expected ParseError"),
+        }
+        let big_width = UNKNOWN_PROPERTY_METADATA.replace("width = 32", "width = 99999999999");
+        match parse_metadata(&big_width) {
+            Err(IconToolError::ParseError(x)) => {
+                assert!(x.starts_with("width = 99999999999"), "{x}")
+            }
+            _ => panic!("test_parse_bad_counts: Expected ParseError"),
+        }
+    }
+
+    #[test]
+    fn test_state_keys_duplicates() {
+        let mut dmi = DreamMakerIconMetadata::new(32, 32);
+        for name in ["open", "closed", "open", "open"] {
+            dmi.states.push(DreamMakerIconState::new(name, 1, 1));
+        }
+        assert_eq!(vec!["open", "closed", "open@2", "open@3"], dmi.state_keys());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let dmi = parse_metadata(UNKNOWN_PROPERTY_METADATA).unwrap();
+        assert_eq!(CURRENT_DMI_VERSION, dmi.version);
+        assert!(dmi.version.is_known());
+        let old = DreamMakerIconVersion { major: 3, minor: 2 };
+        assert!(!old.is_known());
+        assert_eq!("3.2", old.to_string());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let text = "# BEGIN DMI\nversion = 4.0\nstate = \"x\"\n\tdirs = 1\n\tframes = 3\n\tdelay = 1.0,0.50, 2\n\tzap = 1\n\tbar = 2\n# END DMI\n";
+        let mut dmi = parse_metadata(text).unwrap();
+        dmi.normalize();
+        assert_eq!(
+            "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"x\"\n\tdirs = 1\n\tframes = 3\n\tdelay = 1,0.5,2\n\tbar = 2\n\tzap = 1\n# END DMI\n",
+            dmi.to_string()
+        );
+    }
+
+    #[test]
+    fn test_find_state() {
+        let mut dmi = DreamMakerIconMetadata::new(32, 32);
+        for name in ["open", "closed", "open"] {
+            dmi.states.push(DreamMakerIconState::new(name, 1, 1));
+        }
+        assert_eq!(Some(0), dmi.find_state("open"));
+        assert_eq!(Some(2), dmi.find_state("open@2"));
+        assert_eq!(None, dmi.find_state("broken"));
+    }
+
+    #[test]
+    fn test_parse_hotspots() {
+        let text = "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"x\"\n\tdirs = 1\n\tframes = 2\n\thotspot = 16,15,1\n\thotspot = 3,4,2\n# END DMI\n";
+        let dmi = parse_metadata(text).unwrap();
+        assert_eq!(
+            vec![
+                DreamMakerIconHotspot {
+                    x: 16,
+                    y: 15,
+                    frame: 1
+                },
+                DreamMakerIconHotspot {
+                    x: 3,
+                    y: 4,
+                    frame: 2
+                },
+            ],
+            dmi.states[0].hotspots
+        );
+        assert_eq!(text, dmi.to_string());
+    }
+
+    #[test]
+    fn test_parse_hotspot_frame_zero() {
+        let text = "# BEGIN DMI\nversion = 4.0\n\twidth = 32\n\theight = 32\nstate = \"x\"\n\tdirs = 1\n\tframes = 2\n\thotspot = 1,1,0\n# END DMI\n";
+        let dmi = parse_metadata(text).unwrap();
+        assert!(dmi.states[0].hotspots.is_empty());
+        assert_eq!(
+            Some("1,1,0"),
+            dmi.states[0].unknown.get("hotspot").map(|x| x.as_str())
+        );
+        assert_eq!(text, dmi.to_string());
+    }
+
+    #[test]
+    fn test_parse_movement() {
+        let text = "# BEGIN DMI\nversion = 4.0\nstate = \"walk\"\n\tdirs = 4\n\tframes = 1\nstate = \"walk\"\n\tdirs = 4\n\tframes = 2\n\tmovement = 1\n# END DMI\n";
+        let dmi = parse_metadata(text).unwrap();
+        assert!(!dmi.states[0].movement);
+        assert!(dmi.states[1].movement);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let dmi = parse_metadata(UNKNOWN_PROPERTY_METADATA).unwrap();
+        assert_eq!(UNKNOWN_PROPERTY_METADATA, dmi.to_string());
+    }
+
+    #[test]
+    fn test_fps_to_delay() {
+        assert_eq!(1.0, fps_to_delay(10.0).unwrap());
+        assert_eq!(2.5, fps_to_delay(4.0).unwrap());
+        assert!(fps_to_delay(0.0).is_err());
+    }
+
+    #[test]
+    fn test_delay_to_fps() {
+        assert_eq!(10.0, delay_to_fps(1.0));
+        assert_eq!(4.0, delay_to_fps(2.5));
+        assert_eq!(3.33, delay_to_fps(3.0));
+    }
+}
@@ -0,0 +1,132 @@
+// patch.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use crate::cmdline::PatchArgs;
+use crate::compile::compile_bytes;
+use crate::dmi_file::{edit_dmi_file, DmiFile};
+use crate::edit::get_output_path;
+use crate::error::{IconToolError, Result};
+
+pub fn patch(args: &PatchArgs) -> Result<()> {
+    // compile the changed icon_states from their .dmi.yml
+    let yaml = std::fs::read_to_string(&args.changes)?;
+    let changes = DmiFile::from_bytes(&compile_bytes(&yaml)?)?;
+
+    // and swap them into the target .dmi file
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+    edit_dmi_file(&path, &output_path, |sheet| patch_states(sheet, changes))
+}
+
+// replace the icon_states of the sheet that the changes also have, in
+// place, and add the rest of the changed icon_states to the end
+pub fn patch_states(sheet: &mut DmiFile, changes: DmiFile) -> Result<()> {
+    let (width, height) = (sheet.metadata.width, sheet.metadata.height);
+    if (changes.metadata.width, changes.metadata.height) != (width, height) {
+        return Err(IconToolError::InvalidArgument(format!(
+            "the changes have {}x{} icons, but the .dmi file has {width}x{height} icons",
+            changes.metadata.width, changes.metadata.height
+        )));
+    }
+
+    let keys = changes.metadata.state_keys();
+    for ((state, frames), key) in changes
+        .metadata
+        .states
+        .into_iter()
+        .zip(changes.frames)
+        .zip(keys)
+    {
+        match sheet.metadata.find_state(&key) {
+            Some(index) => {
+                sheet.metadata.states[index] = state;
+                sheet.frames[index] = frames;
+            }
+            None => sheet.push_state(state, frames)?,
+        }
+    }
+    Ok(())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::DmiBuilder;
+    use crate::decompile::decompile_bytes;
+    use image::{Rgba, RgbaImage};
+    use std::path::Path;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_patch() {
+        // change the still frame, and add a new icon_state
+        let red = RgbaImage::from_pixel(32, 32, Rgba([255, 0, 0, 255]));
+        let mut builder = DmiBuilder::new(32, 32)
+            .state("still")
+            .push_frame(red.clone())
+            .state("glow")
+            .dirs(4);
+        for _ in 0..4 {
+            builder = builder.push_frame(red.clone());
+        }
+        let changes = builder.build().unwrap();
+        std::fs::create_dir_all("tests/data/patch").unwrap();
+        let yaml = decompile_bytes(&changes.to_bytes().unwrap()).unwrap();
+        std::fs::write("tests/data/patch/changes.out.dmi.yml", yaml).unwrap();
+
+        let args = PatchArgs {
+            output: Some(String::from("tests/data/patch/spin.out.dmi")),
+            file: String::from("tests/data/edit/spin.dmi"),
+            changes: String::from("tests/data/patch/changes.out.dmi.yml"),
+        };
+        patch(&args).unwrap();
+        let original = DmiFile::open(Path::new("tests/data/edit/spin.dmi")).unwrap();
+        let patched = DmiFile::open(Path::new("tests/data/patch/spin.out.dmi")).unwrap();
+        assert_eq!(
+            vec!["spin", "walk", "still", "glow"],
+            patched.metadata.state_keys()
+        );
+        assert_eq!(original.frames[0], patched.frames[0]);
+        assert_eq!(original.frames[1], patched.frames[1]);
+        assert_eq!(vec![red.clone()], patched.frames[2]);
+        assert_eq!(4, patched.metadata.states[3].dirs);
+    }
+
+    #[test]
+    fn test_patch_states_size_mismatch() {
+        let mut sheet = DmiBuilder::new(32, 32).build().unwrap();
+        let changes = DmiBuilder::new(16, 16)
+            .state("still")
+            .push_frame(RgbaImage::new(16, 16))
+            .build()
+            .unwrap();
+        match patch_states(&mut sheet, changes) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_patch_states_size_mismatch: Expected InvalidArgument error"),
+        }
+    }
+}
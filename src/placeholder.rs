@@ -0,0 +1,255 @@
+// placeholder.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::PathBuf;
+
+use crate::cmdline::PlaceholderArgs;
+use crate::color::hsv_to_rgb;
+use crate::constant::{PLACEHOLDER_KEYWORD, ZTXT_KEYWORD};
+use crate::dmi::{write_dmi_file_with_options, WriteOptions};
+use crate::error::Result;
+use crate::parser::{DreamMakerIconMetadata, DreamMakerIconState};
+
+// each glyph is 3 pixels wide and 5 pixels tall; one row per byte
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+pub fn placeholder(args: &PlaceholderArgs) -> Result<()> {
+    // paint the placeholder art
+    let (width, height) = args.size;
+    let image = render_placeholder(width, height, &args.label);
+
+    // describe a single icon_state containing the art
+    let mut dmi = DreamMakerIconMetadata::new(width, height);
+    dmi.states.push(DreamMakerIconState::new(&args.state, 1, 1));
+
+    // write the .dmi file, marked as a placeholder
+    let output_path = get_output_path(args);
+    let options = WriteOptions {
+        text_chunks: vec![(PLACEHOLDER_KEYWORD.to_string(), args.label.clone())],
+        ..Default::default()
+    };
+    write_dmi_file_with_options(
+        &output_path,
+        ZTXT_KEYWORD,
+        &dmi.to_string(),
+        &image,
+        &options,
+    )?;
+
+    // return success to the caller
+    Ok(())
+}
+
+pub fn render_placeholder(width: u32, height: u32, label: &str) -> DynamicImage {
+    // derive a stable color from the label
+    let hash = fnv1a(label.as_bytes());
+    let hue = (hash % 360) as f64;
+    let [r, g, b] = hsv_to_rgb(hue, 0.6, 0.85);
+    let fill = Rgba([r, g, b, 255]);
+    let [r, g, b] = hsv_to_rgb(hue, 0.6, 0.4);
+    let border = Rgba([r, g, b, 255]);
+    let luma = 0.299 * fill[0] as f64 + 0.587 * fill[1] as f64 + 0.114 * fill[2] as f64;
+    let ink = if luma > 140.0 {
+        Rgba([0, 0, 0, 255])
+    } else {
+        Rgba([255, 255, 255, 255])
+    };
+
+    // fill the icon and draw a border around it
+    let mut image = RgbaImage::from_pixel(width, height, fill);
+    for x in 0..width {
+        image.put_pixel(x, 0, border);
+        image.put_pixel(x, height - 1, border);
+    }
+    for y in 0..height {
+        image.put_pixel(0, y, border);
+        image.put_pixel(width - 1, y, border);
+    }
+
+    // lay the label out in as many lines as we need
+    let chars: Vec<char> = label.to_uppercase().chars().collect();
+    let inner_width = width.saturating_sub(2);
+    let inner_height = height.saturating_sub(2);
+    let (scale, per_line) = choose_scale(chars.len() as u32, inner_width, inner_height);
+    if per_line == 0 {
+        return DynamicImage::ImageRgba8(image);
+    }
+    let lines: Vec<&[char]> = chars.chunks(per_line as usize).collect();
+    let line_height = (GLYPH_HEIGHT + 1) * scale;
+    let max_lines = (inner_height + scale) / line_height;
+    let lines = &lines[..lines.len().min(max_lines as usize)];
+    let block_height = (lines.len() as u32 * line_height).saturating_sub(scale);
+    let mut cursor_y = 1 + inner_height.saturating_sub(block_height) / 2;
+
+    // draw each line of the label, centered
+    for line in lines {
+        let line_width = (line.len() as u32 * (GLYPH_WIDTH + 1) * scale).saturating_sub(scale);
+        let mut cursor_x = 1 + inner_width.saturating_sub(line_width) / 2;
+        for ch in line.iter() {
+            draw_glyph(&mut image, glyph(*ch), cursor_x, cursor_y, scale, ink);
+            cursor_x += (GLYPH_WIDTH + 1) * scale;
+        }
+        cursor_y += line_height;
+    }
+
+    DynamicImage::ImageRgba8(image)
+}
+
+fn choose_scale(num_chars: u32, width: u32, height: u32) -> (u32, u32) {
+    // prefer a larger scale when the whole label fits on one line
+    for scale in (1..=4).rev() {
+        let per_line = (width + scale) / ((GLYPH_WIDTH + 1) * scale);
+        let lines = (height + scale) / ((GLYPH_HEIGHT + 1) * scale);
+        if per_line >= num_chars.max(1) && lines >= 1 {
+            return (scale, per_line);
+        }
+    }
+    (1, (width + 1) / (GLYPH_WIDTH + 1))
+}
+
+fn draw_glyph(image: &mut RgbaImage, rows: [u8; 5], x: u32, y: u32, scale: u32, ink: Rgba<u8>) {
+    for (row_index, row) in rows.iter().enumerate() {
+        for col in 0..GLYPH_WIDTH {
+            if row & (0b100 >> col) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let px = x + col * scale + dx;
+                    let py = y + row_index as u32 * scale + dy;
+                    if px < image.width() && py < image.height() {
+                        image.put_pixel(px, py, ink);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn get_output_path(args: &PlaceholderArgs) -> PathBuf {
+    match &args.output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(format!("{}.dmi", args.label.to_lowercase())),
+    }
+}
+
+fn glyph(ch: char) -> [u8; 5] {
+    match ch {
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010], // '?'
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmi::{read_metadata, read_text_chunk};
+    use crate::parser::parse_metadata;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let first = render_placeholder(32, 32, "AMMO");
+        let second = render_placeholder(32, 32, "AMMO");
+        let other = render_placeholder(32, 32, "GUN");
+        assert_eq!(first.as_bytes(), second.as_bytes());
+        assert_ne!(first.as_bytes(), other.as_bytes());
+    }
+
+    #[test]
+    fn test_render_long_label() {
+        let image = render_placeholder(16, 16, "A VERY LONG LABEL INDEED");
+        assert_eq!(16, image.width());
+        assert_eq!(16, image.height());
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let args = PlaceholderArgs {
+            output: Some(String::from("tests/data/placeholder/ammo.out.dmi")),
+            size: (32, 32),
+            label: String::from("AMMO"),
+            state: String::from("ammo"),
+        };
+        std::fs::create_dir_all("tests/data/placeholder").unwrap();
+        placeholder(&args).unwrap();
+        let path = PathBuf::from("tests/data/placeholder/ammo.out.dmi");
+        let dmi = parse_metadata(&read_metadata(&path).unwrap()).unwrap();
+        assert_eq!("ammo", dmi.states[0].name);
+        let marker = read_text_chunk(&path, PLACEHOLDER_KEYWORD).unwrap();
+        assert_eq!(Some(String::from("AMMO")), marker);
+    }
+}
@@ -0,0 +1,119 @@
+// roundtrip.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::Path;
+
+use crate::cmdline::{RoundtripArgs, Tolerance};
+use crate::compare::compare_dmi_files;
+use crate::compile::compile_bytes_with_diagnostics;
+use crate::decompile::decompile_bytes_with_diagnostics;
+use crate::diagnostics::Diagnostics;
+use crate::dmi::decode_metadata;
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+
+pub fn roundtrip(args: &RoundtripArgs) -> Result<()> {
+    // check each of the provided files
+    let mut num_findings = 0;
+    for file in &args.files {
+        let findings = roundtrip_file(Path::new(file), args.tolerance)?;
+        for finding in &findings {
+            println!("{file}: {finding}");
+        }
+        num_findings += findings.len();
+    }
+
+    // if anything didn't survive, tell the caller
+    if num_findings > 0 {
+        return Err(IconToolError::RoundTripFailed(num_findings));
+    }
+    Ok(())
+}
+
+pub fn roundtrip_file(path: &Path, tolerance: Tolerance) -> Result<Vec<String>> {
+    // decompile and compile the icon in memory
+    let original = std::fs::read(path)?;
+    let mut diagnostics = Diagnostics::new();
+    let yaml = decompile_bytes_with_diagnostics(&original, &mut diagnostics)?;
+    let compiled = compile_bytes_with_diagnostics(&yaml, &mut diagnostics)?;
+    diagnostics.print();
+
+    // compare the results against the original
+    compare_dmi(&original, &compiled, tolerance)
+}
+
+fn compare_dmi(original: &[u8], compiled: &[u8], tolerance: Tolerance) -> Result<Vec<String>> {
+    let mut findings = Vec::new();
+
+    // the metadata should be copied exactly
+    if decode_metadata(original)? != decode_metadata(compiled)? {
+        findings.push(String::from("metadata changed"));
+    }
+
+    // everything DreamMaker shows should be the same
+    let original = DmiFile::from_bytes(original)?;
+    let compiled = DmiFile::from_bytes(compiled)?;
+    findings.extend(compare_dmi_files(&original, &compiled, tolerance));
+
+    Ok(findings)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_roundtrip_clean() {
+        let args = RoundtripArgs {
+            tolerance: Tolerance::default(),
+            files: vec![
+                String::from("tests/data/edit/spin.dmi"),
+                String::from("tests/data/compile/chunks.dmi"),
+                String::from("tests/data/dmi/grey16.dmi"),
+            ],
+        };
+        roundtrip(&args).unwrap();
+    }
+
+    #[test]
+    fn test_compare_dmi_changed_pixel() {
+        let original = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        let mut dmi = DmiFile::from_bytes(&original).unwrap();
+        dmi.state_mut("walk")
+            .unwrap()
+            .frame_mut(1, 2)
+            .put_pixel(0, 0, image::Rgba([1, 2, 3, 4]));
+        std::fs::create_dir_all("tests/data/roundtrip").unwrap();
+        let path = Path::new("tests/data/roundtrip/changed.out.dmi");
+        dmi.save(path).unwrap();
+        let changed = std::fs::read(path).unwrap();
+        let findings = compare_dmi(&original, &changed, Tolerance::default()).unwrap();
+        assert_eq!(
+            vec!["icon_state 'walk' frame 2 dir 3 has 1 changed pixel(s)"],
+            findings
+        );
+    }
+}
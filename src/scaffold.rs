@@ -0,0 +1,170 @@
+// scaffold.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::RgbaImage;
+use std::path::Path;
+
+use crate::atomic::write_atomic;
+use crate::builder::DmiBuilder;
+use crate::cmdline::{BlankArgs, BlankState, NewArgs};
+use crate::decompile::decompile_bytes;
+use crate::dmi_file::DmiFile;
+use crate::error::{IconToolError, Result};
+
+pub fn blank(args: &BlankArgs) -> Result<()> {
+    // don't clobber somebody's work
+    let path = Path::new(&args.output);
+    if path.exists() {
+        let problem = format!("'{}' already exists", args.output);
+        return Err(IconToolError::InvalidArgument(problem));
+    }
+
+    // write out the empty icon
+    blank_icon(args.size, &args.states)?.save(path)
+}
+
+pub fn new_dmi_yaml(args: &NewArgs) -> Result<()> {
+    // don't clobber somebody's work
+    let path = Path::new(&args.file);
+    if path.exists() {
+        let problem = format!("'{}' already exists", args.file);
+        return Err(IconToolError::InvalidArgument(problem));
+    }
+
+    // write out the skeleton
+    let yaml = scaffold_yaml(args)?;
+    write_atomic(path, yaml)?;
+    Ok(())
+}
+
+pub fn scaffold_yaml(args: &NewArgs) -> Result<String> {
+    // every icon_state gets the same number of dirs and frames
+    let states: Vec<BlankState> = args
+        .states
+        .iter()
+        .map(|name| BlankState {
+            name: name.clone(),
+            dirs: args.dirs,
+            frames: args.frames,
+        })
+        .collect();
+
+    // decompile a blank icon, so the yaml is exactly what compile expects
+    let dmi = blank_icon(args.size, &states)?;
+    decompile_bytes(&dmi.to_bytes()?)
+}
+
+pub fn blank_icon(size: (u32, u32), states: &[BlankState]) -> Result<DmiFile> {
+    // build an icon where every frame of every icon_state is transparent
+    let (width, height) = size;
+    let mut builder = DmiBuilder::new(width, height);
+    for state in states {
+        builder = builder
+            .state(&state.name)
+            .dirs(state.dirs)
+            .frames(state.frames);
+        if state.frames > 1 {
+            builder = builder.delay(&vec![1.0; state.frames as usize]);
+        }
+        for _ in 0..state.dirs * state.frames {
+            builder = builder.push_frame(RgbaImage::new(width, height));
+        }
+    }
+    builder.build()
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmdline::parse_blank_state;
+    use crate::compile::compile_bytes;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_scaffold_yaml() {
+        let args = NewArgs {
+            file: String::from("door.dmi.yml"),
+            size: (32, 32),
+            states: vec![
+                String::from("idle"),
+                String::from("open"),
+                String::from("broken"),
+            ],
+            dirs: 4,
+            frames: 2,
+        };
+        let yaml = scaffold_yaml(&args).unwrap();
+        let dmi = DmiFile::from_bytes(&compile_bytes(&yaml).unwrap()).unwrap();
+        assert_eq!(3, dmi.metadata.states.len());
+        assert_eq!("broken", dmi.metadata.states[2].name);
+        assert_eq!(8, dmi.frames[2].len());
+        assert!(dmi.frames[2][7].pixels().all(|pixel| pixel[3] == 0));
+    }
+
+    #[test]
+    fn test_new_dmi_yaml_refuses_to_overwrite() {
+        let args = NewArgs {
+            file: String::from("tests/data/edit/spin.dmi"),
+            size: (32, 32),
+            states: vec![String::from("idle")],
+            dirs: 1,
+            frames: 1,
+        };
+        match new_dmi_yaml(&args) {
+            Err(IconToolError::InvalidArgument(_)) => {}
+            _ => panic!("test_new_dmi_yaml_refuses_to_overwrite: Expected InvalidArgument error"),
+        }
+    }
+
+    #[test]
+    fn test_blank() {
+        std::fs::create_dir_all("tests/data/scaffold").unwrap();
+        let path = PathBuf::from("tests/data/scaffold/blank.out.dmi");
+        let _ = std::fs::remove_file(&path);
+        let args = BlankArgs {
+            output: path.to_string_lossy().to_string(),
+            size: (32, 32),
+            states: vec![
+                parse_blank_state("idle:4dirs").unwrap(),
+                parse_blank_state("walk:4dirs:2frames").unwrap(),
+            ],
+        };
+        blank(&args).unwrap();
+        let dmi = DmiFile::open(&path).unwrap();
+        assert_eq!(2, dmi.metadata.states.len());
+        assert_eq!(4, dmi.metadata.states[0].dirs);
+        assert_eq!(8, dmi.frames[1].len());
+        assert!(dmi.frames[1][7].pixels().all(|pixel| pixel[3] == 0));
+        assert!(blank(&args).is_err());
+    }
+
+    #[test]
+    fn test_blank_icon_without_states() {
+        let dmi = blank_icon((32, 32), &[]).unwrap();
+        let dmi = DmiFile::from_bytes(&dmi.to_bytes().unwrap()).unwrap();
+        assert!(dmi.metadata.states.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+// serve.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cmdline::ServeArgs;
+use crate::error::{get_error_message, IconToolError, Result};
+use crate::log::diagnostic;
+use crate::report::{get_page_path, load_entry, render_index, render_page};
+use crate::walk::find_files;
+
+// each page asks for the version of the files every second, and reloads
+// itself when it changes
+const LIVE_RELOAD: &str = "<script>
+let version = null;
+setInterval(async () => {
+  const latest = await fetch('/version').then((x) => x.text()).catch(() => version);
+  if (version !== null && latest !== version) {
+    location.reload();
+  }
+  version = latest;
+}, 1000);
+</script>
+";
+
+// requests are served one at a time, so a client that connects and never
+// finishes its request must not hold up everyone else for long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// an HTTP response: status, content type, and body
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+pub fn serve(args: &ServeArgs) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", args.port))?;
+    println!("icontool: serving on http://{}/", listener.local_addr()?);
+
+    // one request at a time is plenty for previewing sprites
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(x) => {
+                diagnostic(&format!("icontool: {x}"));
+                continue;
+            }
+        };
+        match handle_connection(stream, args) {
+            Ok(()) => {}
+            Err(x) if matches!(x.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                diagnostic(&format!(
+                    "icontool: dropped a connection that was idle for {} seconds",
+                    REQUEST_TIMEOUT.as_secs()
+                ));
+            }
+            Err(x) => diagnostic(&format!("icontool: {x}")),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, args: &ServeArgs) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    // only the request line matters, but read the headers to be polite
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let response = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, ..] => respond(args, target),
+        _ => Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain",
+            body: String::from("only GET is supported\n"),
+        },
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+// render the requested page from the .dmi files as they are right now
+pub fn respond(args: &ServeArgs, target: &str) -> Response {
+    let path = decode_url(target.split('?').next().unwrap_or_default());
+    let files = match find_files(&args.paths, args.recursive, ".dmi") {
+        Ok(files) => files,
+        Err(x) => return server_error(x),
+    };
+
+    match path.as_str() {
+        "/version" => Response {
+            status: "200 OK",
+            content_type: "text/plain",
+            body: get_version(&files),
+        },
+        "/" | "/index.html" => {
+            let entries: Vec<_> = files.iter().map(|x| load_entry(x).0).collect();
+            html(render_index(&entries))
+        }
+        _ => {
+            // find the .dmi file that the page belongs to
+            let wanted = path.trim_start_matches('/');
+            let Some(file) = files.iter().find(|x| to_url_path(x) == wanted) else {
+                return Response {
+                    status: "404 Not Found",
+                    content_type: "text/plain",
+                    body: format!("no .dmi file has the page '{path}'\n"),
+                };
+            };
+            let (entry, sheet) = load_entry(file);
+            match sheet {
+                Some(sheet) => match render_page(&entry.name, &get_page_path(file), &sheet) {
+                    Ok(page) => html(page),
+                    Err(x) => server_error(x),
+                },
+                None => Response {
+                    status: "500 Internal Server Error",
+                    content_type: "text/plain",
+                    body: format!("{}\n", entry.error.unwrap_or_default()),
+                },
+            }
+        }
+    }
+}
+
+fn html(page: String) -> Response {
+    Response {
+        status: "200 OK",
+        content_type: "text/html",
+        body: page.replace("</body>", &format!("{LIVE_RELOAD}</body>")),
+    }
+}
+
+fn server_error(error: IconToolError) -> Response {
+    Response {
+        status: "500 Internal Server Error",
+        content_type: "text/plain",
+        body: format!("{}\n", get_error_message(error)),
+    }
+}
+
+fn to_url_path(file: &Path) -> String {
+    let parts: Vec<String> = get_page_path(file)
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy().to_string())
+        .collect();
+    parts.join("/")
+}
+
+// a fingerprint of the names, sizes, and modification times of the files;
+// any edit, addition, or removal changes it
+fn get_version(files: &[PathBuf]) -> String {
+    let mut hasher = DefaultHasher::new();
+    for file in files {
+        file.hash(&mut hasher);
+        if let Ok(metadata) = std::fs::metadata(file) {
+            metadata.len().hash(&mut hasher);
+            metadata.modified().ok().hash(&mut hasher);
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+// undo the percent encoding a browser applies to a URL path
+fn decode_url(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = path.get(index + 1..index + 3);
+        match (
+            bytes[index],
+            hex.and_then(|x| u8::from_str_radix(x, 16).ok()),
+        ) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_args() -> ServeArgs {
+        ServeArgs {
+            port: 0,
+            recursive: false,
+            paths: vec![String::from("tests/data/edit/spin.dmi")],
+        }
+    }
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_respond() {
+        let args = get_args();
+        let index = respond(&args, "/");
+        assert_eq!("200 OK", index.status);
+        assert!(index.body.contains("tests/data/edit/spin.dmi.html"));
+        assert!(index.body.contains("fetch('/version')"));
+
+        let page = respond(&args, "/tests/data/edit/spin.dmi.html?x=1");
+        assert_eq!("200 OK", page.status);
+        assert_eq!(3, page.body.matches("data:image/gif;base64,").count());
+
+        let version = respond(&args, "/version");
+        assert_eq!(version.body, respond(&args, "/version").body);
+        assert_eq!("404 Not Found", respond(&args, "/neck.dmi.html").status);
+    }
+
+    #[test]
+    fn test_decode_url() {
+        assert_eq!(
+            "/my icons/a+b.dmi.html",
+            decode_url("/my%20icons/a+b.dmi.html")
+        );
+        assert_eq!("/100%", decode_url("/100%"));
+        assert_eq!("/%zz", decode_url("/%zz"));
+    }
+}
@@ -0,0 +1,289 @@
+// sprite_test.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// Sprite tests are small invariants that a sprite author can declare in
+// the __tests section of a .dmi.yml file. Compile evaluates each of them
+// against the painted image, so CI can enforce them. The supported forms:
+//
+//     __tests:
+//     - "state bluetie frame 0 pixel (4,5) == #FF0000"
+//     - 'state "red tie" frame 2 pixel (0,0) == #00000000'
+//     - "state bluetie has no pixels outside radius 14"
+//
+// Quote the tests in YAML, otherwise the '#' starts a YAML comment.
+
+use image::{DynamicImage, GenericImageView};
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, take_while_m_n},
+    character::complete::{digit1, multispace0, multispace1},
+    combinator::{all_consuming, map, map_res, success},
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
+
+use crate::error::{IconToolError, Result};
+use crate::parser::DreamMakerIconMetadata;
+
+#[derive(Debug, PartialEq)]
+pub enum SpriteTest {
+    PixelEquals {
+        state: String,
+        frame: u32,
+        x: u32,
+        y: u32,
+        rgba: [u8; 4],
+    },
+    NoPixelsOutsideRadius {
+        state: String,
+        radius: u32,
+    },
+}
+
+pub fn parse_sprite_test(input: &str) -> Result<SpriteTest> {
+    match all_consuming(nomify_sprite_test)(input.trim()) {
+        Ok((_, test)) => Ok(test),
+        Err(_) => Err(IconToolError::InvalidSpriteTest(input.to_string())),
+    }
+}
+
+// Evaluate each test against the painted image, returning a description of
+// every test that failed.
+pub fn run_sprite_tests(
+    tests: &[String],
+    dmi: &DreamMakerIconMetadata,
+    image: &DynamicImage,
+) -> Result<Vec<String>> {
+    let mut failures = Vec::new();
+    for text in tests {
+        let test = parse_sprite_test(text)?;
+        if let Some(reason) = evaluate(&test, dmi, image) {
+            failures.push(format!("{text}: {reason}"));
+        }
+    }
+    Ok(failures)
+}
+
+fn evaluate(
+    test: &SpriteTest,
+    dmi: &DreamMakerIconMetadata,
+    image: &DynamicImage,
+) -> Option<String> {
+    match test {
+        SpriteTest::PixelEquals {
+            state,
+            frame,
+            x,
+            y,
+            rgba,
+        } => {
+            if *x >= dmi.width || *y >= dmi.height {
+                return Some(format!("pixel ({x},{y}) is outside the icon"));
+            }
+            let (origin_x, origin_y) = match find_frame(dmi, image, state, *frame) {
+                Ok(origin) => origin,
+                Err(reason) => return Some(reason),
+            };
+            let actual = image.get_pixel(origin_x + x, origin_y + y).0;
+            if actual != *rgba {
+                return Some(format!("found {}", format_rgba(&actual)));
+            }
+            None
+        }
+        SpriteTest::NoPixelsOutsideRadius { state, radius } => {
+            let keys = dmi.state_keys();
+            let num_frames = match dmi.states.iter().zip(&keys).find(|(_, k)| *k == state) {
+                Some((s, _)) => s.dirs * s.frames,
+                None => return Some(format!("icon_state '{state}' not found")),
+            };
+            let center_x = dmi.width as f64 / 2.0;
+            let center_y = dmi.height as f64 / 2.0;
+            let radius = *radius as f64;
+            for frame in 0..num_frames {
+                let (origin_x, origin_y) = match find_frame(dmi, image, state, frame) {
+                    Ok(origin) => origin,
+                    Err(reason) => return Some(reason),
+                };
+                for y in 0..dmi.height {
+                    for x in 0..dmi.width {
+                        let dx = x as f64 + 0.5 - center_x;
+                        let dy = y as f64 + 0.5 - center_y;
+                        if (dx * dx + dy * dy).sqrt() <= radius {
+                            continue;
+                        }
+                        if image.get_pixel(origin_x + x, origin_y + y).0[3] != 0 {
+                            return Some(format!("frame {frame} has a pixel at ({x},{y})"));
+                        }
+                    }
+                }
+            }
+            None
+        }
+    }
+}
+
+fn find_frame(
+    dmi: &DreamMakerIconMetadata,
+    image: &DynamicImage,
+    name: &str,
+    frame: u32,
+) -> std::result::Result<(u32, u32), String> {
+    // count the frames that precede the requested one in the sheet
+    let mut index = 0;
+    for (state, key) in dmi.states.iter().zip(dmi.state_keys()) {
+        let num_frames = state.dirs * state.frames;
+        if key == name {
+            if frame >= num_frames {
+                return Err(format!(
+                    "icon_state '{name}' has only {num_frames} frame(s)"
+                ));
+            }
+            let frames_per_row = image.width() / dmi.width;
+            let index = index + frame;
+            let x = (index % frames_per_row) * dmi.width;
+            let y = (index / frames_per_row) * dmi.height;
+            return Ok((x, y));
+        }
+        index += num_frames;
+    }
+    Err(format!("icon_state '{name}' not found"))
+}
+
+fn format_rgba(rgba: &[u8; 4]) -> String {
+    format!(
+        "#{:02X}{:02X}{:02X}{:02X}",
+        rgba[0], rgba[1], rgba[2], rgba[3]
+    )
+}
+
+fn nomify_sprite_test(input: &str) -> IResult<&str, SpriteTest> {
+    let (input, _) = tag("state")(input)?;
+    let (input, state) = delimited(multispace1, parse_state_name, multispace1)(input)?;
+    if let Ok((input, (frame, x, y, rgba))) = parse_pixel_equals(input) {
+        let test = SpriteTest::PixelEquals {
+            state,
+            frame,
+            x,
+            y,
+            rgba,
+        };
+        return Ok((input, test));
+    }
+    let (input, radius) = parse_radius(input)?;
+    Ok((input, SpriteTest::NoPixelsOutsideRadius { state, radius }))
+}
+
+fn parse_state_name(input: &str) -> IResult<&str, String> {
+    alt((
+        map(delimited(tag("\""), is_not("\""), tag("\"")), String::from),
+        map(is_not(" \t"), String::from),
+    ))(input)
+}
+
+fn parse_pixel_equals(input: &str) -> IResult<&str, (u32, u32, u32, [u8; 4])> {
+    let (input, frame) = preceded(tuple((tag("frame"), multispace1)), parse_u32)(input)?;
+    let (input, _) = tuple((multispace1, tag("pixel"), multispace0, tag("(")))(input)?;
+    let (input, x) = delimited(multispace0, parse_u32, multispace0)(input)?;
+    let (input, _) = tag(",")(input)?;
+    let (input, y) = delimited(multispace0, parse_u32, multispace0)(input)?;
+    let (input, _) = tuple((tag(")"), multispace0, tag("=="), multispace0))(input)?;
+    let (input, rgba) = parse_color(input)?;
+    Ok((input, (frame, x, y, rgba)))
+}
+
+fn parse_radius(input: &str) -> IResult<&str, u32> {
+    let (input, _) = tag("has no pixels outside radius")(input)?;
+    preceded(multispace1, parse_u32)(input)
+}
+
+// Parse a #RRGGBB or #RRGGBBAA color; colors without alpha are opaque.
+pub fn parse_color(input: &str) -> IResult<&str, [u8; 4]> {
+    let (input, _) = tag("#")(input)?;
+    let (input, r) = parse_hex_byte(input)?;
+    let (input, g) = parse_hex_byte(input)?;
+    let (input, b) = parse_hex_byte(input)?;
+    let (input, a) = alt((parse_hex_byte, success(255)))(input)?;
+    Ok((input, [r, g, b, a]))
+}
+
+pub fn parse_rgba(value: &str) -> std::result::Result<[u8; 4], String> {
+    // colors are given as #RRGGBB or #RRGGBBAA
+    match all_consuming(parse_color)(value) {
+        Ok((_, rgba)) => Ok(rgba),
+        Err(_) => Err(format!("expected #RRGGBB or #RRGGBBAA, found '{value}'")),
+    }
+}
+
+fn parse_hex_byte(input: &str) -> IResult<&str, u8> {
+    map_res(
+        take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit()),
+        |hex| u8::from_str_radix(hex, 16),
+    )(input)
+}
+
+fn parse_u32(input: &str) -> IResult<&str, u32> {
+    map_res(digit1, |digits: &str| digits.parse::<u32>())(input)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_parse_pixel_equals() {
+        let test = parse_sprite_test("state bluetie frame 0 pixel (4,5) == #FF0000").unwrap();
+        assert_eq!(
+            SpriteTest::PixelEquals {
+                state: String::from("bluetie"),
+                frame: 0,
+                x: 4,
+                y: 5,
+                rgba: [255, 0, 0, 255],
+            },
+            test
+        );
+    }
+
+    #[test]
+    fn test_parse_radius_quoted() {
+        let test = parse_sprite_test("state \"red tie\" has no pixels outside radius 14").unwrap();
+        assert_eq!(
+            SpriteTest::NoPixelsOutsideRadius {
+                state: String::from("red tie"),
+                radius: 14,
+            },
+            test
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        match parse_sprite_test("state bluetie looks nice") {
+            Err(IconToolError::InvalidSpriteTest(_)) => {}
+            _ => panic!("test_parse_invalid: Expected InvalidSpriteTest error"),
+        }
+    }
+}
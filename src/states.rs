@@ -0,0 +1,272 @@
+// states.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::Path;
+
+use crate::cmdline::{StatesArgs, StatesFormat};
+use crate::dmi::read_metadata;
+use crate::dmi_file::{DmiFile, IconState};
+use crate::error::Result;
+use crate::parser::{
+    delay_to_fps, normalize_number, parse_metadata, DreamMakerIconMetadata, DreamMakerIconState,
+};
+use crate::walk::find_files;
+
+const CSV_HEADER: &str = "file,state,dirs,frames,delay,fps,loop,rewind,movement,pixel_hash";
+
+pub fn list_states(args: &StatesArgs) -> Result<()> {
+    let files = find_files(&args.paths, args.recursive, ".dmi")?;
+
+    // one header for every file, so the rows can go straight into a spreadsheet
+    if args.format == StatesFormat::Csv {
+        println!("{CSV_HEADER}");
+        for path in &files {
+            for row in format_csv_rows(path, &DmiFile::open(path)?) {
+                println!("{row}");
+            }
+        }
+        return Ok(());
+    }
+
+    // print a table of the icon_states of each file, naming the file when
+    // there is more than one
+    for (index, path) in files.iter().enumerate() {
+        let metadata_text = read_metadata(path)?;
+        let dmi = parse_metadata(&metadata_text)?;
+        if files.len() > 1 {
+            if index > 0 {
+                println!();
+            }
+            println!("{}:", path.display());
+        }
+        for line in format_states(&dmi) {
+            println!("{line}");
+        }
+    }
+
+    Ok(())
+}
+
+// one CSV row per icon_state of a .dmi file
+pub fn format_csv_rows(path: &Path, dmi: &DmiFile) -> Vec<String> {
+    let file = path.display().to_string();
+    let keys = dmi.metadata.state_keys();
+    dmi.states()
+        .zip(keys)
+        .map(|(state, key)| {
+            let metadata = state.metadata;
+            let fields = [
+                file.clone(),
+                key,
+                metadata.dirs.to_string(),
+                metadata.frames.to_string(),
+                metadata
+                    .delay
+                    .as_ref()
+                    .map(|x| x.join(","))
+                    .unwrap_or_default(),
+                format_fps(metadata),
+                metadata._loop.clone().unwrap_or_default(),
+                metadata.rewind.clone().unwrap_or_default(),
+                String::from(if metadata.movement { "1" } else { "0" }),
+                hash_pixels(&state),
+            ];
+            let fields: Vec<String> = fields.iter().map(|x| escape_csv(x)).collect();
+            fields.join(",")
+        })
+        .collect()
+}
+
+// a CRC-32 of every frame of an icon_state, to spot art changes between audits
+fn hash_pixels(state: &IconState) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    for frame in state.frames {
+        hasher.update(&frame.width().to_be_bytes());
+        hasher.update(&frame.height().to_be_bytes());
+        hasher.update(frame.as_raw());
+    }
+    format!("{:08x}", hasher.finalize())
+}
+
+// quote a CSV field if it holds a comma, quote, or line break
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn format_states(dmi: &DreamMakerIconMetadata) -> Vec<String> {
+    let keys = dmi.state_keys();
+    let name_width = keys
+        .iter()
+        .map(|key| key.len() + 2)
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    let mut lines = vec![format!(
+        "{:name_width$}  {:>4}  {:>6}  {:20}  {:12}  FLAGS",
+        "STATE", "DIRS", "FRAMES", "DELAY", "FPS"
+    )];
+    for (state, key) in dmi.states.iter().zip(keys) {
+        let delay = state
+            .delay
+            .as_ref()
+            .map(|x| x.join(","))
+            .unwrap_or_default();
+        lines.push(
+            format!(
+                "{:name_width$}  {:>4}  {:>6}  {:20}  {:12}  {}",
+                format!("\"{key}\""),
+                state.dirs,
+                state.frames,
+                delay,
+                format_fps(state),
+                format_flags(state)
+            )
+            .trim_end()
+            .to_string(),
+        );
+    }
+    lines
+}
+
+// the delays as frames per second; one number when every frame is the same
+pub fn format_fps(state: &DreamMakerIconState) -> String {
+    // a still has no frame rate, and an animation without delays plays a
+    // frame every tick
+    if state.frames < 2 {
+        return String::new();
+    }
+    let Some(delays) = &state.delay else {
+        return normalize_number(&delay_to_fps(1.0).to_string());
+    };
+    let fps: Vec<String> = delays
+        .iter()
+        .map(|x| match x.parse::<f64>() {
+            Ok(delay) if delay > 0.0 => normalize_number(&delay_to_fps(delay).to_string()),
+            _ => String::from("?"),
+        })
+        .collect();
+    match fps.first() {
+        Some(first) if fps.iter().all(|x| x == first) => first.clone(),
+        _ => fps.join(","),
+    }
+}
+
+fn format_flags(state: &DreamMakerIconState) -> String {
+    let mut flags = Vec::new();
+    if let Some(_loop) = &state._loop {
+        flags.push(format!("loop={_loop}"));
+    }
+    if state.rewind.is_some() {
+        flags.push(String::from("rewind"));
+    }
+    if state.movement {
+        flags.push(String::from("movement"));
+    }
+    if !state.hotspots.is_empty() {
+        flags.push(String::from("hotspot"));
+    }
+    flags.join(" ")
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_format_states() {
+        let text = "# BEGIN DMI\nversion = 4.0\nstate = \"walk\"\n\tdirs = 4\n\tframes = 1\nstate = \"walk\"\n\tdirs = 4\n\tframes = 2\n\tdelay = 1,1\n\tmovement = 1\n# END DMI\n";
+        let dmi = parse_metadata(text).unwrap();
+        let lines = format_states(&dmi);
+        assert_eq!(3, lines.len());
+        assert!(lines[0].starts_with("STATE"));
+        assert_eq!("\"walk\"       4       1", lines[1]);
+        assert!(lines[2].starts_with("\"walk@2\""));
+        assert!(lines[2].ends_with("1,1                   10            movement"));
+    }
+
+    #[test]
+    fn test_list_states() {
+        let mut args = StatesArgs {
+            format: StatesFormat::Table,
+            recursive: false,
+            paths: vec![String::from("tests/data/edit/spin.dmi")],
+        };
+        assert!(list_states(&args).is_ok());
+        args.format = StatesFormat::Csv;
+        assert!(list_states(&args).is_ok());
+    }
+
+    #[test]
+    fn test_format_csv_rows() {
+        let path = Path::new("tests/data/edit/spin.dmi");
+        let mut dmi = DmiFile::open(path).unwrap();
+        let rows = format_csv_rows(path, &dmi);
+        assert_eq!(3, rows.len());
+        assert!(
+            rows[0].starts_with("tests/data/edit/spin.dmi,spin,1,3,\"1,2,3\",\"10,5,3.33\",,,0,")
+        );
+        assert!(rows[2].starts_with("tests/data/edit/spin.dmi,still,1,1,,,,,0,"));
+
+        // the hash follows the pixels
+        dmi.frames[2][0].put_pixel(0, 0, image::Rgba([1, 2, 3, 4]));
+        let changed = format_csv_rows(path, &dmi);
+        assert_eq!(rows[0], changed[0]);
+        assert_ne!(rows[2], changed[2]);
+    }
+
+    #[test]
+    fn test_format_fps() {
+        let mut state = DreamMakerIconState::new("spin", 1, 3);
+        assert_eq!("10", format_fps(&state));
+        state.delay = Some(vec![String::from("2"); 3]);
+        assert_eq!("5", format_fps(&state));
+        state.delay = Some(vec![
+            String::from("1"),
+            String::from("4"),
+            String::from("x"),
+        ]);
+        assert_eq!("10,2.5,?", format_fps(&state));
+        state.delay = Some(Vec::new());
+        assert_eq!("", format_fps(&state));
+        state.delay = None;
+        state.frames = u32::MAX;
+        assert_eq!("10", format_fps(&state));
+        state.frames = 1;
+        assert_eq!("", format_fps(&state));
+    }
+
+    #[test]
+    fn test_escape_csv() {
+        assert_eq!("walk", escape_csv("walk"));
+        assert_eq!("\"1,2\"", escape_csv("1,2"));
+        assert_eq!("\"say \"\"hi\"\"\"", escape_csv("say \"hi\""));
+    }
+}
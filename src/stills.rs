@@ -0,0 +1,97 @@
+// stills.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::ImageFormat;
+use std::path::PathBuf;
+
+use crate::atomic::create_atomic;
+use crate::cmdline::StillsArgs;
+use crate::dmi_file::DmiFile;
+use crate::error::Result;
+
+pub fn stills(args: &StillsArgs) -> Result<()> {
+    // cut the .dmi file up into frames
+    let path = PathBuf::from(&args.file);
+    let sheet = DmiFile::open(&path)?;
+
+    // write the first south-facing frame of each icon_state as a .png
+    std::fs::create_dir_all(&args.output_dir)?;
+    for (index, key) in sheet.metadata.state_keys().iter().enumerate() {
+        if sheet.frames[index].is_empty() {
+            continue;
+        }
+        let mut output_path = PathBuf::from(&args.output_dir);
+        output_path.push(format!("{}.png", get_file_name(key)));
+        create_atomic(&output_path, |writer| {
+            Ok(sheet
+                .frame(index, 0, 0)
+                .write_to(writer, ImageFormat::Png)?)
+        })?;
+    }
+
+    Ok(())
+}
+
+fn get_file_name(key: &str) -> String {
+    // icon_state names can contain anything; keep file names portable
+    if key.is_empty() {
+        return String::from("_");
+    }
+    key.chars()
+        .map(|ch| match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '@' | ' ' => ch,
+            _ => '_',
+        })
+        .collect()
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dmi::read_image;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_get_file_name() {
+        assert_eq!("_", get_file_name(""));
+        assert_eq!("bluetie@2", get_file_name("bluetie@2"));
+        assert_eq!("a_b_c", get_file_name("a/b:c"));
+    }
+
+    #[test]
+    fn test_stills() {
+        let args = StillsArgs {
+            output_dir: String::from("tests/data/stills/spin.out.d"),
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        stills(&args).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from(&args.file)).unwrap();
+        for (index, name) in ["spin", "walk", "still"].iter().enumerate() {
+            let path = PathBuf::from(format!("tests/data/stills/spin.out.d/{name}.png"));
+            let image = read_image(&path).unwrap().to_rgba8();
+            assert_eq!(sheet.frame(index, 0, 0), &image);
+        }
+    }
+}
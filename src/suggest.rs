@@ -0,0 +1,134 @@
+// suggest.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// find the candidate closest to a name that wasn't found, and describe it
+// as a hint for the user
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    // a near miss in whitespace, capitalization, or quoting beats any
+    // candidate that is merely a few edits away
+    let mut best: Option<((bool, usize), &str)> = None;
+    for candidate in candidates {
+        let score = (
+            get_note(name, candidate).is_none(),
+            edit_distance(name, candidate),
+        );
+        if best.is_none_or(|(x, _)| score < x) {
+            best = Some((score, candidate));
+        }
+    }
+
+    // a suggestion that needs too many edits is just noise
+    let ((_, distance), candidate) = best?;
+    let note = get_note(name, candidate);
+    let limit = (name.chars().count() / 3).max(2);
+    if distance > limit && note.is_none() {
+        return None;
+    }
+    match note {
+        Some(note) => Some(format!("did you mean '{candidate}'? note the {note}")),
+        None => Some(format!("did you mean '{candidate}'?")),
+    }
+}
+
+fn get_note(name: &str, candidate: &str) -> Option<&'static str> {
+    let unquote = |x: &str| x.replace(['"', '\''], "");
+    if name == candidate {
+        None
+    } else if name.trim() == candidate.trim() {
+        if name.trim_end() == candidate.trim_end() {
+            Some("trailing space")
+        } else if name.trim_start() == candidate.trim_start() {
+            Some("leading space")
+        } else {
+            Some("leading and trailing spaces")
+        }
+    } else if name.to_lowercase() == candidate.to_lowercase() {
+        Some("capitalization")
+    } else if unquote(name) == unquote(candidate) {
+        Some("quotes")
+    } else {
+        None
+    }
+}
+
+// the number of single character insertions, deletions, or substitutions
+// needed to turn one string into the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(x != *y);
+            current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("open", "open"));
+        assert_eq!(1, edit_distance("open", "opens"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+        assert_eq!(4, edit_distance("", "door"));
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let candidates = ["closed", "opened ", "broken"];
+        assert_eq!(
+            Some(String::from(
+                "did you mean 'opened '? note the trailing space"
+            )),
+            did_you_mean("opened", candidates)
+        );
+        assert_eq!(
+            Some(String::from(
+                "did you mean 'broken'? note the capitalization"
+            )),
+            did_you_mean("BROKEN", candidates)
+        );
+        assert_eq!(
+            Some(String::from("did you mean 'closed'?")),
+            did_you_mean("clsoed", candidates)
+        );
+        assert_eq!(None, did_you_mean("sparkle", candidates));
+        assert_eq!(None, did_you_mean("open", []));
+        assert_eq!(
+            Some(String::from("did you mean '\"on\"'? note the quotes")),
+            did_you_mean("on", ["\"on\"", "off"])
+        );
+    }
+}
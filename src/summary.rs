@@ -0,0 +1,90 @@
+// summary.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use crate::log::diagnostic;
+
+// what happened to each file of a command that works through many of them,
+// so a long run ends by saying which ones failed, and why
+#[derive(Clone, Debug, Default)]
+pub struct BatchSummary {
+    succeeded: usize,
+    failures: Vec<(String, String)>,
+}
+
+impl BatchSummary {
+    pub fn new() -> BatchSummary {
+        BatchSummary::default()
+    }
+
+    pub fn succeed(&mut self) {
+        self.succeeded += 1;
+    }
+
+    pub fn fail(&mut self, name: impl Into<String>, reason: impl Into<String>) {
+        self.failures.push((name.into(), reason.into()));
+    }
+
+    pub fn num_failed(&self) -> usize {
+        self.failures.len()
+    }
+
+    pub fn format(&self) -> Vec<String> {
+        let (succeeded, failed) = (self.succeeded, self.failures.len());
+        let mut lines = vec![format!("icontool: {succeeded} succeeded, {failed} failed")];
+        for (name, reason) in &self.failures {
+            lines.push(format!("  {name}: {reason}"));
+        }
+        lines
+    }
+
+    // print the summary; a single file says all there is to say by itself
+    pub fn report(&self) {
+        if self.succeeded + self.failures.len() > 1 {
+            diagnostic(&self.format().join("\n"));
+        }
+    }
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_format() {
+        let mut summary = BatchSummary::new();
+        summary.succeed();
+        summary.succeed();
+        summary.fail("icons/door.dmi", "missing the PNG signature");
+        assert_eq!(1, summary.num_failed());
+        assert_eq!(
+            vec![
+                "icontool: 2 succeeded, 1 failed",
+                "  icons/door.dmi: missing the PNG signature",
+            ],
+            summary.format()
+        );
+    }
+}
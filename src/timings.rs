@@ -0,0 +1,247 @@
+// timings.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "cli")]
+use crate::log::diagnostic;
+
+// the phases of compiling and decompiling that --timings measures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    YamlParse,
+    MetadataParse,
+    FrameDecode,
+    Paint,
+    PngEncode,
+    PngDecode,
+    FrameEncode,
+}
+
+const NUM_PHASES: usize = 7;
+
+const PHASES: [Phase; NUM_PHASES] = [
+    Phase::YamlParse,
+    Phase::MetadataParse,
+    Phase::FrameDecode,
+    Phase::Paint,
+    Phase::PngEncode,
+    Phase::PngDecode,
+    Phase::FrameEncode,
+];
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::YamlParse => "YAML parse",
+            Phase::MetadataParse => "metadata parse",
+            Phase::FrameDecode => "frame decode",
+            Phase::Paint => "paint",
+            Phase::PngEncode => "PNG encode",
+            Phase::PngDecode => "PNG decode",
+            Phase::FrameEncode => "frame encode",
+        }
+    }
+}
+
+// the time spent in each phase, for the file being worked on and for the
+// whole run
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+    phases: [Duration; NUM_PHASES],
+    started: Option<Instant>,
+    // for each phase still running, the time spent in the phases inside it,
+    // which doesn't count as its own
+    nested: Vec<Duration>,
+    totals: [Duration; NUM_PHASES],
+    total: Duration,
+    num_files: usize,
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings::default()
+    }
+
+    pub fn enter(&mut self) {
+        self.nested.push(Duration::ZERO);
+    }
+
+    pub fn exit(&mut self, phase: Phase, elapsed: Duration) {
+        let inner = self.nested.pop().unwrap_or_default();
+        self.phases[phase as usize] += elapsed.saturating_sub(inner);
+        if let Some(outer) = self.nested.last_mut() {
+            *outer += elapsed;
+        }
+    }
+
+    pub fn start_file(&mut self) {
+        self.phases = Default::default();
+        self.started = Some(Instant::now());
+    }
+
+    // the timings of the file just finished, which are added to the run
+    pub fn finish_file(&mut self, name: &str) -> Vec<String> {
+        let total = self.started.take().map(|x| x.elapsed()).unwrap_or_default();
+        for (sum, phase) in self.totals.iter_mut().zip(self.phases) {
+            *sum += phase;
+        }
+        self.total += total;
+        self.num_files += 1;
+        format_timings(&format!("timings for {name}"), &self.phases, total)
+    }
+
+    // the timings of the run; a single file has already said it all
+    pub fn format_totals(&self) -> Vec<String> {
+        if self.num_files < 2 {
+            return Vec::new();
+        }
+        let title = format!("timings for all {} files", self.num_files);
+        format_timings(&title, &self.totals, self.total)
+    }
+}
+
+// the timings of this run, if the user asked for them with --timings
+static TIMINGS: OnceLock<Mutex<Timings>> = OnceLock::new();
+
+pub fn set_timings() {
+    let _ = TIMINGS.set(Mutex::new(Timings::new()));
+}
+
+// run some work, counting the time it takes toward a phase
+pub fn time_phase<T>(phase: Phase, work: impl FnOnce() -> T) -> T {
+    let Some(timings) = TIMINGS.get() else {
+        return work();
+    };
+    timings.lock().unwrap_or_else(|x| x.into_inner()).enter();
+    let started = Instant::now();
+    let result = work();
+    let elapsed = started.elapsed();
+    timings
+        .lock()
+        .unwrap_or_else(|x| x.into_inner())
+        .exit(phase, elapsed);
+    result
+}
+
+#[cfg(feature = "cli")]
+pub fn start_file() {
+    if let Some(timings) = TIMINGS.get() {
+        timings
+            .lock()
+            .unwrap_or_else(|x| x.into_inner())
+            .start_file();
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn finish_file(name: &str) {
+    if let Some(timings) = TIMINGS.get() {
+        let lines = timings
+            .lock()
+            .unwrap_or_else(|x| x.into_inner())
+            .finish_file(name);
+        diagnostic(&lines.join("\n"));
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn report_timings() {
+    if let Some(timings) = TIMINGS.get() {
+        let lines = timings
+            .lock()
+            .unwrap_or_else(|x| x.into_inner())
+            .format_totals();
+        if !lines.is_empty() {
+            diagnostic(&lines.join("\n"));
+        }
+    }
+}
+
+// only the phases that took any time are listed; compile and decompile each
+// go through some of them
+fn format_timings(title: &str, phases: &[Duration], total: Duration) -> Vec<String> {
+    let mut lines = vec![format!("icontool: {title}")];
+    for (phase, elapsed) in PHASES.iter().zip(phases) {
+        if !elapsed.is_zero() {
+            lines.push(format_line(phase.name(), *elapsed));
+        }
+    }
+    lines.push(format_line("total", total));
+    lines
+}
+
+fn format_line(name: &str, elapsed: Duration) -> String {
+    format!("  {name:<16}{:>10.3} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_nested_phases() {
+        let mut timings = Timings::new();
+        timings.start_file();
+        timings.enter();
+        timings.enter();
+        timings.exit(Phase::FrameDecode, Duration::from_millis(3));
+        timings.enter();
+        timings.exit(Phase::FrameDecode, Duration::from_millis(2));
+        timings.exit(Phase::Paint, Duration::from_millis(12));
+        timings.enter();
+        timings.exit(Phase::PngEncode, Duration::from_millis(20));
+        let lines = timings.finish_file("icon.dmi.yml");
+        assert_eq!("icontool: timings for icon.dmi.yml", lines[0]);
+        assert_eq!("  frame decode         5.000 ms", lines[1]);
+        assert_eq!("  paint                7.000 ms", lines[2]);
+        assert_eq!("  PNG encode          20.000 ms", lines[3]);
+        assert!(lines[4].starts_with("  total "));
+        assert_eq!(5, lines.len());
+    }
+
+    #[test]
+    fn test_format_totals() {
+        let mut timings = Timings::new();
+        for _ in 0..2 {
+            timings.start_file();
+            timings.enter();
+            timings.exit(Phase::YamlParse, Duration::from_millis(4));
+            timings.finish_file("icon.dmi.yml");
+        }
+        let lines = timings.format_totals();
+        assert_eq!("icontool: timings for all 2 files", lines[0]);
+        assert_eq!("  YAML parse           8.000 ms", lines[1]);
+        assert_eq!(3, lines.len());
+
+        // a single file isn't totalled again
+        let mut timings = Timings::new();
+        timings.start_file();
+        timings.finish_file("icon.dmi.yml");
+        assert!(timings.format_totals().is_empty());
+    }
+}
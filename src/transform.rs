@@ -0,0 +1,869 @@
+// transform.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::{imageops, Rgba, RgbaImage};
+use serde_yml::Value;
+#[cfg(feature = "cli")]
+use std::path::PathBuf;
+
+#[cfg(feature = "cli")]
+use crate::cmdline::{
+    Anchor, ConvertSizeArgs, FlipArgs, GenDirsArgs, GenDirsMode, RepadArgs, RotateArgs, ScaleArgs,
+    ScaleFilter, ShiftArgs, SizeStrategy,
+};
+#[cfg(feature = "cli")]
+use crate::dmi_file::{edit_dmi_file, DmiFile};
+#[cfg(feature = "cli")]
+use crate::edit::get_output_path;
+use crate::error::{IconToolError, Result};
+#[cfg(feature = "cli")]
+use crate::log::diagnostic;
+use crate::parser::DreamMakerIconHotspot;
+#[cfg(feature = "cli")]
+use crate::parser::DreamMakerIconState;
+use crate::sprite_test::parse_rgba;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlendMode {
+    Add,
+    Subtract,
+    Multiply,
+    Overlay,
+    Underlay,
+}
+
+// the directions of an icon_state in sheet order, as (x, y) steps on screen
+const DIR_VECTORS: [(i32, i32); 8] = [
+    (0, 1),   // SOUTH
+    (0, -1),  // NORTH
+    (1, 0),   // EAST
+    (-1, 0),  // WEST
+    (1, 1),   // SOUTHEAST
+    (-1, 1),  // SOUTHWEST
+    (1, -1),  // NORTHEAST
+    (-1, -1), // NORTHWEST
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    Identity,
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90, // clockwise
+    Rotate180,
+    Rotate270,
+}
+
+impl Transform {
+    pub fn apply(&self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Transform::Identity => image.clone(),
+            Transform::FlipHorizontal => imageops::flip_horizontal(image),
+            Transform::FlipVertical => imageops::flip_vertical(image),
+            Transform::Rotate90 => imageops::rotate90(image),
+            Transform::Rotate180 => imageops::rotate180(image),
+            Transform::Rotate270 => imageops::rotate270(image),
+        }
+    }
+
+    // where a pixel of a width x height image ends up after the transform
+    pub fn apply_point(&self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Identity => (x, y),
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Rotate90 => (height - 1 - y, x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (y, width - 1 - x),
+        }
+    }
+
+    pub fn apply_hotspot(
+        &self,
+        hotspot: &DreamMakerIconHotspot,
+        width: u32,
+        height: u32,
+    ) -> DreamMakerIconHotspot {
+        let (x, y) = self.apply_point(hotspot.x, hotspot.y, width, height);
+        DreamMakerIconHotspot { x, y, ..*hotspot }
+    }
+
+    // which direction a sprite facing the provided direction faces afterwards
+    pub fn apply_dir(&self, dir: usize) -> usize {
+        let (x, y) = DIR_VECTORS[dir];
+        let turned = match self {
+            Transform::Identity => (x, y),
+            Transform::FlipHorizontal => (-x, y),
+            Transform::FlipVertical => (x, -y),
+            Transform::Rotate90 => (-y, x),
+            Transform::Rotate180 => (-x, -y),
+            Transform::Rotate270 => (y, -x),
+        };
+        DIR_VECTORS.iter().position(|v| *v == turned).unwrap_or(dir)
+    }
+
+    pub fn is_quarter_turn(&self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270)
+    }
+}
+
+// one step of a __transforms declaration in a .dmi.yml
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameOp {
+    Transform(Transform),
+    Tint([u8; 4]),
+}
+
+// read a list of ops, like [flip_h, tint: "#802020"], for width x height icons
+pub fn parse_frame_ops(value: &Value, width: u32, height: u32) -> Result<Vec<FrameOp>> {
+    let invalid = |reason: String| IconToolError::InvalidTransform(reason);
+    let list = value
+        .as_sequence()
+        .ok_or_else(|| invalid(String::from("ops must be a list")))?;
+    let mut ops = Vec::new();
+    for op in list {
+        let op = match op {
+            Value::String(name) => match name.as_str() {
+                "flip_h" => FrameOp::Transform(Transform::FlipHorizontal),
+                "flip_v" => FrameOp::Transform(Transform::FlipVertical),
+                "rotate_90" => FrameOp::Transform(Transform::Rotate90),
+                "rotate_180" => FrameOp::Transform(Transform::Rotate180),
+                "rotate_270" => FrameOp::Transform(Transform::Rotate270),
+                _ => return Err(invalid(format!("unknown op '{name}'"))),
+            },
+            // an op with a parameter is written as a one-entry mapping
+            Value::Mapping(mapping) if mapping.len() == 1 => {
+                let (name, param) = mapping.iter().next().expect("the mapping has one entry");
+                match (name.as_str(), param.as_str()) {
+                    (Some("tint"), Some(color)) => {
+                        FrameOp::Tint(parse_rgba(color).map_err(invalid)?)
+                    }
+                    _ => {
+                        return Err(invalid(format!(
+                            "unknown op {}",
+                            serde_yml::to_string(mapping)?.trim()
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "unknown op {}",
+                    serde_yml::to_string(op)?.trim()
+                )))
+            }
+        };
+        ops.push(op);
+    }
+    let transforms: Vec<Transform> = ops
+        .iter()
+        .filter_map(|op| match op {
+            FrameOp::Transform(transform) => Some(*transform),
+            FrameOp::Tint(_) => None,
+        })
+        .collect();
+    check_transforms(&transforms, width, height)?;
+    Ok(ops)
+}
+
+// apply the ops to the frames of an icon_state, in order
+pub fn apply_frame_ops(frames: Vec<RgbaImage>, dirs: usize, ops: &[FrameOp]) -> Vec<RgbaImage> {
+    let mut frames = frames;
+    for op in ops {
+        frames = match op {
+            FrameOp::Transform(transform) => transform_frames(&frames, dirs, *transform),
+            FrameOp::Tint(color) => frames
+                .into_iter()
+                .map(|mut frame| {
+                    let tint = RgbaImage::from_pixel(frame.width(), frame.height(), Rgba(*color));
+                    blend(&mut frame, &tint, &BlendMode::Multiply);
+                    frame
+                })
+                .collect(),
+        };
+    }
+    frames
+}
+
+#[cfg(feature = "cli")]
+pub fn convert_size(args: &ConvertSizeArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let old = (sheet.metadata.width, sheet.metadata.height);
+
+        // every icon_state uses the default, unless it was given its own
+        let mut strategies = vec![&args.default; sheet.metadata.states.len()];
+        for state in &args.state {
+            let index = sheet
+                .metadata
+                .find_state(&state.name)
+                .ok_or_else(|| IconToolError::StateNotFound(state.name.clone()))?;
+            strategies[index] = &state.strategy;
+        }
+
+        // scale or pad each icon_state to the new size
+        let states = sheet.metadata.states.iter_mut().zip(&mut sheet.frames);
+        for ((state, frames), strategy) in states.zip(strategies) {
+            match strategy {
+                SizeStrategy::Scale(filter) => resize_state(state, frames, filter, old, args.to),
+                SizeStrategy::Pad(anchor) => pad_state(state, frames, anchor, old, args.to),
+            }
+        }
+        (sheet.metadata.width, sheet.metadata.height) = args.to;
+
+        Ok(())
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn flip(args: &FlipArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    let mut transforms = Vec::new();
+    if args.horizontal {
+        transforms.push(Transform::FlipHorizontal);
+    }
+    if args.vertical {
+        transforms.push(Transform::FlipVertical);
+    }
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        for index in select_states(sheet, &args.state)? {
+            for transform in &transforms {
+                transform_state(sheet, index, *transform)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn gen_dirs(args: &GenDirsArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let (width, height) = (sheet.metadata.width, sheet.metadata.height);
+
+        // find the icon_state that we're expanding
+        let index = sheet
+            .metadata
+            .find_state(&args.state)
+            .ok_or_else(|| IconToolError::StateNotFound(args.state.clone()))?;
+        let state = &mut sheet.metadata.states[index];
+        if state.dirs != 1 {
+            return Err(IconToolError::InvalidTransform(format!(
+                "icon_state '{}' already has {} dirs",
+                args.state, state.dirs
+            )));
+        }
+
+        // derive the S, N, E, W frames from the south-facing frame
+        let transforms = match args.mode {
+            GenDirsMode::Rotate => [
+                Transform::Identity,
+                Transform::Rotate180,
+                Transform::Rotate270,
+                Transform::Rotate90,
+            ],
+            GenDirsMode::Mirror => [
+                Transform::Identity,
+                Transform::Identity,
+                Transform::Identity,
+                Transform::FlipHorizontal,
+            ],
+        };
+        check_transforms(&transforms, width, height)?;
+
+        // build the new frames, keeping the directions of each frame together
+        sheet.frames[index] = sheet.frames[index]
+            .iter()
+            .flat_map(|frame| transforms.iter().map(|t| t.apply(frame)))
+            .collect();
+
+        // every direction gets its own copy of the hotspot
+        let mut hotspots = Vec::new();
+        for hotspot in &state.hotspots {
+            let Some(index) = hotspot.frame.checked_sub(1) else {
+                continue;
+            };
+            for (dir, transform) in transforms.iter().enumerate() {
+                let mut hotspot = transform.apply_hotspot(hotspot, width, height);
+                hotspot.frame = index * 4 + dir as u32 + 1;
+                hotspots.push(hotspot);
+            }
+        }
+        state.hotspots = hotspots;
+        state.dirs = 4;
+
+        Ok(())
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn repad(args: &RepadArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let old = (sheet.metadata.width, sheet.metadata.height);
+        let states = sheet.metadata.states.iter_mut().zip(&mut sheet.frames);
+        for (state, frames) in states {
+            pad_state(state, frames, &args.anchor, old, args.size);
+        }
+        (sheet.metadata.width, sheet.metadata.height) = args.size;
+
+        Ok(())
+    })
+}
+
+// pad or crop the frames of an icon_state onto a new canvas
+#[cfg(feature = "cli")]
+fn pad_state(
+    state: &mut DreamMakerIconState,
+    frames: &mut [RgbaImage],
+    anchor: &Anchor,
+    old: (u32, u32),
+    new: (u32, u32),
+) {
+    let (width, height) = new;
+
+    // work out where the old icon sits on the new canvas
+    let (dx, dy) = get_anchor_offset(anchor, old, new);
+
+    // pad or crop every frame onto the new canvas
+    for frame in frames.iter_mut() {
+        let mut canvas = RgbaImage::new(width, height);
+        imageops::replace(&mut canvas, frame, dx, dy);
+        *frame = canvas;
+    }
+
+    // keep the hotspots over the same pixels, if they're still there
+    let count = state.hotspots.len();
+    state.hotspots = state
+        .hotspots
+        .iter()
+        .filter_map(|hotspot| {
+            let x = u32::try_from(hotspot.x as i64 + dx).ok()?;
+            let y = u32::try_from(hotspot.y as i64 + dy).ok()?;
+            (x < width && y < height).then_some(DreamMakerIconHotspot { x, y, ..*hotspot })
+        })
+        .collect();
+    if state.hotspots.len() < count {
+        diagnostic(&format!(
+            "icontool: removed {} hotspot(s) cropped out of icon_state '{}'",
+            count - state.hotspots.len(),
+            state.name
+        ));
+    }
+}
+
+// resize the frames of an icon_state, keeping the hotspots on the same
+// part of the art
+#[cfg(feature = "cli")]
+fn resize_state(
+    state: &mut DreamMakerIconState,
+    frames: &mut [RgbaImage],
+    filter: &ScaleFilter,
+    old: (u32, u32),
+    new: (u32, u32),
+) {
+    let filter = match filter {
+        ScaleFilter::Nearest => imageops::FilterType::Nearest,
+        ScaleFilter::Bilinear => imageops::FilterType::Triangle,
+    };
+    for frame in frames.iter_mut() {
+        *frame = imageops::resize(frame, new.0, new.1, filter);
+    }
+    for hotspot in &mut state.hotspots {
+        hotspot.x = hotspot.x * new.0 / old.0.max(1);
+        hotspot.y = hotspot.y * new.1 / old.1.max(1);
+    }
+}
+
+#[cfg(feature = "cli")]
+fn get_anchor_offset(anchor: &Anchor, old: (u32, u32), new: (u32, u32)) -> (i64, i64) {
+    let spare_x = new.0 as i64 - old.0 as i64;
+    let spare_y = new.1 as i64 - old.1 as i64;
+    let (x, y) = match anchor {
+        Anchor::TopLeft => (0, 0),
+        Anchor::Top => (spare_x / 2, 0),
+        Anchor::TopRight => (spare_x, 0),
+        Anchor::Left => (0, spare_y / 2),
+        Anchor::Center => (spare_x / 2, spare_y / 2),
+        Anchor::Right => (spare_x, spare_y / 2),
+        Anchor::BottomLeft => (0, spare_y),
+        Anchor::Bottom => (spare_x / 2, spare_y),
+        Anchor::BottomRight => (spare_x, spare_y),
+    };
+    (x, y)
+}
+
+#[cfg(feature = "cli")]
+pub fn rotate(args: &RotateArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    // rotations are clockwise
+    let transform = match args.degrees {
+        90 => Transform::Rotate90,
+        180 => Transform::Rotate180,
+        _ => Transform::Rotate270,
+    };
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        for index in select_states(sheet, &args.state)? {
+            transform_state(sheet, index, transform)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn scale(args: &ScaleArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let old = (sheet.metadata.width, sheet.metadata.height);
+        let new = (old.0 * args.factor, old.1 * args.factor);
+
+        // scale up every frame
+        let states = sheet.metadata.states.iter_mut().zip(&mut sheet.frames);
+        for (state, frames) in states {
+            resize_state(state, frames, &args.filter, old, new);
+        }
+        (sheet.metadata.width, sheet.metadata.height) = new;
+
+        Ok(())
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn shift(args: &ShiftArgs) -> Result<()> {
+    let path = PathBuf::from(&args.file);
+    let output_path = get_output_path(&args.file, &args.output);
+    let (dx, dy) = args.by;
+
+    edit_dmi_file(&path, &output_path, |sheet| {
+        let (width, height) = (sheet.metadata.width as i64, sheet.metadata.height as i64);
+        for index in select_states(sheet, &args.state)? {
+            // move the pixels of every frame within its tile
+            for frame in &mut sheet.frames[index] {
+                let mut shifted = RgbaImage::new(frame.width(), frame.height());
+                if args.wrap {
+                    for (x, y, pixel) in frame.enumerate_pixels() {
+                        let x = (x as i64 + dx).rem_euclid(width) as u32;
+                        let y = (y as i64 + dy).rem_euclid(height) as u32;
+                        shifted.put_pixel(x, y, *pixel);
+                    }
+                } else {
+                    imageops::replace(&mut shifted, frame, dx, dy);
+                }
+                *frame = shifted;
+            }
+
+            // move the hotspots along with the pixels
+            let state = &mut sheet.metadata.states[index];
+            for hotspot in &mut state.hotspots {
+                let x = hotspot.x as i64 + dx;
+                let y = hotspot.y as i64 + dy;
+                let (x, y) = match args.wrap {
+                    true => (x.rem_euclid(width), y.rem_euclid(height)),
+                    false => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+                };
+                hotspot.x = x as u32;
+                hotspot.y = y as u32;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn check_transforms(transforms: &[Transform], width: u32, height: u32) -> Result<()> {
+    // turning a frame on its side only works if it still fits afterwards
+    if width != height && transforms.iter().any(Transform::is_quarter_turn) {
+        return Err(IconToolError::InvalidTransform(format!(
+            "cannot rotate {width}x{height} icons by 90 degrees; they must be square"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+pub fn select_states(sheet: &DmiFile, state: &Option<String>) -> Result<Vec<usize>> {
+    // transform the named icon_state, or every icon_state in the file
+    match state {
+        Some(name) => match sheet.metadata.find_state(name) {
+            Some(index) => Ok(vec![index]),
+            None => Err(IconToolError::StateNotFound(name.clone())),
+        },
+        None => Ok((0..sheet.metadata.states.len()).collect()),
+    }
+}
+
+#[cfg(feature = "cli")]
+pub fn transform_state(sheet: &mut DmiFile, index: usize, transform: Transform) -> Result<()> {
+    let (width, height) = (sheet.metadata.width, sheet.metadata.height);
+    check_transforms(&[transform], width, height)?;
+    let state = &mut sheet.metadata.states[index];
+    let dirs = state.dirs as usize;
+
+    // transform the pixels of every frame
+    sheet.frames[index] = transform_frames(&sheet.frames[index], dirs, transform);
+
+    // move the hotspots along with their frames
+    for hotspot in &mut state.hotspots {
+        let Some(position) = hotspot.frame.checked_sub(1) else {
+            continue;
+        };
+        let target = get_target_position(position as usize, dirs, transform);
+        *hotspot = transform.apply_hotspot(hotspot, width, height);
+        hotspot.frame = target as u32 + 1;
+    }
+    state.hotspots.sort_by_key(|hotspot| hotspot.frame);
+
+    Ok(())
+}
+
+pub fn blend(base: &mut RgbaImage, layer: &RgbaImage, mode: &BlendMode) {
+    match mode {
+        BlendMode::Overlay => imageops::overlay(base, layer, 0, 0),
+        BlendMode::Underlay => {
+            let mut result = layer.clone();
+            imageops::overlay(&mut result, base, 0, 0);
+            *base = result;
+        }
+        _ => {
+            for (pixel, other) in base.pixels_mut().zip(layer.pixels()) {
+                let [r, g, b, a] = pixel.0;
+                let [or, og, ob, oa] = other.0;
+                pixel.0 = match mode {
+                    BlendMode::Add => [
+                        r.saturating_add(or),
+                        g.saturating_add(og),
+                        b.saturating_add(ob),
+                        a,
+                    ],
+                    BlendMode::Subtract => [
+                        r.saturating_sub(or),
+                        g.saturating_sub(og),
+                        b.saturating_sub(ob),
+                        a,
+                    ],
+                    _ => [mul(r, or), mul(g, og), mul(b, ob), mul(a, oa)],
+                };
+            }
+        }
+    }
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    ((a as u32 * b as u32 + 127) / 255) as u8
+}
+
+fn transform_frames(frames: &[RgbaImage], dirs: usize, transform: Transform) -> Vec<RgbaImage> {
+    let mut result = frames.to_vec();
+    for (position, frame) in frames.iter().enumerate() {
+        result[get_target_position(position, dirs, transform)] = transform.apply(frame);
+    }
+    result
+}
+
+// a single direction is shown for every facing, so it stays put;
+// otherwise each frame moves to the direction it now faces
+fn get_target_position(position: usize, dirs: usize, transform: Transform) -> usize {
+    let dir = position % dirs;
+    let new_dir = match dirs {
+        1 => dir,
+        _ => transform.apply_dir(dir),
+    };
+    position - dir + new_dir
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "cli")]
+    use crate::cmdline::StateSizeStrategy;
+    #[cfg(feature = "cli")]
+    use crate::dmi_file::DmiFile;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_apply_point_matches_apply() {
+        let mut image = RgbaImage::new(4, 3);
+        image.put_pixel(1, 0, image::Rgba([255, 0, 0, 255]));
+        for transform in [
+            Transform::FlipHorizontal,
+            Transform::FlipVertical,
+            Transform::Rotate90,
+            Transform::Rotate180,
+            Transform::Rotate270,
+        ] {
+            let (x, y) = transform.apply_point(1, 0, 4, 3);
+            let result = transform.apply(&image);
+            assert_eq!(&image::Rgba([255, 0, 0, 255]), result.get_pixel(x, y));
+        }
+    }
+
+    #[test]
+    fn test_apply_dir() {
+        // SOUTH=0, NORTH=1, EAST=2, WEST=3, SOUTHEAST=4, SOUTHWEST=5, NORTHEAST=6, NORTHWEST=7
+        assert_eq!(3, Transform::FlipHorizontal.apply_dir(2));
+        assert_eq!(0, Transform::FlipHorizontal.apply_dir(0));
+        assert_eq!(6, Transform::FlipVertical.apply_dir(4));
+        assert_eq!(3, Transform::Rotate90.apply_dir(0));
+        assert_eq!(2, Transform::Rotate270.apply_dir(0));
+        assert_eq!(7, Transform::Rotate180.apply_dir(4));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_flip_horizontal() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = FlipArgs {
+            output: Some(String::from("tests/data/edit/flip.out.dmi")),
+            state: None,
+            horizontal: true,
+            vertical: false,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        flip(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/flip.out.dmi")).unwrap();
+        let mirrored = imageops::flip_horizontal(&original.frames[0][1]);
+        assert_eq!(mirrored, sheet.frames[0][1]);
+        // east and west trade places in directional icon_states
+        let east = imageops::flip_horizontal(original.frame(1, 1, 2));
+        assert_eq!(&east, sheet.frame(1, 1, 3));
+        let south = imageops::flip_horizontal(original.frame(1, 1, 0));
+        assert_eq!(&south, sheet.frame(1, 1, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_get_anchor_offset() {
+        let (old, new) = ((32, 32), (48, 48));
+        assert_eq!((8, 8), get_anchor_offset(&Anchor::Center, old, new));
+        assert_eq!((8, 16), get_anchor_offset(&Anchor::Bottom, old, new));
+        assert_eq!((0, 0), get_anchor_offset(&Anchor::TopLeft, old, new));
+        assert_eq!((-8, -8), get_anchor_offset(&Anchor::Center, new, old));
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_repad() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = RepadArgs {
+            output: Some(String::from("tests/data/edit/repad.out.dmi")),
+            size: (48, 40),
+            anchor: Anchor::Bottom,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        repad(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/repad.out.dmi")).unwrap();
+        assert_eq!((48, 40), (sheet.metadata.width, sheet.metadata.height));
+        let inner = imageops::crop_imm(&sheet.frames[1][3], 8, 8, 32, 32).to_image();
+        assert_eq!(original.frames[1][3], inner);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_convert_size() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = ConvertSizeArgs {
+            output: Some(String::from("tests/data/edit/convert_size.out.dmi")),
+            to: (64, 64),
+            default: SizeStrategy::Scale(ScaleFilter::Nearest),
+            state: vec![StateSizeStrategy {
+                name: String::from("walk"),
+                strategy: SizeStrategy::Pad(Anchor::Bottom),
+            }],
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        convert_size(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/convert_size.out.dmi")).unwrap();
+        assert_eq!((64, 64), (sheet.metadata.width, sheet.metadata.height));
+
+        // spin was scaled up, while walk was padded
+        let pixel = original.frames[0][1].get_pixel(3, 5);
+        assert_eq!(pixel, sheet.frames[0][1].get_pixel(7, 11));
+        let inner = imageops::crop_imm(&sheet.frames[1][3], 16, 32, 32, 32).to_image();
+        assert_eq!(original.frames[1][3], inner);
+
+        // unknown icon_states are an error
+        let args = ConvertSizeArgs {
+            state: vec![StateSizeStrategy {
+                name: String::from("run"),
+                strategy: SizeStrategy::Pad(Anchor::Center),
+            }],
+            ..args
+        };
+        match convert_size(&args) {
+            Err(IconToolError::StateNotFound(x)) => assert_eq!("run", x),
+            _ => panic!("test_convert_size: Expected StateNotFound error"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_rotate() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = RotateArgs {
+            output: Some(String::from("tests/data/edit/rotate.out.dmi")),
+            state: Some(String::from("walk")),
+            degrees: 90,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        rotate(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/rotate.out.dmi")).unwrap();
+        // the north frame turns to face east, and so on around the compass
+        for (from, to) in [(1, 2), (2, 0), (0, 3), (3, 1)] {
+            let turned = imageops::rotate90(original.frame(1, 0, from));
+            assert_eq!(&turned, sheet.frame(1, 0, to));
+        }
+        assert_eq!(original.frames[0], sheet.frames[0]);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_scale_nearest() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = ScaleArgs {
+            output: Some(String::from("tests/data/edit/scale.out.dmi")),
+            factor: 2,
+            filter: ScaleFilter::Nearest,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        scale(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/scale.out.dmi")).unwrap();
+        assert_eq!((64, 64), (sheet.metadata.width, sheet.metadata.height));
+        let frame = &original.frames[1][5];
+        let scaled = &sheet.frames[1][5];
+        for (x, y, pixel) in frame.enumerate_pixels() {
+            assert_eq!(pixel, scaled.get_pixel(x * 2, y * 2));
+            assert_eq!(pixel, scaled.get_pixel(x * 2 + 1, y * 2 + 1));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_shift_wrap_and_clip() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let mut args = ShiftArgs {
+            output: Some(String::from("tests/data/edit/shift.out.dmi")),
+            state: Some(String::from("spin")),
+            by: (3, -2),
+            wrap: true,
+            clip: false,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        shift(&args).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/shift.out.dmi")).unwrap();
+        let frame = &original.frames[0][0];
+        assert_eq!(frame.get_pixel(0, 0), sheet.frames[0][0].get_pixel(3, 30));
+        assert_eq!(frame.get_pixel(31, 1), sheet.frames[0][0].get_pixel(2, 31));
+        assert_eq!(original.frames[1], sheet.frames[1]);
+
+        args.wrap = false;
+        args.clip = true;
+        shift(&args).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/shift.out.dmi")).unwrap();
+        assert_eq!(frame.get_pixel(0, 2), sheet.frames[0][0].get_pixel(3, 0));
+        assert_eq!(
+            &image::Rgba([0, 0, 0, 0]),
+            sheet.frames[0][0].get_pixel(0, 0)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_gen_dirs_rotate() {
+        std::fs::create_dir_all("tests/data/edit").unwrap();
+        let args = GenDirsArgs {
+            output: Some(String::from("tests/data/edit/gen_dirs.out.dmi")),
+            state: String::from("spin"),
+            mode: GenDirsMode::Rotate,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        gen_dirs(&args).unwrap();
+        let original = DmiFile::open(&PathBuf::from("tests/data/edit/spin.dmi")).unwrap();
+        let sheet = DmiFile::open(&PathBuf::from("tests/data/edit/gen_dirs.out.dmi")).unwrap();
+        assert_eq!(4, sheet.metadata.states[0].dirs);
+        assert_eq!(12, sheet.frames[0].len());
+        assert_eq!(&original.frames[0][1], sheet.frame(0, 1, 0));
+        let north = imageops::rotate180(&original.frames[0][1]);
+        assert_eq!(&north, sheet.frame(0, 1, 1));
+        assert_eq!(original.frames[1], sheet.frames[1]);
+    }
+
+    #[test]
+    #[cfg(feature = "cli")]
+    fn test_gen_dirs_already_directional() {
+        let args = GenDirsArgs {
+            output: Some(String::from("tests/data/edit/gen_dirs_walk.out.dmi")),
+            state: String::from("walk"),
+            mode: GenDirsMode::Mirror,
+            file: String::from("tests/data/edit/spin.dmi"),
+        };
+        match gen_dirs(&args) {
+            Err(IconToolError::InvalidTransform(_)) => {}
+            _ => panic!("test_gen_dirs_already_directional: Expected InvalidTransform error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_ops() {
+        let value: Value = serde_yml::from_str("[flip_h, rotate_90, tint: \"#80202040\"]").unwrap();
+        assert_eq!(
+            vec![
+                FrameOp::Transform(Transform::FlipHorizontal),
+                FrameOp::Transform(Transform::Rotate90),
+                FrameOp::Tint([0x80, 0x20, 0x20, 0x40]),
+            ],
+            parse_frame_ops(&value, 32, 32).unwrap()
+        );
+        match parse_frame_ops(&value, 32, 16) {
+            Err(IconToolError::InvalidTransform(_)) => {}
+            _ => panic!("test_parse_frame_ops: Expected InvalidTransform error"),
+        }
+        for bad in ["flip_h", "[tint: red]", "[blur: 2]", "[[flip_h]]"] {
+            let value: Value = serde_yml::from_str(bad).unwrap();
+            match parse_frame_ops(&value, 32, 32) {
+                Err(IconToolError::InvalidTransform(_)) => {}
+                _ => panic!("test_parse_frame_ops: Expected InvalidTransform error for {bad}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// upgrade.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use image::DynamicImage;
+use std::path::PathBuf;
+
+use crate::cmdline::UpgradeArgs;
+use crate::constant::ZTXT_KEYWORD;
+use crate::dmi::{read_metadata, read_rgba_image, write_dmi_file};
+use crate::error::Result;
+use crate::log::diagnostic;
+use crate::parser::{parse_metadata, CURRENT_DMI_VERSION};
+
+pub fn upgrade(args: &UpgradeArgs) -> Result<()> {
+    // determine the path to the provided dmi file
+    let path = PathBuf::from(&args.file);
+
+    // read the image and metadata from the provided dmi file
+    let image = read_rgba_image(&path)?;
+    let metadata_text = read_metadata(&path)?;
+    let mut dmi_metadata = parse_metadata(&metadata_text)?;
+
+    // rewrite the metadata in the current canonical format
+    let old_version = dmi_metadata.version;
+    dmi_metadata.version = CURRENT_DMI_VERSION;
+    let upgraded_text = dmi_metadata.to_string();
+
+    // if there is nothing to do, leave the file alone
+    let output_path = match &args.output {
+        Some(output) => PathBuf::from(output),
+        None => path.clone(),
+    };
+    if upgraded_text == metadata_text && output_path == path {
+        diagnostic(&format!("icontool: {} is already up to date", args.file));
+        return Ok(());
+    }
+
+    // write the upgraded .dmi file
+    if old_version != CURRENT_DMI_VERSION {
+        diagnostic(&format!(
+            "icontool: upgraded {} from version {old_version} to {CURRENT_DMI_VERSION}",
+            args.file
+        ));
+    }
+    let image = DynamicImage::ImageRgba8(image);
+    write_dmi_file(&output_path, ZTXT_KEYWORD, &upgraded_text, &image)?;
+
+    // return success to the caller
+    Ok(())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_upgrade_output() {
+        let args = UpgradeArgs {
+            output: Some(String::from("tests/data/decompile/neck.upgrade.out.dmi")),
+            file: String::from("tests/data/decompile/neck.dmi"),
+        };
+        upgrade(&args).unwrap();
+        let path = PathBuf::from("tests/data/decompile/neck.upgrade.out.dmi");
+        let dmi = parse_metadata(&read_metadata(&path).unwrap()).unwrap();
+        assert_eq!(CURRENT_DMI_VERSION, dmi.version);
+        assert_eq!(read_rgba_image(&path).unwrap().width(), 256);
+    }
+}
@@ -0,0 +1,153 @@
+// validate.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use crate::diagnostics::Diagnostics;
+use crate::error::{IconToolError, Result};
+use crate::parser::{DreamMakerIconMetadata, CURRENT_DMI_VERSION};
+
+pub const VALID_DIRS: [u32; 3] = [1, 4, 8];
+
+pub fn validate_metadata(dmi: &DreamMakerIconMetadata) -> Result<()> {
+    // report the first problem we find to the caller
+    match find_problems(dmi).into_iter().next() {
+        Some(problem) => Err(problem),
+        None => Ok(()),
+    }
+}
+
+pub fn warn_for_unknown_version(dmi: &DreamMakerIconMetadata, diagnostics: &mut Diagnostics) {
+    // newer versions of BYOND may change the meaning of the metadata
+    if !dmi.version.is_known() {
+        diagnostics.warn(format!(
+            "icontool: dmi metadata version {} is unknown to icontool; expected {CURRENT_DMI_VERSION}",
+            dmi.version
+        ));
+    }
+}
+
+pub fn find_problems(dmi: &DreamMakerIconMetadata) -> Vec<IconToolError> {
+    let mut problems = Vec::new();
+
+    // for each icon_state in the dmi metadata
+    for state in &dmi.states {
+        // BYOND only understands 1, 4, or 8 directions
+        if !VALID_DIRS.contains(&state.dirs) {
+            problems.push(IconToolError::InvalidDirs(state.name.clone(), state.dirs));
+        }
+        // every icon_state needs at least one frame
+        if state.frames < 1 {
+            problems.push(IconToolError::InvalidFrames(
+                state.name.clone(),
+                state.frames,
+            ));
+        }
+        // if there is a delay list, it needs one delay per frame
+        if let Some(delay) = &state.delay {
+            if delay.len() != state.frames as usize {
+                problems.push(IconToolError::DelayCountMismatch(
+                    state.name.clone(),
+                    state.frames as usize,
+                    delay.len(),
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+pub fn find_warnings(dmi: &DreamMakerIconMetadata) -> Vec<IconToolError> {
+    let mut warnings = Vec::new();
+
+    // movement states are paired with a regular state of the same name
+    for state in dmi.states.iter().filter(|state| state.movement) {
+        let has_base = dmi
+            .states
+            .iter()
+            .any(|base| !base.movement && base.name == state.name);
+        if !has_base {
+            warnings.push(IconToolError::MovementWithoutBase(state.name.clone()));
+        }
+    }
+
+    warnings
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_metadata;
+
+    fn metadata(state: &str) -> DreamMakerIconMetadata {
+        let text = format!("# BEGIN DMI\nversion = 4.0\n{state}# END DMI\n");
+        parse_metadata(&text).unwrap()
+    }
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_valid_metadata() {
+        let dmi = metadata("state = \"ok\"\n\tdirs = 4\n\tframes = 2\n\tdelay = 1,2\n");
+        assert!(validate_metadata(&dmi).is_ok());
+    }
+
+    #[test]
+    fn test_movement_without_base() {
+        let dmi = metadata("state = \"walk\"\n\tdirs = 4\n\tframes = 1\n\tmovement = 1\nstate = \"run\"\n\tdirs = 4\n\tframes = 1\nstate = \"run\"\n\tdirs = 4\n\tframes = 1\n\tmovement = 1\n");
+        let warnings = find_warnings(&dmi);
+        assert_eq!(1, warnings.len());
+        match &warnings[0] {
+            IconToolError::MovementWithoutBase(name) => assert_eq!("walk", name),
+            _ => panic!("test_movement_without_base: Expected MovementWithoutBase warning"),
+        }
+        assert!(validate_metadata(&dmi).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_dirs() {
+        let dmi = metadata("state = \"bad\"\n\tdirs = 3\n\tframes = 1\n");
+        match validate_metadata(&dmi) {
+            Err(IconToolError::InvalidDirs(name, 3)) => assert_eq!("bad", name),
+            _ => panic!("test_invalid_dirs: Expected InvalidDirs error"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let dmi = metadata("state = \"bad\"\n\tdirs = 1\n\tframes = 0\n");
+        match validate_metadata(&dmi) {
+            Err(IconToolError::InvalidFrames(name, 0)) => assert_eq!("bad", name),
+            _ => panic!("test_invalid_frames: Expected InvalidFrames error"),
+        }
+    }
+
+    #[test]
+    fn test_delay_count_mismatch() {
+        let dmi = metadata("state = \"bad\"\n\tdirs = 1\n\tframes = 3\n\tdelay = 1,1\n");
+        match validate_metadata(&dmi) {
+            Err(IconToolError::DelayCountMismatch(name, 3, 2)) => assert_eq!("bad", name),
+            _ => panic!("test_delay_count_mismatch: Expected DelayCountMismatch error"),
+        }
+    }
+}
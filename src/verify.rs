@@ -0,0 +1,223 @@
+// verify.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::Path;
+
+use crate::archive::{find_inputs, Input};
+use crate::cmdline::VerifyArgs;
+use crate::dmi::{decode_image, decode_metadata, PNG_SIGNATURE};
+use crate::error::{get_error_message, get_error_text, IconToolError, Result};
+use crate::parser::parse_metadata;
+use crate::summary::BatchSummary;
+
+pub fn verify(args: &VerifyArgs) -> Result<()> {
+    // check each of the .dmi files, and tell the user about any damage
+    let mut summary = BatchSummary::new();
+    for input in find_inputs(&args.paths, args.recursive, ".dmi")? {
+        let problems = verify_input(&input);
+        for problem in &problems {
+            println!("{}: {problem}", input.name());
+        }
+        match problems.is_empty() {
+            true => summary.succeed(),
+            false => summary.fail(input.name(), problems.join("; ")),
+        }
+    }
+
+    // if anything was damaged, tell the caller
+    summary.report();
+    if summary.num_failed() > 0 {
+        return Err(IconToolError::VerifyFailed(summary.num_failed()));
+    }
+    Ok(())
+}
+
+pub fn verify_file(path: &Path) -> Vec<String> {
+    verify_input(&Input::File(path.to_path_buf()))
+}
+
+// like verify_file, but the file may be inside a .zip archive
+pub fn verify_input(input: &Input) -> Vec<String> {
+    match input.read() {
+        Ok(bytes) => verify_bytes(&bytes),
+        Err(x) => vec![format!("can't be read: {}", get_error_text(x))],
+    }
+}
+
+pub fn verify_bytes(bytes: &[u8]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // without the signature, nothing else in the file can be trusted
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        problems.push(String::from("missing the PNG signature"));
+        return problems;
+    }
+
+    // walk the chunks, checking each one against its CRC
+    let mut dimensions = None;
+    let mut has_end = false;
+    let mut cursor = PNG_SIGNATURE.len();
+    while cursor < bytes.len() {
+        let Some(header) = bytes.get(cursor..cursor + 8) else {
+            problems.push(format!("truncated chunk at offset {cursor}"));
+            break;
+        };
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let chunk_type = String::from_utf8_lossy(&header[4..8]).to_string();
+        let data = bytes.get(cursor + 8..cursor + 8 + length);
+        let crc = bytes.get(cursor + 8 + length..cursor + 12 + length);
+        let (Some(data), Some(crc)) = (data, crc) else {
+            problems.push(format!("truncated {chunk_type} chunk at offset {cursor}"));
+            break;
+        };
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[4..8]);
+        hasher.update(data);
+        if hasher.finalize().to_be_bytes() != crc {
+            problems.push(format!("bad CRC in {chunk_type} chunk at offset {cursor}"));
+        }
+
+        // the header declares the dimensions the image data should have
+        if chunk_type == "IHDR" && data.len() >= 8 {
+            let width = u32::from_be_bytes(data[0..4].try_into().unwrap());
+            let height = u32::from_be_bytes(data[4..8].try_into().unwrap());
+            dimensions = Some((width, height));
+        }
+        has_end = chunk_type == "IEND";
+        cursor += length + 12;
+    }
+    if !has_end && problems.is_empty() {
+        problems.push(String::from("missing the IEND chunk"));
+    }
+
+    // damaged chunks would only make the decoding below fail again
+    if !problems.is_empty() {
+        return problems;
+    }
+    let Some((width, height)) = dimensions else {
+        problems.push(String::from("missing the IHDR chunk"));
+        return problems;
+    };
+
+    // the metadata should decompress and parse
+    match decode_metadata(bytes) {
+        Ok(Some(text)) => {
+            if let Err(x) = parse_metadata(&text) {
+                problems.push(format!("the metadata doesn't parse: {}", describe(x)));
+            }
+        }
+        Ok(None) => problems.push(String::from("missing the dmi metadata")),
+        Err(x) => problems.push(format!("the metadata can't be decoded: {}", describe(x))),
+    }
+
+    // and the image data should decode to the declared dimensions
+    match decode_image(bytes) {
+        Ok(image) if image.width() != width || image.height() != height => {
+            problems.push(format!(
+                "the image data decodes to {}x{}, but the header declares {width}x{height}",
+                image.width(),
+                image.height()
+            ));
+        }
+        Ok(_) => {}
+        Err(x) => problems.push(format!("the image data can't be decoded: {}", describe(x))),
+    }
+
+    problems
+}
+
+fn describe(error: IconToolError) -> String {
+    let message = get_error_message(error);
+    message.trim_start_matches("icontool: ").to_string()
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // find the chunk with the provided type, and return the offset of its data
+    fn find_chunk(bytes: &[u8], chunk_type: &[u8]) -> (usize, usize) {
+        let mut cursor = PNG_SIGNATURE.len();
+        loop {
+            let length = u32::from_be_bytes(bytes[cursor..cursor + 4].try_into().unwrap()) as usize;
+            if &bytes[cursor + 4..cursor + 8] == chunk_type {
+                return (cursor + 8, length);
+            }
+            cursor += length + 12;
+        }
+    }
+
+    fn fix_crc(bytes: &mut [u8], offset: usize, length: usize) {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&bytes[offset - 4..offset + length]);
+        let crc = hasher.finalize().to_be_bytes();
+        bytes[offset + length..offset + length + 4].copy_from_slice(&crc);
+    }
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_verify_bytes() {
+        let bytes = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+        assert!(verify_bytes(&bytes).is_empty());
+
+        assert_eq!(vec!["missing the PNG signature"], verify_bytes(b"GIF89a"));
+
+        let truncated = &bytes[..bytes.len() - 2];
+        let problems = verify_bytes(truncated);
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("truncated IEND chunk"));
+
+        let mut damaged = bytes.clone();
+        let (offset, _) = find_chunk(&damaged, b"IDAT");
+        damaged[offset + 2] ^= 0xff;
+        let problems = verify_bytes(&damaged);
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("bad CRC in IDAT chunk"));
+    }
+
+    #[test]
+    fn test_verify_bytes_decoding() {
+        let bytes = std::fs::read("tests/data/edit/spin.dmi").unwrap();
+
+        // damage the compressed metadata, but keep the CRC valid
+        let mut damaged = bytes.clone();
+        let (offset, length) = find_chunk(&damaged, b"zTXt");
+        damaged[offset + length - 3] ^= 0xff;
+        fix_crc(&mut damaged, offset, length);
+        let problems = verify_bytes(&damaged);
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("the metadata can't be decoded"));
+
+        // declare a bigger image than the image data holds
+        let mut damaged = bytes.clone();
+        let (offset, length) = find_chunk(&damaged, b"IHDR");
+        damaged[offset + 3] = 200;
+        fix_crc(&mut damaged, offset, length);
+        let problems = verify_bytes(&damaged);
+        assert!(problems
+            .iter()
+            .any(|x| x.starts_with("the image data can't be decoded")));
+    }
+}
@@ -0,0 +1,147 @@
+// walk.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{IconToolError, Result};
+
+// expand the provided paths into a sorted list of files; files are used as
+// given, while directories contribute the files ending with the extension,
+// and if recursive, the files of their subdirectories as well
+pub fn find_files(paths: &[String], recursive: bool, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            walk_dir(path, recursive, extension, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+// move an output path under the output directory, keeping its place in the
+// tree; if a prefix is provided, it is removed from the path first, so with
+// a prefix of icons/, icons/mob/neck.dmi.yml becomes <output_dir>/mob/neck.dmi.yml
+pub fn relocate(path: &Path, output_dir: &str, strip_prefix: &Option<String>) -> Result<PathBuf> {
+    let relative = match strip_prefix {
+        Some(prefix) => path.strip_prefix(prefix).map_err(|_| {
+            IconToolError::InvalidArgument(format!(
+                "'{}' does not start with '{prefix}'",
+                path.display()
+            ))
+        })?,
+        None => path,
+    };
+
+    // an absolute path would replace the output directory when joined
+    let relative: PathBuf = relative
+        .components()
+        .filter(|x| matches!(x, std::path::Component::Normal(_)))
+        .collect();
+
+    Ok(Path::new(output_dir).join(relative))
+}
+
+// make sure the directory that will hold an output file exists
+pub fn create_parent_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+fn walk_dir(dir: &Path, recursive: bool, extension: &str, files: &mut Vec<PathBuf>) -> Result<()> {
+    // sort the entries so the output doesn't depend on the filesystem
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                walk_dir(&path, recursive, extension, files)?;
+            }
+        } else if path.to_string_lossy().ends_with(extension) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+
+    #[test]
+    fn test_find_files() {
+        std::fs::create_dir_all("tests/data/walk/sub").unwrap();
+        for file in ["b.out.dmi", "a.out.dmi", "c.out.txt", "sub/d.out.dmi"] {
+            std::fs::write(Path::new("tests/data/walk").join(file), "").unwrap();
+        }
+        let paths = vec![String::from("tests/data/walk")];
+        assert_eq!(
+            vec![
+                PathBuf::from("tests/data/walk/a.out.dmi"),
+                PathBuf::from("tests/data/walk/b.out.dmi"),
+            ],
+            find_files(&paths, false, ".dmi").unwrap()
+        );
+        let paths = vec![String::from("README.md"), String::from("tests/data/walk")];
+        assert_eq!(
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("tests/data/walk/a.out.dmi"),
+                PathBuf::from("tests/data/walk/b.out.dmi"),
+                PathBuf::from("tests/data/walk/sub/d.out.dmi"),
+            ],
+            find_files(&paths, true, ".dmi").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_relocate() {
+        let path = Path::new("icons/mob/neck.dmi.yml");
+        let prefix = Some(String::from("icons/"));
+        assert_eq!(
+            PathBuf::from("build/mob/neck.dmi.yml"),
+            relocate(path, "build/", &prefix).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("build/icons/mob/neck.dmi.yml"),
+            relocate(path, "build", &None).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("build/icons/neck.dmi.yml"),
+            relocate(Path::new("/icons/./neck.dmi.yml"), "build", &None).unwrap()
+        );
+        let prefix = Some(String::from("sprites"));
+        assert!(relocate(path, "build", &prefix).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+// wasm.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+// the WebAssembly API, for running icontool in the browser; build with:
+//
+//     cargo build --lib --release --target wasm32-unknown-unknown \
+//         --no-default-features --features wasm
+//
+// then generate the JavaScript bindings with wasm-bindgen
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::{get_error_message, IconToolError};
+
+fn to_js_error(error: IconToolError) -> JsError {
+    JsError::new(&get_error_message(error))
+}
+
+// compile .dmi.yml text into the bytes of a .dmi file (a Uint8Array)
+#[wasm_bindgen]
+pub fn compile(yaml: &str) -> Result<Vec<u8>, JsError> {
+    crate::compile_bytes(yaml).map_err(to_js_error)
+}
+
+// decompile the bytes of a .dmi file into .dmi.yml text
+#[wasm_bindgen]
+pub fn decompile(bytes: &[u8]) -> Result<String, JsError> {
+    crate::decompile_bytes(bytes).map_err(to_js_error)
+}
+
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+//---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    // use super::*;
+
+    #[test]
+    fn test_always_succeed() {
+        assert!(true);
+    }
+}
@@ -0,0 +1,39 @@
+// compile_stdout.rs
+// Copyright 2024 Patrick Meade.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//---------------------------------------------------------------------------
+
+use std::process::Command;
+
+#[test]
+fn test_compile_to_stdout() {
+    // compile the same yaml to a file and to stdout
+    std::fs::create_dir_all("tests/data/compile").unwrap();
+    let output = "tests/data/compile/stdout.out.dmi";
+    let status = Command::new(env!("CARGO_BIN_EXE_icontool"))
+        .args(["compile", "-o", output, "tests/data/compile/neck.dmi.yml"])
+        .status()
+        .expect("Failed to run icontool");
+    assert!(status.success());
+    let piped = Command::new(env!("CARGO_BIN_EXE_icontool"))
+        .args(["compile", "-o", "-", "tests/data/compile/neck.dmi.yml"])
+        .output()
+        .expect("Failed to run icontool");
+    assert!(piped.status.success());
+
+    // the bytes should be the same
+    let written = std::fs::read(output).unwrap();
+    assert_eq!(written, piped.stdout);
+}